@@ -0,0 +1,471 @@
+//! Account-based execution of block transactions: a transaction's `from`
+//! address pays its fee and owns its nonce sequence.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+use types::{hash_bytes, Address, BlockId, ExecStatus, Hash, Receipt, Transaction, TxId};
+
+/// Balance and next expected nonce of one account.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: u64,
+    /// Nonce the account's next transaction must carry. Starts at 0.
+    pub nonce: u64,
+}
+
+/// Every known account, keyed by sender.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    accounts: HashMap<Address, Account>,
+}
+
+impl AccountState {
+    /// Seed accounts with initial balances, e.g. from genesis config.
+    pub fn with_balances(balances: impl IntoIterator<Item = (Address, u64)>) -> Self {
+        let accounts = balances
+            .into_iter()
+            .map(|(id, balance)| (id, Account { balance, nonce: 0 }))
+            .collect();
+        Self { accounts }
+    }
+
+    /// The account for `id`; unknown accounts are empty.
+    pub fn get(&self, id: Address) -> Account {
+        self.accounts.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Commitment to the full account set: the hash of
+    /// [`AccountState::encode`], or a zero hash if there are no accounts.
+    pub fn root(&self) -> Hash {
+        if self.accounts.is_empty() {
+            return Hash([0u8; 32]);
+        }
+        hash_bytes(&self.encode())
+    }
+
+    /// Every account sorted by address, each as its address followed by
+    /// its big-endian balance and nonce.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut entries: Vec<(&Address, &Account)> = self.accounts.iter().collect();
+        entries.sort_by_key(|(id, _)| **id);
+
+        let mut data = Vec::with_capacity(entries.len() * ACCOUNT_BYTES);
+        for (id, account) in entries {
+            data.extend_from_slice(&id.0);
+            data.extend_from_slice(&account.balance.to_be_bytes());
+            data.extend_from_slice(&account.nonce.to_be_bytes());
+        }
+        data
+    }
+
+    /// Undo [`AccountState::encode`], or `None` if `bytes` is not a whole
+    /// number of accounts.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(ACCOUNT_BYTES) {
+            return None;
+        }
+        let accounts = bytes
+            .chunks_exact(ACCOUNT_BYTES)
+            .map(|entry| {
+                let (id, rest) = entry.split_at(20);
+                let (balance, nonce) = rest.split_at(8);
+                let account = Account {
+                    balance: u64::from_be_bytes(balance.try_into().expect("8 bytes")),
+                    nonce: u64::from_be_bytes(nonce.try_into().expect("8 bytes")),
+                };
+                (Address(id.try_into().expect("20 bytes")), account)
+            })
+            .collect();
+        Some(Self { accounts })
+    }
+}
+
+/// Length of one account in [`AccountState::encode`].
+const ACCOUNT_BYTES: usize = 20 + 8 + 8;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ExecutionError {
+    #[error("insufficient balance: fee {fee}, balance {balance}")]
+    InsufficientBalance { fee: u64, balance: u64 },
+    #[error("fee overflows u64")]
+    FeeOverflow,
+    #[error("nonce {got} already used (expected {expected})")]
+    NonceTooLow { expected: u64, got: u64 },
+    #[error("nonce {got} skips ahead of expected {expected}")]
+    NonceGap { expected: u64, got: u64 },
+}
+
+impl ExecutionError {
+    /// Whether the transaction can never execute because its nonce is
+    /// already used. Other rejections are retried in later blocks.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, ExecutionError::NonceTooLow { .. })
+    }
+}
+
+/// Result of [`StateExecutor::apply`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionOutcome {
+    /// Transactions that executed, in execution order.
+    pub applied: Vec<(TxId, Transaction)>,
+    pub rejected: Vec<(TxId, ExecutionError)>,
+    /// Fees paid by the applied transactions.
+    pub fees: u64,
+}
+
+impl ExecutionOutcome {
+    /// Receipts for a block built from this outcome: one per applied
+    /// transaction, in block order, followed by one per permanently
+    /// rejected transaction. No transaction emits logs yet.
+    pub fn receipts(&self, block_id: BlockId) -> Vec<Receipt> {
+        let applied = self
+            .applied
+            .iter()
+            .map(|(id, tx)| (*id, ExecStatus::Success, tx.gas_used(), fee(tx)));
+        let reverted = self
+            .rejected
+            .iter()
+            .filter(|(_, e)| e.is_permanent())
+            .map(|(id, e)| {
+                let status = ExecStatus::Reverted {
+                    reason: e.to_string(),
+                };
+                (*id, status, 0, 0)
+            });
+        applied
+            .chain(reverted)
+            .enumerate()
+            .map(|(index, (tx_id, status, gas_used, fee))| Receipt {
+                tx_id,
+                block_id,
+                index: index as u32,
+                status,
+                gas_used,
+                fee,
+                logs: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+/// Applies transactions to an [`AccountState`]: each one pays
+/// `gas_price * gas_used` from its sender and must carry the sender's next
+/// nonce. Fees leave the sender's account; the block builder hands them to
+/// the fee recipient with [`StateExecutor::credit`].
+#[derive(Clone, Debug, Default)]
+pub struct StateExecutor {
+    state: AccountState,
+}
+
+impl StateExecutor {
+    pub fn new(state: AccountState) -> Self {
+        Self { state }
+    }
+
+    pub fn state(&self) -> &AccountState {
+        &self.state
+    }
+
+    pub fn root(&self) -> Hash {
+        self.state.root()
+    }
+
+    /// Execute `txs` in order. A transaction whose nonce is ahead of its
+    /// sender's is retried after the rest, so a sender's transactions need
+    /// not arrive in nonce order (the mempool orders by fee).
+    pub fn apply(&mut self, txs: Vec<(TxId, Transaction)>) -> ExecutionOutcome {
+        let mut outcome = ExecutionOutcome::default();
+        let mut pending = txs;
+        loop {
+            let mut deferred = Vec::new();
+            let applied_before = outcome.applied.len();
+            for (id, tx) in pending {
+                match self.apply_tx(&tx, outcome.fees) {
+                    Ok(fees) => {
+                        outcome.fees = fees;
+                        outcome.applied.push((id, tx));
+                    }
+                    Err(ExecutionError::NonceGap { .. }) => deferred.push((id, tx)),
+                    Err(e) => outcome.rejected.push((id, e)),
+                }
+            }
+            if deferred.is_empty() {
+                break;
+            }
+            if outcome.applied.len() == applied_before {
+                // No progress: the remaining gaps cannot be filled.
+                for (id, tx) in deferred {
+                    let expected = self.state.get(tx.from).nonce;
+                    let got = tx.nonce;
+                    outcome
+                        .rejected
+                        .push((id, ExecutionError::NonceGap { expected, got }));
+                }
+                break;
+            }
+            pending = deferred;
+        }
+        outcome
+    }
+
+    /// Whether `tx` would apply against the current state, without
+    /// applying it.
+    pub fn check(&self, tx: &Transaction) -> Result<(), ExecutionError> {
+        let account = self.state.get(tx.from);
+        if tx.nonce < account.nonce {
+            return Err(ExecutionError::NonceTooLow {
+                expected: account.nonce,
+                got: tx.nonce,
+            });
+        }
+        if tx.nonce > account.nonce {
+            return Err(ExecutionError::NonceGap {
+                expected: account.nonce,
+                got: tx.nonce,
+            });
+        }
+        let fee = tx
+            .gas_price
+            .checked_mul(tx.gas_used())
+            .ok_or(ExecutionError::FeeOverflow)?;
+        if fee > account.balance {
+            return Err(ExecutionError::InsufficientBalance {
+                fee,
+                balance: account.balance,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add `amount` to the balance of `account`, e.g. a block's fees.
+    pub fn credit(&mut self, account: Address, amount: u64) {
+        if amount == 0 {
+            // Keep untouched accounts, and so the root, as they were.
+            return;
+        }
+        let entry = self.state.accounts.entry(account).or_default();
+        entry.balance = entry.balance.saturating_add(amount);
+    }
+
+    /// Apply `tx` and return `fees` plus the fee it paid. A fee that
+    /// would overflow the total is rejected before the state changes.
+    fn apply_tx(&mut self, tx: &Transaction, fees: u64) -> Result<u64, ExecutionError> {
+        self.check(tx)?;
+        let account = self.state.get(tx.from);
+        let fee = fee(tx);
+        let fees = fees.checked_add(fee).ok_or(ExecutionError::FeeOverflow)?;
+        self.state.accounts.insert(
+            tx.from,
+            Account {
+                balance: account.balance - fee,
+                nonce: account.nonce + 1,
+            },
+        );
+        Ok(fees)
+    }
+}
+
+/// `gas_price * gas_used`, for a transaction [`StateExecutor::check`]
+/// has accepted, so it does not overflow.
+fn fee(tx: &Transaction) -> u64 {
+    tx.gas_price * tx.gas_used()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::NamespaceId;
+
+    const ALICE: Address = Address([1; 20]);
+    const BOB: Address = Address([2; 20]);
+
+    fn tx(sender: Address, nonce: u64, gas_price: u64) -> (TxId, Transaction) {
+        let tx = Transaction {
+            chain_id: 0,
+            from: sender,
+            namespace: NamespaceId(1),
+            gas_price,
+            nonce,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        (tx.id(), tx)
+    }
+
+    fn executor() -> StateExecutor {
+        StateExecutor::new(AccountState::with_balances([(ALICE, 5_000), (BOB, 5_000)]))
+    }
+
+    fn ids(txs: &[(TxId, Transaction)]) -> Vec<TxId> {
+        txs.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn sequential_transactions_pay_fees_and_bump_nonces() {
+        let mut exec = executor();
+        let genesis_root = exec.root();
+        // Out of nonce order, as the mempool may hand them over by fee.
+        let txs = vec![tx(ALICE, 1, 1), tx(ALICE, 0, 2), tx(BOB, 0, 1)];
+
+        let outcome = exec.apply(txs.clone());
+
+        assert!(outcome.rejected.is_empty());
+        assert_eq!(
+            ids(&outcome.applied),
+            ids(&[txs[1].clone(), txs[2].clone(), txs[0].clone()])
+        );
+        let fee = types::TX_BASE_GAS;
+        assert_eq!(
+            exec.state().get(ALICE),
+            Account {
+                balance: 5_000 - 3 * fee,
+                nonce: 2
+            }
+        );
+        assert_eq!(
+            exec.state().get(BOB),
+            Account {
+                balance: 5_000 - fee,
+                nonce: 1
+            }
+        );
+        assert_ne!(exec.root(), genesis_root);
+    }
+
+    #[test]
+    fn account_state_encoding_roundtrips() {
+        let mut exec = executor();
+        exec.apply(vec![tx(ALICE, 0, 2)]);
+        let state = exec.state();
+        assert_eq!(AccountState::decode(&state.encode()).as_ref(), Some(state));
+        assert_eq!(AccountState::decode(&[]), Some(AccountState::default()));
+        assert_eq!(AccountState::decode(&[0; 35]), None);
+    }
+
+    #[test]
+    fn fees_are_totalled_and_credited() {
+        let mut exec = executor();
+        let outcome = exec.apply(vec![tx(ALICE, 0, 2), tx(BOB, 0, 3), tx(BOB, 5, 1)]);
+        let fee = types::TX_BASE_GAS;
+        assert_eq!(outcome.fees, 5 * fee);
+        let receipts = outcome.receipts(BlockId(Hash([0; 32])));
+        let fees: Vec<u64> = receipts.iter().map(|r| r.fee).collect();
+        assert_eq!(fees, vec![2 * fee, 3 * fee]);
+
+        let carol = Address([3; 20]);
+        let root = exec.root();
+        exec.credit(carol, 0);
+        assert_eq!(exec.root(), root);
+        exec.credit(carol, outcome.fees);
+        exec.credit(ALICE, outcome.fees);
+        assert_eq!(exec.state().get(carol).balance, 5 * fee);
+        assert_eq!(exec.state().get(ALICE).balance, 5_000 - 2 * fee + 5 * fee);
+    }
+
+    #[test]
+    fn fee_total_overflow_rejects_the_tx() {
+        let mut exec = StateExecutor::new(AccountState::with_balances([
+            (ALICE, u64::MAX),
+            (BOB, u64::MAX),
+        ]));
+        let gas_price = u64::MAX / types::TX_BASE_GAS / 2 + 1;
+        let (alice_id, alice_tx) = tx(ALICE, 0, gas_price);
+        let (bob_id, bob_tx) = tx(BOB, 0, gas_price);
+        let fee = fee(&alice_tx);
+        let outcome = exec.apply(vec![(alice_id, alice_tx), (bob_id, bob_tx)]);
+
+        assert_eq!(ids(&outcome.applied), vec![alice_id]);
+        assert_eq!(
+            outcome.rejected,
+            vec![(bob_id, ExecutionError::FeeOverflow)]
+        );
+        assert_eq!(outcome.fees, fee);
+        assert_eq!(
+            exec.state().get(BOB),
+            Account {
+                balance: u64::MAX,
+                nonce: 0
+            }
+        );
+    }
+
+    #[test]
+    fn overdrawn_sender_is_rejected_without_state_change() {
+        let mut exec = executor();
+        let before = exec.root();
+        // 6 * TX_BASE_GAS exceeds Alice's balance.
+        let overdraw = tx(ALICE, 0, 6);
+
+        let outcome = exec.apply(vec![overdraw.clone()]);
+
+        assert!(outcome.applied.is_empty());
+        assert_eq!(
+            outcome.rejected,
+            vec![(
+                overdraw.0,
+                ExecutionError::InsufficientBalance {
+                    fee: 6_000,
+                    balance: 5_000
+                }
+            )]
+        );
+        assert_eq!(exec.root(), before);
+    }
+
+    #[test]
+    fn nonce_gap_is_rejected_but_other_senders_apply() {
+        let mut exec = executor();
+        let gap = tx(ALICE, 2, 1);
+        let ok = tx(BOB, 0, 1);
+
+        let outcome = exec.apply(vec![gap.clone(), ok.clone()]);
+
+        assert_eq!(ids(&outcome.applied), vec![ok.0]);
+        assert_eq!(
+            outcome.rejected,
+            vec![(
+                gap.0,
+                ExecutionError::NonceGap {
+                    expected: 0,
+                    got: 2
+                }
+            )]
+        );
+        assert_eq!(
+            exec.state().get(ALICE),
+            Account {
+                balance: 5_000,
+                nonce: 0
+            }
+        );
+
+        // Replaying an applied transaction is rejected as stale.
+        let replay = exec.apply(vec![ok.clone()]);
+        assert_eq!(
+            replay.rejected,
+            vec![(
+                ok.0,
+                ExecutionError::NonceTooLow {
+                    expected: 1,
+                    got: 0
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn account_root_depends_on_every_account() {
+        let mut a = executor();
+        let mut b = executor();
+        a.apply(vec![tx(ALICE, 0, 1)]);
+        b.apply(vec![tx(BOB, 0, 1)]);
+
+        // Same total spent, different payer.
+        assert_ne!(a.root(), b.root());
+        assert_eq!(AccountState::default().root(), Hash([0u8; 32]));
+    }
+}
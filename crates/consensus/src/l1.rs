@@ -0,0 +1,609 @@
+//! Posting L1 batch commitments built from the finality event stream.
+//!
+//! An [`L1Batcher`] subscribes to [`FinalityEvent`]s, groups committed blocks
+//! into batches of [`L1BatcherConfig::batch_size`], and hands each
+//! [`L1BatchCommitment`] to an [`L1Sink`], retrying with exponential backoff
+//! when the sink fails. A [`BatchBuilder`] cuts batches by block count and
+//! serialized size instead, for callers that drive posting themselves.
+//! Each batch the batcher builds is reported to its [`L1BatchLog`] along with
+//! whether it was posted.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use types::{Block, Hash, L1BatchCommitment};
+
+use crate::{build_l1_batch_commitment, FinalityEvent};
+
+/// Hash of the L1 transaction that carried a batch commitment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct L1TxHash(pub Hash);
+
+#[derive(Debug, Error)]
+pub enum L1Error {
+    #[error("L1 submission failed: {0}")]
+    Submission(String),
+}
+
+/// Destination for batch commitments, e.g. an L1 settlement contract.
+pub trait L1Sink: Send + Sync + 'static {
+    fn post_batch(
+        &self,
+        commitment: &L1BatchCommitment,
+    ) -> impl Future<Output = Result<L1TxHash, L1Error>> + Send;
+}
+
+/// Batching and retry settings for [`L1Batcher`].
+#[derive(Clone, Debug)]
+pub struct L1BatcherConfig {
+    /// Number of committed blocks per batch.
+    pub batch_size: usize,
+    /// Attempts per batch before giving up until the next flush.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on each further failure.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for L1BatcherConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 10,
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Batches kept by an [`L1BatchLog`]; older ones are forgotten.
+pub const L1_BATCH_HISTORY: usize = 100;
+
+/// How far a batch has got towards L1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum L1BatchStatus {
+    /// Being submitted, or waiting between retries.
+    Pending,
+    Posted(L1TxHash),
+    /// Every attempt failed. The batcher tries again when the next block
+    /// arrives, or on shutdown, reusing the batch number.
+    Failed {
+        error: String,
+    },
+}
+
+/// A batch built by an [`L1Batcher`], with the heights it covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct L1BatchRecord {
+    pub commitment: L1BatchCommitment,
+    pub first_height: u64,
+    pub last_height: u64,
+    pub status: L1BatchStatus,
+}
+
+/// The most recent batches of an [`L1Batcher`], shared with readers such as
+/// the RPC server. Clones share the same log.
+#[derive(Clone, Debug, Default)]
+pub struct L1BatchLog {
+    records: Arc<Mutex<VecDeque<L1BatchRecord>>>,
+}
+
+impl L1BatchLog {
+    /// Up to [`L1_BATCH_HISTORY`] batches, newest first.
+    pub fn recent(&self) -> Vec<L1BatchRecord> {
+        self.records.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, batch_number: u64) -> Option<L1BatchRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|r| r.commitment.batch_number == batch_number)
+            .cloned()
+    }
+
+    /// Add `record`, replacing an earlier attempt at the same batch number.
+    fn record(&self, record: L1BatchRecord) {
+        let mut records = self.records.lock().unwrap();
+        let number = record.commitment.batch_number;
+        match records
+            .iter_mut()
+            .find(|r| r.commitment.batch_number == number)
+        {
+            Some(existing) => *existing = record,
+            None => {
+                records.push_back(record);
+                if records.len() > L1_BATCH_HISTORY {
+                    records.pop_front();
+                }
+            }
+        }
+    }
+
+    fn set_status(&self, batch_number: u64, status: L1BatchStatus) {
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records
+            .iter_mut()
+            .find(|r| r.commitment.batch_number == batch_number)
+        {
+            record.status = status;
+        }
+    }
+}
+
+/// Background task that turns finality events into posted L1 batches.
+pub struct L1Batcher<S: L1Sink> {
+    sink: S,
+    config: L1BatcherConfig,
+    next_batch_number: u64,
+    pending: Vec<Block>,
+    log: L1BatchLog,
+}
+
+impl<S: L1Sink> L1Batcher<S> {
+    pub fn new(sink: S, config: L1BatcherConfig) -> Self {
+        Self {
+            sink,
+            config,
+            next_batch_number: 0,
+            pending: Vec::new(),
+            log: L1BatchLog::default(),
+        }
+    }
+
+    /// A handle on the batches this batcher builds; take it before
+    /// [`L1Batcher::spawn`].
+    pub fn log(&self) -> L1BatchLog {
+        self.log.clone()
+    }
+
+    /// Run the batcher on its own task until the event channel closes.
+    pub fn spawn(self, events: broadcast::Receiver<FinalityEvent>) -> JoinHandle<()> {
+        tokio::spawn(self.run(events))
+    }
+
+    /// Consume events until the channel closes, then post whatever partial
+    /// batch is left so no committed block is silently dropped.
+    pub async fn run(mut self, mut events: broadcast::Receiver<FinalityEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(FinalityEvent::BlockCommitted { block, .. }) => {
+                    self.pending.push(block);
+                    self.flush_full_batches().await;
+                }
+                Ok(FinalityEvent::BlockReverted { block_id, height }) => {
+                    let before = self.pending.len();
+                    self.pending.retain(|b| b.header.id() != block_id);
+                    if self.pending.len() == before {
+                        warn!(height, "reverted block was already posted to L1");
+                    }
+                }
+                Ok(FinalityEvent::ViewTimeout { .. }) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    error!(
+                        skipped,
+                        "L1 batcher lagged; blocks are missing from batches"
+                    );
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        if !self.pending.is_empty() {
+            let len = self.pending.len();
+            self.post(len).await;
+        }
+    }
+
+    async fn flush_full_batches(&mut self) {
+        let batch_size = self.config.batch_size.max(1);
+        while self.pending.len() >= batch_size {
+            if !self.post(batch_size).await {
+                // Keep the blocks and try again once the next block arrives.
+                break;
+            }
+        }
+    }
+
+    /// Post the oldest `len` pending blocks as one batch, retrying with
+    /// backoff. Returns whether the batch was accepted.
+    async fn post(&mut self, len: usize) -> bool {
+        let commitment = build_l1_batch_commitment(self.next_batch_number, &self.pending[..len]);
+        let batch_number = commitment.batch_number;
+        self.log.record(L1BatchRecord {
+            commitment: commitment.clone(),
+            first_height: self.pending[0].header.height,
+            last_height: self.pending[len - 1].header.height,
+            status: L1BatchStatus::Pending,
+        });
+        let mut backoff = self.config.initial_backoff;
+        for attempt in 1..=self.config.max_attempts {
+            match self.sink.post_batch(&commitment).await {
+                Ok(tx_hash) => {
+                    info!(
+                        batch_number = commitment.batch_number,
+                        blocks = len,
+                        ?tx_hash,
+                        "posted L1 batch"
+                    );
+                    self.log
+                        .set_status(batch_number, L1BatchStatus::Posted(tx_hash));
+                    self.pending.drain(..len);
+                    self.next_batch_number += 1;
+                    return true;
+                }
+                Err(e) if attempt < self.config.max_attempts => {
+                    warn!(attempt, error = %e, "L1 batch submission failed; retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                }
+                Err(e) => {
+                    error!(attempt, error = %e, "L1 batch submission failed; giving up for now");
+                    self.log.set_status(
+                        batch_number,
+                        L1BatchStatus::Failed {
+                            error: e.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Groups committed blocks into batches bounded by block count and by the
+/// total bincode-encoded size of their blocks ([`Block::size_bytes`]).
+///
+/// A batch is cut once it holds `max_blocks` blocks or reaches `max_bytes`,
+/// and before a block that would take it past `max_bytes`. A single block
+/// larger than `max_bytes` forms a batch of its own. Batch numbers start at
+/// 0, so the same blocks always yield the same commitments.
+#[derive(Clone, Debug)]
+pub struct BatchBuilder {
+    max_blocks: usize,
+    max_bytes: u64,
+    next_batch_number: u64,
+    current: Vec<Block>,
+    current_bytes: u64,
+    ready: VecDeque<L1BatchCommitment>,
+}
+
+impl BatchBuilder {
+    /// A builder cutting batches at `max_blocks` blocks (at least 1) or
+    /// `max_bytes` serialized bytes.
+    pub fn new(max_blocks: usize, max_bytes: u64) -> Self {
+        Self {
+            max_blocks: max_blocks.max(1),
+            max_bytes,
+            next_batch_number: 0,
+            current: Vec::new(),
+            current_bytes: 0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Add the next committed block, cutting batches as limits are reached.
+    pub fn push_block(&mut self, block: Block) {
+        let size = block.size_bytes() as u64;
+        if !self.current.is_empty() && self.current_bytes + size > self.max_bytes {
+            self.cut();
+        }
+        self.current.push(block);
+        self.current_bytes += size;
+        if self.current.len() >= self.max_blocks || self.current_bytes >= self.max_bytes {
+            self.cut();
+        }
+    }
+
+    /// Batches completed so far, oldest first.
+    pub fn take_ready(&mut self) -> Vec<L1BatchCommitment> {
+        self.ready.drain(..).collect()
+    }
+
+    /// Close the partial batch, if any, e.g. on shutdown. It is returned by
+    /// the next [`take_ready`](Self::take_ready).
+    pub fn flush(&mut self) {
+        if !self.current.is_empty() {
+            self.cut();
+        }
+    }
+
+    /// Blocks waiting for the current batch to fill.
+    pub fn pending_blocks(&self) -> usize {
+        self.current.len()
+    }
+
+    fn cut(&mut self) {
+        let commitment = build_l1_batch_commitment(self.next_batch_number, &self.current);
+        self.ready.push_back(commitment);
+        self.next_batch_number += 1;
+        self.current.clear();
+        self.current_bytes = 0;
+    }
+}
+
+/// In-memory [`L1Sink`] that records every accepted commitment. Failures can
+/// be injected with [`InMemoryL1Sink::fail_next`].
+#[derive(Default)]
+pub struct InMemoryL1Sink {
+    inner: Mutex<InMemoryL1SinkState>,
+}
+
+#[derive(Default)]
+struct InMemoryL1SinkState {
+    posted: Vec<L1BatchCommitment>,
+    failures_left: u32,
+    attempts: u32,
+}
+
+impl InMemoryL1Sink {
+    /// Reject the next `n` submissions.
+    pub fn fail_next(&self, n: u32) {
+        self.inner.lock().unwrap().failures_left = n;
+    }
+
+    /// Commitments accepted so far, in order.
+    pub fn posted(&self) -> Vec<L1BatchCommitment> {
+        self.inner.lock().unwrap().posted.clone()
+    }
+
+    /// Total submissions, including rejected ones.
+    pub fn attempts(&self) -> u32 {
+        self.inner.lock().unwrap().attempts
+    }
+}
+
+impl L1Sink for InMemoryL1Sink {
+    async fn post_batch(&self, commitment: &L1BatchCommitment) -> Result<L1TxHash, L1Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.attempts += 1;
+        if inner.failures_left > 0 {
+            inner.failures_left -= 1;
+            return Err(L1Error::Submission("injected failure".into()));
+        }
+        inner.posted.push(commitment.clone());
+        Ok(L1TxHash(commitment.hash()))
+    }
+}
+
+/// Lets a caller keep a handle on the sink (e.g. to inspect it) while the
+/// batcher owns a clone.
+impl<T: L1Sink> L1Sink for Arc<T> {
+    fn post_batch(
+        &self,
+        commitment: &L1BatchCommitment,
+    ) -> impl Future<Output = Result<L1TxHash, L1Error>> + Send {
+        (**self).post_batch(commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{QuorumCertificate, ViewNumber};
+    use types::{BlockHeader, BlockId};
+
+    fn block(height: u64) -> Block {
+        Block {
+            header: BlockHeader {
+                height,
+                parent: None,
+                tx_root: Hash([0u8; 32]),
+                gas_used: 0,
+                fees: 0,
+                state_root: Hash([0u8; 32]),
+                timestamp_ms: height,
+                proposer: [0u8; 32],
+            },
+            txs: Vec::new(),
+        }
+    }
+
+    fn committed(block: Block) -> FinalityEvent {
+        let qc = QuorumCertificate {
+            view: ViewNumber(block.header.height),
+            block_id: block.header.id(),
+            signatures: Vec::new(),
+        };
+        FinalityEvent::BlockCommitted { block, qc }
+    }
+
+    fn fast_config(batch_size: usize) -> L1BatcherConfig {
+        L1BatcherConfig {
+            batch_size,
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+        }
+    }
+
+    fn ids(blocks: &[Block]) -> Vec<BlockId> {
+        blocks.iter().map(|b| b.header.id()).collect()
+    }
+
+    /// A block whose encoding grows with `tx_count`.
+    fn block_with_txs(height: u64, tx_count: usize) -> Block {
+        Block {
+            txs: vec![types::TxId(Hash([height as u8; 32])); tx_count],
+            ..block(height)
+        }
+    }
+
+    fn encoded_len(block: &Block) -> u64 {
+        bincode::serialized_size(block).unwrap()
+    }
+
+    #[test]
+    fn batch_builder_cuts_on_block_count() {
+        let mut builder = BatchBuilder::new(2, u64::MAX);
+        let blocks: Vec<Block> = (1..=5).map(block).collect();
+        for b in &blocks {
+            builder.push_block(b.clone());
+        }
+
+        let ready = builder.take_ready();
+        assert_eq!(ready.len(), 2);
+        assert_eq!(ready[0].batch_number, 0);
+        assert_eq!(ready[0].block_ids, ids(&blocks[..2]));
+        assert_eq!(ready[1].batch_number, 1);
+        assert_eq!(ready[1].block_ids, ids(&blocks[2..4]));
+        assert!(builder.take_ready().is_empty());
+        assert_eq!(builder.pending_blocks(), 1);
+
+        builder.flush();
+        let last = builder.take_ready();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].batch_number, 2);
+        assert_eq!(last[0].block_ids, ids(&blocks[4..]));
+    }
+
+    #[test]
+    fn batch_builder_cuts_on_serialized_size() {
+        let small = block_with_txs(1, 1);
+        let large = block_with_txs(2, 8);
+        let oversized = block_with_txs(3, 64);
+        let after = block_with_txs(4, 1);
+        // Room for the small and the large block together, not for a third.
+        let max_bytes = encoded_len(&small) + encoded_len(&large) + 1;
+        let mut builder = BatchBuilder::new(100, max_bytes);
+
+        builder.push_block(small.clone());
+        builder.push_block(large.clone());
+        assert!(builder.take_ready().is_empty());
+        // Cut before the oversized block, which then fills a batch alone.
+        builder.push_block(oversized.clone());
+        builder.push_block(after.clone());
+
+        let ready = builder.take_ready();
+        assert_eq!(ready.len(), 2);
+        assert_eq!(ready[0].block_ids, ids(&[small, large]));
+        assert_eq!(ready[1].block_ids, ids(&[oversized]));
+        assert_eq!(builder.pending_blocks(), 1);
+    }
+
+    #[test]
+    fn batch_builder_commitments_are_deterministic() {
+        let batches = || {
+            let mut builder = BatchBuilder::new(3, 1024);
+            for height in 1..=7 {
+                builder.push_block(block_with_txs(height, height as usize));
+            }
+            builder.flush();
+            builder.take_ready()
+        };
+
+        let hashes = |batches: &[L1BatchCommitment]| -> Vec<Hash> {
+            batches.iter().map(L1BatchCommitment::hash).collect()
+        };
+        let (first, second) = (batches(), batches());
+        assert_eq!(first, second);
+        assert_eq!(hashes(&first), hashes(&second));
+        // Different batch numbers commit to different hashes.
+        assert_ne!(hashes(&first)[0], hashes(&first)[1]);
+    }
+
+    #[tokio::test]
+    async fn failed_submission_is_retried_with_backoff() {
+        let sink = Arc::new(InMemoryL1Sink::default());
+        sink.fail_next(2);
+        let (tx, rx) = broadcast::channel(16);
+        let task = L1Batcher::new(Arc::clone(&sink), fast_config(2)).spawn(rx);
+
+        let blocks = vec![block(1), block(2)];
+        for b in &blocks {
+            tx.send(committed(b.clone())).unwrap();
+        }
+        drop(tx);
+        task.await.unwrap();
+
+        assert_eq!(sink.attempts(), 3);
+        let posted = sink.posted();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].batch_number, 0);
+        assert_eq!(posted[0].block_ids, ids(&blocks));
+    }
+
+    #[tokio::test]
+    async fn batch_log_reports_posted_and_failed_batches() {
+        let sink = Arc::new(InMemoryL1Sink::default());
+        let (tx, rx) = broadcast::channel(16);
+        let batcher = L1Batcher::new(Arc::clone(&sink), fast_config(1));
+        let log = batcher.log();
+        let task = batcher.spawn(rx);
+
+        tx.send(committed(block(1))).unwrap();
+        while log
+            .get(0)
+            .is_none_or(|r| r.status == L1BatchStatus::Pending)
+        {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        // Fail the flush and the retry on shutdown.
+        sink.fail_next(6);
+        tx.send(committed(block(2))).unwrap();
+        drop(tx);
+        task.await.unwrap();
+
+        let recent = log.recent();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].commitment.batch_number, 1);
+        assert_eq!((recent[0].first_height, recent[0].last_height), (2, 2));
+        assert!(matches!(recent[0].status, L1BatchStatus::Failed { .. }));
+        assert_eq!(recent[1], log.get(0).unwrap());
+        assert_eq!(recent[1].commitment, sink.posted()[0]);
+        assert!(matches!(recent[1].status, L1BatchStatus::Posted(_)));
+        assert!(log.get(2).is_none());
+    }
+
+    #[tokio::test]
+    async fn reverted_blocks_are_dropped_from_pending_batch() {
+        let sink = Arc::new(InMemoryL1Sink::default());
+        let (tx, rx) = broadcast::channel(16);
+        let task = L1Batcher::new(Arc::clone(&sink), fast_config(3)).spawn(rx);
+
+        let (b1, stale, b2, b3) = (block(1), block(2), block(3), block(4));
+        tx.send(committed(b1.clone())).unwrap();
+        tx.send(committed(stale.clone())).unwrap();
+        tx.send(FinalityEvent::BlockReverted {
+            block_id: stale.header.id(),
+            height: 2,
+        })
+        .unwrap();
+        tx.send(committed(b2.clone())).unwrap();
+        tx.send(committed(b3.clone())).unwrap();
+        drop(tx);
+        task.await.unwrap();
+
+        let posted = sink.posted();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].block_ids, ids(&[b1, b2, b3]));
+    }
+
+    #[tokio::test]
+    async fn partial_batch_is_posted_on_shutdown() {
+        let sink = Arc::new(InMemoryL1Sink::default());
+        let (tx, rx) = broadcast::channel(16);
+        let task = L1Batcher::new(Arc::clone(&sink), fast_config(2)).spawn(rx);
+
+        let blocks: Vec<Block> = (1..=3).map(block).collect();
+        for b in &blocks {
+            tx.send(committed(b.clone())).unwrap();
+        }
+        drop(tx);
+        task.await.unwrap();
+
+        let posted = sink.posted();
+        assert_eq!(posted.len(), 2);
+        assert_eq!(posted[0].block_ids, ids(&blocks[..2]));
+        assert_eq!(posted[1].batch_number, 1);
+        assert_eq!(posted[1].block_ids, ids(&blocks[2..]));
+    }
+}
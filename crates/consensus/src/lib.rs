@@ -1,12 +1,36 @@
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use mempool::{Mempool, SimpleMempool};
-use storage::{BlockStore, InMemoryStorage, StateStore, TxStore};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use mempool::{BoxedMempool, Mempool, SimpleMempool};
+use storage::{
+    BatchStore, BlockStore, BoxedStorage, HeadStore, InMemoryStorage, ReadSnapshot, ReceiptStore,
+    StateStore, StorageBatch, TxStore,
+};
 use thiserror::Error;
-use types::{merkle_root, Block, BlockHeader, BlockId, Hash, L1BatchCommitment, Transaction, TxId};
+use types::{
+    merkle_root, validate_block_header, Address, Block, BlockHeader, BlockId, ExecStatus, Hash,
+    L1BatchCommitment, NamespaceId, NamespaceRegistry, Receipt, Transaction, TxId,
+};
 
 use metrics as sequencer_metrics;
-use tracing::instrument;
+use tracing::{info, instrument, warn};
+
+mod clock;
+mod execution;
+mod l1;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use execution::{Account, AccountState, ExecutionError, ExecutionOutcome, StateExecutor};
+pub use l1::{
+    BatchBuilder, InMemoryL1Sink, L1BatchLog, L1BatchRecord, L1BatchStatus, L1Batcher,
+    L1BatcherConfig, L1Error, L1Sink, L1TxHash, L1_BATCH_HISTORY,
+};
+
+/// Heights whose competing blocks [`SingleNodeConsensus::forks_at`]
+/// remembers; the lowest are forgotten first.
+pub const FORK_HISTORY: usize = 1024;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ViewNumber(pub u64);
@@ -14,10 +38,147 @@ pub struct ViewNumber(pub u64);
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ValidatorId(pub [u8; 32]);
 
+impl ValidatorId {
+    /// Account credited with the fees of the blocks this validator
+    /// proposes, unless [`ConsensusConfig::fee_recipient`] names another:
+    /// the address of its key.
+    pub fn fee_account(self) -> Address {
+        Address::from_pubkey(&self.0)
+    }
+}
+
+/// Validators that take turns proposing blocks, one view each, in list
+/// order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorSet {
+    validators: Vec<ValidatorId>,
+}
+
+impl ValidatorSet {
+    /// # Panics
+    ///
+    /// If `validators` is empty.
+    pub fn new(validators: Vec<ValidatorId>) -> Self {
+        assert!(!validators.is_empty(), "validator set must not be empty");
+        Self { validators }
+    }
+
+    pub fn validators(&self) -> &[ValidatorId] {
+        &self.validators
+    }
+
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Round-robin proposer of `view`.
+    pub fn proposer_for_view(&self, view: ViewNumber) -> ValidatorId {
+        self.validators[(view.0 % self.validators.len() as u64) as usize]
+    }
+
+    /// Proposers of the `count` views starting at `from`.
+    pub fn schedule(&self, from: ViewNumber, count: usize) -> Vec<(ViewNumber, ValidatorId)> {
+        (from.0..)
+            .take(count)
+            .map(|view| (ViewNumber(view), self.proposer_for_view(ViewNumber(view))))
+            .collect()
+    }
+}
+
+/// A single all-zero validator, the proposer of every block.
+impl Default for ValidatorSet {
+    fn default() -> Self {
+        Self::new(vec![ValidatorId([0u8; 32])])
+    }
+}
+
+/// Ed25519 public key of a validator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PubKey(pub [u8; 32]);
+
+/// A single validator's signed vote for a block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vote {
+    pub view: ViewNumber,
+    pub block_id: BlockId,
+    pub validator: ValidatorId,
+    pub signature: Vec<u8>,
+}
+
+impl Vote {
+    /// Sign `block_id` on behalf of `validator`.
+    pub fn sign(
+        key: &SigningKey,
+        validator: ValidatorId,
+        view: ViewNumber,
+        block_id: BlockId,
+    ) -> Self {
+        Self {
+            view,
+            block_id,
+            validator,
+            signature: key.sign(&block_id.0 .0).to_bytes().to_vec(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct QuorumCertificate {
     pub view: ViewNumber,
     pub block_id: BlockId,
+    /// Validator signatures over the `block_id` bytes.
+    pub signatures: Vec<(ValidatorId, Vec<u8>)>,
+}
+
+impl QuorumCertificate {
+    /// Assemble a QC from individual votes. Votes for a different view or
+    /// block are ignored, as are repeat votes from the same validator.
+    pub fn from_votes(
+        view: ViewNumber,
+        block_id: BlockId,
+        votes: impl IntoIterator<Item = Vote>,
+    ) -> Self {
+        let mut seen = HashSet::new();
+        let signatures = votes
+            .into_iter()
+            .filter(|v| v.view == view && v.block_id == block_id)
+            .filter(|v| seen.insert(v.validator))
+            .map(|v| (v.validator, v.signature))
+            .collect();
+
+        Self {
+            view,
+            block_id,
+            signatures,
+        }
+    }
+
+    /// Check that every signature is a valid signature over `block_id` by a
+    /// known validator, and that at least `threshold` distinct validators
+    /// signed.
+    pub fn verify(&self, validators: &[(ValidatorId, PubKey)], threshold: usize) -> bool {
+        let mut signers = HashSet::new();
+        for (validator, signature) in &self.signatures {
+            let Some((_, pubkey)) = validators.iter().find(|(id, _)| id == validator) else {
+                return false;
+            };
+            let Ok(key) = VerifyingKey::from_bytes(&pubkey.0) else {
+                return false;
+            };
+            let Ok(signature) = Signature::from_slice(signature) else {
+                return false;
+            };
+            if key.verify(&self.block_id.0 .0, &signature).is_err() {
+                return false;
+            }
+            signers.insert(*validator);
+        }
+        signers.len() >= threshold
+    }
 }
 
 #[derive(Debug, Error)]
@@ -26,6 +187,10 @@ pub enum ConsensusError {
     Mempool(String),
     #[error("storage error: {0}")]
     Storage(String),
+    #[error("invalid block: {0}")]
+    InvalidBlock(String),
+    #[error("execution error: {0}")]
+    Execution(String),
 }
 
 impl From<storage::StorageError> for ConsensusError {
@@ -35,15 +200,104 @@ impl From<storage::StorageError> for ConsensusError {
 }
 
 /// Events emitted by the consensus engine.
+// Events are mostly `BlockCommitted`, so boxing the block would only add an
+// allocation to the common case.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FinalityEvent {
-    BlockCommitted { block: Block, qc: QuorumCertificate },
+    BlockCommitted {
+        block: Block,
+        qc: QuorumCertificate,
+    },
+    /// A previously committed block was dropped from the canonical chain by
+    /// a reorg.
+    BlockReverted {
+        block_id: BlockId,
+        height: u64,
+    },
+    /// Steps have produced no block for [`ConsensusConfig::view_timeout_views`]
+    /// consecutive views or [`ConsensusConfig::view_timeout_ms`]; `view` is
+    /// the view that timed out. A stall that goes on is reported again
+    /// each time the timeout elapses anew.
+    ViewTimeout {
+        view: ViewNumber,
+    },
+}
+
+/// Result of importing a block with [`SingleNodeConsensus::import_block`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+    /// The block built directly on the previous head and is the new head.
+    Extended { head: BlockId },
+    /// The block lost fork choice; it is stored but the head is unchanged.
+    NoOp,
+    /// The block was already stored; nothing was written or emitted.
+    AlreadyKnown,
+    /// The block won fork choice on a different branch than the old head.
+    Reorg {
+        old_head: BlockId,
+        new_head: BlockId,
+    },
 }
 
 /// Basic consensus engine interface for a single-node, step-driven engine.
 pub trait ConsensusEngine {
     fn submit_tx(&mut self, tx: Transaction) -> Result<TxId, ConsensusError>;
+    /// Dry run of `submit_tx`: returns why `tx` would be rejected, if it
+    /// would be, without inserting it anywhere.
+    fn simulate_tx(&self, tx: &Transaction) -> Result<(), ConsensusError>;
+    /// Whether `id` is waiting in the mempool.
+    fn is_pending(&self, id: TxId) -> bool;
+    /// Number of transactions waiting in the mempool.
+    fn pending_count(&self) -> usize;
+    /// Height and id of the head of the canonical chain, or `None` before
+    /// the first block is committed or imported.
+    fn head(&self) -> Option<(u64, BlockId)>;
     fn step(&mut self) -> Result<Option<FinalityEvent>, ConsensusError>;
+    /// Look up a committed transaction body. Returns `None` if unknown.
+    fn get_tx(&self, id: TxId) -> Result<Option<Transaction>, ConsensusError>;
+    /// Look up a stored block by id. Returns `None` if unknown.
+    fn get_block(&self, id: BlockId) -> Result<Option<Block>, ConsensusError>;
+    /// Look up a committed block by height. Returns `None` if unknown.
+    fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, ConsensusError>;
+    /// The state root committed at `height`. Returns `None` if unknown.
+    fn state_root_at_height(&self, height: u64) -> Result<Option<Hash>, ConsensusError>;
+    /// The highest committed block, or `None` before the first one.
+    fn latest_block(&self) -> Result<Option<Block>, ConsensusError>;
+    /// Committed blocks with heights in `from..=to`, in ascending order.
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, ConsensusError>;
+    /// Look up the execution receipt of a transaction. Returns `None` if it
+    /// has not been settled by a block produced here.
+    fn get_receipt(&self, id: TxId) -> Result<Option<Receipt>, ConsensusError>;
+
+    /// Drive [`ConsensusEngine::step`] until it has nothing left to commit,
+    /// returning every event in order. Stops at the first step that
+    /// produces no block, and also after an empty block or a view timeout,
+    /// so an engine that emits either for liveness cannot loop forever.
+    fn step_until_empty(&mut self) -> Result<Vec<FinalityEvent>, ConsensusError> {
+        let mut events = Vec::new();
+        while let Some(event) = self.step()? {
+            let empty = match &event {
+                FinalityEvent::BlockCommitted { block, .. } => block.txs.is_empty(),
+                FinalityEvent::BlockReverted { .. } => false,
+                FinalityEvent::ViewTimeout { .. } => true,
+            };
+            events.push(event);
+            if empty {
+                break;
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Map a storage lookup result into `Option`, treating `NotFound` as `None`.
+fn found<T>(res: Result<T, storage::StorageError>) -> Result<Option<T>, ConsensusError> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(storage::StorageError::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
 }
 
 /// Build an L1 batch commitment for a set of committed L2 blocks.
@@ -59,67 +313,569 @@ pub fn build_l1_batch_commitment(batch_number: u64, blocks: &[Block]) -> L1Batch
     }
 }
 
+/// Block production settings for [`SingleNodeConsensus`].
+#[derive(Clone, Debug)]
+pub struct ConsensusConfig {
+    /// Target delay between blocks, in milliseconds. The node's main loop
+    /// calls `step` at this cadence.
+    pub block_interval_ms: u64,
+    /// Emit a header-only block when the mempool is empty and at least
+    /// `block_interval_ms` has passed since the last block, so the chain
+    /// keeps advancing for liveness. When off, empty steps produce nothing.
+    pub produce_empty_blocks: bool,
+    /// Executable transactions a block waits for before it is sealed,
+    /// unless it is already full or `max_wait_ms` has passed. 1 seals as
+    /// soon as anything is pending.
+    pub min_txs_per_block: usize,
+    /// Longest a pending transaction waits for `min_txs_per_block` to be
+    /// reached, in milliseconds of the engine's [`Clock`], counted from
+    /// the first step that could have included it.
+    pub max_wait_ms: u64,
+    /// Maximum total [`Transaction::gas_used`] packed into one block.
+    pub gas_limit: u64,
+    /// Initial account balances. Transactions from accounts that cannot pay
+    /// their fee are not included in blocks.
+    pub genesis_balances: Vec<(Address, u64)>,
+    /// Proposer rotation stamped into `BlockHeader::proposer`.
+    pub validators: ValidatorSet,
+    /// Account credited with every block's fees. `None` credits each
+    /// block's proposer, at its [`ValidatorId::fee_account`].
+    pub fee_recipient: Option<Address>,
+    /// Consecutive views without a block after which a step reports a
+    /// [`FinalityEvent::ViewTimeout`]. Off when `None`.
+    pub view_timeout_views: Option<u64>,
+    /// Like `view_timeout_views`, but in milliseconds of the engine's
+    /// [`Clock`] since the first view without a block. Whichever is
+    /// reached first fires.
+    pub view_timeout_ms: Option<u64>,
+    /// Names used for namespace labels in metrics.
+    pub namespaces: NamespaceRegistry,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            block_interval_ms: 500,
+            produce_empty_blocks: false,
+            min_txs_per_block: 1,
+            max_wait_ms: 2_000,
+            gas_limit: 30_000_000,
+            genesis_balances: Vec::new(),
+            validators: ValidatorSet::default(),
+            fee_recipient: None,
+            view_timeout_views: None,
+            view_timeout_ms: None,
+            namespaces: NamespaceRegistry::default(),
+        }
+    }
+}
+
 /// A single-node consensus engine that periodically pulls transactions from
 /// the mempool, builds blocks, and commits them to storage. QCs are
-/// synthetic: the single validator implicitly forms a quorum.
+/// synthetic: the single validator implicitly forms a quorum. Each block
+/// names the proposer [`ConsensusConfig::validators`] schedules for its
+/// view, although this node produces every block itself.
 pub struct SingleNodeConsensus<M, S>
 where
     M: Mempool,
-    S: BlockStore + StateStore + TxStore,
+    S: BlockStore + StateStore + TxStore + ReceiptStore + BatchStore + HeadStore + ReadSnapshot,
 {
+    config: ConsensusConfig,
     view: ViewNumber,
-    validator: ValidatorId,
     mempool: M,
     storage: S,
+    executor: StateExecutor,
     last_block_id: Option<BlockId>,
     last_height: u64,
+    /// Timestamp of the head block; the next block's must be later.
+    last_timestamp_ms: u64,
+    last_block_at: Option<Instant>,
+    /// When a step first found executable transactions it did not seal,
+    /// waiting for `min_txs_per_block`.
+    first_pending_ms: Option<u64>,
+    /// Distinct blocks imported for the same height, in arrival order.
+    forks: BTreeMap<u64, Vec<BlockId>>,
+    /// Views in a row whose step produced no block, and when the first of
+    /// them ran; cleared by a block or a view timeout.
+    empty_views: u64,
+    empty_since_ms: Option<u64>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Default for SingleNodeConsensus<SimpleMempool, InMemoryStorage> {
     fn default() -> Self {
         Self::new(SimpleMempool::default(), InMemoryStorage::default())
+            .expect("empty in-memory storage has no head to read")
     }
 }
 
+/// An engine over a mempool and storage backend picked at runtime, e.g.
+/// from the node config, instead of at compile time.
+pub type BoxedConsensus = SingleNodeConsensus<BoxedMempool, BoxedStorage>;
+
 impl<M, S> SingleNodeConsensus<M, S>
 where
     M: Mempool,
-    S: BlockStore + StateStore + TxStore,
+    S: BlockStore + StateStore + TxStore + ReceiptStore + BatchStore + HeadStore + ReadSnapshot,
 {
-    pub fn new(mempool: M, storage: S) -> Self {
-        Self {
+    /// Create an engine with the default [`ConsensusConfig`], resuming from
+    /// the head recorded in `storage` if there is one.
+    pub fn new(mempool: M, storage: S) -> Result<Self, ConsensusError> {
+        Self::with_config(mempool, storage, ConsensusConfig::default())
+    }
+
+    /// Create an engine with explicit block production settings. Only a
+    /// store without a head starts a new chain; failing to read the head
+    /// is an error rather than a reason to start over. Account state
+    /// resumes from the head's, see [`SingleNodeConsensus::accounts`].
+    pub fn with_config(
+        mempool: M,
+        storage: S,
+        config: ConsensusConfig,
+    ) -> Result<Self, ConsensusError> {
+        let head = match found(storage.head())? {
+            Some(id) => Some(storage.get_block(id)?.header),
+            None => None,
+        };
+
+        let mut engine = Self {
+            config,
             view: ViewNumber(0),
-            validator: ValidatorId([0u8; 32]),
             mempool,
             storage,
-            last_block_id: None,
-            last_height: 0,
+            executor: StateExecutor::default(),
+            last_block_id: head.as_ref().map(BlockHeader::id),
+            last_height: head.as_ref().map_or(0, |h| h.height),
+            last_timestamp_ms: head.as_ref().map_or(0, |h| h.timestamp_ms),
+            last_block_at: None,
+            first_pending_ms: None,
+            forks: BTreeMap::new(),
+            empty_views: 0,
+            empty_since_ms: None,
+            clock: Arc::new(SystemClock),
+        };
+        engine.executor = StateExecutor::new(engine.state_after(engine.last_block_id)?);
+        Ok(engine)
+    }
+
+    /// Stamp blocks produced by [`ConsensusEngine::step`] with `clock`
+    /// instead of the system clock.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn config(&self) -> &ConsensusConfig {
+        &self.config
+    }
+
+    /// The view of the last step; the next step runs view `view + 1`.
+    pub fn view(&self) -> ViewNumber {
+        self.view
+    }
+
+    /// Proposers of the next `count` views, starting with the next step.
+    pub fn proposer_schedule(&self, count: usize) -> Vec<(ViewNumber, ValidatorId)> {
+        self.config
+            .validators
+            .schedule(ViewNumber(self.view.0 + 1), count)
+    }
+
+    /// Account balances and nonces after the last committed block.
+    pub fn accounts(&self) -> &AccountState {
+        self.executor.state()
+    }
+
+    /// Account state after `block`, or the genesis state for `None`. Taken
+    /// from storage where it was recorded; otherwise, e.g. for blocks
+    /// committed before account states were stored, rebuilt by executing
+    /// the stored blocks since the nearest ancestor that has one.
+    fn state_after(&self, block: Option<BlockId>) -> Result<AccountState, ConsensusError> {
+        let mut cursor = block;
+        let mut unexecuted = Vec::new();
+        let mut state = loop {
+            let Some(id) = cursor else {
+                break AccountState::with_balances(self.config.genesis_balances.iter().copied());
+            };
+            if let Some(bytes) = found(self.storage.accounts_at(id))? {
+                break AccountState::decode(&bytes).ok_or_else(|| {
+                    ConsensusError::Storage(format!("corrupt account state for block {id:?}"))
+                })?;
+            }
+            let block = self.storage.get_block(id)?;
+            cursor = block.header.parent;
+            unexecuted.push(block);
+        };
+        for block in unexecuted.into_iter().rev() {
+            let txs = block
+                .txs
+                .iter()
+                .map(|id| self.storage.get_tx(*id))
+                .collect::<Result<Vec<_>, _>>()?;
+            state = self.execute(state, &block, txs)?.0.state().clone();
+        }
+        Ok(state)
+    }
+
+    /// Execute `block` on top of `state`, its parent's account state. Every
+    /// transaction must apply, in block order, and the header's fees and
+    /// state root must match the result.
+    fn execute(
+        &self,
+        state: AccountState,
+        block: &Block,
+        txs: Vec<Transaction>,
+    ) -> Result<(StateExecutor, ExecutionOutcome), ConsensusError> {
+        let mut executor = StateExecutor::new(state);
+        let outcome = executor.apply(block.txs.iter().copied().zip(txs).collect());
+        if let Some((tx_id, e)) = outcome.rejected.first() {
+            return Err(ConsensusError::InvalidBlock(format!(
+                "transaction {tx_id:?} does not execute: {e}"
+            )));
+        }
+        if !outcome
+            .applied
+            .iter()
+            .map(|(tx_id, _)| *tx_id)
+            .eq(block.txs.iter().copied())
+        {
+            return Err(ConsensusError::InvalidBlock(
+                "transactions do not execute in block order".into(),
+            ));
+        }
+        if outcome.fees != block.header.fees {
+            return Err(ConsensusError::InvalidBlock(format!(
+                "fees {} do not match the {} paid",
+                block.header.fees, outcome.fees
+            )));
+        }
+        executor.credit(
+            self.fee_recipient(ValidatorId(block.header.proposer)),
+            outcome.fees,
+        );
+        if executor.root() != block.header.state_root {
+            return Err(ConsensusError::InvalidBlock(format!(
+                "state_root {:?} does not match execution",
+                block.header.state_root
+            )));
+        }
+        Ok((executor, outcome))
+    }
+
+    /// Account credited with the fees of a block by `proposer`.
+    fn fee_recipient(&self, proposer: ValidatorId) -> Address {
+        self.config
+            .fee_recipient
+            .unwrap_or_else(|| proposer.fee_account())
+    }
+
+    /// Import a block received from a peer and run fork choice. `txs` are
+    /// the bodies of `block.txs`, in order; they are stored with the block
+    /// whether or not it becomes canonical, so a later reorg onto its
+    /// branch finds them.
+    ///
+    /// The block is executed on its parent's account state and rejected
+    /// unless every transaction applies and its fees and state root match.
+    /// The resulting state is stored with it, and becomes the engine's if
+    /// the block becomes the head.
+    ///
+    /// The heaviest chain wins: a greater height is preferred, and at equal
+    /// height the block with the lexicographically smaller id wins. Blocks
+    /// that lose fork choice are still stored so that a later descendant can
+    /// trigger a reorg onto their branch.
+    ///
+    /// Alongside the outcome, returns the finality events to publish: on a
+    /// reorg, a `BlockReverted` for each dropped block (highest first),
+    /// followed by a `BlockCommitted` for each block of the new branch
+    /// (lowest first).
+    pub fn import_block(
+        &mut self,
+        block: Block,
+        txs: Vec<Transaction>,
+    ) -> Result<(ImportOutcome, Vec<FinalityEvent>), ConsensusError> {
+        let id = block.header.id();
+        let height = block.header.height;
+        let timestamp_ms = block.header.timestamp_ms;
+
+        if found(self.storage.get_block(id))?.is_some() {
+            return Ok((ImportOutcome::AlreadyKnown, Vec::new()));
+        }
+        if merkle_root(&block.txs) != block.header.tx_root {
+            return Err(ConsensusError::InvalidBlock(format!(
+                "tx_root {:?} does not match its transactions",
+                block.header.tx_root
+            )));
+        }
+        if !txs
+            .iter()
+            .map(Transaction::id)
+            .eq(block.txs.iter().copied())
+        {
+            return Err(ConsensusError::InvalidBlock(
+                "transaction bodies do not match the block".into(),
+            ));
+        }
+
+        let parent = match block.header.parent {
+            Some(parent_id) => {
+                let Some(parent) = found(self.storage.get_block(parent_id))? else {
+                    return Err(ConsensusError::InvalidBlock(format!(
+                        "unknown parent {:?}",
+                        parent_id
+                    )));
+                };
+                Some(parent.header)
+            }
+            None => None,
+        };
+        validate_block_header(&block.header, parent.as_ref())
+            .map_err(|e| ConsensusError::InvalidBlock(e.to_string()))?;
+        // Competing blocks are both stored under their ids; only the height
+        // index follows fork choice.
+        let conflicting = found(self.storage.get_block_by_height(height))?
+            .map(|existing| existing.header.id())
+            .filter(|existing| *existing != id);
+
+        let (executor, executed) =
+            self.execute(self.state_after(block.header.parent)?, &block, txs)?;
+
+        let mut batch = StorageBatch::new();
+        for (_, tx) in &executed.applied {
+            batch.put_tx(tx.clone());
+        }
+        batch.put_accounts(id, executor.state().encode());
+
+        if !self.is_better_head(height, id) {
+            batch.put_side_block(block);
+            self.storage.commit_batch(batch)?;
+            if let Some(existing) = conflicting {
+                self.record_fork(height, existing, id);
+            }
+            return Ok((ImportOutcome::NoOp, Vec::new()));
+        }
+
+        let mut events = Vec::new();
+        let outcome = match self.last_block_id {
+            Some(old_head) if block.header.parent != Some(old_head) => {
+                // Walk the new branch back to the fork point and repoint the
+                // height index and state roots at it. The new head is at
+                // least as high as the old one, so every height on the old
+                // branch is overwritten.
+                let mut cursor = block.header.parent;
+                let mut fork_height = 0;
+                let mut new_branch = vec![block.clone()];
+                batch.put_side_block(block);
+                batch.set_canonical(height, id);
+                batch.put_state_root(height, new_branch[0].header.state_root);
+                for receipt in executed.receipts(id) {
+                    batch.put_receipt(receipt);
+                }
+                while let Some(ancestor_id) = cursor {
+                    let ancestor = self.storage.get_block(ancestor_id)?;
+                    let ancestor_height = ancestor.header.height;
+                    let canonical = found(self.storage.get_block_by_height(ancestor_height))?;
+                    if canonical.is_some_and(|b| b.header.id() == ancestor_id) {
+                        fork_height = ancestor_height;
+                        break;
+                    }
+                    batch.set_canonical(ancestor_height, ancestor_id);
+                    batch.put_state_root(ancestor_height, ancestor.header.state_root);
+                    // Side blocks executed when imported, so their stored
+                    // bodies make up the receipts.
+                    let applied = ancestor
+                        .txs
+                        .iter()
+                        .map(|tx_id| Ok((*tx_id, self.storage.get_tx(*tx_id)?)))
+                        .collect::<Result<_, ConsensusError>>()?;
+                    let ancestor_outcome = ExecutionOutcome {
+                        applied,
+                        ..ExecutionOutcome::default()
+                    };
+                    for receipt in ancestor_outcome.receipts(ancestor_id) {
+                        batch.put_receipt(receipt);
+                    }
+                    cursor = ancestor.header.parent;
+                    new_branch.push(ancestor);
+                }
+
+                for reverted_height in (fork_height + 1..=self.last_height).rev() {
+                    if let Some(reverted) =
+                        found(self.storage.get_block_by_height(reverted_height))?
+                    {
+                        events.push(FinalityEvent::BlockReverted {
+                            block_id: reverted.header.id(),
+                            height: reverted_height,
+                        });
+                    }
+                }
+                events.extend(new_branch.into_iter().rev().map(|b| self.committed(b)));
+
+                ImportOutcome::Reorg {
+                    old_head,
+                    new_head: id,
+                }
+            }
+            _ => {
+                batch.put_state_root(height, block.header.state_root);
+                for receipt in executed.receipts(id) {
+                    batch.put_receipt(receipt);
+                }
+                batch.put_block(block.clone());
+                events.push(self.committed(block));
+                ImportOutcome::Extended { head: id }
+            }
+        };
+
+        batch.set_head(id);
+        self.storage.commit_batch(batch)?;
+        self.executor = executor;
+        for event in &events {
+            if let FinalityEvent::BlockCommitted { block, .. } = event {
+                self.mempool.remove_committed(&block.txs);
+            }
+        }
+        self.last_block_id = Some(id);
+        self.last_height = height;
+        self.last_timestamp_ms = timestamp_ms;
+        if let Some(existing) = conflicting {
+            self.record_fork(height, existing, id);
+        }
+
+        Ok((outcome, events))
+    }
+
+    /// Every distinct block imported at `height` since the engine started,
+    /// in arrival order, if more than one was. Empty for heights without
+    /// a fork or forgotten under [`FORK_HISTORY`].
+    pub fn forks_at(&self, height: u64) -> &[BlockId] {
+        self.forks.get(&height).map_or(&[], Vec::as_slice)
+    }
+
+    fn record_fork(&mut self, height: u64, existing: BlockId, incoming: BlockId) {
+        warn!(
+            height,
+            ?existing,
+            ?incoming,
+            "competing blocks at the same height"
+        );
+        let ids = self.forks.entry(height).or_default();
+        for id in [existing, incoming] {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        while self.forks.len() > FORK_HISTORY {
+            self.forks.pop_first();
         }
     }
 
-    fn build_block(&mut self) -> Result<Option<Block>, ConsensusError> {
-        // For now, pull a small fixed batch.
-        let batch = self.mempool.get_batch(100);
-        if batch.is_empty() {
+    /// A `BlockCommitted` event for `block` with a synthetic QC for the
+    /// current view.
+    fn committed(&self, block: Block) -> FinalityEvent {
+        let qc = QuorumCertificate {
+            view: self.view,
+            block_id: block.header.id(),
+            signatures: Vec::new(),
+        };
+        FinalityEvent::BlockCommitted { block, qc }
+    }
+
+    /// Fork choice: whether a block at `height` with `id` should replace the
+    /// current head.
+    fn is_better_head(&self, height: u64, id: BlockId) -> bool {
+        match self.last_block_id {
+            None => true,
+            Some(head) => {
+                height > self.last_height || (height == self.last_height && id.0 .0 < head.0 .0)
+            }
+        }
+    }
+
+    /// The underlying storage backend, e.g. to flush it on shutdown.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Mutable access to the underlying storage backend, e.g. to prune old
+    /// blocks.
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Mutable access to the underlying mempool, e.g. for periodic
+    /// maintenance such as expiring stale transactions.
+    pub fn mempool_mut(&mut self) -> &mut M {
+        &mut self.mempool
+    }
+
+    /// Pack and execute the next block. Expired transactions are evicted
+    /// straight away; the caller adopts the executor state and evicts the
+    /// settled transactions once the block is committed.
+    fn build_block(&mut self, now_ms: u64) -> Result<Option<BuiltBlock>, ConsensusError> {
+        // Storage reads go through a frozen view, so writers sharing the
+        // backend, e.g. through a sled clone, cannot tear them.
+        let view = self.storage.snapshot()?;
+
+        // Candidates arrive highest gas price first; pack them greedily
+        // until the next one would exceed the block gas limit.
+        let height = self.last_height + 1;
+        let gas_limit = self.config.gas_limit;
+        let mut gas_used = 0u64;
+        let mut batch = Vec::new();
+        let mut expired = Vec::new();
+        let mut full = false;
+        for (id, tx) in self.mempool.get_batch(self.mempool.len()) {
+            if tx.is_expired_at(height) {
+                // Heights only grow, so it can never be included.
+                expired.push(id);
+                continue;
+            }
+            let gas = tx.gas_used();
+            if gas > gas_limit {
+                // Can never fit in any block; skip rather than stall.
+                continue;
+            }
+            if gas_used + gas > gas_limit {
+                full = true;
+                break;
+            }
+            gas_used += gas;
+            batch.push((id, tx));
+        }
+        if !expired.is_empty() {
+            self.mempool.remove_committed(&expired);
+        }
+
+        // Only transactions that execute make it into the block.
+        let mut executor = self.executor.clone();
+        let outcome = executor.apply(batch);
+        if outcome.applied.is_empty() {
+            self.first_pending_ms = None;
+            if !self.empty_block_due() {
+                return Ok(None);
+            }
+        } else if !full && !self.fill_reached(outcome.applied.len(), now_ms) {
             return Ok(None);
         }
 
-        let tx_ids: Vec<TxId> = batch.iter().map(|(id, _)| *id).collect();
+        let (tx_ids, txs): (Vec<TxId>, Vec<Transaction>) = outcome.applied.iter().cloned().unzip();
         let tx_root = merkle_root(&tx_ids);
+        let gas_used = txs.iter().map(Transaction::gas_used).sum();
 
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
+        // Fees are credited once the block's transactions have run, so
+        // the recipient cannot spend them within the same block.
+        let proposer = self.config.validators.proposer_for_view(self.view);
+        executor.credit(self.fee_recipient(proposer), outcome.fees);
 
         let header = BlockHeader {
-            height: self.last_height + 1,
+            height,
             parent: self.last_block_id,
             tx_root,
-            // Placeholder: real state root will come from execution.
-            state_root: Hash([0u8; 32]),
-            timestamp_ms: now_ms,
-            proposer: self.validator.0,
+            gas_used,
+            fees: outcome.fees,
+            state_root: executor.root(),
+            timestamp_ms: now_ms.max(self.last_timestamp_ms + 1),
+            proposer: proposer.0,
         };
 
         let block = Block {
@@ -127,80 +883,386 @@ where
             txs: tx_ids,
         };
 
-        Ok(Some(block))
-    }
-}
+        // A used nonce can never become valid again, so such transactions
+        // are dropped with a reverted receipt. A resubmitted copy of an
+        // already committed transaction keeps its original receipt.
+        let dropped: Vec<TxId> = outcome
+            .rejected
+            .iter()
+            .filter(|(_, e)| e.is_permanent())
+            .map(|(id, _)| *id)
+            .collect();
+        let mut receipts = Vec::new();
+        for receipt in outcome.receipts(block.header.id()) {
+            if receipt.status == ExecStatus::Success || found(view.get_tx(receipt.tx_id))?.is_none()
+            {
+                receipts.push(receipt);
+            }
+        }
 
-impl<M, S> ConsensusEngine for SingleNodeConsensus<M, S>
-where
-    M: Mempool,
-    S: BlockStore + StateStore + TxStore,
-{
-    fn submit_tx(&mut self, tx: Transaction) -> Result<TxId, ConsensusError> {
-        self
-            .mempool
-            .insert(tx)
-            .map_err(|e| ConsensusError::Mempool(e.to_string()))
+        Ok(Some(BuiltBlock {
+            block,
+            txs,
+            receipts,
+            dropped,
+            executor,
+        }))
     }
 
-    #[instrument(skip(self))]
-    fn step(&mut self) -> Result<Option<FinalityEvent>, ConsensusError> {
+    /// Like [`ConsensusEngine::step`], but stamps the block with `now_ms`
+    /// instead of the engine's [`Clock`]. The timestamp is still clamped to one
+    /// millisecond past the parent's, so blocks stay ordered in time even
+    /// if the clock goes backwards.
+    pub fn step_at(&mut self, now_ms: u64) -> Result<Option<FinalityEvent>, ConsensusError> {
         let start = Instant::now();
         self.view.0 += 1;
 
-        let Some(block) = self.build_block()? else {
+        let Some(BuiltBlock {
+            block,
+            txs,
+            receipts,
+            dropped,
+            executor,
+        }) = self.build_block(now_ms)?
+        else {
             let elapsed = start.elapsed().as_secs_f64() * 1000.0;
             sequencer_metrics::record_consensus_step_duration_ms(elapsed);
-            return Ok(None);
+            return Ok(self.empty_view(now_ms));
         };
 
         let block_id = block.header.id();
         let height = block.header.height;
 
-        // Persist block and txs.
-        self.storage.put_block(block.clone())?;
-        for tx_id in &block.txs {
-            // We don't store full txs here because they should already
-            // be present from earlier, but for now keep it simple by
-            // ignoring this step. Future work can link tx bodies.
-            let _ = tx_id;
+        // Persist block, tx bodies, receipts, state root and account state
+        // in a single atomic batch.
+        let mut batch = StorageBatch::new();
+        batch.put_block(block.clone());
+        let mut committed_by_namespace: BTreeMap<u64, usize> = BTreeMap::new();
+        for tx in txs {
+            *committed_by_namespace.entry(tx.namespace.0).or_default() += 1;
+            batch.put_tx(tx);
         }
+        for receipt in receipts {
+            batch.put_receipt(receipt);
+        }
+        batch.put_state_root(height, block.header.state_root);
+        batch.put_accounts(block_id, executor.state().encode());
+        batch.set_head(block_id);
+        self.storage.commit_batch(batch)?;
 
-        let qc = QuorumCertificate {
-            view: self.view,
-            block_id,
-        };
-
+        self.executor = executor;
+        self.mempool.remove_committed(&block.txs);
+        self.mempool.remove_committed(&dropped);
         self.last_block_id = Some(block_id);
         self.last_height = height;
+        self.last_timestamp_ms = block.header.timestamp_ms;
+        self.last_block_at = Some(Instant::now());
+        self.first_pending_ms = None;
+        self.empty_views = 0;
+        self.empty_since_ms = None;
         sequencer_metrics::record_block_committed(block.txs.len());
+        for (namespace, count) in committed_by_namespace {
+            let label = NamespaceId(namespace).name(&self.config.namespaces);
+            sequencer_metrics::record_namespace_txs_committed(&label, count);
+        }
         let elapsed = start.elapsed().as_secs_f64() * 1000.0;
         sequencer_metrics::record_consensus_step_duration_ms(elapsed);
+        info!(height, tx_count = block.txs.len(), "committed block");
+
+        Ok(Some(self.committed(block)))
+    }
+
+    /// Count a view whose step produced no block, timing it out once the
+    /// run of such views reaches the configured limit.
+    fn empty_view(&mut self, now_ms: u64) -> Option<FinalityEvent> {
+        self.empty_views += 1;
+        let since = *self.empty_since_ms.get_or_insert(now_ms);
+        let timed_out = self
+            .config
+            .view_timeout_views
+            .is_some_and(|views| self.empty_views >= views)
+            || self
+                .config
+                .view_timeout_ms
+                .is_some_and(|ms| now_ms.saturating_sub(since) >= ms);
+        if !timed_out {
+            return None;
+        }
+        // Start a new run, so a stall that goes on fires once per timeout.
+        self.empty_views = 0;
+        self.empty_since_ms = None;
+        warn!(view = self.view.0, "view timed out without a block");
+        sequencer_metrics::record_view_timeout();
+        Some(FinalityEvent::ViewTimeout { view: self.view })
+    }
+
+    /// Whether `ready` executable transactions are worth sealing at
+    /// `now_ms`: enough of them, or the wait for more has run out.
+    fn fill_reached(&mut self, ready: usize, now_ms: u64) -> bool {
+        if ready >= self.config.min_txs_per_block {
+            return true;
+        }
+        let since = *self.first_pending_ms.get_or_insert(now_ms);
+        now_ms.saturating_sub(since) >= self.config.max_wait_ms
+    }
+
+    fn empty_block_due(&self) -> bool {
+        let interval = Duration::from_millis(self.config.block_interval_ms);
+        self.config.produce_empty_blocks
+            && self.last_block_at.is_none_or(|at| at.elapsed() >= interval)
+    }
+}
+
+/// A packed and executed block awaiting commit.
+struct BuiltBlock {
+    block: Block,
+    /// Bodies of `block.txs`, in order.
+    txs: Vec<Transaction>,
+    receipts: Vec<Receipt>,
+    /// Transactions rejected for good, to evict from the mempool.
+    dropped: Vec<TxId>,
+    executor: StateExecutor,
+}
+
+impl<M, S> ConsensusEngine for SingleNodeConsensus<M, S>
+where
+    M: Mempool,
+    S: BlockStore + StateStore + TxStore + ReceiptStore + BatchStore + HeadStore + ReadSnapshot,
+{
+    fn submit_tx(&mut self, tx: Transaction) -> Result<TxId, ConsensusError> {
+        self.mempool
+            .insert(tx)
+            .map_err(|e| ConsensusError::Mempool(e.to_string()))
+    }
+
+    /// Besides the mempool's admission checks, previews execution on top of
+    /// the sender's pending transactions, so a transaction that would sit
+    /// in the mempool without ever executing (a nonce gap, an empty
+    /// balance) is reported too.
+    fn simulate_tx(&self, tx: &Transaction) -> Result<(), ConsensusError> {
+        self.mempool
+            .check(tx)
+            .map_err(|e| ConsensusError::Mempool(e.to_string()))?;
+        let next_height = self.last_height + 1;
+        if tx.is_expired_at(next_height) {
+            return Err(ConsensusError::Execution(format!(
+                "transaction expires before height {next_height}"
+            )));
+        }
+
+        let pending: Vec<(TxId, Transaction)> = self
+            .mempool
+            .get_batch(self.mempool.len())
+            .into_iter()
+            .filter(|(_, p)| p.from == tx.from && p.nonce != tx.nonce)
+            .collect();
+        let mut executor = self.executor.clone();
+        executor.apply(pending);
+        executor
+            .check(tx)
+            .map_err(|e| ConsensusError::Execution(e.to_string()))
+    }
 
-        Ok(Some(FinalityEvent::BlockCommitted { block, qc }))
+    fn is_pending(&self, id: TxId) -> bool {
+        self.mempool.contains(&id)
+    }
+
+    fn pending_count(&self) -> usize {
+        self.mempool.len()
+    }
+
+    fn head(&self) -> Option<(u64, BlockId)> {
+        self.last_block_id.map(|id| (self.last_height, id))
+    }
+
+    #[instrument(skip(self))]
+    fn step(&mut self) -> Result<Option<FinalityEvent>, ConsensusError> {
+        let now_ms = self.clock.now_ms();
+        self.step_at(now_ms)
+    }
+
+    fn get_tx(&self, id: TxId) -> Result<Option<Transaction>, ConsensusError> {
+        found(self.storage.get_tx(id))
+    }
+
+    fn get_block(&self, id: BlockId) -> Result<Option<Block>, ConsensusError> {
+        found(self.storage.get_block(id))
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, ConsensusError> {
+        found(self.storage.get_block_by_height(height))
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Option<Hash>, ConsensusError> {
+        found(self.storage.state_root_at_height(height))
+    }
+
+    fn latest_block(&self) -> Result<Option<Block>, ConsensusError> {
+        found(self.storage.latest_block())
+    }
+
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, ConsensusError> {
+        Ok(self.storage.blocks_in_range(from, to)?)
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Option<Receipt>, ConsensusError> {
+        found(self.storage.get_receipt(id))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use types::{NamespaceId, Transaction};
+    use types::Hash;
 
     fn make_tx(nonce: u64) -> Transaction {
         Transaction {
+            chain_id: 0,
+            from: sender(1),
             namespace: NamespaceId(1),
             gas_price: 1,
             nonce,
+            valid_until_height: None,
+            priority: false,
             payload: vec![],
             signature: vec![],
+            pow_nonce: 0,
+        }
+    }
+
+    /// One of the funded test senders, 1 to 3.
+    fn sender(n: u8) -> Address {
+        Address([n; 20])
+    }
+
+    /// Genesis funding for the test senders.
+    fn funded_config() -> ConsensusConfig {
+        ConsensusConfig {
+            genesis_balances: (1..=3).map(|n| (sender(n), 1_000_000_000)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn head_id(engine: &impl ConsensusEngine) -> Option<BlockId> {
+        engine.head().map(|(_, id)| id)
+    }
+
+    fn funded_engine() -> SingleNodeConsensus<SimpleMempool, InMemoryStorage> {
+        SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            funded_config(),
+        )
+        .unwrap()
+    }
+
+    fn validator_set(n: u8) -> Vec<(ValidatorId, SigningKey)> {
+        (1..=n)
+            .map(|i| (ValidatorId([i; 32]), SigningKey::from_bytes(&[i; 32])))
+            .collect()
+    }
+
+    fn pubkeys(set: &[(ValidatorId, SigningKey)]) -> Vec<(ValidatorId, PubKey)> {
+        set.iter()
+            .map(|(id, key)| (*id, PubKey(key.verifying_key().to_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn qc_with_two_of_three_signatures_verifies() {
+        let set = validator_set(3);
+        let block_id = child_of(None, 1).header.id();
+        let view = ViewNumber(1);
+        let votes = set[..2]
+            .iter()
+            .map(|(id, key)| Vote::sign(key, *id, view, block_id));
+
+        let qc = QuorumCertificate::from_votes(view, block_id, votes);
+
+        assert_eq!(qc.signatures.len(), 2);
+        assert!(qc.verify(&pubkeys(&set), 2));
+    }
+
+    #[test]
+    fn qc_under_threshold_fails() {
+        let set = validator_set(3);
+        let block_id = child_of(None, 1).header.id();
+        let view = ViewNumber(1);
+        let (id, key) = &set[0];
+        // A duplicate vote from the same validator must not count twice.
+        let votes = vec![
+            Vote::sign(key, *id, view, block_id),
+            Vote::sign(key, *id, view, block_id),
+        ];
+
+        let qc = QuorumCertificate::from_votes(view, block_id, votes);
+
+        assert!(!qc.verify(&pubkeys(&set), 2));
+    }
+
+    #[test]
+    fn qc_with_forged_signature_fails() {
+        let set = validator_set(3);
+        let block_id = child_of(None, 1).header.id();
+        let view = ViewNumber(1);
+        // Validator 3 signs a vote claiming to be validator 2.
+        let votes = vec![
+            Vote::sign(&set[0].1, set[0].0, view, block_id),
+            Vote::sign(&set[2].1, set[1].0, view, block_id),
+        ];
+
+        let qc = QuorumCertificate::from_votes(view, block_id, votes);
+
+        assert!(!qc.verify(&pubkeys(&set), 2));
+    }
+
+    #[test]
+    fn proposers_rotate_round_robin() {
+        let ids: Vec<ValidatorId> = (1..=3).map(|i| ValidatorId([i; 32])).collect();
+        let set = ValidatorSet::new(ids.clone());
+
+        let schedule = set.schedule(ViewNumber(0), 30);
+        for (i, (view, proposer)) in schedule.iter().enumerate() {
+            assert_eq!(*view, ViewNumber(i as u64));
+            assert_eq!(*proposer, ids[i % 3]);
+        }
+        for id in &ids {
+            assert_eq!(schedule.iter().filter(|(_, p)| p == id).count(), 10);
+        }
+        assert_eq!(set.proposer_for_view(ViewNumber(u64::MAX)), ids[0]);
+    }
+
+    #[test]
+    fn blocks_are_stamped_with_the_scheduled_proposer() {
+        let ids: Vec<ValidatorId> = (1..=3).map(|i| ValidatorId([i; 32])).collect();
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            produce_empty_blocks: true,
+            validators: ValidatorSet::new(ids.clone()),
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        let schedule = engine.proposer_schedule(6);
+        assert_eq!(schedule[0], (ViewNumber(1), ids[1]));
+        for (view, proposer) in schedule {
+            let Some(FinalityEvent::BlockCommitted { block, qc }) = engine.step().unwrap() else {
+                panic!("expected an empty block");
+            };
+            assert_eq!(qc.view, view);
+            assert_eq!(block.header.proposer, proposer.0);
         }
+        assert_eq!(engine.view(), ViewNumber(6));
     }
 
     #[test]
     fn single_node_commits_blocks_from_mempool() {
-        let mempool = SimpleMempool::default();
-        let storage = InMemoryStorage::default();
-        let mut engine = SingleNodeConsensus::new(mempool, storage);
+        let mut engine = funded_engine();
 
         // Submit a few transactions.
         for i in 0..3 {
@@ -218,11 +1280,916 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unreadable_head_is_an_error_not_a_new_chain() {
+        // A head whose block is missing is corruption, not an empty store.
+        let mut storage = InMemoryStorage::default();
+        let mut batch = StorageBatch::new();
+        batch.set_head(child_of(None, 1).header.id());
+        storage.commit_batch(batch).unwrap();
+
+        let engine = SingleNodeConsensus::new(SimpleMempool::default(), storage);
+        assert!(matches!(engine, Err(ConsensusError::Storage(_))));
+    }
+
+    #[test]
+    fn empty_mempool_produces_no_block_when_suppressed() {
+        let mut engine = SingleNodeConsensus::default();
+        assert!(!engine.config().produce_empty_blocks);
+
+        assert_eq!(engine.step().unwrap(), None);
+        assert_eq!(head_id(&engine), None);
+    }
+
+    #[test]
+    fn empty_block_produced_when_enabled() {
+        let config = ConsensusConfig {
+            block_interval_ms: 60_000,
+            produce_empty_blocks: true,
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected empty block");
+        };
+        assert_eq!(block.header.height, 1);
+        assert!(block.txs.is_empty());
+        assert_eq!(block.header.tx_root, merkle_root(&[]));
+
+        // The next empty block is not due until the interval has elapsed.
+        assert_eq!(engine.step().unwrap(), None);
+    }
+
+    #[test]
+    fn empty_views_time_out_after_the_configured_count_or_duration() {
+        let config = ConsensusConfig {
+            view_timeout_views: Some(3),
+            view_timeout_ms: Some(10_000),
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        assert_eq!(engine.step_at(1_000).unwrap(), None);
+        assert_eq!(engine.step_at(1_100).unwrap(), None);
+        assert_eq!(
+            engine.step_at(1_200).unwrap(),
+            Some(FinalityEvent::ViewTimeout {
+                view: ViewNumber(3)
+            })
+        );
+        // The count starts over, so the stall is reported again later.
+        assert_eq!(engine.step_at(1_300).unwrap(), None);
+
+        // A block ends the run of empty views.
+        engine.submit_tx(make_tx(0)).unwrap();
+        assert!(matches!(
+            engine.step_at(1_400).unwrap(),
+            Some(FinalityEvent::BlockCommitted { .. })
+        ));
+        assert_eq!(engine.step_at(2_000).unwrap(), None);
+
+        // The duration fires first when steps are further apart.
+        assert_eq!(
+            engine.step_at(12_000).unwrap(),
+            Some(FinalityEvent::ViewTimeout {
+                view: ViewNumber(7)
+            })
+        );
+    }
+
+    #[test]
+    fn blocks_wait_for_min_txs_until_max_wait() {
+        let config = ConsensusConfig {
+            min_txs_per_block: 3,
+            max_wait_ms: 1_000,
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        engine.submit_tx(make_tx(0)).unwrap();
+        engine.submit_tx(make_tx(1)).unwrap();
+        assert_eq!(engine.step_at(10_000).unwrap(), None);
+        assert_eq!(engine.step_at(10_500).unwrap(), None);
+
+        // Reaching the threshold seals straight away.
+        engine.submit_tx(make_tx(2)).unwrap();
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step_at(10_600).unwrap()
+        else {
+            panic!("expected a block at the threshold");
+        };
+        assert_eq!(block.txs.len(), 3);
+
+        // The wait starts over for the next transaction and seals it alone
+        // once it runs out.
+        engine.submit_tx(make_tx(3)).unwrap();
+        assert_eq!(engine.step_at(20_000).unwrap(), None);
+        assert_eq!(engine.step_at(20_999).unwrap(), None);
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step_at(21_000).unwrap()
+        else {
+            panic!("expected a block after max_wait_ms");
+        };
+        assert_eq!(block.txs.len(), 1);
+        assert_eq!(engine.pending_count(), 0);
+    }
+
+    /// The first transaction of the funded sender `n`.
+    fn priced_tx(n: u8, gas_price: u64) -> Transaction {
+        Transaction {
+            from: sender(n),
+            gas_price,
+            ..make_tx(0)
+        }
+    }
+
+    #[test]
+    fn block_fees_are_credited_to_the_proposer_or_fee_recipient() {
+        let proposer = ValidatorId([7; 32]);
+        let config = ConsensusConfig {
+            validators: ValidatorSet::new(vec![proposer]),
+            ..funded_config()
+        };
+        let fees = 8 * types::TX_BASE_GAS;
+        for fee_recipient in [None, Some(sender(3))] {
+            let mut engine = SingleNodeConsensus::with_config(
+                SimpleMempool::default(),
+                InMemoryStorage::default(),
+                ConsensusConfig {
+                    fee_recipient,
+                    ..config.clone()
+                },
+            )
+            .unwrap();
+            let recipient = fee_recipient.unwrap_or(proposer.fee_account());
+            let before = engine.accounts().get(recipient).balance;
+            let ids = [
+                engine.submit_tx(priced_tx(1, 3)).unwrap(),
+                engine.submit_tx(priced_tx(2, 5)).unwrap(),
+            ];
+
+            let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+                panic!("expected committed block");
+            };
+            assert_eq!(block.header.fees, fees);
+            assert_eq!(engine.accounts().get(recipient).balance, before + fees);
+            assert_eq!(block.header.state_root, engine.accounts().root());
+            let receipt_fees: u64 = ids
+                .iter()
+                .map(|id| engine.get_receipt(*id).unwrap().unwrap().fee)
+                .sum();
+            assert_eq!(receipt_fees, fees);
+        }
+    }
+
+    #[test]
+    fn block_packing_stops_at_gas_limit_and_prefers_higher_fees() {
+        let config = ConsensusConfig {
+            // Room for two empty-payload transactions, not three.
+            gas_limit: 2 * types::TX_BASE_GAS + 1,
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        let cheap = engine.submit_tx(priced_tx(1, 1)).unwrap();
+        let best = engine.submit_tx(priced_tx(2, 10)).unwrap();
+        let mid = engine.submit_tx(priced_tx(3, 5)).unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.txs, vec![best, mid]);
+        assert!(!block.txs.contains(&cheap));
+        assert_eq!(block.header.gas_used, 2 * types::TX_BASE_GAS);
+    }
+
+    #[test]
+    fn priority_transaction_is_packed_ahead_of_higher_fees() {
+        let config = ConsensusConfig {
+            gas_limit: 2 * types::TX_BASE_GAS,
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        let best = engine.submit_tx(priced_tx(1, 10)).unwrap();
+        let mid = engine.submit_tx(priced_tx(2, 5)).unwrap();
+        let forced = engine
+            .submit_tx(Transaction {
+                priority: true,
+                ..priced_tx(3, 1)
+            })
+            .unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.txs, vec![forced, best]);
+        assert!(!block.txs.contains(&mid));
+    }
+
+    #[test]
+    fn boxed_backends_commit_blocks() {
+        let mempool: BoxedMempool = Box::<SimpleMempool>::default();
+        let storage: BoxedStorage = Box::<InMemoryStorage>::default();
+        let mut engine: BoxedConsensus =
+            SingleNodeConsensus::with_config(mempool, storage, funded_config()).unwrap();
+
+        let id = engine.submit_tx(priced_tx(1, 1)).unwrap();
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.txs, vec![id]);
+        assert_eq!(engine.latest_block().unwrap(), Some(block));
+        assert!(engine.get_tx(id).unwrap().is_some());
+    }
+
+    #[test]
+    fn step_until_empty_commits_every_pending_block() {
+        let config = ConsensusConfig {
+            // One empty-payload transaction per block.
+            gas_limit: types::TX_BASE_GAS,
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+        for n in 1..=3 {
+            engine.submit_tx(priced_tx(n, 1)).unwrap();
+        }
+
+        let events = engine.step_until_empty().unwrap();
+        assert_eq!(events.len(), 3);
+        let heights: Vec<u64> = events
+            .iter()
+            .map(|event| match event {
+                FinalityEvent::BlockCommitted { block, .. } => block.header.height,
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect();
+        assert_eq!(heights, vec![1, 2, 3]);
+        assert!(engine.step_until_empty().unwrap().is_empty());
+    }
+
+    #[test]
+    fn expired_transactions_are_skipped_and_evicted() {
+        let mut engine = funded_engine();
+        engine.submit_tx(priced_tx(1, 1)).unwrap();
+        engine.step().unwrap();
+        assert_eq!(engine.last_height, 1);
+
+        // The next block is height 2.
+        let past = Transaction {
+            valid_until_height: Some(1),
+            ..priced_tx(2, 10)
+        };
+        let future = Transaction {
+            valid_until_height: Some(2),
+            ..priced_tx(3, 1)
+        };
+        assert!(matches!(
+            engine.simulate_tx(&past),
+            Err(ConsensusError::Execution(_))
+        ));
+        let past = engine.submit_tx(past).unwrap();
+        let future = engine.submit_tx(future).unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.header.height, 2);
+        assert_eq!(block.txs, vec![future]);
+        assert!(!engine.is_pending(past));
+        assert_eq!(engine.get_receipt(past).unwrap(), None);
+    }
+
+    #[test]
+    fn transaction_larger_than_gas_limit_is_skipped() {
+        let config = ConsensusConfig {
+            gas_limit: types::TX_BASE_GAS + 4,
+            ..funded_config()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+
+        let mut huge = priced_tx(1, 100);
+        huge.payload = vec![0; 5];
+        engine.submit_tx(huge).unwrap();
+        let small = engine.submit_tx(priced_tx(2, 1)).unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.txs, vec![small]);
+    }
+
+    #[test]
+    fn committed_block_persists_txs_and_state_root() {
+        let mut engine = funded_engine();
+
+        let tx = make_tx(0);
+        let tx_id = engine.submit_tx(tx.clone()).unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+
+        let stored = engine.storage.get_block_by_height(1).unwrap();
+        assert_eq!(stored.header.id(), block.header.id());
+        assert_eq!(engine.storage.get_tx(tx_id).unwrap(), tx);
+        assert_eq!(
+            engine.storage.latest_state_root().unwrap(),
+            (1, block.header.state_root)
+        );
+        assert_eq!(
+            engine.state_root_at_height(1).unwrap(),
+            Some(block.header.state_root)
+        );
+        assert_eq!(engine.state_root_at_height(2).unwrap(), None);
+    }
+
+    #[test]
+    fn block_commits_only_executed_transactions() {
+        let mut engine = funded_engine();
+        let ok = engine.submit_tx(make_tx(0)).unwrap();
+        let gap = engine.submit_tx(make_tx(5)).unwrap();
+        let unfunded = engine.submit_tx(priced_tx(9, 1)).unwrap();
+
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+        assert_eq!(block.txs, vec![ok]);
+        assert_eq!(block.header.gas_used, types::TX_BASE_GAS);
+        assert_eq!(block.header.state_root, engine.accounts().root());
+        assert_eq!(engine.accounts().get(sender(1)).nonce, 1);
+
+        // Rejected transactions stay pending; they may become valid later.
+        let pending: Vec<TxId> = engine
+            .mempool
+            .get_batch(10)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(pending.len(), 2);
+        assert!(pending.contains(&gap) && pending.contains(&unfunded));
+        assert_eq!(engine.step().unwrap(), None);
+    }
+
+    #[test]
+    fn settled_transactions_get_receipts() {
+        let mut engine = funded_engine();
+        let first = engine.submit_tx(make_tx(0)).unwrap();
+        engine.step().unwrap();
+        let receipt = engine.get_receipt(first).unwrap().unwrap();
+        assert_eq!(receipt.status, ExecStatus::Success);
+        assert_eq!(receipt.index, 0);
+        assert_eq!(receipt.gas_used, types::TX_BASE_GAS);
+        assert_eq!(receipt.block_id, head_id(&engine).unwrap());
+
+        // A different tx reusing nonce 0 is dropped with a reverted receipt.
+        let mut stale = make_tx(0);
+        stale.payload = vec![1];
+        let stale = engine.submit_tx(stale).unwrap();
+        let next = engine.submit_tx(make_tx(1)).unwrap();
+        engine.step().unwrap();
+
+        assert_eq!(engine.get_receipt(next).unwrap().unwrap().index, 0);
+        let reverted = engine.get_receipt(stale).unwrap().unwrap();
+        assert_eq!(reverted.index, 1);
+        assert_eq!(reverted.gas_used, 0);
+        assert_eq!(reverted.block_id, head_id(&engine).unwrap());
+        assert!(matches!(
+            reverted.status,
+            ExecStatus::Reverted { ref reason } if reason.contains("already used")
+        ));
+        assert!(engine.mempool.is_empty());
+
+        // A resubmitted copy of a committed tx keeps its original receipt.
+        engine.submit_tx(make_tx(0)).unwrap();
+        engine.submit_tx(make_tx(2)).unwrap();
+        engine.step().unwrap();
+        assert_eq!(engine.get_receipt(first).unwrap().unwrap(), receipt);
+        assert!(engine.mempool.is_empty());
+    }
+
+    /// A block on `parent` stamped `offset_ms` after it, so different
+    /// offsets give different ids.
+    fn child_of(parent: Option<&Block>, offset_ms: u64) -> Block {
+        Block {
+            header: BlockHeader {
+                height: parent.map_or(1, |p| p.header.height + 1),
+                parent: parent.map(|p| p.header.id()),
+                tx_root: Hash([0u8; 32]),
+                gas_used: 0,
+                fees: 0,
+                state_root: Hash([0u8; 32]),
+                timestamp_ms: parent.map_or(0, |p| p.header.timestamp_ms) + offset_ms,
+                proposer: [0u8; 32],
+            },
+            txs: vec![],
+        }
+    }
+
+    /// Build two siblings of `parent`, returned as (winner, loser) under the
+    /// smaller-id tiebreak.
+    fn siblings(parent: Option<&Block>) -> (Block, Block) {
+        let a = child_of(parent, 1);
+        let b = child_of(parent, 2);
+        if a.header.id().0 .0 < b.header.id().0 .0 {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    #[test]
+    fn import_linear_chain_extends_head() {
+        let mut engine = SingleNodeConsensus::default();
+
+        let b1 = child_of(None, 1);
+        let b2 = child_of(Some(&b1), 2);
+        for block in [&b1, &b2] {
+            let (outcome, events) = engine.import_block(block.clone(), vec![]).unwrap();
+            assert_eq!(
+                outcome,
+                ImportOutcome::Extended {
+                    head: block.header.id()
+                }
+            );
+            assert!(matches!(
+                events.as_slice(),
+                [FinalityEvent::BlockCommitted { block: committed, .. }] if committed == block
+            ));
+        }
+
+        assert_eq!(head_id(&engine), Some(b2.header.id()));
+        assert_eq!(engine.storage.head().unwrap(), b2.header.id());
+    }
+
+    #[test]
+    fn duplicate_import_is_already_known() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        let (winner, loser) = siblings(Some(&b1));
+        for block in [&b1, &winner, &loser] {
+            engine.import_block(block.clone(), vec![]).unwrap();
+        }
+
+        // Both the head and a stored side block are recognized.
+        for block in [winner.clone(), loser] {
+            assert_eq!(
+                engine.import_block(block, vec![]).unwrap(),
+                (ImportOutcome::AlreadyKnown, Vec::new())
+            );
+        }
+        assert_eq!(head_id(&engine), Some(winner.header.id()));
+    }
+
+    #[test]
+    fn import_rejects_mismatched_tx_root() {
+        let mut producer = funded_engine();
+        producer.submit_tx(make_tx(0)).unwrap();
+        let (block, txs) = produce(&mut producer, 1_000);
+
+        let mut engine = funded_engine();
+        let mut tampered = block.clone();
+        tampered.header.tx_root = Hash([7; 32]);
+        assert!(matches!(
+            engine.import_block(tampered.clone(), txs.clone()),
+            Err(ConsensusError::InvalidBlock(_))
+        ));
+        assert!(engine.storage.get_block(tampered.header.id()).is_err());
+        assert_eq!(head_id(&engine), None);
+
+        for bodies in [vec![], vec![make_tx(1)]] {
+            assert!(matches!(
+                engine.import_block(block.clone(), bodies),
+                Err(ConsensusError::InvalidBlock(_))
+            ));
+        }
+        assert!(matches!(
+            engine.import_block(block, txs).unwrap().0,
+            ImportOutcome::Extended { .. }
+        ));
+        assert_eq!(engine.get_tx(make_tx(0).id()).unwrap(), Some(make_tx(0)));
+    }
+
+    #[test]
+    fn import_executes_blocks_against_their_state_root() {
+        let mut producer = funded_engine();
+        producer.submit_tx(make_tx(0)).unwrap();
+        let (block, txs) = produce(&mut producer, 1_000);
+
+        let mut engine = funded_engine();
+        let mut wrong_root = block.clone();
+        wrong_root.header.state_root = Hash([7; 32]);
+        let mut wrong_fees = block.clone();
+        wrong_fees.header.fees += 1;
+        for bad in [wrong_root, wrong_fees] {
+            assert!(matches!(
+                engine.import_block(bad, txs.clone()),
+                Err(ConsensusError::InvalidBlock(_))
+            ));
+        }
+        // Without genesis funds the sender cannot pay the fee.
+        assert!(matches!(
+            SingleNodeConsensus::default().import_block(block.clone(), txs.clone()),
+            Err(ConsensusError::InvalidBlock(_))
+        ));
+        assert_eq!(head_id(&engine), None);
+
+        engine.submit_tx(make_tx(0)).unwrap();
+        engine.import_block(block.clone(), txs).unwrap();
+        assert_eq!(engine.accounts(), producer.accounts());
+        assert!(engine.mempool.is_empty());
+        assert_eq!(
+            engine
+                .get_receipt(make_tx(0).id())
+                .unwrap()
+                .unwrap()
+                .block_id,
+            block.header.id()
+        );
+    }
+
+    #[test]
+    fn import_rejects_non_monotonic_timestamp() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1_000);
+        engine.import_block(b1.clone(), vec![]).unwrap();
+
+        for timestamp_ms in [999, 1_000] {
+            let mut stale = child_of(Some(&b1), 1);
+            stale.header.timestamp_ms = timestamp_ms;
+            assert!(matches!(
+                engine.import_block(stale, vec![]),
+                Err(ConsensusError::InvalidBlock(_))
+            ));
+        }
+        assert_eq!(head_id(&engine), Some(b1.header.id()));
+    }
+
+    #[test]
+    fn produced_timestamps_are_clamped_when_the_clock_goes_back() {
+        let mut engine = funded_engine();
+        let mut timestamps = Vec::new();
+        for (sender, now_ms) in [(1, 5_000), (2, 4_000), (3, 4_000)] {
+            engine.submit_tx(priced_tx(sender, 1)).unwrap();
+            let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step_at(now_ms).unwrap()
+            else {
+                panic!("expected committed block");
+            };
+            timestamps.push(block.header.timestamp_ms);
+        }
+        assert_eq!(timestamps, vec![5_000, 5_001, 5_002]);
+    }
+
+    #[test]
+    fn same_clock_and_txs_give_identical_blocks() {
+        let clock = MockClock::new(1_700_000_000_000);
+        let mut engines = [
+            funded_engine().with_clock(clock.clone()),
+            funded_engine().with_clock(clock.clone()),
+        ];
+        let mut headers = [Vec::new(), Vec::new()];
+        for step in 0..3 {
+            for (engine, headers) in engines.iter_mut().zip(&mut headers) {
+                engine.submit_tx(priced_tx(1 + step, 1)).unwrap();
+                let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap()
+                else {
+                    panic!("expected committed block");
+                };
+                headers.push(block.header);
+            }
+            clock.advance(500);
+        }
+        assert_eq!(headers[0], headers[1]);
+        assert_eq!(headers[0][0].timestamp_ms, 1_700_000_000_000);
+        assert_eq!(headers[0][2].timestamp_ms, 1_700_000_001_000);
+    }
+
+    #[test]
+    fn sibling_losing_tiebreak_is_noop() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        engine.import_block(b1.clone(), vec![]).unwrap();
+
+        let (winner, loser) = siblings(Some(&b1));
+        engine.import_block(winner.clone(), vec![]).unwrap();
+
+        assert_eq!(
+            engine.import_block(loser.clone(), vec![]).unwrap().0,
+            ImportOutcome::NoOp
+        );
+        assert_eq!(head_id(&engine), Some(winner.header.id()));
+        // The losing sibling is still stored for a possible later reorg.
+        assert!(engine.storage.get_block(loser.header.id()).is_ok());
+        assert_eq!(
+            engine.storage.get_block_by_height(2).unwrap().header.id(),
+            winner.header.id()
+        );
+    }
+
+    #[test]
+    fn competing_blocks_at_a_height_are_kept_and_recorded() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        let (winner, loser) = siblings(Some(&b1));
+        let third = child_of(Some(&b1), 3);
+        for block in [&b1, &loser, &winner, &third, &loser] {
+            engine.import_block(block.clone(), vec![]).unwrap();
+        }
+
+        // The height index follows fork choice; every block stays readable
+        // by id.
+        assert_eq!(
+            engine.storage.get_block_by_height(2).unwrap().header.id(),
+            winner.header.id()
+        );
+        for block in [&loser, &winner, &third] {
+            assert_eq!(
+                engine.get_block(block.header.id()).unwrap().as_ref(),
+                Some(block)
+            );
+        }
+        assert_eq!(
+            engine.forks_at(2),
+            [loser.header.id(), winner.header.id(), third.header.id()]
+        );
+        assert!(engine.forks_at(1).is_empty());
+    }
+
+    #[test]
+    fn sibling_winning_tiebreak_reorgs() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        engine.import_block(b1.clone(), vec![]).unwrap();
+
+        let (winner, loser) = siblings(Some(&b1));
+        engine.import_block(loser.clone(), vec![]).unwrap();
+
+        assert_eq!(
+            engine.import_block(winner.clone(), vec![]).unwrap().0,
+            ImportOutcome::Reorg {
+                old_head: loser.header.id(),
+                new_head: winner.header.id(),
+            }
+        );
+        assert_eq!(engine.storage.head().unwrap(), winner.header.id());
+        assert_eq!(
+            engine.storage.get_block_by_height(2).unwrap().header.id(),
+            winner.header.id()
+        );
+    }
+
+    #[test]
+    fn longer_side_branch_reorgs_and_reindexes_ancestors() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        engine.import_block(b1.clone(), vec![]).unwrap();
+
+        let (main, side) = siblings(Some(&b1));
+        engine.import_block(main.clone(), vec![]).unwrap();
+        assert_eq!(
+            engine.import_block(side.clone(), vec![]).unwrap().0,
+            ImportOutcome::NoOp
+        );
+
+        let side_child = child_of(Some(&side), 3);
+        assert_eq!(
+            engine.import_block(side_child.clone(), vec![]).unwrap().0,
+            ImportOutcome::Reorg {
+                old_head: main.header.id(),
+                new_head: side_child.header.id(),
+            }
+        );
+        assert_eq!(
+            engine.storage.get_block_by_height(2).unwrap().header.id(),
+            side.header.id()
+        );
+        assert_eq!(
+            engine.storage.get_block_by_height(3).unwrap().header.id(),
+            side_child.header.id()
+        );
+    }
+
+    #[test]
+    fn reorg_reverts_old_branch_before_committing_new_one() {
+        let mut engine = SingleNodeConsensus::default();
+        let b1 = child_of(None, 1);
+        let (main2, side2) = siblings(Some(&b1));
+        let main3 = child_of(Some(&main2), 3);
+        for block in [&b1, &main2, &main3] {
+            engine.import_block(block.clone(), vec![]).unwrap();
+        }
+
+        // Side branch blocks known to storage but never the head.
+        let side3 = child_of(Some(&side2), 3);
+        let mut batch = StorageBatch::new();
+        batch.put_side_block(side2.clone());
+        batch.put_side_block(side3.clone());
+        engine.storage.commit_batch(batch).unwrap();
+
+        let side4 = child_of(Some(&side3), 4);
+        let (outcome, events) = engine.import_block(side4.clone(), vec![]).unwrap();
+        assert!(matches!(outcome, ImportOutcome::Reorg { .. }));
+
+        let summary: Vec<(&str, BlockId)> = events
+            .iter()
+            .map(|event| match event {
+                FinalityEvent::BlockReverted { block_id, .. } => ("reverted", *block_id),
+                FinalityEvent::BlockCommitted { block, .. } => ("committed", block.header.id()),
+                FinalityEvent::ViewTimeout { .. } => unreachable!("imports do not time out"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("reverted", main3.header.id()),
+                ("reverted", main2.header.id()),
+                ("committed", side2.header.id()),
+                ("committed", side3.header.id()),
+                ("committed", side4.header.id()),
+            ]
+        );
+        assert!(matches!(
+            events[0],
+            FinalityEvent::BlockReverted { height: 3, .. }
+        ));
+    }
+
+    /// The block committed by a step of `producer` at `now_ms`, with the
+    /// bodies of its transactions.
+    fn produce(
+        producer: &mut SingleNodeConsensus<SimpleMempool, InMemoryStorage>,
+        now_ms: u64,
+    ) -> (Block, Vec<Transaction>) {
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = producer.step_at(now_ms).unwrap()
+        else {
+            panic!("expected committed block");
+        };
+        let txs = block
+            .txs
+            .iter()
+            .map(|id| producer.get_tx(*id).unwrap().unwrap())
+            .collect();
+        (block, txs)
+    }
+
+    #[test]
+    fn reorg_repoints_state_roots_and_stores_the_new_branch() {
+        // Two producers from the same genesis build competing branches.
+        let mut short = funded_engine();
+        short.submit_tx(priced_tx(1, 1)).unwrap();
+        let (a1, a1_txs) = produce(&mut short, 1_000);
+        let mut long = funded_engine();
+        long.submit_tx(priced_tx(2, 1)).unwrap();
+        let (b1, b1_txs) = produce(&mut long, 2_000);
+        long.submit_tx(priced_tx(3, 1)).unwrap();
+        let (b2, b2_txs) = produce(&mut long, 3_000);
+        assert_ne!(a1.header.state_root, b1.header.state_root);
+
+        let mut engine = funded_engine();
+        engine.import_block(a1.clone(), a1_txs).unwrap();
+        assert_eq!(
+            engine.state_root_at_height(1).unwrap(),
+            Some(a1.header.state_root)
+        );
+        engine.import_block(b1.clone(), b1_txs.clone()).unwrap();
+        let (outcome, _) = engine.import_block(b2.clone(), b2_txs.clone()).unwrap();
+        assert_eq!(head_id(&engine), Some(b2.header.id()));
+        assert!(!matches!(outcome, ImportOutcome::NoOp));
+
+        for block in [&b1, &b2] {
+            assert_eq!(
+                engine.state_root_at_height(block.header.height).unwrap(),
+                Some(block.header.state_root)
+            );
+        }
+        for tx in b1_txs.into_iter().chain(b2_txs) {
+            assert_eq!(engine.get_tx(tx.id()).unwrap(), Some(tx));
+        }
+        // The executor follows the reorg: a1's transaction is undone.
+        assert_eq!(engine.accounts(), long.accounts());
+        assert_eq!(engine.accounts().get(sender(1)).nonce, 0);
+    }
+
+    #[test]
+    fn import_rejects_unknown_parent() {
+        let mut engine = SingleNodeConsensus::default();
+        let orphan = child_of(Some(&child_of(None, 1)), 2);
+        assert!(matches!(
+            engine.import_block(orphan, vec![]),
+            Err(ConsensusError::InvalidBlock(_))
+        ));
+    }
+
+    #[test]
+    fn engine_resumes_from_persisted_head() {
+        let mut engine = funded_engine();
+        engine.submit_tx(make_tx(0)).unwrap();
+        let Some(FinalityEvent::BlockCommitted { block, .. }) = engine.step().unwrap() else {
+            panic!("expected committed block");
+        };
+
+        let accounts = engine.accounts().clone();
+        let resumed = SingleNodeConsensus::new(SimpleMempool::default(), engine.storage).unwrap();
+        assert_eq!(head_id(&resumed), Some(block.header.id()));
+        assert_eq!(resumed.last_height, 1);
+        assert_eq!(resumed.accounts(), &accounts);
+    }
+
+    #[test]
+    fn account_state_survives_reopening_sled() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = storage::SledConfig {
+            flush_every_ms: None,
+            ..Default::default()
+        };
+        let accounts = {
+            let storage = storage::SledStorage::open_with_config(dir.path(), config).unwrap();
+            let mut engine = SingleNodeConsensus::with_config(
+                SimpleMempool::default(),
+                storage,
+                funded_config(),
+            )
+            .unwrap();
+            for nonce in 0..2 {
+                engine.submit_tx(make_tx(nonce)).unwrap();
+            }
+            engine.step_at(1_000).unwrap().unwrap();
+            engine.storage().flush().unwrap();
+            engine.accounts().clone()
+        };
+        assert_eq!(accounts.get(sender(1)).nonce, 2);
+
+        let storage = storage::SledStorage::open_with_config(dir.path(), config).unwrap();
+        let mut engine =
+            SingleNodeConsensus::with_config(SimpleMempool::default(), storage, funded_config())
+                .unwrap();
+        assert_eq!(engine.accounts(), &accounts);
+
+        // The used nonces stay used, so a replay is not included again.
+        engine.submit_tx(make_tx(0)).unwrap();
+        assert!(engine.step_at(2_000).unwrap().is_none());
+        assert_eq!(engine.accounts(), &accounts);
+    }
+
+    #[test]
+    fn account_state_is_rebuilt_from_blocks_when_none_is_stored() {
+        let mut engine = funded_engine();
+        engine.submit_tx(make_tx(0)).unwrap();
+        let (block, txs) = produce(&mut engine, 1_000);
+
+        // A store written before account states were recorded.
+        let mut storage = InMemoryStorage::default();
+        let mut batch = StorageBatch::new();
+        batch.put_block(block.clone());
+        for tx in txs {
+            batch.put_tx(tx);
+        }
+        batch.set_head(block.header.id());
+        storage.commit_batch(batch).unwrap();
+
+        let resumed =
+            SingleNodeConsensus::with_config(SimpleMempool::default(), storage, funded_config())
+                .unwrap();
+        assert_eq!(resumed.accounts(), engine.accounts());
+    }
+
     #[test]
     fn committed_block_heights_are_strictly_increasing() {
-        let mempool = SimpleMempool::default();
-        let storage = InMemoryStorage::default();
-        let mut engine = SingleNodeConsensus::new(mempool, storage);
+        let mut engine = funded_engine();
 
         // Submit several transactions so multiple blocks can be produced.
         for i in 0..5 {
@@ -240,9 +2207,7 @@ mod tests {
 
     #[test]
     fn no_two_distinct_blocks_at_same_height() {
-        let mempool = SimpleMempool::default();
-        let storage = InMemoryStorage::default();
-        let mut engine = SingleNodeConsensus::new(mempool, storage);
+        let mut engine = funded_engine();
 
         // Pre-fill enough transactions for several blocks.
         for i in 0..10 {
@@ -267,9 +2232,7 @@ mod tests {
 
     #[test]
     fn l1_batch_commitment_covers_committed_blocks() {
-        let mempool = SimpleMempool::default();
-        let storage = InMemoryStorage::default();
-        let mut engine = SingleNodeConsensus::new(mempool, storage);
+        let mut engine = funded_engine();
 
         // Submit a few transactions so at least one block is produced.
         for i in 0..3 {
@@ -1,53 +1,486 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use types::{NamespaceId, Transaction, TxId};
+use tokio::sync::{Mutex, RwLock};
+use tracing::warn;
+use types::{
+    Address, NamespaceId, NamespaceRegistry, Transaction, TxId, TxValidationError,
+    TxValidationRules,
+};
 
 use metrics as sequencer_metrics;
 
+mod persistent;
+mod wal;
+
+pub use persistent::PersistentMempool;
+pub use wal::{MempoolWal, WalConfig, DEFAULT_WAL_COMPACT_AFTER};
+
 #[derive(Clone, Debug)]
 pub struct MempoolConfig {
     pub max_tx: usize,
+    /// Minimum gas price increase, in percent, required for a transaction
+    /// to replace a pending one with the same `(from, nonce)`.
+    pub replacement_bump_percent: u64,
+    /// Chain id every accepted transaction must carry.
+    pub expected_chain_id: u64,
+    /// Size, fee and namespace rules checked on insert.
+    pub validation: TxValidationRules,
+    /// Order in which `get_batch` hands out transactions.
+    pub ordering: OrderingStrategy,
+    /// What a full mempool does with a new transaction. Only
+    /// [`SimpleMempool`] and [`PersistentMempool`] evict; a full
+    /// [`ShardedMempool`] always rejects.
+    pub overflow: OverflowPolicy,
+    /// Optional fee floor and proof of work asked of every transaction.
+    pub anti_spam: AntiSpamConfig,
+    /// Names used for namespace labels in metrics.
+    pub namespaces: NamespaceRegistry,
+    /// Log of inserts and removals that [`SimpleMempool::open`] replays
+    /// after a crash. Off when `None`.
+    pub wal: Option<WalConfig>,
 }
 
 impl Default for MempoolConfig {
     fn default() -> Self {
-        Self { max_tx: 10_000 }
+        Self {
+            max_tx: 10_000,
+            replacement_bump_percent: 10,
+            expected_chain_id: 0,
+            validation: TxValidationRules::default(),
+            ordering: OrderingStrategy::default(),
+            overflow: OverflowPolicy::default(),
+            anti_spam: AntiSpamConfig::default(),
+            namespaces: NamespaceRegistry::default(),
+            wal: None,
+        }
     }
 }
 
+/// Admission gate against spam on a public sequencer. Each check is off
+/// when `None` and applies independently of the other.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AntiSpamConfig {
+    /// Lowest `gas_price` accepted.
+    pub min_gas_price: Option<u64>,
+    /// Leading zero bits [`Transaction::pow_bits`] must have.
+    pub pow_difficulty_bits: Option<u32>,
+}
+
+impl AntiSpamConfig {
+    fn check(&self, tx: &Transaction) -> Result<(), SpamGateError> {
+        if let Some(floor) = self.min_gas_price {
+            if tx.gas_price < floor {
+                return Err(SpamGateError::BelowFeeFloor {
+                    offered: tx.gas_price,
+                    floor,
+                });
+            }
+        }
+        if let Some(required) = self.pow_difficulty_bits {
+            let bits = tx.pow_bits();
+            if bits < required {
+                return Err(SpamGateError::InsufficientWork { bits, required });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which [`AntiSpamConfig`] check a transaction failed.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SpamGateError {
+    #[error("gas price {offered} is below the floor of {floor}")]
+    BelowFeeFloor { offered: u64, floor: u64 },
+    #[error("proof of work has {bits} leading zero bits, {required} required")]
+    InsufficientWork { bits: u32, required: u32 },
+}
+
+/// What a mempool at `max_tx` does with a new transaction that does not
+/// replace a pending one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Reject it with [`MempoolError::Full`].
+    #[default]
+    Reject,
+    /// Evict the pending transaction with the lowest gas price, the most
+    /// recent one among equals, if the newcomer pays strictly more;
+    /// otherwise reject it.
+    EvictLowestFee,
+}
+
+/// How `get_batch` orders pending transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderingStrategy {
+    /// Highest gas price first; arrival order among equal prices.
+    #[default]
+    GasPriceThenFifo,
+    /// Arrival order, ignoring fees.
+    Fifo,
+    /// Random order in which a transaction's chance of coming first is
+    /// proportional to its gas price, so arrival timing buys nothing.
+    /// `seed` makes the sequence of batches reproducible; `None` seeds from
+    /// the OS.
+    RandomWeightedByFee { seed: Option<u64> },
+}
+
+/// Lock-free splitmix64 generator, so `get_batch` can draw random numbers
+/// through `&self`.
+#[derive(Debug)]
+struct BatchRng(AtomicU64);
+
+impl BatchRng {
+    fn new(ordering: OrderingStrategy) -> Self {
+        let seed = match ordering {
+            OrderingStrategy::RandomWeightedByFee { seed: Some(seed) } => seed,
+            _ => RandomState::new().build_hasher().finish(),
+        };
+        Self(AtomicU64::new(seed))
+    }
+
+    fn next_u64(&self) -> u64 {
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut z = self
+            .0
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `(0, 1]`.
+    fn next_unit(&self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Order `candidates` by `strategy` and keep the first `max`. `key` yields a
+/// candidate's priority flag, gas price and arrival sequence number.
+///
+/// Priority transactions come first in arrival order whatever the strategy;
+/// only the remainder is ordered by `strategy`.
+fn order_batch<T>(
+    strategy: OrderingStrategy,
+    rng: &BatchRng,
+    candidates: Vec<T>,
+    key: impl Fn(&T) -> (bool, u64, u64),
+    max: usize,
+) -> Vec<T> {
+    let (mut batch, mut candidates): (Vec<T>, Vec<T>) =
+        candidates.into_iter().partition(|c| key(c).0);
+    batch.sort_by_key(|c| key(c).2);
+    let key = |c: &T| {
+        let (_, price, seq) = key(c);
+        (price, seq)
+    };
+    match strategy {
+        OrderingStrategy::GasPriceThenFifo => candidates.sort_by(|a, b| {
+            let ((price_a, seq_a), (price_b, seq_b)) = (key(a), key(b));
+            price_b.cmp(&price_a).then(seq_a.cmp(&seq_b))
+        }),
+        OrderingStrategy::Fifo => candidates.sort_by_key(|c| key(c).1),
+        OrderingStrategy::RandomWeightedByFee { .. } => {
+            // Efraimidis-Spirakis: sorting by u^(1/w) descending samples
+            // without replacement with probability proportional to w. The
+            // log form avoids underflow for large weights.
+            let mut keyed: Vec<(f64, T)> = candidates
+                .into_iter()
+                .map(|c| {
+                    let weight = key(&c).0.max(1) as f64;
+                    (rng.next_unit().ln() / weight, c)
+                })
+                .collect();
+            keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+            candidates = keyed.into_iter().map(|(_, c)| c).collect();
+        }
+    }
+    batch.extend(candidates);
+    batch.truncate(max);
+    batch
+}
+
 #[derive(Debug, Error)]
 pub enum MempoolError {
     #[error("mempool is full")]
     Full,
+    #[error("replacement transaction underpriced: gas price {offered} below required {required}")]
+    ReplacementUnderpriced { offered: u64, required: u64 },
+    #[error("wrong chain id: expected {expected}, got {got}")]
+    WrongChain { expected: u64, got: u64 },
+    #[error("invalid transaction: {0}")]
+    Invalid(#[from] TxValidationError),
+    #[error("rejected by anti-spam gate: {0}")]
+    SpamGate(#[from] SpamGateError),
+    #[error("mempool storage error: {0}")]
+    Storage(String),
+}
+
+impl MempoolError {
+    /// Short label used for the `reason` metrics label.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            MempoolError::Full => "full",
+            MempoolError::ReplacementUnderpriced { .. } => "replacement_underpriced",
+            MempoolError::WrongChain { .. } => "wrong_chain",
+            MempoolError::Invalid(TxValidationError::ZeroGasPrice) => "zero_gas_price",
+            MempoolError::Invalid(TxValidationError::PayloadTooLarge { .. }) => "payload_too_large",
+            MempoolError::Invalid(TxValidationError::NamespaceNotAllowed(_)) => {
+                "namespace_not_allowed"
+            }
+            MempoolError::SpamGate(SpamGateError::BelowFeeFloor { .. }) => "below_fee_floor",
+            MempoolError::SpamGate(SpamGateError::InsufficientWork { .. }) => "insufficient_work",
+            MempoolError::Storage(_) => "storage",
+        }
+    }
 }
 
-/// Basic mempool interface. 
-/// Intentional TODO: add async support later, when integrating with the rest of the system.
+/// Count a refused insert in `sequencer_tx_rejected_total`.
+fn record_rejection(e: &MempoolError) {
+    sequencer_metrics::record_tx_rejected(e.reason());
+}
+
+/// Admission checks shared by every mempool: chain id, validation rules and
+/// the anti-spam gate.
+fn check_tx(config: &MempoolConfig, tx: &Transaction) -> Result<(), MempoolError> {
+    if tx.chain_id != config.expected_chain_id {
+        return Err(MempoolError::WrongChain {
+            expected: config.expected_chain_id,
+            got: tx.chain_id,
+        });
+    }
+    tx.validate_with(&config.validation)?;
+    config.anti_spam.check(tx)?;
+    Ok(())
+}
+
+/// Basic synchronous mempool interface. See [`AsyncMempool`] for a
+/// variant that can be shared between tasks without an outer lock.
 pub trait Mempool {
     fn insert(&mut self, tx: Transaction) -> Result<TxId, MempoolError>;
+    /// Whether `insert` would currently accept `tx`, without inserting it.
+    fn check(&self, tx: &Transaction) -> Result<(), MempoolError>;
+    /// Whether `id` is pending.
+    fn contains(&self, id: &TxId) -> bool;
+    /// The pending transaction with `id`, if any.
+    fn get(&self, id: &TxId) -> Option<Transaction>;
     fn get_batch(&self, max: usize) -> Vec<(TxId, Transaction)>;
+    /// Like `get_batch`, but only transactions in namespace `ns`.
+    fn get_batch_for_namespace(&self, ns: NamespaceId, max: usize) -> Vec<(TxId, Transaction)>;
     fn remove_committed(&mut self, ids: &[TxId]);
+    /// Every pending transaction once, in no particular order. Borrows
+    /// instead of cloning and sorting like `get_batch`, e.g. for
+    /// monitoring.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&TxId, &Transaction)> + '_>;
     fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A mempool chosen at runtime, e.g. from the node config.
+pub type BoxedMempool = Box<dyn Mempool + Send>;
+
+impl<M: Mempool + ?Sized> Mempool for Box<M> {
+    fn insert(&mut self, tx: Transaction) -> Result<TxId, MempoolError> {
+        (**self).insert(tx)
+    }
+
+    fn check(&self, tx: &Transaction) -> Result<(), MempoolError> {
+        (**self).check(tx)
+    }
+
+    fn contains(&self, id: &TxId) -> bool {
+        (**self).contains(id)
+    }
+
+    fn get(&self, id: &TxId) -> Option<Transaction> {
+        (**self).get(id)
+    }
+
+    fn get_batch(&self, max: usize) -> Vec<(TxId, Transaction)> {
+        (**self).get_batch(max)
+    }
+
+    fn get_batch_for_namespace(&self, ns: NamespaceId, max: usize) -> Vec<(TxId, Transaction)> {
+        (**self).get_batch_for_namespace(ns, max)
+    }
+
+    fn remove_committed(&mut self, ids: &[TxId]) {
+        (**self).remove_committed(ids)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&TxId, &Transaction)> + '_> {
+        (**self).iter()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
 }
 
 /// A mempool that tracks transactions per namespace and supports
 /// gas-price-based prioritization when building batches.
+///
+/// A pending transaction can be replaced by one from the same sender with
+/// the same nonce that pays a higher fee.
 #[derive(Debug)]
 pub struct SimpleMempool {
     config: MempoolConfig,
     queue: VecDeque<TxId>,
-    txs: HashMap<TxId, Transaction>,
+    txs: HashMap<TxId, PendingTx>,
     by_namespace: HashMap<NamespaceId, Vec<TxId>>,
+    by_slot: HashMap<(Address, u64), TxId>,
+    rng: BatchRng,
+    wal: Option<MempoolWal>,
+}
+
+/// A pending transaction together with the time it entered the mempool.
+#[derive(Debug)]
+struct PendingTx {
+    tx: Transaction,
+    received_at: Instant,
 }
 
 impl SimpleMempool {
+    /// An empty mempool. `config.wal` is not opened; see
+    /// [`SimpleMempool::open`].
     pub fn new(config: MempoolConfig) -> Self {
         Self {
+            rng: BatchRng::new(config.ordering),
             config,
             queue: VecDeque::new(),
             txs: HashMap::new(),
             by_namespace: HashMap::new(),
+            by_slot: HashMap::new(),
+            wal: None,
+        }
+    }
+
+    /// A mempool that logs its changes to `config.wal`, if set, starting
+    /// with the transactions the log leaves pending. Logged transactions
+    /// the mempool no longer accepts, e.g. after a chain id or capacity
+    /// change, are dropped from the log.
+    pub fn open(config: MempoolConfig) -> Result<Self, MempoolError> {
+        let wal = config.wal.clone();
+        let mut pool = Self::new(config);
+        let Some(wal) = wal else {
+            return Ok(pool);
+        };
+        let (mut wal, replayed) = MempoolWal::open(wal)?;
+        for tx in replayed {
+            let _ = pool.insert(tx);
+        }
+        wal.compact(pool.pending_in_order())?;
+        pool.wal = Some(wal);
+        Ok(pool)
+    }
+
+    fn pending_in_order(&self) -> impl Iterator<Item = &Transaction> {
+        self.queue
+            .iter()
+            .filter_map(|id| self.txs.get(id).map(|entry| &entry.tx))
+    }
+
+    /// Log the removal of `ids`, compacting the log when due. A failed
+    /// write only means the transactions are replayed after a restart,
+    /// where the usual nonce checks drop them if stale.
+    fn log_removals(&mut self, ids: &[TxId]) {
+        let Some(mut wal) = self.wal.take() else {
+            return;
+        };
+        if let Ok(true) = wal.append_removals(ids) {
+            if let Err(e) = wal.compact(self.pending_in_order()) {
+                warn!(error = %e, "mempool wal compaction failed");
+            }
         }
+        self.wal = Some(wal);
+    }
+
+    /// Capacity and replacement checks for a new, valid transaction.
+    /// Returns the pending transaction it would replace or evict, if any.
+    fn admit(&self, tx: &Transaction) -> Result<Option<TxId>, MempoolError> {
+        let Some(existing_id) = self.by_slot.get(&(tx.from, tx.nonce)).copied() else {
+            if self.txs.len() < self.config.max_tx {
+                return Ok(None);
+            }
+            return match self.config.overflow {
+                OverflowPolicy::Reject => Err(MempoolError::Full),
+                OverflowPolicy::EvictLowestFee => self
+                    .cheapest()
+                    .filter(|(_, price)| tx.gas_price > *price)
+                    .map(|(id, _)| Some(id))
+                    .ok_or(MempoolError::Full),
+            };
+        };
+        let existing_price = self.txs[&existing_id].tx.gas_price;
+        let required =
+            existing_price.saturating_mul(100 + self.config.replacement_bump_percent) / 100;
+        let required = required.max(existing_price.saturating_add(1));
+        if tx.gas_price < required {
+            return Err(MempoolError::ReplacementUnderpriced {
+                offered: tx.gas_price,
+                required,
+            });
+        }
+        Ok(Some(existing_id))
+    }
+
+    /// The pending transaction with the lowest gas price and its price,
+    /// preferring the latest arrival among equals.
+    fn cheapest(&self) -> Option<(TxId, u64)> {
+        self.queue
+            .iter()
+            .rev()
+            .filter_map(|id| self.txs.get(id).map(|entry| (*id, entry.tx.gas_price)))
+            .min_by_key(|(_, price)| *price)
+    }
+
+    /// Remove a single transaction from every index.
+    fn evict(&mut self, id: &TxId) -> Option<Transaction> {
+        let tx = self.txs.remove(id)?.tx;
+        self.forget_in_namespace(tx.namespace, |tid| tid == id);
+        self.by_slot.remove(&(tx.from, tx.nonce));
+        self.queue.retain(|tid| tid != id);
+        Some(tx)
+    }
+
+    /// Drop the ids matching `removed` from the index of `namespace`, and the
+    /// index itself once it is empty.
+    fn forget_in_namespace(&mut self, namespace: NamespaceId, removed: impl Fn(&TxId) -> bool) {
+        if let Some(list) = self.by_namespace.get_mut(&namespace) {
+            list.retain(|tid| !removed(tid));
+            if list.is_empty() {
+                self.by_namespace.remove(&namespace);
+            }
+        }
+    }
+
+    /// Remove all transactions that have been pending for longer than
+    /// `ttl`, returning how many were evicted.
+    pub fn evict_expired(&mut self, ttl: Duration) -> usize {
+        self.evict_expired_at(ttl, Instant::now())
+    }
+
+    /// Like [`SimpleMempool::evict_expired`], but measured against the
+    /// supplied `now` instead of the system clock.
+    pub fn evict_expired_at(&mut self, ttl: Duration, now: Instant) -> usize {
+        let expired: Vec<TxId> = self
+            .txs
+            .iter()
+            .filter(|(_, entry)| now.saturating_duration_since(entry.received_at) > ttl)
+            .map(|(id, _)| *id)
+            .collect();
+        if !expired.is_empty() {
+            self.remove_committed(&expired);
+        }
+        expired.len()
     }
 }
 
@@ -59,66 +492,135 @@ impl Default for SimpleMempool {
 
 impl Mempool for SimpleMempool {
     fn insert(&mut self, tx: Transaction) -> Result<TxId, MempoolError> {
-        if self.txs.len() >= self.config.max_tx {
-            return Err(MempoolError::Full);
-        }
-
+        check_tx(&self.config, &tx).inspect_err(record_rejection)?;
         let id = tx.id();
         if self.txs.contains_key(&id) {
             return Ok(id);
         }
+        let replaced = self.admit(&tx).inspect_err(record_rejection)?;
+        if let Some(wal) = &mut self.wal {
+            // Logged before anything changes, so a failed write leaves the
+            // mempool as it was.
+            wal.append_insert(&tx).inspect_err(record_rejection)?;
+        }
+        if let Some(replaced) = replaced {
+            self.evict(&replaced);
+            self.log_removals(&[replaced]);
+        }
 
+        let namespace = tx.namespace;
+        let size_bytes = tx.size_bytes();
+        self.by_slot.insert((tx.from, tx.nonce), id);
         self.queue.push_back(id);
-        self.by_namespace
-            .entry(tx.namespace)
-            .or_insert_with(Vec::new)
-            .push(id);
-        self.txs.insert(id, tx);
+        self.by_namespace.entry(tx.namespace).or_default().push(id);
+        self.txs.insert(
+            id,
+            PendingTx {
+                tx,
+                received_at: Instant::now(),
+            },
+        );
 
         sequencer_metrics::record_tx_submitted();
+        sequencer_metrics::record_namespace_tx_submitted(&namespace.name(&self.config.namespaces));
+        sequencer_metrics::record_tx_size_bytes(size_bytes);
         sequencer_metrics::record_mempool_size(self.txs.len());
 
         Ok(id)
     }
 
+    fn check(&self, tx: &Transaction) -> Result<(), MempoolError> {
+        check_tx(&self.config, tx)?;
+        if self.txs.contains_key(&tx.id()) {
+            return Ok(());
+        }
+        self.admit(tx).map(|_| ())
+    }
+
+    fn contains(&self, id: &TxId) -> bool {
+        self.txs.contains_key(id)
+    }
+
+    fn get(&self, id: &TxId) -> Option<Transaction> {
+        self.txs.get(id).map(|entry| entry.tx.clone())
+    }
+
     fn get_batch(&self, max: usize) -> Vec<(TxId, Transaction)> {
         if max == 0 || self.txs.is_empty() {
             return Vec::new();
         }
 
-        let mut candidates: Vec<(TxId, &Transaction, usize)> = Vec::with_capacity(self.txs.len());
+        let mut candidates: Vec<(TxId, &Transaction, u64)> = Vec::with_capacity(self.txs.len());
 
         for (pos, id) in self.queue.iter().enumerate() {
-            if let Some(tx) = self.txs.get(id) {
-                candidates.push((*id, tx, pos));
+            if let Some(entry) = self.txs.get(id) {
+                candidates.push((*id, &entry.tx, pos as u64));
             }
         }
 
-        candidates.sort_by(|a, b| {
-            let gas_ord = b.1.gas_price.cmp(&a.1.gas_price);
-            if gas_ord != std::cmp::Ordering::Equal {
-                return gas_ord;
-            }
-            a.2.cmp(&b.2)
-        });
+        order_batch(
+            self.config.ordering,
+            &self.rng,
+            candidates,
+            |(_, tx, pos)| (tx.priority, tx.gas_price, *pos),
+            max,
+        )
+        .into_iter()
+        .map(|(id, tx, _)| (id, tx.clone()))
+        .collect()
+    }
 
-        candidates
-            .into_iter()
-            .take(max)
-            .map(|(id, tx, _)| (id, tx.clone()))
-            .collect()
+    fn get_batch_for_namespace(&self, ns: NamespaceId, max: usize) -> Vec<(TxId, Transaction)> {
+        let Some(ids) = self.by_namespace.get(&ns) else {
+            return Vec::new();
+        };
+        if max == 0 {
+            return Vec::new();
+        }
+
+        // The namespace index is in arrival order, like the queue.
+        let candidates: Vec<(TxId, &Transaction, u64)> = ids
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, id)| self.txs.get(id).map(|entry| (*id, &entry.tx, pos as u64)))
+            .collect();
+
+        order_batch(
+            self.config.ordering,
+            &self.rng,
+            candidates,
+            |(_, tx, pos)| (tx.priority, tx.gas_price, *pos),
+            max,
+        )
+        .into_iter()
+        .map(|(id, tx, _)| (id, tx.clone()))
+        .collect()
     }
 
     fn remove_committed(&mut self, ids: &[TxId]) {
+        // Collect the removals first so each index is filtered in one pass,
+        // instead of once per committed id.
+        let mut removed = HashSet::with_capacity(ids.len());
+        let mut namespaces = HashSet::new();
         for id in ids {
-            if let Some(tx) = self.txs.remove(id) {
-                if let Some(list) = self.by_namespace.get_mut(&tx.namespace) {
-                    list.retain(|tid| tid != id);
-                }
+            if let Some(PendingTx { tx, .. }) = self.txs.remove(id) {
+                self.by_slot.remove(&(tx.from, tx.nonce));
+                namespaces.insert(tx.namespace);
+                removed.insert(*id);
             }
         }
-        self.queue.retain(|id| !ids.contains(id));
+        for namespace in namespaces {
+            self.forget_in_namespace(namespace, |id| removed.contains(id));
+        }
+        self.queue.retain(|id| !removed.contains(id));
         sequencer_metrics::record_mempool_size(self.txs.len());
+        if !removed.is_empty() {
+            self.log_removals(&removed.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&TxId, &Transaction)> + '_> {
+        Box::new(self.txs.iter().map(|(id, pending)| (id, &pending.tx)))
     }
 
     fn len(&self) -> usize {
@@ -126,17 +628,174 @@ impl Mempool for SimpleMempool {
     }
 }
 
+/// Async mempool interface. Methods take `&self` so an implementation can
+/// be shared behind an `Arc` and handle concurrent callers internally.
+pub trait AsyncMempool: Send + Sync {
+    fn insert(&self, tx: Transaction) -> impl Future<Output = Result<TxId, MempoolError>> + Send;
+    fn get_batch(&self, max: usize) -> impl Future<Output = Vec<(TxId, Transaction)>> + Send;
+    fn remove_committed(&self, ids: &[TxId]) -> impl Future<Output = ()> + Send;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Transactions belonging to a single namespace. `seq` records the global
+/// arrival order so batches stay FIFO across shards for equal gas prices.
+#[derive(Debug, Default)]
+struct Shard {
+    txs: HashMap<TxId, (Transaction, u64)>,
+}
+
+/// An [`AsyncMempool`] that keeps one lock per namespace, so inserts into
+/// different namespaces do not contend with each other.
+#[derive(Debug)]
+pub struct ShardedMempool {
+    config: MempoolConfig,
+    shards: RwLock<HashMap<NamespaceId, Arc<Mutex<Shard>>>>,
+    len: AtomicUsize,
+    next_seq: AtomicU64,
+    rng: BatchRng,
+}
+
+impl ShardedMempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self {
+            rng: BatchRng::new(config.ordering),
+            config,
+            shards: RwLock::new(HashMap::new()),
+            len: AtomicUsize::new(0),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    async fn shard(&self, namespace: NamespaceId) -> Arc<Mutex<Shard>> {
+        if let Some(shard) = self.shards.read().await.get(&namespace) {
+            return Arc::clone(shard);
+        }
+        let mut shards = self.shards.write().await;
+        Arc::clone(shards.entry(namespace).or_default())
+    }
+
+    async fn all_shards(&self) -> Vec<Arc<Mutex<Shard>>> {
+        self.shards.read().await.values().cloned().collect()
+    }
+}
+
+impl Default for ShardedMempool {
+    fn default() -> Self {
+        Self::new(MempoolConfig::default())
+    }
+}
+
+impl AsyncMempool for ShardedMempool {
+    async fn insert(&self, tx: Transaction) -> Result<TxId, MempoolError> {
+        check_tx(&self.config, &tx).inspect_err(record_rejection)?;
+        let id = tx.id();
+        let shard = self.shard(tx.namespace).await;
+        let mut shard = shard.lock().await;
+        if shard.txs.contains_key(&id) {
+            return Ok(id);
+        }
+
+        // Reserve a slot up front so concurrent inserts cannot overshoot
+        // the configured capacity.
+        let reserved = self
+            .len
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |len| {
+                (len < self.config.max_tx).then_some(len + 1)
+            });
+        let Ok(prev_len) = reserved else {
+            record_rejection(&MempoolError::Full);
+            return Err(MempoolError::Full);
+        };
+
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let namespace = tx.namespace;
+        let size_bytes = tx.size_bytes();
+        shard.txs.insert(id, (tx, seq));
+
+        sequencer_metrics::record_tx_submitted();
+        sequencer_metrics::record_namespace_tx_submitted(&namespace.name(&self.config.namespaces));
+        sequencer_metrics::record_tx_size_bytes(size_bytes);
+        sequencer_metrics::record_mempool_size(prev_len + 1);
+
+        Ok(id)
+    }
+
+    async fn get_batch(&self, max: usize) -> Vec<(TxId, Transaction)> {
+        if max == 0 {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<(TxId, Transaction, u64)> = Vec::new();
+        for shard in self.all_shards().await {
+            let shard = shard.lock().await;
+            candidates.extend(
+                shard
+                    .txs
+                    .iter()
+                    .map(|(id, (tx, seq))| (*id, tx.clone(), *seq)),
+            );
+        }
+
+        order_batch(
+            self.config.ordering,
+            &self.rng,
+            candidates,
+            |(_, tx, seq)| (tx.priority, tx.gas_price, *seq),
+            max,
+        )
+        .into_iter()
+        .map(|(id, tx, _)| (id, tx))
+        .collect()
+    }
+
+    async fn remove_committed(&self, ids: &[TxId]) {
+        let mut removed = 0;
+        for shard in self.all_shards().await {
+            let mut shard = shard.lock().await;
+            for id in ids {
+                if shard.txs.remove(id).is_some() {
+                    removed += 1;
+                }
+            }
+        }
+        let len = self.len.fetch_sub(removed, Ordering::SeqCst) - removed;
+        sequencer_metrics::record_mempool_size(len);
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A transaction from a sender of its own per namespace, so nonces
+    /// only collide within one namespace.
     fn make_tx(namespace: u64, nonce: u64) -> Transaction {
         Transaction {
+            chain_id: 0,
+            from: Address([namespace as u8; 20]),
             namespace: NamespaceId(namespace),
             gas_price: 1,
             nonce,
+            valid_until_height: None,
+            priority: false,
             payload: vec![],
             signature: vec![],
+            pow_nonce: 0,
+        }
+    }
+
+    fn priced_tx(namespace: u64, nonce: u64, gas_price: u64) -> Transaction {
+        Transaction {
+            gas_price,
+            ..make_tx(namespace, nonce)
         }
     }
 
@@ -170,14 +829,284 @@ mod tests {
         assert_eq!(remaining, vec![id2]);
     }
 
+    /// Every index names exactly the pending transactions.
+    fn assert_indexes_consistent(mp: &SimpleMempool) {
+        let pending: HashSet<TxId> = mp.txs.keys().copied().collect();
+        assert_eq!(mp.queue.len(), pending.len());
+        assert!(mp.queue.iter().all(|id| pending.contains(id)));
+        let mut indexed = 0;
+        for (ns, ids) in &mp.by_namespace {
+            assert!(!ids.is_empty(), "empty index left for {ns:?}");
+            assert!(ids.iter().all(|id| mp.txs[id].tx.namespace == *ns));
+            indexed += ids.len();
+        }
+        assert_eq!(indexed, pending.len());
+        assert_eq!(mp.by_slot.len(), pending.len());
+        assert!(mp.by_slot.values().all(|id| pending.contains(id)));
+    }
+
+    #[test]
+    fn committing_a_large_block_keeps_indexes_consistent() {
+        let mut mp = SimpleMempool::default();
+        let ids: Vec<TxId> = (0..3_000)
+            .map(|i| mp.insert(make_tx(i % 4, i)).unwrap())
+            .collect();
+
+        // Commit every namespace-0 tx and a third of the rest, in one block.
+        let committed: Vec<TxId> = ids
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 4 == 0 || i % 3 == 0)
+            .map(|(_, id)| *id)
+            .collect();
+        assert!(committed.len() > 1_000);
+        let start = Instant::now();
+        mp.remove_committed(&committed);
+        // Only a coarse bound, to keep the test stable on slow machines.
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(mp.len(), ids.len() - committed.len());
+        assert!(committed.iter().all(|id| !mp.contains(id)));
+        assert!(!mp.by_namespace.contains_key(&NamespaceId(0)));
+        assert_indexes_consistent(&mp);
+
+        // Committing ids that are no longer pending changes nothing.
+        mp.remove_committed(&committed);
+        assert_eq!(mp.len(), ids.len() - committed.len());
+        let rest: Vec<TxId> = ids.iter().filter(|id| mp.contains(id)).copied().collect();
+        mp.remove_committed(&rest);
+        assert!(mp.is_empty());
+        assert!(mp.by_namespace.is_empty());
+        assert_indexes_consistent(&mp);
+    }
+
+    #[test]
+    fn contains_and_get_find_pending_txs_only() {
+        let mut mp = SimpleMempool::default();
+        let tx = make_tx(1, 1);
+        let id = mp.insert(tx.clone()).unwrap();
+        let absent = make_tx(2, 1).id();
+
+        assert!(mp.contains(&id));
+        assert_eq!(mp.get(&id), Some(tx));
+        assert!(!mp.contains(&absent));
+        assert_eq!(mp.get(&absent), None);
+
+        mp.remove_committed(&[id]);
+        assert!(!mp.contains(&id));
+        assert_eq!(mp.get(&id), None);
+    }
+
+    #[test]
+    fn namespace_batch_returns_only_that_namespace_in_fee_order() {
+        let mut mp = SimpleMempool::default();
+        let mut insert = |namespace, nonce, fee| {
+            let mut tx = make_tx(namespace, nonce);
+            tx.gas_price = fee;
+            mp.insert(tx).unwrap()
+        };
+        let a = insert(1, 0, 5);
+        insert(2, 0, 50);
+        let b = insert(1, 1, 1);
+        let c = insert(1, 2, 5);
+        insert(2, 1, 7);
+        let d = insert(1, 3, 9);
+
+        let ids = |batch: Vec<(TxId, Transaction)>| -> Vec<TxId> {
+            batch.into_iter().map(|(id, _)| id).collect()
+        };
+        let batch = mp.get_batch_for_namespace(NamespaceId(1), 10);
+        assert!(batch.iter().all(|(_, tx)| tx.namespace == NamespaceId(1)));
+        assert_eq!(ids(batch), vec![d, a, c, b]);
+        assert_eq!(
+            ids(mp.get_batch_for_namespace(NamespaceId(1), 2)),
+            vec![d, a]
+        );
+        assert!(mp.get_batch_for_namespace(NamespaceId(3), 10).is_empty());
+
+        mp.remove_committed(&[d]);
+        assert_eq!(
+            ids(mp.get_batch_for_namespace(NamespaceId(1), 10)),
+            vec![a, c, b]
+        );
+    }
+
     #[test]
     fn mempool_respects_capacity_limit() {
-        let mut mp = SimpleMempool::new(MempoolConfig { max_tx: 1 });
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            max_tx: 1,
+            ..Default::default()
+        });
         mp.insert(make_tx(1, 1)).unwrap();
         let res = mp.insert(make_tx(1, 2));
         assert!(matches!(res, Err(MempoolError::Full)));
     }
 
+    #[test]
+    fn full_mempool_evicts_a_cheaper_transaction() {
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            max_tx: 2,
+            overflow: OverflowPolicy::EvictLowestFee,
+            ..Default::default()
+        });
+        let mid = mp.insert(priced_tx(1, 0, 5)).unwrap();
+        let cheap = mp.insert(priced_tx(2, 0, 2)).unwrap();
+
+        let rich = mp.insert(priced_tx(3, 0, 9)).unwrap();
+        assert_eq!(mp.len(), 2);
+        assert!(!mp.contains(&cheap));
+        assert!(mp.contains(&mid) && mp.contains(&rich));
+        assert!(mp.get_batch_for_namespace(NamespaceId(2), 10).is_empty());
+        let ids: Vec<TxId> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![rich, mid]);
+        // The evicted slot is free again.
+        assert!(mp.check(&priced_tx(2, 0, 6)).is_ok());
+    }
+
+    #[test]
+    fn full_mempool_rejects_a_newcomer_that_pays_no_more() {
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            max_tx: 2,
+            overflow: OverflowPolicy::EvictLowestFee,
+            ..Default::default()
+        });
+        mp.insert(priced_tx(1, 0, 5)).unwrap();
+        mp.insert(priced_tx(2, 0, 2)).unwrap();
+
+        for price in [1, 2] {
+            assert!(matches!(
+                mp.insert(priced_tx(3, 0, price)),
+                Err(MempoolError::Full)
+            ));
+        }
+        assert_eq!(mp.len(), 2);
+    }
+
+    #[test]
+    fn rejections_are_counted_by_reason() {
+        let metrics = sequencer_metrics::reset_metrics_for_test();
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            max_tx: 1,
+            ..Default::default()
+        });
+        mp.insert(make_tx(1, 1)).unwrap();
+        assert!(mp.insert(make_tx(1, 2)).is_err());
+        assert!(mp.insert(make_tx(1, 3)).is_err());
+        let mut foreign = make_tx(1, 4);
+        foreign.chain_id = 9;
+        assert!(mp.insert(foreign).is_err());
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("sequencer_tx_rejected_total{reason=\"full\"} 2"));
+        assert!(rendered.contains("sequencer_tx_rejected_total{reason=\"wrong_chain\"} 1"));
+    }
+
+    /// Raise `tx.pow_nonce` until its proof of work has `bits` leading
+    /// zero bits.
+    fn mine(mut tx: Transaction, bits: u32) -> Transaction {
+        while tx.pow_bits() < bits {
+            tx.pow_nonce += 1;
+        }
+        tx
+    }
+
+    #[test]
+    fn anti_spam_gate_checks_fee_floor_and_work() {
+        let gated = |min_gas_price, pow_difficulty_bits| {
+            SimpleMempool::new(MempoolConfig {
+                anti_spam: AntiSpamConfig {
+                    min_gas_price,
+                    pow_difficulty_bits,
+                },
+                ..Default::default()
+            })
+        };
+        let mut mp = gated(Some(5), Some(8));
+
+        let cheap = mine(priced_tx(1, 0, 4), 8);
+        assert!(matches!(
+            mp.insert(cheap),
+            Err(MempoolError::SpamGate(SpamGateError::BelowFeeFloor {
+                offered: 4,
+                floor: 5
+            }))
+        ));
+        let mut lazy = priced_tx(1, 0, 5);
+        while lazy.pow_bits() >= 8 {
+            lazy.pow_nonce += 1;
+        }
+        assert!(matches!(
+            mp.insert(lazy),
+            Err(MempoolError::SpamGate(SpamGateError::InsufficientWork {
+                required: 8,
+                ..
+            }))
+        ));
+        assert!(mp.is_empty());
+        mp.insert(mine(priced_tx(1, 0, 5), 8)).unwrap();
+
+        // Each check can be enabled on its own.
+        let mut lazy = priced_tx(1, 0, 5);
+        while lazy.pow_bits() > 0 {
+            lazy.pow_nonce += 1;
+        }
+        gated(Some(5), None).insert(lazy).unwrap();
+        gated(None, Some(8))
+            .insert(mine(priced_tx(1, 0, 1), 8))
+            .unwrap();
+    }
+
+    #[test]
+    fn iter_yields_every_pending_tx_once() {
+        let mut mp = SimpleMempool::default();
+        let mut expected = Vec::new();
+        for namespace in 1..=3 {
+            for nonce in 0..3 {
+                expected.push(mp.insert(make_tx(namespace, nonce)).unwrap());
+            }
+        }
+        // Duplicates, replacements and commits are reflected.
+        mp.insert(make_tx(1, 0)).unwrap();
+        let replaced = expected.remove(1);
+        expected.push(mp.insert(priced_tx(1, 1, 100)).unwrap());
+        let committed = expected.remove(0);
+        mp.remove_committed(&[committed]);
+
+        let mut seen: Vec<TxId> = mp
+            .iter()
+            .map(|(id, tx)| {
+                assert_eq!(*id, tx.id());
+                *id
+            })
+            .collect();
+        assert!(!seen.contains(&replaced));
+        seen.sort_by_key(|id| id.0 .0);
+        expected.sort_by_key(|id| id.0 .0);
+        assert_eq!(seen, expected);
+        assert_eq!(mp.iter().count(), mp.len());
+    }
+
+    #[test]
+    fn tx_sizes_are_recorded() {
+        let metrics = sequencer_metrics::reset_metrics_for_test();
+        let mut mp = SimpleMempool::default();
+        let mut total = 0;
+        for (nonce, size) in [(0, 0), (1, 100), (2, 1_000)] {
+            let mut tx = make_tx(1, nonce);
+            tx.payload = vec![0; size];
+            total += tx.size_bytes();
+            mp.insert(tx).unwrap();
+        }
+        // A duplicate is not admitted again, so it is not recorded.
+        let mut duplicate = make_tx(1, 2);
+        duplicate.payload = vec![0; 1_000];
+        mp.insert(duplicate).unwrap();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("sequencer_tx_size_bytes_count 3"));
+        assert!(rendered.contains(&format!("sequencer_tx_size_bytes_sum {total}")));
+    }
+
     #[test]
     fn higher_gas_price_is_prioritized() {
         let mut mp = SimpleMempool::default();
@@ -195,4 +1124,330 @@ mod tests {
         assert_eq!(batch[0].0, id_high);
         assert_eq!(batch[1].0, id_low);
     }
+
+    #[test]
+    fn higher_fee_replaces_pending_tx_with_same_nonce() {
+        let mut mp = SimpleMempool::default();
+
+        let mut original = make_tx(1, 7);
+        original.gas_price = 100;
+        let old_id = mp.insert(original).unwrap();
+
+        let mut bumped = make_tx(1, 7);
+        bumped.gas_price = 110;
+        bumped.payload = b"bumped".to_vec();
+        let new_id = mp.insert(bumped).unwrap();
+
+        assert_ne!(old_id, new_id);
+        assert_eq!(mp.len(), 1);
+        let batch: Vec<_> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![new_id]);
+    }
+
+    #[test]
+    fn insufficient_fee_bump_is_rejected() {
+        let mut mp = SimpleMempool::default();
+
+        let mut original = make_tx(1, 7);
+        original.gas_price = 100;
+        let old_id = mp.insert(original).unwrap();
+
+        let mut bumped = make_tx(1, 7);
+        bumped.gas_price = 105;
+        bumped.payload = b"bumped".to_vec();
+        let res = mp.insert(bumped);
+        assert!(matches!(
+            res,
+            Err(MempoolError::ReplacementUnderpriced {
+                offered: 105,
+                required: 110
+            })
+        ));
+
+        let batch: Vec<_> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![old_id]);
+    }
+
+    #[test]
+    fn senders_sharing_a_namespace_do_not_replace_each_other() {
+        let mut mp = SimpleMempool::default();
+
+        let alice = priced_tx(1, 7, 100);
+        let bob = Transaction {
+            from: Address([0xb0; 20]),
+            ..priced_tx(1, 7, 200)
+        };
+        let alice_id = mp.insert(alice).unwrap();
+        let bob_id = mp.insert(bob).unwrap();
+
+        assert_eq!(mp.len(), 2);
+        let batch: Vec<_> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![bob_id, alice_id]);
+    }
+
+    #[test]
+    fn replacement_is_allowed_when_mempool_is_full() {
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            max_tx: 1,
+            ..Default::default()
+        });
+        mp.insert(make_tx(1, 1)).unwrap();
+
+        let mut bumped = make_tx(1, 1);
+        bumped.gas_price = 2;
+        let new_id = mp.insert(bumped).unwrap();
+
+        let batch: Vec<_> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![new_id]);
+    }
+
+    #[test]
+    fn transactions_for_other_chains_are_rejected() {
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            expected_chain_id: 7,
+            ..Default::default()
+        });
+
+        let mut tx = make_tx(1, 1);
+        tx.chain_id = 7;
+        mp.insert(tx).unwrap();
+
+        let mut foreign = make_tx(1, 2);
+        foreign.chain_id = 8;
+        assert!(matches!(
+            mp.insert(foreign),
+            Err(MempoolError::WrongChain {
+                expected: 7,
+                got: 8
+            })
+        ));
+        assert_eq!(mp.len(), 1);
+    }
+
+    fn mempool_with_rules(validation: TxValidationRules) -> SimpleMempool {
+        SimpleMempool::new(MempoolConfig {
+            validation,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected() {
+        let mut mp = mempool_with_rules(TxValidationRules {
+            max_payload_bytes: 4,
+            ..Default::default()
+        });
+
+        let mut tx = make_tx(1, 1);
+        tx.payload = vec![0; 5];
+        assert!(matches!(
+            mp.insert(tx),
+            Err(MempoolError::Invalid(TxValidationError::PayloadTooLarge {
+                len: 5,
+                max: 4
+            }))
+        ));
+        assert!(mp.is_empty());
+    }
+
+    #[test]
+    fn zero_gas_price_is_rejected_unless_allowed() {
+        let mut free = make_tx(1, 1);
+        free.gas_price = 0;
+
+        let mut strict = SimpleMempool::default();
+        assert!(matches!(
+            strict.insert(free.clone()),
+            Err(MempoolError::Invalid(TxValidationError::ZeroGasPrice))
+        ));
+
+        let mut lenient = mempool_with_rules(TxValidationRules {
+            allow_zero_gas_price: true,
+            ..Default::default()
+        });
+        lenient.insert(free).unwrap();
+    }
+
+    #[test]
+    fn disallowed_namespace_is_rejected() {
+        let mut mp = mempool_with_rules(TxValidationRules {
+            allowed_namespaces: Some(vec![NamespaceId(1)]),
+            ..Default::default()
+        });
+
+        mp.insert(make_tx(1, 1)).unwrap();
+        assert!(matches!(
+            mp.insert(make_tx(2, 1)),
+            Err(MempoolError::Invalid(
+                TxValidationError::NamespaceNotAllowed(2)
+            ))
+        ));
+        assert_eq!(mp.len(), 1);
+    }
+
+    #[test]
+    fn expired_transactions_are_evicted() {
+        let mut mp = SimpleMempool::default();
+        let ttl = Duration::from_secs(60);
+
+        let id_old = mp.insert(make_tx(1, 1)).unwrap();
+        assert_eq!(mp.evict_expired(ttl), 0);
+
+        let later = Instant::now() + Duration::from_secs(120);
+        let id_new = mp.insert(make_tx(2, 1)).unwrap();
+        let ids: Vec<_> = mp.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![id_old, id_new]);
+
+        assert_eq!(mp.evict_expired_at(ttl, later), 2);
+        assert!(mp.is_empty());
+        assert!(mp.get_batch(10).is_empty());
+
+        // Indexes are cleared too, so the same nonce can be reused.
+        mp.insert(make_tx(1, 1)).unwrap();
+        assert_eq!(mp.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn sharded_mempool_handles_concurrent_inserts() {
+        let mp = Arc::new(ShardedMempool::default());
+
+        let mut handles = Vec::new();
+        for namespace in 0..8u64 {
+            let mp = Arc::clone(&mp);
+            handles.push(tokio::spawn(async move {
+                let mut ids = Vec::new();
+                for nonce in 0..50 {
+                    ids.push(mp.insert(make_tx(namespace, nonce)).await.unwrap());
+                }
+                ids
+            }));
+        }
+
+        let mut all_ids = Vec::new();
+        for handle in handles {
+            all_ids.extend(handle.await.unwrap());
+        }
+
+        assert_eq!(AsyncMempool::len(&*mp), 400);
+        assert_eq!(AsyncMempool::get_batch(&*mp, 1_000).await.len(), 400);
+
+        AsyncMempool::remove_committed(&*mp, &all_ids[..100]).await;
+        assert_eq!(AsyncMempool::len(&*mp), 300);
+        let remaining = AsyncMempool::get_batch(&*mp, 1_000).await;
+        assert!(remaining.iter().all(|(id, _)| !all_ids[..100].contains(id)));
+    }
+
+    #[tokio::test]
+    async fn sharded_mempool_respects_capacity_and_order() {
+        let mp = ShardedMempool::new(MempoolConfig {
+            max_tx: 2,
+            ..Default::default()
+        });
+
+        let mut tx_high = make_tx(2, 1);
+        tx_high.gas_price = 10;
+        let id_low = mp.insert(make_tx(1, 1)).await.unwrap();
+        let id_high = mp.insert(tx_high).await.unwrap();
+        assert!(matches!(
+            mp.insert(make_tx(3, 1)).await,
+            Err(MempoolError::Full)
+        ));
+
+        let batch: Vec<_> = AsyncMempool::get_batch(&mp, 10)
+            .await
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(batch, vec![id_high, id_low]);
+    }
+
+    /// Crafted pool: arrival order a(fee 5), b(fee 1), c(fee 5), d(fee 9).
+    fn ordering_pool(ordering: OrderingStrategy) -> (SimpleMempool, [TxId; 4]) {
+        let mut mp = SimpleMempool::new(MempoolConfig {
+            ordering,
+            ..Default::default()
+        });
+        let ids = [5, 1, 5, 9].map(|fee| {
+            let mut tx = make_tx(mp.len() as u64, 0);
+            tx.gas_price = fee;
+            mp.insert(tx).unwrap()
+        });
+        (mp, ids)
+    }
+
+    fn batch_ids(mp: &SimpleMempool, max: usize) -> Vec<TxId> {
+        mp.get_batch(max).into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn gas_price_then_fifo_ordering() {
+        let (mp, [a, b, c, d]) = ordering_pool(OrderingStrategy::GasPriceThenFifo);
+        assert_eq!(batch_ids(&mp, 10), vec![d, a, c, b]);
+        assert_eq!(batch_ids(&mp, 2), vec![d, a]);
+    }
+
+    #[test]
+    fn fifo_ordering_ignores_fees() {
+        let (mp, [a, b, c, d]) = ordering_pool(OrderingStrategy::Fifo);
+        assert_eq!(batch_ids(&mp, 10), vec![a, b, c, d]);
+        assert_eq!(batch_ids(&mp, 3), vec![a, b, c]);
+    }
+
+    #[test]
+    fn random_ordering_is_seeded_and_weighted_by_fee() {
+        let seeded = OrderingStrategy::RandomWeightedByFee { seed: Some(42) };
+        let (first, ids) = ordering_pool(seeded);
+        let (second, _) = ordering_pool(seeded);
+        let batch = batch_ids(&first, 10);
+        assert_eq!(batch, batch_ids(&second, 10));
+        let mut sorted = batch.clone();
+        sorted.sort_by_key(|id| id.0 .0);
+        let mut expected = ids.to_vec();
+        expected.sort_by_key(|id| id.0 .0);
+        assert_eq!(sorted, expected, "every tx is returned exactly once");
+
+        // d pays 9 of the 20 total, so it should lead roughly 45% of
+        // batches, and b (1 of 20) roughly 5%.
+        let [_, b, _, d] = ids;
+        let (mp, _) = ordering_pool(OrderingStrategy::RandomWeightedByFee { seed: Some(7) });
+        let leaders: Vec<TxId> = (0..2_000).map(|_| batch_ids(&mp, 1)[0]).collect();
+        let share = |id: TxId| leaders.iter().filter(|l| **l == id).count();
+        assert!((700..1_100).contains(&share(d)), "d led {}", share(d));
+        assert!(share(b) < 200, "b led {}", share(b));
+    }
+
+    #[test]
+    fn priority_transactions_lead_the_batch_in_arrival_order() {
+        let (mut mp, [a, b, c, d]) = ordering_pool(OrderingStrategy::GasPriceThenFifo);
+        let urgent = |namespace, gas_price| Transaction {
+            priority: true,
+            ..priced_tx(namespace, 0, gas_price)
+        };
+        let first = mp.insert(urgent(10, 1)).unwrap();
+        let second = mp.insert(urgent(11, 3)).unwrap();
+
+        assert_eq!(batch_ids(&mp, 10), vec![first, second, d, a, c, b]);
+        assert_eq!(batch_ids(&mp, 1), vec![first]);
+    }
+
+    #[tokio::test]
+    async fn sharded_mempool_honours_fifo_ordering() {
+        let mp = ShardedMempool::new(MempoolConfig {
+            ordering: OrderingStrategy::Fifo,
+            ..Default::default()
+        });
+        let mut ids = Vec::new();
+        for (namespace, fee) in [(1, 1), (2, 10), (3, 5)] {
+            let mut tx = make_tx(namespace, 0);
+            tx.gas_price = fee;
+            ids.push(mp.insert(tx).await.unwrap());
+        }
+
+        let batch: Vec<_> = AsyncMempool::get_batch(&mp, 10)
+            .await
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(batch, ids);
+    }
 }
@@ -0,0 +1,224 @@
+//! A [`SimpleMempool`] whose pending transactions are mirrored to a sled
+//! tree, so they survive a node restart.
+//!
+//! Entries are keyed by a big-endian arrival sequence number, so replaying
+//! the tree restores the original FIFO order. Pending time restarts from
+//! the moment of replay.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use types::{NamespaceId, Transaction, TxId};
+
+use crate::{record_rejection, Mempool, MempoolConfig, MempoolError, SimpleMempool};
+
+#[derive(Debug)]
+pub struct PersistentMempool {
+    inner: SimpleMempool,
+    tree: sled::Tree,
+    /// Sequence number each pending transaction is stored under.
+    keys: HashMap<TxId, u64>,
+    next_seq: u64,
+}
+
+fn storage_error(e: impl std::fmt::Display) -> MempoolError {
+    MempoolError::Storage(e.to_string())
+}
+
+impl PersistentMempool {
+    /// Load the transactions stored in `tree` into a fresh mempool. Entries
+    /// the mempool no longer accepts, e.g. after a chain id or capacity
+    /// change, are dropped from the tree.
+    pub fn open(config: MempoolConfig, tree: sled::Tree) -> Result<Self, MempoolError> {
+        let mut pool = Self {
+            inner: SimpleMempool::new(config),
+            tree,
+            keys: HashMap::new(),
+            next_seq: 0,
+        };
+
+        let mut rejected = Vec::new();
+        for entry in pool.tree.iter() {
+            let (key, value) = entry.map_err(storage_error)?;
+            let seq = u64::from_be_bytes(
+                key.as_ref()
+                    .try_into()
+                    .map_err(|_| storage_error("malformed mempool key"))?,
+            );
+            let tx = Transaction::decode(&value).map_err(storage_error)?;
+            pool.next_seq = seq + 1;
+            match pool.inner.insert(tx) {
+                Ok(id) => {
+                    pool.keys.insert(id, seq);
+                }
+                Err(_) => rejected.push(key),
+            }
+        }
+        for key in rejected {
+            pool.tree.remove(key).map_err(storage_error)?;
+        }
+        Ok(pool)
+    }
+
+    /// Write all buffered changes to disk.
+    pub fn flush(&self) -> Result<(), MempoolError> {
+        self.tree.flush().map_err(storage_error)?;
+        Ok(())
+    }
+
+    /// See [`SimpleMempool::evict_expired`].
+    pub fn evict_expired(&mut self, ttl: Duration) -> usize {
+        self.evict_expired_at(ttl, Instant::now())
+    }
+
+    /// See [`SimpleMempool::evict_expired_at`].
+    pub fn evict_expired_at(&mut self, ttl: Duration, now: Instant) -> usize {
+        let evicted = self.inner.evict_expired_at(ttl, now);
+        if evicted > 0 {
+            self.forget_removed();
+        }
+        evicted
+    }
+
+    /// Delete the stored copies of transactions that are no longer
+    /// pending in memory.
+    fn forget_removed(&mut self) {
+        let inner = &self.inner;
+        let tree = &self.tree;
+        self.keys.retain(|id, seq| {
+            if inner.contains(id) {
+                return true;
+            }
+            // A failed delete only means the transaction is replayed after
+            // a restart, where the usual nonce checks drop it if stale.
+            let _ = tree.remove(seq.to_be_bytes());
+            false
+        });
+    }
+}
+
+impl Mempool for PersistentMempool {
+    fn insert(&mut self, tx: Transaction) -> Result<TxId, MempoolError> {
+        self.inner.check(&tx).inspect_err(record_rejection)?;
+        let id = tx.id();
+        if self.keys.contains_key(&id) {
+            return Ok(id);
+        }
+
+        let seq = self.next_seq;
+        self.tree
+            .insert(seq.to_be_bytes(), tx.encode())
+            .map_err(storage_error)
+            .inspect_err(record_rejection)?;
+        self.next_seq += 1;
+
+        let id = match self.inner.insert(tx) {
+            Ok(id) => id,
+            Err(e) => {
+                let _ = self.tree.remove(seq.to_be_bytes());
+                return Err(e);
+            }
+        };
+        self.keys.insert(id, seq);
+        // The insert replaced or evicted another transaction.
+        if self.keys.len() > self.inner.len() {
+            self.forget_removed();
+        }
+        Ok(id)
+    }
+
+    fn check(&self, tx: &Transaction) -> Result<(), MempoolError> {
+        self.inner.check(tx)
+    }
+
+    fn contains(&self, id: &TxId) -> bool {
+        self.inner.contains(id)
+    }
+
+    fn get(&self, id: &TxId) -> Option<Transaction> {
+        self.inner.get(id)
+    }
+
+    fn get_batch(&self, max: usize) -> Vec<(TxId, Transaction)> {
+        self.inner.get_batch(max)
+    }
+
+    fn get_batch_for_namespace(&self, ns: NamespaceId, max: usize) -> Vec<(TxId, Transaction)> {
+        self.inner.get_batch_for_namespace(ns, max)
+    }
+
+    fn remove_committed(&mut self, ids: &[TxId]) {
+        self.inner.remove_committed(ids);
+        self.forget_removed();
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&TxId, &Transaction)> + '_> {
+        self.inner.iter()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{Address, NamespaceId};
+
+    /// A transaction from a sender of its own per namespace.
+    fn make_tx(namespace: u64, nonce: u64, gas_price: u64) -> Transaction {
+        Transaction {
+            chain_id: 0,
+            from: Address([namespace as u8; 20]),
+            namespace: NamespaceId(namespace),
+            gas_price,
+            nonce,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        }
+    }
+
+    fn open(db: &sled::Db, config: MempoolConfig) -> PersistentMempool {
+        PersistentMempool::open(config, db.open_tree("mempool").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pending_transactions_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        // Both pools share one handle: reopening the directory would race
+        // the first handle's background threads for sled's file lock.
+        let db = sled::open(dir.path()).unwrap();
+        let config = MempoolConfig {
+            ordering: crate::OrderingStrategy::Fifo,
+            ..MempoolConfig::default()
+        };
+
+        let (kept, committed, replaced, replacement) = {
+            let mut pool = open(&db, config.clone());
+            let kept = pool.insert(make_tx(1, 0, 5)).unwrap();
+            let committed = pool.insert(make_tx(2, 0, 1)).unwrap();
+            let replaced = pool.insert(make_tx(3, 0, 1)).unwrap();
+            let replacement = pool.insert(make_tx(3, 0, 2)).unwrap();
+            pool.remove_committed(&[committed]);
+            pool.flush().unwrap();
+            (kept, committed, replaced, replacement)
+        };
+
+        let mut pool = open(&db, config);
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&committed));
+        assert!(!pool.contains(&replaced));
+        // Arrival order is preserved across the restart.
+        let batch: Vec<TxId> = pool.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![kept, replacement]);
+
+        // New arrivals are stored after the replayed ones.
+        let late = pool.insert(make_tx(4, 0, 1)).unwrap();
+        let batch: Vec<TxId> = pool.get_batch(10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(batch, vec![kept, replacement, late]);
+    }
+}
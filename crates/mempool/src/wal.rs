@@ -0,0 +1,287 @@
+//! An append-only log of mempool inserts and removals, so a
+//! [`SimpleMempool`](crate::SimpleMempool) can rebuild its pending set
+//! after a crash.
+//!
+//! Each record is a tag byte, a little-endian `u32` length and the payload:
+//! the [`Transaction::encode`] bytes of an insert, or the id of a removed
+//! transaction. Records are written straight to the file without fsync, so
+//! they survive the process dying but not necessarily the machine. A record
+//! torn by a crash ends the replay and is cut off. Once enough removals
+//! have accumulated, the log is rewritten with just the pending
+//! transactions; that rewrite is fsynced before it replaces the old log.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use types::{Hash, Transaction, TxId};
+
+use crate::MempoolError;
+
+/// Removals logged before [`WalConfig::new`] compacts the log.
+pub const DEFAULT_WAL_COMPACT_AFTER: usize = 1_024;
+
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+
+/// Where a mempool logs its changes; see [`crate::MempoolConfig::wal`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalConfig {
+    pub path: PathBuf,
+    /// Removals logged since the last compaction that trigger the next.
+    pub compact_after: usize,
+}
+
+impl WalConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            compact_after: DEFAULT_WAL_COMPACT_AFTER,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MempoolWal {
+    config: WalConfig,
+    file: File,
+    removals: usize,
+}
+
+fn wal_error(e: impl std::fmt::Display) -> MempoolError {
+    MempoolError::Storage(format!("mempool wal: {e}"))
+}
+
+impl MempoolWal {
+    /// Open the log at `config.path`, creating it if missing, and return
+    /// the transactions it leaves pending in the order they were inserted.
+    pub fn open(config: WalConfig) -> Result<(Self, Vec<Transaction>), MempoolError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&config.path)
+            .map_err(wal_error)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(wal_error)?;
+
+        // Insertion order, with removed slots left empty so a removal does
+        // not shift the rest.
+        let mut pending: Vec<Option<Transaction>> = Vec::new();
+        let mut slots: HashMap<TxId, usize> = HashMap::new();
+        let mut pos = 0;
+        while let Some((tag, payload)) = next_record(&bytes[pos..]) {
+            match tag {
+                INSERT => {
+                    let tx = Transaction::decode(payload).map_err(wal_error)?;
+                    slots.entry(tx.id()).or_insert_with(|| {
+                        pending.push(Some(tx));
+                        pending.len() - 1
+                    });
+                }
+                REMOVE => {
+                    let id = TxId(Hash(
+                        payload
+                            .try_into()
+                            .map_err(|_| wal_error("malformed removal record"))?,
+                    ));
+                    if let Some(slot) = slots.remove(&id) {
+                        pending[slot] = None;
+                    }
+                }
+                other => return Err(wal_error(format!("unknown record tag {other}"))),
+            }
+            pos += 5 + payload.len();
+        }
+        if pos < bytes.len() {
+            // Torn by a crash mid-append.
+            file.set_len(pos as u64).map_err(wal_error)?;
+        }
+
+        let wal = Self {
+            config,
+            file,
+            removals: 0,
+        };
+        Ok((wal, pending.into_iter().flatten().collect()))
+    }
+
+    pub fn append_insert(&mut self, tx: &Transaction) -> Result<(), MempoolError> {
+        self.append(&record(INSERT, &tx.encode()))
+            .map_err(wal_error)
+    }
+
+    /// Log the removal of `ids`. Returns whether the log is due for
+    /// [`MempoolWal::compact`].
+    pub fn append_removals(&mut self, ids: &[TxId]) -> Result<bool, MempoolError> {
+        if ids.is_empty() {
+            return Ok(false);
+        }
+        let mut bytes = Vec::with_capacity(ids.len() * (5 + 32));
+        for id in ids {
+            bytes.extend(record(REMOVE, &id.0 .0));
+        }
+        self.append(&bytes).map_err(wal_error)?;
+        self.removals += ids.len();
+        Ok(self.removals >= self.config.compact_after)
+    }
+
+    /// Rewrite the log to hold just `pending`, in order. The new log is
+    /// written and fsynced next to the old one, renamed over it, and the
+    /// directory fsynced, so a crash keeps one log or the other.
+    pub fn compact<'a>(
+        &mut self,
+        pending: impl IntoIterator<Item = &'a Transaction>,
+    ) -> Result<(), MempoolError> {
+        let tmp = self.config.path.with_extension("compacting");
+        let mut bytes = Vec::new();
+        for tx in pending {
+            bytes.extend(record(INSERT, &tx.encode()));
+        }
+        let mut file = File::create(&tmp).map_err(wal_error)?;
+        file.write_all(&bytes).map_err(wal_error)?;
+        file.sync_all().map_err(wal_error)?;
+        fs::rename(&tmp, &self.config.path).map_err(wal_error)?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.config.path)
+            .map_err(wal_error)?;
+        self.removals = 0;
+        sync_dir(&self.config.path).map_err(wal_error)
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)
+    }
+}
+
+/// Fsync the directory holding `path`, making a rename into it durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+fn record(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(5 + payload.len());
+    out.push(tag);
+    out.extend((payload.len() as u32).to_le_bytes());
+    out.extend(payload);
+    out
+}
+
+/// The tag and payload of the record at the start of `bytes`, or `None` if
+/// it is incomplete.
+fn next_record(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    Some((tag, rest.get(4..4 + len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mempool, MempoolConfig, OrderingStrategy, SimpleMempool};
+    use types::{Address, NamespaceId};
+
+    fn make_tx(nonce: u64, gas_price: u64) -> Transaction {
+        Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price,
+            nonce,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        }
+    }
+
+    fn config(wal: WalConfig) -> MempoolConfig {
+        MempoolConfig {
+            ordering: OrderingStrategy::Fifo,
+            wal: Some(wal),
+            ..MempoolConfig::default()
+        }
+    }
+
+    fn pending_ids(pool: &SimpleMempool) -> Vec<TxId> {
+        pool.get_batch(pool.len())
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn replay_after_a_crash_restores_the_pending_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WalConfig::new(dir.path().join("mempool.wal"));
+
+        let mut pool = SimpleMempool::open(config(wal.clone())).unwrap();
+        let ids: Vec<TxId> = (0..4)
+            .map(|nonce| pool.insert(make_tx(nonce, 10)).unwrap())
+            .collect();
+        pool.remove_committed(&ids[..1]);
+        // Replacing nonce 2 logs the removal of the original.
+        let replacement = pool.insert(make_tx(2, 20)).unwrap();
+        let expected = pending_ids(&pool);
+        assert_eq!(expected, vec![ids[1], ids[3], replacement]);
+        // Crash: nothing is closed or flushed.
+        std::mem::forget(pool);
+
+        let replayed = SimpleMempool::open(config(wal)).unwrap();
+        assert_eq!(pending_ids(&replayed), expected);
+    }
+
+    #[test]
+    fn replay_keeps_insertion_order_across_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WalConfig::new(dir.path().join("mempool.wal"));
+        let txs: Vec<Transaction> = (0..3).map(|nonce| make_tx(nonce, 10)).collect();
+
+        let (mut log, replayed) = MempoolWal::open(wal.clone()).unwrap();
+        assert!(replayed.is_empty());
+        for tx in &txs {
+            log.append_insert(tx).unwrap();
+        }
+        log.append_removals(&[txs[0].id(), txs[1].id()]).unwrap();
+        // A removed transaction that comes back goes to the end.
+        log.append_insert(&txs[0]).unwrap();
+        drop(log);
+
+        let (_, replayed) = MempoolWal::open(wal).unwrap();
+        assert_eq!(replayed, vec![txs[2].clone(), txs[0].clone()]);
+    }
+
+    #[test]
+    fn log_is_compacted_and_a_torn_record_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mempool.wal");
+        let wal = WalConfig {
+            compact_after: 2,
+            ..WalConfig::new(&path)
+        };
+
+        let mut pool = SimpleMempool::open(config(wal.clone())).unwrap();
+        let ids: Vec<TxId> = (0..3)
+            .map(|nonce| pool.insert(make_tx(nonce, 10)).unwrap())
+            .collect();
+        pool.remove_committed(&ids[..2]);
+        // Only the one pending insert is left.
+        let kept = record(INSERT, &make_tx(2, 10).encode());
+        assert_eq!(fs::read(&path).unwrap(), kept);
+        drop(pool);
+
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&record(INSERT, &make_tx(3, 10).encode())[..9])
+            .unwrap();
+        let replayed = SimpleMempool::open(config(wal)).unwrap();
+        assert_eq!(pending_ids(&replayed), vec![ids[2]]);
+        assert_eq!(fs::read(&path).unwrap(), kept);
+    }
+}
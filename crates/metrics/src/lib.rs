@@ -1,4 +1,8 @@
 //! Sequencer metrics and Prometheus exporter wiring.
+//!
+//! The `record_*` functions report to the recorder in scope: a recorder set
+//! for the current thread with [`metrics::set_default_local_recorder`] if
+//! there is one, otherwise the global recorder from [`init_metrics`].
 
 use metrics::{counter, gauge, histogram};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
@@ -23,7 +27,38 @@ pub fn render_metrics() -> String {
 	PROM_HANDLE
 		.get()
 		.map(|h| h.render())
-		.unwrap_or_else(|| "".to_string())
+		.unwrap_or_default()
+}
+
+/// Metrics recorded on one thread, isolated from the global recorder and
+/// from other tests. Returned by [`reset_metrics_for_test`].
+#[cfg(any(test, feature = "test-util"))]
+pub struct TestMetrics {
+	handle: PrometheusHandle,
+	_guard: metrics::LocalRecorderGuard<'static>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl TestMetrics {
+	/// Render the metrics recorded since the reset in Prometheus text
+	/// format.
+	pub fn render(&self) -> String {
+		self.handle.render()
+	}
+}
+
+/// Install a fresh recorder for the current thread, replacing any earlier
+/// one until the returned value is dropped.
+///
+/// Only metrics recorded on this thread are captured, so async tests need a
+/// current-thread runtime. Each call leaks one small recorder.
+#[cfg(any(test, feature = "test-util"))]
+pub fn reset_metrics_for_test() -> TestMetrics {
+	let recorder: &'static _ = Box::leak(Box::new(PrometheusBuilder::new().build_recorder()));
+	TestMetrics {
+		handle: recorder.handle(),
+		_guard: metrics::set_default_local_recorder(recorder),
+	}
 }
 
 /// Record that a transaction was submitted into the mempool.
@@ -31,6 +66,23 @@ pub fn record_tx_submitted() {
 	counter!("sequencer_tx_submitted").increment(1);
 }
 
+/// Record a transaction submitted into the mempool, labeled by namespace:
+/// its registered name, or its numeric id. [`record_tx_submitted`] keeps
+/// the unlabeled total.
+pub fn record_namespace_tx_submitted(namespace: &str) {
+	counter!("sequencer_tx_submitted_total", "namespace" => namespace.to_string()).increment(1);
+}
+
+/// Record a transaction the mempool refused, labeled by reason.
+pub fn record_tx_rejected(reason: &'static str) {
+	counter!("sequencer_tx_rejected_total", "reason" => reason).increment(1);
+}
+
+/// Record the encoded size of a transaction admitted to the mempool.
+pub fn record_tx_size_bytes(bytes: usize) {
+	histogram!("sequencer_tx_size_bytes").record(bytes as f64);
+}
+
 /// Update the mempool size gauge.
 pub fn record_mempool_size(len: usize) {
 	gauge!("sequencer_mempool_size").set(len as f64);
@@ -42,12 +94,166 @@ pub fn record_block_committed(tx_count: usize) {
 	counter!("sequencer_txs_committed").increment(tx_count as u64);
 }
 
+/// Record `count` committed transactions from one namespace, labeled like
+/// [`record_namespace_tx_submitted`]. [`record_block_committed`] keeps the
+/// unlabeled total.
+pub fn record_namespace_txs_committed(namespace: &str, count: usize) {
+	counter!("sequencer_txs_committed_total", "namespace" => namespace.to_string())
+		.increment(count as u64);
+}
+
 /// Record the duration of a consensus step in milliseconds.
 pub fn record_consensus_step_duration_ms(ms: f64) {
 	histogram!("sequencer_consensus_step_ms").record(ms);
 }
 
+/// Record a consensus view that timed out without producing a block.
+pub fn record_view_timeout() {
+	counter!("sequencer_view_timeouts_total").increment(1);
+}
+
 /// Record the duration of a storage operation in milliseconds, labeled by op.
 pub fn record_storage_op_duration_ms(op: &'static str, ms: f64) {
 	histogram!("sequencer_storage_op_ms", "op" => op).record(ms);
 }
+
+/// Update the gauge of bytes the database occupies on disk.
+pub fn record_storage_bytes(bytes: u64) {
+	gauge!("sequencer_storage_bytes").set(bytes as f64);
+}
+
+/// Update the gauge of blocks held in storage.
+pub fn record_block_count(count: usize) {
+	gauge!("sequencer_block_count").set(count as f64);
+}
+
+/// Record a gossip message sent to a peer, labeled by message kind.
+pub fn record_gossip_sent(kind: &'static str) {
+	counter!("sequencer_gossip_sent_total", "kind" => kind).increment(1);
+}
+
+/// Record a gossip message received from a peer, labeled by message kind.
+pub fn record_gossip_received(kind: &'static str) {
+	counter!("sequencer_gossip_received_total", "kind" => kind).increment(1);
+}
+
+/// Record an incoming gossip datagram that could not be decoded, labeled by
+/// the sending peer's address.
+pub fn record_gossip_decode_error(peer: String) {
+	counter!("sequencer_gossip_decode_errors_total", "peer" => peer).increment(1);
+}
+
+/// Record an incoming gossip message dropped because its sender exceeded
+/// the per-peer rate limit, labeled by the sender's address.
+pub fn record_gossip_rate_limited(peer: String) {
+	counter!("sequencer_gossip_rate_limited_total", "peer" => peer).increment(1);
+}
+
+/// Record an outgoing gossip message dropped before it was queued for
+/// sending, labeled by message kind.
+pub fn record_gossip_dropped(kind: &'static str) {
+	counter!("sequencer_gossip_dropped_total", "kind" => kind).increment(1);
+}
+
+/// Record a block message sent to a peer: the size of its envelope as
+/// encoded, and as put on the wire after compression. Both are the same
+/// when compression is off or does not pay.
+pub fn record_gossip_block_bytes(uncompressed: usize, compressed: usize) {
+	counter!("sequencer_gossip_block_bytes_uncompressed").increment(uncompressed as u64);
+	counter!("sequencer_gossip_block_bytes_compressed").increment(compressed as u64);
+}
+
+/// Record a gossip peer being banned for misbehaving.
+pub fn record_gossip_peer_banned() {
+	counter!("sequencer_gossip_peers_banned_total").increment(1);
+}
+
+/// Update the number of peers currently considered alive.
+pub fn record_peers_alive(count: usize) {
+	gauge!("sequencer_peers_alive").set(count as f64);
+}
+
+/// Record a served RPC request, labeled by HTTP method, route and status.
+pub fn record_rpc_request(method: String, path: String, status: u16) {
+	counter!(
+		"sequencer_rpc_requests_total",
+		"method" => method,
+		"path" => path,
+		"status" => status.to_string()
+	)
+	.increment(1);
+}
+
+/// Record the duration of an RPC request in milliseconds, labeled by route.
+pub fn record_rpc_request_duration_ms(path: String, ms: f64) {
+	histogram!("sequencer_rpc_request_duration_ms", "path" => path).record(ms);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn gossip_metrics_are_rendered() {
+		// The recorder is process-global; another test may have installed it.
+		let _ = init_metrics();
+
+		record_gossip_sent("tx");
+		record_gossip_sent("tx");
+		record_gossip_received("block");
+		record_gossip_decode_error("127.0.0.1:9000".to_string());
+		record_gossip_peer_banned();
+		record_gossip_dropped("tx");
+		record_gossip_block_bytes(1_000, 400);
+		record_peers_alive(3);
+
+		let rendered = render_metrics();
+		assert!(rendered.contains("sequencer_gossip_sent_total{kind=\"tx\"} 2"));
+		assert!(rendered.contains("sequencer_gossip_received_total{kind=\"block\"} 1"));
+		assert!(rendered.contains(
+			"sequencer_gossip_decode_errors_total{peer=\"127.0.0.1:9000\"} 1"
+		));
+		assert!(rendered.contains("sequencer_gossip_peers_banned_total 1"));
+		assert!(rendered.contains("sequencer_gossip_dropped_total{kind=\"tx\"} 1"));
+		assert!(rendered.contains("sequencer_gossip_block_bytes_uncompressed 1000"));
+		assert!(rendered.contains("sequencer_gossip_block_bytes_compressed 400"));
+		assert!(rendered.contains("sequencer_peers_alive 3"));
+	}
+
+	#[test]
+	fn test_recorders_are_isolated() {
+		let first = reset_metrics_for_test();
+		record_tx_submitted();
+		record_tx_submitted();
+		let first_snapshot = first.render();
+		drop(first);
+
+		let second = reset_metrics_for_test();
+		record_tx_submitted();
+		record_block_committed(4);
+		let second_snapshot = second.render();
+
+		assert!(first_snapshot.contains("sequencer_tx_submitted 2"));
+		assert!(!first_snapshot.contains("sequencer_blocks_committed"));
+		assert!(second_snapshot.contains("sequencer_tx_submitted 1"));
+		assert!(second_snapshot.contains("sequencer_txs_committed 4"));
+		// Nothing reached the global recorder.
+		assert!(!render_metrics().contains("sequencer_tx_submitted"));
+	}
+
+	#[test]
+	fn namespace_metrics_are_labeled() {
+		let metrics = reset_metrics_for_test();
+		record_namespace_tx_submitted("payments");
+		record_namespace_tx_submitted("payments");
+		record_namespace_tx_submitted("2");
+		record_namespace_txs_committed("payments", 2);
+		record_namespace_txs_committed("2", 1);
+
+		let rendered = metrics.render();
+		assert!(rendered.contains("sequencer_tx_submitted_total{namespace=\"payments\"} 2"));
+		assert!(rendered.contains("sequencer_tx_submitted_total{namespace=\"2\"} 1"));
+		assert!(rendered.contains("sequencer_txs_committed_total{namespace=\"payments\"} 2"));
+		assert!(rendered.contains("sequencer_txs_committed_total{namespace=\"2\"} 1"));
+	}
+}
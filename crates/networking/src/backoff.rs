@@ -0,0 +1,189 @@
+//! Retrying the gossip socket after receive errors.
+//!
+//! A failed `recv_from` is either transient, e.g. the kernel ran out of
+//! buffers, and retried after a delay that doubles up to a cap, or fatal:
+//! the socket itself is unusable, so the receiver loop stops and the
+//! network reports itself unhealthy (see
+//! [`NetworkHandle::is_healthy`](crate::NetworkHandle::is_healthy)).
+
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, error};
+
+use crate::NetworkStatus;
+
+/// Delays between attempts to receive after a transient socket error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvBackoff {
+	/// Delay after the first error of a run.
+	pub initial: Duration,
+	/// Longest delay; each further error doubles the previous one up to it.
+	pub max: Duration,
+}
+
+impl Default for RecvBackoff {
+	fn default() -> Self {
+		Self {
+			initial: Duration::from_millis(10),
+			max: Duration::from_secs(5),
+		}
+	}
+}
+
+/// The delay sequence of one [`RecvBackoff`], restarted by a successful
+/// receive.
+#[derive(Debug)]
+pub(crate) struct Backoff {
+	config: RecvBackoff,
+	next: Duration,
+}
+
+impl Backoff {
+	pub(crate) fn new(config: RecvBackoff) -> Self {
+		Self {
+			config,
+			next: config.initial.min(config.max),
+		}
+	}
+
+	pub(crate) fn next_delay(&mut self) -> Duration {
+		let delay = self.next;
+		self.next = (delay * 2).min(self.config.max);
+		delay
+	}
+
+	pub(crate) fn reset(&mut self) {
+		self.next = self.config.initial.min(self.config.max);
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RecvErrorKind {
+	/// Says nothing about our socket; read again straight away.
+	Ignored,
+	Transient,
+	Fatal,
+}
+
+pub(crate) fn classify(e: &io::Error) -> RecvErrorKind {
+	match e.kind() {
+		// A ping to a stopped peer surfaces as an ICMP "connection
+		// refused" on the next receive.
+		io::ErrorKind::ConnectionRefused => RecvErrorKind::Ignored,
+		io::ErrorKind::InvalidInput
+		| io::ErrorKind::NotConnected
+		| io::ErrorKind::PermissionDenied
+		| io::ErrorKind::Unsupported => RecvErrorKind::Fatal,
+		_ => RecvErrorKind::Transient,
+	}
+}
+
+/// Where the receiver loop reads datagrams from.
+pub(crate) trait Datagrams {
+	async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+}
+
+impl Datagrams for UdpSocket {
+	async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+		UdpSocket::recv_from(self, buf).await
+	}
+}
+
+/// Receive the next datagram into `buf`, waiting out transient errors with
+/// `backoff`. `None` after a fatal error, which is published on `status`.
+pub(crate) async fn recv_next<S: Datagrams>(
+	socket: &S,
+	buf: &mut [u8],
+	backoff: &mut Backoff,
+	status: &watch::Sender<NetworkStatus>,
+) -> Option<(usize, SocketAddr)> {
+	loop {
+		let e = match socket.recv_from(buf).await {
+			Ok(received) => {
+				backoff.reset();
+				return Some(received);
+			}
+			Err(e) => e,
+		};
+		match classify(&e) {
+			RecvErrorKind::Ignored => {}
+			RecvErrorKind::Transient => {
+				let delay = backoff.next_delay();
+				debug!(error = %e, ?delay, "gossip socket error; retrying");
+				sleep(delay).await;
+			}
+			RecvErrorKind::Fatal => {
+				error!(error = %e, "gossip socket failed; no longer receiving");
+				status.send_replace(NetworkStatus::Failed {
+					error: e.to_string(),
+				});
+				return None;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::VecDeque;
+	use std::sync::Mutex;
+
+	#[test]
+	fn delays_double_up_to_the_cap_and_restart_on_reset() {
+		let mut backoff = Backoff::new(RecvBackoff {
+			initial: Duration::from_millis(10),
+			max: Duration::from_millis(50),
+		});
+		let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_millis() as u64).collect();
+		assert_eq!(delays, vec![10, 20, 40, 50, 50]);
+		backoff.reset();
+		assert_eq!(backoff.next_delay(), Duration::from_millis(10));
+	}
+
+	/// Replays scripted receive results, then reports the socket closed.
+	struct Scripted(Mutex<VecDeque<io::Result<(usize, SocketAddr)>>>);
+
+	impl Datagrams for Scripted {
+		async fn recv_from(&self, _: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+			self.0.lock().unwrap().pop_front().unwrap_or_else(|| {
+				Err(io::Error::from(io::ErrorKind::NotConnected))
+			})
+		}
+	}
+
+	#[tokio::test]
+	async fn transient_errors_back_off_and_fatal_ones_mark_the_network_failed() {
+		let addr = SocketAddr::from(([127, 0, 0, 1], 9000));
+		let transient = || Err(io::Error::from(io::ErrorKind::Interrupted));
+		let socket = Scripted(Mutex::new(VecDeque::from([
+			transient(),
+			Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+			transient(),
+			transient(),
+			Ok((3, addr)),
+		])));
+		let mut backoff = Backoff::new(RecvBackoff {
+			initial: Duration::from_millis(1),
+			max: Duration::from_secs(1),
+		});
+		let (status, health) = watch::channel(NetworkStatus::Healthy);
+		let mut buf = [0u8; 8];
+
+		let start = std::time::Instant::now();
+		let received = recv_next(&socket, &mut buf, &mut backoff, &status).await;
+		assert_eq!(received, Some((3, addr)));
+		assert_eq!(*health.borrow(), NetworkStatus::Healthy);
+		// The three transient errors waited 1, 2 and 4 ms, the refusal
+		// nothing; the success restarted the sequence.
+		assert!(start.elapsed() >= Duration::from_millis(7));
+		assert_eq!(backoff.next_delay(), Duration::from_millis(1));
+
+		assert_eq!(recv_next(&socket, &mut buf, &mut backoff, &status).await, None);
+		assert!(matches!(*health.borrow(), NetworkStatus::Failed { .. }));
+	}
+}
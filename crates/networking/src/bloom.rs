@@ -0,0 +1,101 @@
+//! Bloom filters summarizing which transactions a node already has.
+//!
+//! Nodes periodically gossip a [`BloomFilter`] of their pending
+//! transactions in a `HaveTxs` message. Senders skip a peer whose latest
+//! filter contains a transaction. A false positive means a peer misses one
+//! forward and has to get the transaction elsewhere, so this only trims
+//! redundant traffic and never replaces the mempool's own dedup.
+
+use serde::{Deserialize, Serialize};
+use types::TxId;
+
+/// Target false positive rate of [`BloomFilter::for_txs`].
+pub const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Upper bound on filter size accepted from peers, 8 Mbit.
+const MAX_WORDS: usize = 128 * 1024;
+
+/// Upper bound on hash functions accepted from peers.
+const MAX_HASHES: u32 = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BloomFilter {
+	bits: Vec<u64>,
+	hashes: u32,
+}
+
+impl BloomFilter {
+	/// An empty filter sized for `items` entries at
+	/// [`FALSE_POSITIVE_RATE`].
+	pub fn with_capacity(items: usize) -> Self {
+		let items = items.max(1) as f64;
+		let ln2 = std::f64::consts::LN_2;
+		let bits = (-items * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as usize;
+		let words = bits.div_ceil(64).clamp(1, MAX_WORDS);
+		let hashes = ((words * 64) as f64 / items * ln2).round() as u32;
+		Self {
+			bits: vec![0; words],
+			hashes: hashes.clamp(1, MAX_HASHES),
+		}
+	}
+
+	/// A filter holding every id in `ids`.
+	pub fn for_txs<'a>(ids: impl ExactSizeIterator<Item = &'a TxId>) -> Self {
+		let mut filter = Self::with_capacity(ids.len());
+		for id in ids {
+			filter.insert(id);
+		}
+		filter
+	}
+
+	pub fn insert(&mut self, id: &TxId) {
+		for bit in self.bit_indices(id) {
+			self.bits[bit / 64] |= 1 << (bit % 64);
+		}
+	}
+
+	/// Whether `id` was probably inserted. Never false for an inserted id.
+	pub fn contains(&self, id: &TxId) -> bool {
+		self.bit_indices(id)
+			.all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+	}
+
+	/// Whether a filter received from a peer is safe to query.
+	pub(crate) fn is_well_formed(&self) -> bool {
+		(1..=MAX_WORDS).contains(&self.bits.len()) && (1..=MAX_HASHES).contains(&self.hashes)
+	}
+
+	/// Kirsch-Mitzenmacher double hashing over the id, which is already a
+	/// uniform hash.
+	fn bit_indices(&self, id: &TxId) -> impl Iterator<Item = usize> {
+		let bytes = &id.0 .0;
+		let h1 = u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"));
+		let h2 = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes")) | 1;
+		let len = (self.bits.len() * 64) as u64;
+		(0..u64::from(self.hashes))
+			.map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use types::hash_bytes;
+
+	fn id(n: u32) -> TxId {
+		TxId(hash_bytes(&n.to_le_bytes()))
+	}
+
+	#[test]
+	fn filter_has_no_false_negatives_and_few_false_positives() {
+		let inserted: Vec<TxId> = (0..1_000).map(id).collect();
+		let filter = BloomFilter::for_txs(inserted.iter());
+		assert!(filter.is_well_formed());
+		assert!(inserted.iter().all(|id| filter.contains(id)));
+
+		let false_positives = (1_000..11_000).filter(|n| filter.contains(&id(*n))).count();
+		// 1% expected; allow for variance.
+		assert!(false_positives < 300, "{false_positives} false positives");
+		assert!(!BloomFilter::with_capacity(0).contains(&id(0)));
+	}
+}
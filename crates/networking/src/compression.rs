@@ -0,0 +1,71 @@
+//! Optional zstd compression of serialized envelopes.
+//!
+//! A compressed envelope is [`COMPRESSED_MAGIC`] followed by a zstd frame;
+//! a plain one starts with its wire-format tag. Receivers accept both
+//! whatever their own setting, so nodes can enable compression one at a
+//! time.
+
+/// First byte of a compressed envelope. Distinct from the fragment magic and
+/// not valid as the start of UTF-8.
+const COMPRESSED_MAGIC: u8 = 0xF8;
+
+/// Largest envelope a compressed message may expand to.
+pub const MAX_DECOMPRESSED: usize = 16 * 1024 * 1024;
+
+/// Compress `bytes` at `level`, or return them unchanged if compression is
+/// off or would not make them smaller.
+pub fn encode(bytes: Vec<u8>, level: Option<i32>) -> Vec<u8> {
+	let Some(level) = level else {
+		return bytes;
+	};
+	match zstd::bulk::compress(&bytes, level) {
+		Ok(compressed) if compressed.len() + 1 < bytes.len() => {
+			let mut out = Vec::with_capacity(compressed.len() + 1);
+			out.push(COMPRESSED_MAGIC);
+			out.extend(compressed);
+			out
+		}
+		_ => bytes,
+	}
+}
+
+/// Undo [`encode`]. Returns `None` for a corrupt frame or one that expands
+/// beyond [`MAX_DECOMPRESSED`].
+pub fn decode(bytes: Vec<u8>) -> Option<Vec<u8>> {
+	if bytes.first() != Some(&COMPRESSED_MAGIC) {
+		return Some(bytes);
+	}
+	zstd::bulk::decompress(&bytes[1..], MAX_DECOMPRESSED).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compressed_bytes_roundtrip() {
+		let bytes = br#"{"txs":[1,2,3,1,2,3,1,2,3,1,2,3,1,2,3,1,2,3,1,2,3]}"#.repeat(50);
+		let encoded = encode(bytes.clone(), Some(3));
+		assert_eq!(encoded[0], COMPRESSED_MAGIC);
+		assert!(encoded.len() < bytes.len());
+		assert_eq!(decode(encoded), Some(bytes));
+	}
+
+	#[test]
+	fn plain_bytes_pass_through() {
+		assert_eq!(encode(b"{}".to_vec(), None), b"{}".to_vec());
+		// Too small to shrink.
+		assert_eq!(encode(b"{}".to_vec(), Some(3)), b"{}".to_vec());
+		assert_eq!(decode(b"{}".to_vec()), Some(b"{}".to_vec()));
+	}
+
+	#[test]
+	fn corrupt_and_oversized_frames_are_rejected() {
+		assert_eq!(decode(vec![COMPRESSED_MAGIC, 1, 2, 3]), None);
+
+		let bomb = zstd::bulk::compress(&vec![0u8; MAX_DECOMPRESSED + 1], 3).unwrap();
+		let mut framed = vec![COMPRESSED_MAGIC];
+		framed.extend(bomb);
+		assert_eq!(decode(framed), None);
+	}
+}
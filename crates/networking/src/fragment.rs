@@ -0,0 +1,287 @@
+//! Application-level fragmentation for gossip datagrams.
+//!
+//! Serialized envelopes up to [`MAX_DATAGRAM`] bytes are sent as-is. Larger
+//! ones are split into fragments, each prefixed with [`FRAGMENT_MAGIC`] and
+//! a `{msg_id, frag_index, frag_count}` header, and reassembled on the
+//! receiving side. A plain envelope starts with its wire-format tag, never
+//! the magic byte, so the two kinds of datagram are unambiguous.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+
+use tokio::time::{Duration, Instant};
+
+/// Largest datagram we send; keeps fragments under a typical path MTU.
+pub const MAX_DATAGRAM: usize = 1200;
+
+/// First byte of every fragment datagram (not valid as the start of UTF-8).
+const FRAGMENT_MAGIC: u8 = 0xF7;
+
+/// Magic byte, `msg_id: u64`, `frag_index: u16`, `frag_count: u16`.
+const HEADER_LEN: usize = 1 + 8 + 2 + 2;
+
+const CHUNK_LEN: usize = MAX_DATAGRAM - HEADER_LEN;
+
+/// Upper bound on fragments per message, capping reassembly memory at
+/// roughly 1 MiB per in-flight message.
+const MAX_FRAGMENTS: u16 = 1024;
+
+/// Incomplete sets buffered per sender. Starting another drops the
+/// sender's oldest.
+const MAX_PARTIAL_PER_PEER: usize = 4;
+
+/// Incomplete sets buffered across all senders. Starting another drops the
+/// oldest of any sender.
+const MAX_PARTIAL: usize = 64;
+
+/// Datagram as received, after fragment handling.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Incoming {
+	/// A complete message: either a single datagram or a reassembled set.
+	Complete(Vec<u8>),
+	/// A fragment was buffered; more are needed.
+	Pending,
+	/// A fragment with an invalid header.
+	Malformed,
+}
+
+/// Split `bytes` into datagrams, fragmenting only if it does not fit in one.
+pub fn encode(msg_id: u64, bytes: Vec<u8>) -> Vec<Vec<u8>> {
+	if bytes.len() <= MAX_DATAGRAM {
+		return vec![bytes];
+	}
+
+	let chunks: Vec<&[u8]> = bytes.chunks(CHUNK_LEN).collect();
+	let frag_count = chunks.len() as u16;
+	chunks
+		.into_iter()
+		.enumerate()
+		.map(|(index, chunk)| {
+			let mut datagram = Vec::with_capacity(HEADER_LEN + chunk.len());
+			datagram.push(FRAGMENT_MAGIC);
+			datagram.extend_from_slice(&msg_id.to_be_bytes());
+			datagram.extend_from_slice(&(index as u16).to_be_bytes());
+			datagram.extend_from_slice(&frag_count.to_be_bytes());
+			datagram.extend_from_slice(chunk);
+			datagram
+		})
+		.collect()
+}
+
+/// Whether a message of `len` bytes can be sent at all.
+pub fn fits(len: usize) -> bool {
+	len <= CHUNK_LEN * MAX_FRAGMENTS as usize
+}
+
+#[derive(Debug)]
+struct Partial {
+	count: u16,
+	/// Fragments received so far, by index; slots fill as they arrive.
+	fragments: BTreeMap<u16, Vec<u8>>,
+	started: Instant,
+}
+
+/// Buffers fragments per `(sender, msg_id)` until a set is complete.
+/// Incomplete sets older than `timeout` are discarded by
+/// [`Reassembler::expire`], and at most [`MAX_PARTIAL_PER_PEER`] per sender
+/// and [`MAX_PARTIAL`] overall are kept.
+#[derive(Debug)]
+pub struct Reassembler {
+	timeout: Duration,
+	partial: HashMap<(SocketAddr, u64), Partial>,
+}
+
+impl Reassembler {
+	pub fn new(timeout: Duration) -> Self {
+		Self {
+			timeout,
+			partial: HashMap::new(),
+		}
+	}
+
+	pub fn accept(&mut self, from: SocketAddr, datagram: &[u8]) -> Incoming {
+		self.accept_at(from, datagram, Instant::now())
+	}
+
+	/// Like [`Reassembler::accept`], but measured against the supplied `now`.
+	pub fn accept_at(&mut self, from: SocketAddr, datagram: &[u8], now: Instant) -> Incoming {
+		if datagram.first() != Some(&FRAGMENT_MAGIC) {
+			return Incoming::Complete(datagram.to_vec());
+		}
+		if datagram.len() < HEADER_LEN {
+			return Incoming::Malformed;
+		}
+
+		let msg_id = u64::from_be_bytes(datagram[1..9].try_into().unwrap());
+		let index = u16::from_be_bytes(datagram[9..11].try_into().unwrap());
+		let count = u16::from_be_bytes(datagram[11..13].try_into().unwrap());
+		if count == 0 || count > MAX_FRAGMENTS || index >= count {
+			return Incoming::Malformed;
+		}
+
+		let key = (from, msg_id);
+		// A set that timed out before the next sweep starts over.
+		let timeout = self.timeout;
+		if self
+			.partial
+			.get(&key)
+			.is_some_and(|p| now.saturating_duration_since(p.started) >= timeout)
+		{
+			self.partial.remove(&key);
+		}
+		if !self.partial.contains_key(&key) {
+			self.make_room(from);
+		}
+		let partial = self.partial.entry(key).or_insert_with(|| Partial {
+			count,
+			fragments: BTreeMap::new(),
+			started: now,
+		});
+		if partial.count != count {
+			self.partial.remove(&key);
+			return Incoming::Malformed;
+		}
+		partial
+			.fragments
+			.entry(index)
+			.or_insert_with(|| datagram[HEADER_LEN..].to_vec());
+		if partial.fragments.len() < count as usize {
+			return Incoming::Pending;
+		}
+
+		let partial = self.partial.remove(&key).expect("entry exists");
+		Incoming::Complete(partial.fragments.into_values().flatten().collect())
+	}
+
+	/// Drop incomplete sets older than the timeout. The receiver calls this
+	/// on a timer rather than scanning every set per datagram.
+	pub fn expire(&mut self, now: Instant) {
+		let timeout = self.timeout;
+		self.partial
+			.retain(|_, p| now.saturating_duration_since(p.started) < timeout);
+	}
+
+	/// Drop the oldest sets so a new one from `from` fits under both caps.
+	fn make_room(&mut self, from: SocketAddr) {
+		let from_peer = self
+			.partial
+			.keys()
+			.filter(|(addr, _)| *addr == from)
+			.count();
+		if from_peer >= MAX_PARTIAL_PER_PEER {
+			self.drop_oldest(|addr| addr == from);
+		}
+		if self.partial.len() >= MAX_PARTIAL {
+			self.drop_oldest(|_| true);
+		}
+	}
+
+	fn drop_oldest(&mut self, sender: impl Fn(SocketAddr) -> bool) {
+		let oldest = self
+			.partial
+			.iter()
+			.filter(|((addr, _), _)| sender(*addr))
+			.min_by_key(|(_, p)| p.started)
+			.map(|(key, _)| *key);
+		if let Some(key) = oldest {
+			self.partial.remove(&key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn addr() -> SocketAddr {
+		"127.0.0.1:9000".parse().unwrap()
+	}
+
+	#[test]
+	fn small_messages_stay_single_datagram() {
+		let datagrams = encode(1, b"{}".to_vec());
+		assert_eq!(datagrams, vec![b"{}".to_vec()]);
+
+		let mut r = Reassembler::new(Duration::from_secs(1));
+		assert_eq!(r.accept(addr(), b"{}"), Incoming::Complete(b"{}".to_vec()));
+	}
+
+	#[test]
+	fn fragments_reassemble_out_of_order() {
+		let bytes: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+		let mut datagrams = encode(7, bytes.clone());
+		assert_eq!(datagrams.len(), 5);
+		assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM));
+		datagrams.reverse();
+
+		let mut r = Reassembler::new(Duration::from_secs(1));
+		let last = datagrams.pop().unwrap();
+		for d in &datagrams {
+			assert_eq!(r.accept(addr(), d), Incoming::Pending);
+		}
+		assert_eq!(r.accept(addr(), &last), Incoming::Complete(bytes));
+		assert_eq!(r.partial.len(), 0);
+	}
+
+	#[test]
+	fn incomplete_sets_are_discarded_after_timeout() {
+		let datagrams = encode(7, vec![1u8; 3000]);
+		let mut r = Reassembler::new(Duration::from_millis(100));
+		let start = Instant::now();
+
+		assert_eq!(r.accept_at(addr(), &datagrams[0], start), Incoming::Pending);
+		assert_eq!(r.partial.len(), 1);
+
+		// A late fragment starts a fresh set instead of completing the old one.
+		let later = start + Duration::from_millis(200);
+		assert_eq!(r.accept_at(addr(), &datagrams[1], later), Incoming::Pending);
+		assert_eq!(r.partial.len(), 1);
+
+		r.expire(later + Duration::from_millis(50));
+		assert_eq!(r.partial.len(), 1);
+		r.expire(later + Duration::from_millis(100));
+		assert_eq!(r.partial.len(), 0);
+	}
+
+	#[test]
+	fn incomplete_sets_are_capped_per_sender_and_overall() {
+		let mut r = Reassembler::new(Duration::from_secs(60));
+		let start = Instant::now();
+		let first_fragment = |msg_id| encode(msg_id, vec![0u8; 3000]).remove(0);
+
+		// One sender cannot hold more than its share; its oldest set goes.
+		for msg_id in 0..=MAX_PARTIAL_PER_PEER as u64 {
+			let now = start + Duration::from_millis(msg_id);
+			r.accept_at(addr(), &first_fragment(msg_id), now);
+		}
+		assert_eq!(r.partial.len(), MAX_PARTIAL_PER_PEER);
+		assert!(!r.partial.contains_key(&(addr(), 0)));
+
+		// Many senders together cannot exceed the overall cap.
+		for port in 0..MAX_PARTIAL as u16 {
+			let sender = SocketAddr::from(([10, 0, 0, 1], port));
+			let now = start + Duration::from_secs(1) + Duration::from_millis(port.into());
+			r.accept_at(sender, &first_fragment(1), now);
+		}
+		assert_eq!(r.partial.len(), MAX_PARTIAL);
+		assert!(r.partial.keys().all(|(sender, _)| *sender != addr()));
+
+		// Slots are only allocated for fragments that arrived.
+		let big = encode(9, vec![0u8; CHUNK_LEN * MAX_FRAGMENTS as usize]).remove(0);
+		r.accept_at(addr(), &big, start + Duration::from_secs(2));
+		assert_eq!(r.partial[&(addr(), 9)].fragments.len(), 1);
+	}
+
+	#[test]
+	fn malformed_headers_are_rejected() {
+		let mut r = Reassembler::new(Duration::from_secs(1));
+		assert_eq!(
+			r.accept(addr(), &[FRAGMENT_MAGIC, 0, 1]),
+			Incoming::Malformed
+		);
+
+		let mut bad_index = encode(1, vec![0u8; 3000]).remove(0);
+		bad_index[9..11].copy_from_slice(&9u16.to_be_bytes());
+		assert_eq!(r.accept(addr(), &bad_index), Incoming::Malformed);
+	}
+}
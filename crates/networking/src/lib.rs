@@ -3,14 +3,120 @@
 //! This is **not** a full libp2p implementation, but it provides a
 //! simple UDP-based gossip channel that allows two (or more) nodes to
 //! exchange transactions and committed blocks.
+//!
+//! Every datagram is an [`Envelope`] signed with the sender's ed25519 key;
+//! messages from keys outside `NetworkConfig::trusted_peers` are dropped.
+//! Peers that keep sending undecodable, badly signed or inconsistent
+//! messages are banned for a while (see [`scoring`]), and with
+//! `NetworkConfig::rate_limit` set, each peer's messages are throttled
+//! (see [`rate_limit`]).
+//! With `NetworkConfig::compression_level` set, outgoing envelopes are zstd
+//! compressed. Envelopes are encoded in `NetworkConfig::wire_format`, and
+//! peers must agree on it (see [`wire`]).
+//!
+//! Nodes introduce themselves with a [`GossipMessage::Hello`] carrying
+//! their [`PROTOCOL_VERSION`]. Once a peer has advertised a different
+//! major version, its messages are dropped, since their layout may have
+//! changed.
+
+mod backoff;
+pub mod bloom;
+mod compression;
+mod fragment;
+pub mod rate_limit;
+pub mod scoring;
+pub mod wire;
 
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration};
-use types::{Block, Transaction};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration, Instant};
+use tracing::{debug, warn};
+use types::{merkle_root, Block, CodecError, NamespaceId, Transaction, TxId};
+
+use metrics as sequencer_metrics;
+use rate_limit::PeerRateLimiter;
+use scoring::{BannedPeer, Offence, PeerScores};
+pub use backoff::RecvBackoff;
+pub use bloom::BloomFilter;
+pub use rate_limit::PeerRateLimit;
+pub use wire::WireFormat;
+
+/// Default interval between heartbeat pings.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default time without hearing from a peer before it is considered dead.
+pub const DEFAULT_PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default time to wait for the remaining fragments of a large message.
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default score below which a peer is banned.
+pub const DEFAULT_BAN_THRESHOLD: i64 = -100;
+
+/// Default length of a ban.
+pub const DEFAULT_BAN_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// Outgoing messages that can wait for the sender loop before
+/// `try_broadcast_*` starts failing with [`NetworkError::ChannelFull`].
+pub const SEND_QUEUE_CAPACITY: usize = 1024;
+
+/// Default number of peers each message is sent to: all of them.
+pub const DEFAULT_FANOUT: usize = usize::MAX;
+
+/// Version of the [`GossipMessage`] layout spoken by this build.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+/// A gossip protocol version. Peers can talk when their major versions
+/// match; minor versions only add things older nodes can ignore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+	pub major: u16,
+	pub minor: u16,
+}
+
+impl ProtocolVersion {
+	pub fn is_compatible_with(self, other: ProtocolVersion) -> bool {
+		self.major == other.major
+	}
+}
+
+impl std::fmt::Display for ProtocolVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}.{}", self.major, self.minor)
+	}
+}
+
+/// Why an outgoing message was not queued for gossip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum NetworkError {
+	/// The send queue is at capacity; only returned by the `try_` methods.
+	#[error("gossip send queue is full")]
+	ChannelFull,
+	/// The network has been shut down.
+	#[error("gossip network is shut down")]
+	Closed,
+}
+
+/// Whether the network is still receiving gossip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkStatus {
+	Healthy,
+	/// The receiver loop stopped on a socket error it cannot recover from.
+	Failed { error: String },
+}
 
 /// Messages exchanged between peers.
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +124,175 @@ use types::{Block, Transaction};
 pub enum GossipMessage {
 	Tx(Transaction),
 	Block(Block),
+	/// Heartbeat request; answered with `Pong` by the network layer.
+	Ping,
+	/// Heartbeat response.
+	Pong,
+	/// Request for up to `limit` committed blocks from `from_height` on.
+	/// Answered by the application, typically with `Block` messages sent
+	/// back to the requester alone through [`NetworkHandle::send_to`].
+	GetBlocks { from_height: u64, limit: u32 },
+	/// The sender's pending transactions, announced with
+	/// [`NetworkHandle::announce_txs`]. Handled by the network layer, which
+	/// stops gossiping those transactions to the sender.
+	HaveTxs(BloomFilter),
+	/// Introduction sent to peers whose version is not known yet, and in
+	/// answer to one. Handled by the network layer. `node_id` must be the
+	/// key that signed the envelope.
+	Hello {
+		protocol_version: ProtocolVersion,
+		node_id: [u8; 32],
+	},
+}
+
+impl GossipMessage {
+	/// Short label used for the `kind` metrics label.
+	pub fn kind(&self) -> &'static str {
+		match self {
+			GossipMessage::Tx(_) => "tx",
+			GossipMessage::Block(_) => "block",
+			GossipMessage::Ping => "ping",
+			GossipMessage::Pong => "pong",
+			GossipMessage::GetBlocks { .. } => "get_blocks",
+			GossipMessage::HaveTxs(_) => "have_txs",
+			GossipMessage::Hello { .. } => "hello",
+		}
+	}
+
+	/// Serialize for an envelope payload in `format`.
+	fn to_wire(&self, format: WireFormat) -> Option<Vec<u8>> {
+		match format {
+			WireFormat::Json => format.serialize(self),
+			WireFormat::Bincode => format.serialize(&BinaryMessageRef::from(self)),
+		}
+	}
+
+	/// Undo [`GossipMessage::to_wire`].
+	fn from_wire(bytes: &[u8], format: WireFormat) -> Option<Self> {
+		match format {
+			WireFormat::Json => format.deserialize(bytes),
+			WireFormat::Bincode => format
+				.deserialize::<BinaryMessage>(bytes)
+				.and_then(|msg| msg.try_into().ok()),
+		}
+	}
+}
+
+/// [`GossipMessage`] as a plain externally tagged enum, for binary
+/// formats: bincode cannot read the adjacently tagged JSON layout.
+/// Transactions travel in their canonical [`Transaction::encode`] form.
+#[derive(Serialize)]
+enum BinaryMessageRef<'a> {
+	Tx(Vec<u8>),
+	Block(&'a Block),
+	Ping,
+	Pong,
+	GetBlocks { from_height: u64, limit: u32 },
+	HaveTxs(&'a BloomFilter),
+	Hello {
+		protocol_version: ProtocolVersion,
+		node_id: [u8; 32],
+	},
+}
+
+/// Owned counterpart of [`BinaryMessageRef`]; both encode identically.
+#[derive(Deserialize)]
+enum BinaryMessage {
+	Tx(Vec<u8>),
+	Block(Block),
+	Ping,
+	Pong,
+	GetBlocks { from_height: u64, limit: u32 },
+	HaveTxs(BloomFilter),
+	Hello {
+		protocol_version: ProtocolVersion,
+		node_id: [u8; 32],
+	},
+}
+
+impl<'a> From<&'a GossipMessage> for BinaryMessageRef<'a> {
+	fn from(msg: &'a GossipMessage) -> Self {
+		match msg {
+			GossipMessage::Tx(tx) => BinaryMessageRef::Tx(tx.encode()),
+			GossipMessage::Block(block) => BinaryMessageRef::Block(block),
+			GossipMessage::Ping => BinaryMessageRef::Ping,
+			GossipMessage::Pong => BinaryMessageRef::Pong,
+			GossipMessage::GetBlocks { from_height, limit } => BinaryMessageRef::GetBlocks {
+				from_height: *from_height,
+				limit: *limit,
+			},
+			GossipMessage::HaveTxs(filter) => BinaryMessageRef::HaveTxs(filter),
+			GossipMessage::Hello {
+				protocol_version,
+				node_id,
+			} => BinaryMessageRef::Hello {
+				protocol_version: *protocol_version,
+				node_id: *node_id,
+			},
+		}
+	}
+}
+
+impl TryFrom<BinaryMessage> for GossipMessage {
+	type Error = CodecError;
+
+	fn try_from(msg: BinaryMessage) -> Result<Self, CodecError> {
+		Ok(match msg {
+			BinaryMessage::Tx(bytes) => GossipMessage::Tx(Transaction::decode(&bytes)?),
+			BinaryMessage::Block(block) => GossipMessage::Block(block),
+			BinaryMessage::Ping => GossipMessage::Ping,
+			BinaryMessage::Pong => GossipMessage::Pong,
+			BinaryMessage::GetBlocks { from_height, limit } => {
+				GossipMessage::GetBlocks { from_height, limit }
+			}
+			BinaryMessage::HaveTxs(filter) => GossipMessage::HaveTxs(filter),
+			BinaryMessage::Hello {
+				protocol_version,
+				node_id,
+			} => GossipMessage::Hello {
+				protocol_version,
+				node_id,
+			},
+		})
+	}
+}
+
+/// Signed wrapper carried by every gossip datagram.
+///
+/// `node_id` is the sender's ed25519 public key and `signature` covers the
+/// bytes of `payload`, which is a [`GossipMessage`] encoded in the same
+/// [`WireFormat`] as the envelope.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope {
+	pub node_id: [u8; 32],
+	#[serde(with = "wire::payload")]
+	pub payload: Vec<u8>,
+	pub signature: Vec<u8>,
+}
+
+impl Envelope {
+	/// Serialize `msg` in `format` and sign it with `key`.
+	pub fn seal(key: &SigningKey, msg: &GossipMessage, format: WireFormat) -> Self {
+		let payload = msg
+			.to_wire(format)
+			.expect("gossip message should serialize");
+		let signature = key.sign(&payload).to_bytes().to_vec();
+		Self {
+			node_id: key.verifying_key().to_bytes(),
+			payload,
+			signature,
+		}
+	}
+
+	/// Verify the envelope against `trusted` keys and decode its payload
+	/// from `format`. Returns `None` for unknown senders, bad signatures or
+	/// bad payloads.
+	pub fn open(&self, trusted: &[VerifyingKey], format: WireFormat) -> Option<GossipMessage> {
+		let key = trusted.iter().find(|k| k.as_bytes() == &self.node_id)?;
+		let signature = Signature::from_slice(&self.signature).ok()?;
+		key.verify(&self.payload, &signature).ok()?;
+		GossipMessage::from_wire(&self.payload, format)
+	}
 }
 
 /// Simple networking configuration for a node.
@@ -25,76 +300,1320 @@ pub enum GossipMessage {
 pub struct NetworkConfig {
 	pub listen_addr: SocketAddr,
 	pub peers: Vec<SocketAddr>,
+	/// This node's signing key; its public half is the node id.
+	pub keypair: SigningKey,
+	/// Public keys of peers whose messages are accepted.
+	pub trusted_peers: Vec<VerifyingKey>,
+	/// How often to ping every configured peer.
+	pub heartbeat_interval: Duration,
+	/// How long a peer may stay silent before it is reported as dead.
+	pub peer_timeout: Duration,
+	/// How long to keep an incomplete set of fragments before dropping it.
+	pub fragment_timeout: Duration,
+	/// zstd level for outgoing transactions and blocks; `None` sends them
+	/// uncompressed. Compressed envelopes are always accepted.
+	pub compression_level: Option<i32>,
+	/// Encoding of sent envelopes. Only envelopes in this format are
+	/// accepted, so every peer must use the same one.
+	pub wire_format: WireFormat,
+	/// Score below which a peer is banned; see [`scoring::Offence`] for the
+	/// penalties.
+	pub ban_threshold: i64,
+	/// How long a banned peer's datagrams are dropped and nothing is sent
+	/// to it.
+	pub ban_cooldown: Duration,
+	/// How many peers each transaction or block is sent to, picked at
+	/// random per message. Nodes do not relay what they receive, so a
+	/// fan-out below the number of peers trades delivery for bandwidth.
+	pub fanout: usize,
+	/// Namespaces whose transactions this node handles; transactions in
+	/// any other namespace are dropped on receipt. `None` accepts all of
+	/// them. Blocks are always delivered.
+	pub subscribed_namespaces: Option<HashSet<NamespaceId>>,
+	/// Per-peer limit on messages processed; messages over it are dropped
+	/// without penalty. `None` processes everything.
+	pub rate_limit: Option<PeerRateLimit>,
+	/// Retry delays after transient errors reading the socket.
+	pub recv_backoff: RecvBackoff,
+}
+
+impl NetworkConfig {
+	/// Configuration with the default heartbeat interval and peer timeout.
+	pub fn new(
+		listen_addr: SocketAddr,
+		peers: Vec<SocketAddr>,
+		keypair: SigningKey,
+		trusted_peers: Vec<VerifyingKey>,
+	) -> Self {
+		Self {
+			listen_addr,
+			peers,
+			keypair,
+			trusted_peers,
+			heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+			peer_timeout: DEFAULT_PEER_TIMEOUT,
+			fragment_timeout: DEFAULT_FRAGMENT_TIMEOUT,
+			compression_level: None,
+			wire_format: WireFormat::default(),
+			ban_threshold: DEFAULT_BAN_THRESHOLD,
+			ban_cooldown: DEFAULT_BAN_COOLDOWN,
+			fanout: DEFAULT_FANOUT,
+			subscribed_namespaces: None,
+			rate_limit: None,
+			recv_backoff: RecvBackoff::default(),
+		}
+	}
+}
+
+/// Liveness snapshot of a configured peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerInfo {
+	pub addr: SocketAddr,
+	/// Milliseconds since any message was last received from the peer, or
+	/// `None` if it has never been heard from.
+	pub last_seen_ms: Option<u64>,
+	pub alive: bool,
+	/// Version from the peer's latest `Hello`, or `None` before one
+	/// arrives.
+	pub protocol_version: Option<ProtocolVersion>,
+}
+
+impl PeerInfo {
+	/// False once the peer has advertised a version incompatible with
+	/// [`PROTOCOL_VERSION`]; its messages are then dropped.
+	pub fn is_compatible(&self) -> bool {
+		self.protocol_version
+			.is_none_or(|version| version.is_compatible_with(PROTOCOL_VERSION))
+	}
+}
+
+/// A message queued for the sender loop.
+struct Outgoing {
+	msg: GossipMessage,
+	/// Only peer to send to; `None` gossips to the fan-out.
+	to: Option<SocketAddr>,
 }
 
 /// Handle for sending gossip messages to peers.
 #[derive(Clone)]
 pub struct NetworkHandle {
-	tx: mpsc::Sender<GossipMessage>,
+	tx: mpsc::Sender<Outgoing>,
+	peers: Vec<SocketAddr>,
+	peer_timeout: Duration,
+	last_seen: Arc<Mutex<HashMap<SocketAddr, Instant>>>,
+	peer_versions: Arc<Mutex<HashMap<SocketAddr, ProtocolVersion>>>,
+	scores: Arc<Mutex<PeerScores>>,
+	status: watch::Receiver<NetworkStatus>,
+	tasks: Arc<Vec<JoinHandle<()>>>,
 }
 
 impl NetworkHandle {
-	pub async fn broadcast_tx(&self, tx_obj: Transaction) {
-		let _ = self.tx.send(GossipMessage::Tx(tx_obj)).await;
+	/// Liveness and protocol version of every configured peer, in
+	/// configuration order.
+	pub fn peers(&self) -> Vec<PeerInfo> {
+		let last_seen = self.last_seen.lock().unwrap();
+		let peer_versions = self.peer_versions.lock().unwrap();
+		self.peers
+			.iter()
+			.map(|addr| {
+				let elapsed = last_seen.get(addr).map(|seen| seen.elapsed());
+				PeerInfo {
+					addr: *addr,
+					last_seen_ms: elapsed.map(|e| e.as_millis() as u64),
+					alive: elapsed.is_some_and(|e| e < self.peer_timeout),
+					protocol_version: peer_versions.get(addr).copied(),
+				}
+			})
+			.collect()
+	}
+
+	/// False once a fatal socket error has stopped the receiver loop.
+	pub fn is_healthy(&self) -> bool {
+		*self.status.borrow() == NetworkStatus::Healthy
+	}
+
+	/// Watch the network's status, e.g. to log or exit when it fails.
+	pub fn status(&self) -> watch::Receiver<NetworkStatus> {
+		self.status.clone()
+	}
+
+	/// Peers currently banned for misbehaving, ordered by address.
+	pub fn banned_peers(&self) -> Vec<BannedPeer> {
+		self.scores.lock().unwrap().banned(Instant::now())
+	}
+
+	/// Stop the gossip loops and release the socket.
+	pub fn shutdown(&self) {
+		for task in self.tasks.iter() {
+			task.abort();
+		}
+	}
+
+	/// Queue `tx_obj` for gossip, waiting for room in the send queue.
+	pub async fn broadcast_tx(&self, tx_obj: Transaction) -> Result<(), NetworkError> {
+		self.send(GossipMessage::Tx(tx_obj), None).await
+	}
+
+	/// Queue `block` for gossip, waiting for room in the send queue.
+	pub async fn broadcast_block(&self, block: Block) -> Result<(), NetworkError> {
+		self.send(GossipMessage::Block(block), None).await
+	}
+
+	/// Queue `tx_obj` for gossip, or drop it if the send queue is full.
+	pub fn try_broadcast_tx(&self, tx_obj: Transaction) -> Result<(), NetworkError> {
+		self.try_send(GossipMessage::Tx(tx_obj), None)
+	}
+
+	/// Queue `block` for gossip, or drop it if the send queue is full.
+	pub fn try_broadcast_block(&self, block: Block) -> Result<(), NetworkError> {
+		self.try_send(GossipMessage::Block(block), None)
+	}
+
+	/// Announce the transactions identified by `ids`, typically every
+	/// pending one, to all peers, so they stop forwarding those to us.
+	/// Each announcement replaces the previous one.
+	pub async fn announce_txs<'a>(
+		&self,
+		ids: impl ExactSizeIterator<Item = &'a TxId>,
+	) -> Result<(), NetworkError> {
+		self.send(GossipMessage::HaveTxs(BloomFilter::for_txs(ids)), None)
+			.await
+	}
+
+	/// Queue `msg` for `peer` alone, e.g. a reply to the address a message
+	/// came from, waiting for room in the send queue. Nothing is sent while
+	/// `peer` is banned.
+	pub async fn send_to(&self, peer: SocketAddr, msg: GossipMessage) -> Result<(), NetworkError> {
+		self.send(msg, Some(peer)).await
 	}
 
-	pub async fn broadcast_block(&self, block: Block) {
-		let _ = self.tx.send(GossipMessage::Block(block)).await;
+	/// Queue `msg` for `peer` alone, or drop it if the send queue is full.
+	pub fn try_send_to(&self, peer: SocketAddr, msg: GossipMessage) -> Result<(), NetworkError> {
+		self.try_send(msg, Some(peer))
+	}
+
+	async fn send(&self, msg: GossipMessage, to: Option<SocketAddr>) -> Result<(), NetworkError> {
+		let kind = msg.kind();
+		self.tx.send(Outgoing { msg, to }).await.map_err(|_| {
+			sequencer_metrics::record_gossip_dropped(kind);
+			NetworkError::Closed
+		})
+	}
+
+	fn try_send(&self, msg: GossipMessage, to: Option<SocketAddr>) -> Result<(), NetworkError> {
+		let kind = msg.kind();
+		self.tx.try_send(Outgoing { msg, to }).map_err(|e| {
+			sequencer_metrics::record_gossip_dropped(kind);
+			match e {
+				TrySendError::Full(_) => NetworkError::ChannelFull,
+				TrySendError::Closed(_) => NetworkError::Closed,
+			}
+		})
 	}
 }
 
 /// Start a UDP gossip loop.
 ///
 /// - Binds to `config.listen_addr`.
-/// - Broadcasts any outgoing messages to all configured peers.
-/// - For every incoming message, calls `on_message`.
+/// - Broadcasts any outgoing messages to all configured peers, splitting
+///   messages larger than one datagram into fragments.
+/// - Pings every peer each `config.heartbeat_interval` and records when
+///   each peer was last heard from.
+/// - Sends a `Hello` along with the ping to peers whose version is not
+///   known yet, and answers each `Hello` with one. Drops every other
+///   message from a peer whose advertised major version differs from
+///   ours.
+/// - Drops incoming messages that are not signed by a trusted peer, and
+///   those encoded in a different `config.wire_format`.
+/// - Penalizes peers for malformed, badly signed or inconsistent messages,
+///   and ignores banned peers in both directions.
+/// - Drops transactions outside `config.subscribed_namespaces`.
+/// - For every other incoming message except heartbeats, calls `on_message`
+///   with the address it came from, so the application can answer that
+///   peer alone with [`NetworkHandle::send_to`].
+/// - Sends messages queued with `send_to` only to their peer, and others
+///   to up to `config.fanout` peers.
+/// - Waits out transient socket errors with `config.recv_backoff`. A fatal
+///   one stops the receiver loop and turns
+///   [`NetworkHandle::is_healthy`] false.
 pub async fn start_network<F>(
 	config: NetworkConfig,
 	on_message: F,
 ) -> NetworkHandle
 where
-	F: Fn(GossipMessage) + Send + Sync + 'static,
+	F: Fn(SocketAddr, GossipMessage) + Send + Sync + 'static,
 {
 	let socket = UdpSocket::bind(config.listen_addr)
 		.await
 		.expect("failed to bind UDP gossip socket");
-	let (tx, mut rx) = mpsc::channel::<GossipMessage>(1024);
+	let (tx, mut rx) = mpsc::channel::<Outgoing>(SEND_QUEUE_CAPACITY);
  
-	let socket = std::sync::Arc::new(socket);
-	let on_message = std::sync::Arc::new(on_message);
-	let recv_socket = std::sync::Arc::clone(&socket);
+	let socket = Arc::new(socket);
+	let on_message = Arc::new(on_message);
+	let recv_socket = Arc::clone(&socket);
 	let peers = config.peers.clone();
+	let last_seen = Arc::new(Mutex::new(HashMap::new()));
+	let recv_last_seen = Arc::clone(&last_seen);
+	let keypair = config.keypair.clone();
+	let trusted_peers = config.trusted_peers.clone();
+	let scores = Arc::new(Mutex::new(PeerScores::new(
+		config.ban_threshold,
+		config.ban_cooldown,
+	)));
+	let recv_scores = Arc::clone(&scores);
+	// Latest `HaveTxs` filter of each peer.
+	let peer_txs: Arc<Mutex<HashMap<SocketAddr, BloomFilter>>> = Arc::default();
+	let recv_peer_txs = Arc::clone(&peer_txs);
+	let peer_versions: Arc<Mutex<HashMap<SocketAddr, ProtocolVersion>>> = Arc::default();
+	let recv_peer_versions = Arc::clone(&peer_versions);
+	let penalize = move |addr: SocketAddr, offence: Offence| {
+		if offence == Offence::Malformed {
+			debug!(peer = %addr, "dropping undecodable gossip datagram");
+			sequencer_metrics::record_gossip_decode_error(addr.to_string());
+		}
+		if recv_scores.lock().unwrap().penalize(addr, offence, Instant::now()) {
+			sequencer_metrics::record_gossip_peer_banned();
+		}
+	};
+	let banned = {
+		let scores = Arc::clone(&scores);
+		move |addr: SocketAddr| scores.lock().unwrap().is_banned(addr, Instant::now())
+	};
+	let wire_format = config.wire_format;
+	let subscribed = config.subscribed_namespaces;
+	let pong = wire_format
+		.encode(&Envelope::seal(&keypair, &GossipMessage::Pong, wire_format))
+		.expect("envelope should serialize");
+	let hello = GossipMessage::Hello {
+		protocol_version: PROTOCOL_VERSION,
+		node_id: keypair.verifying_key().to_bytes(),
+	};
+	let hello = wire_format
+		.encode(&Envelope::seal(&keypair, &hello, wire_format))
+		.expect("envelope should serialize");
+	let heartbeat_interval = config.heartbeat_interval;
 
 	// Receiver loop.
-	tokio::spawn(async move {
+	let mut reassembler = fragment::Reassembler::new(config.fragment_timeout);
+	let mut rate_limiter = config.rate_limit.map(PeerRateLimiter::new);
+	let recv_hello = hello.clone();
+	let mut recv_backoff = backoff::Backoff::new(config.recv_backoff);
+	let (status_tx, status_rx) = watch::channel(NetworkStatus::Healthy);
+	// Incomplete fragment sets are swept on a timer, not per datagram.
+	let expire_every = (config.fragment_timeout / 2).max(Duration::from_millis(10));
+	let mut expire_fragments = interval(expire_every);
+	let receiver = tokio::spawn(async move {
 		let mut buf = vec![0u8; 64 * 1024];
+		// When each peer was last answered a `Hello`. Two nodes would
+		// otherwise keep answering each other's answers.
+		let mut hello_answered: HashMap<SocketAddr, Instant> = HashMap::new();
 		loop {
-			match recv_socket.recv_from(&mut buf).await {
-				Ok((len, _addr)) => {
-					if let Ok(msg) = serde_json::from_slice::<GossipMessage>(&buf[..len]) {
-						let handler = on_message.clone();
-						tokio::spawn(async move { handler(msg) });
+			let received = tokio::select! {
+				received = backoff::recv_next(
+					&*recv_socket,
+					&mut buf,
+					&mut recv_backoff,
+					&status_tx,
+				) => received,
+				_ = expire_fragments.tick() => {
+					reassembler.expire(Instant::now());
+					continue;
+				}
+			};
+			let Some((len, addr)) = received else {
+				break;
+			};
+			if banned(addr) {
+				continue;
+			}
+			let bytes = match reassembler.accept(addr, &buf[..len]) {
+				fragment::Incoming::Complete(bytes) => bytes,
+				fragment::Incoming::Pending => continue,
+				fragment::Incoming::Malformed => {
+					penalize(addr, Offence::Malformed);
+					continue;
+				}
+			};
+			if let Some(limiter) = &mut rate_limiter {
+				if !limiter.allow(addr, Instant::now()) {
+					sequencer_metrics::record_gossip_rate_limited(addr.to_string());
+					continue;
+				}
+			}
+			let Some(bytes) = compression::decode(bytes) else {
+				penalize(addr, Offence::Malformed);
+				continue;
+			};
+			let envelope = match wire_format.decode::<Envelope>(&bytes) {
+				Ok(envelope) => envelope,
+				Err(wire::DecodeError::Mismatch(format)) => {
+					debug!(peer = %addr, ?format, "dropping gossip in another wire format");
+					continue;
+				}
+				Err(wire::DecodeError::Malformed) => {
+					penalize(addr, Offence::Malformed);
+					continue;
+				}
+			};
+			let Some(msg) = envelope.open(&trusted_peers, wire_format) else {
+				penalize(addr, Offence::BadSignature);
+				continue;
+			};
+			let incompatible = !matches!(msg, GossipMessage::Hello { .. })
+				&& recv_peer_versions
+					.lock()
+					.unwrap()
+					.get(&addr)
+					.is_some_and(|v| !v.is_compatible_with(PROTOCOL_VERSION));
+			if incompatible {
+				debug!(
+					peer = %addr,
+					kind = msg.kind(),
+					"dropping gossip from a peer on an incompatible protocol version"
+				);
+				continue;
+			}
+			if let GossipMessage::Block(block) = &msg {
+				if merkle_root(&block.txs) != block.header.tx_root {
+					penalize(addr, Offence::InvalidBlock);
+					continue;
+				}
+			}
+			sequencer_metrics::record_gossip_received(msg.kind());
+			recv_last_seen.lock().unwrap().insert(addr, Instant::now());
+			match msg {
+				GossipMessage::Ping => {
+					if recv_socket.send_to(&pong, addr).await.is_ok() {
+						sequencer_metrics::record_gossip_sent("pong");
+					}
+				}
+				GossipMessage::Pong => {}
+				GossipMessage::Hello {
+					protocol_version,
+					node_id,
+				} => {
+					if node_id != envelope.node_id {
+						penalize(addr, Offence::BadSignature);
+						continue;
+					}
+					let previous =
+						recv_peer_versions.lock().unwrap().insert(addr, protocol_version);
+					if previous != Some(protocol_version)
+						&& !protocol_version.is_compatible_with(PROTOCOL_VERSION)
+					{
+						warn!(
+							peer = %addr,
+							version = %protocol_version,
+							ours = %PROTOCOL_VERSION,
+							"peer speaks an incompatible gossip protocol; ignoring it"
+						);
+					}
+					let now = Instant::now();
+					let answered = hello_answered.get(&addr).is_some_and(|at| {
+						now.duration_since(*at) < heartbeat_interval
+					});
+					if !answered {
+						hello_answered.insert(addr, now);
+						if recv_socket.send_to(&recv_hello, addr).await.is_ok() {
+							sequencer_metrics::record_gossip_sent("hello");
+						}
 					}
 				}
-				Err(_e) => {
-					// Back off briefly on error.
-					sleep(Duration::from_millis(100)).await;
+				GossipMessage::HaveTxs(filter) => {
+					if filter.is_well_formed() {
+						recv_peer_txs.lock().unwrap().insert(addr, filter);
+					} else {
+						penalize(addr, Offence::Malformed);
+					}
+				}
+				GossipMessage::Tx(tx)
+					if subscribed.as_ref().is_some_and(|ns| !ns.contains(&tx.namespace)) => {}
+				msg => {
+					let handler = on_message.clone();
+					tokio::spawn(async move { handler(addr, msg) });
+				}
+			}
+		}
+	});
+
+	// Heartbeat loop; also refreshes the alive-peers gauge.
+	let ping_socket = Arc::clone(&socket);
+	let ping_peers = peers.clone();
+	let ping_last_seen = Arc::clone(&last_seen);
+	let ping_scores = Arc::clone(&scores);
+	let ping_peer_versions = Arc::clone(&peer_versions);
+	let peer_timeout = config.peer_timeout;
+	let heartbeat = tokio::spawn(async move {
+		let mut ticker = interval(heartbeat_interval);
+		let bytes = wire_format
+			.encode(&Envelope::seal(&keypair, &GossipMessage::Ping, wire_format))
+			.expect("envelope should serialize");
+		loop {
+			ticker.tick().await;
+			for peer in &ping_peers {
+				if ping_scores.lock().unwrap().is_banned(*peer, Instant::now()) {
+					continue;
+				}
+				if ping_socket.send_to(&bytes, peer).await.is_ok() {
+					sequencer_metrics::record_gossip_sent("ping");
+				}
+				let known = ping_peer_versions.lock().unwrap().contains_key(peer);
+				if !known && ping_socket.send_to(&hello, peer).await.is_ok() {
+					sequencer_metrics::record_gossip_sent("hello");
 				}
 			}
+			let alive = {
+				let last_seen = ping_last_seen.lock().unwrap();
+				ping_peers
+					.iter()
+					.filter(|addr| {
+						last_seen
+							.get(addr)
+							.is_some_and(|seen| seen.elapsed() < peer_timeout)
+					})
+					.count()
+			};
+			sequencer_metrics::record_peers_alive(alive);
 		}
 	});
 
-	// Sender loop.
+	// Sender loop. Message ids only need to be unique per sender within
+	// the fragment timeout; seeding from the clock avoids reuse across
+	// restarts.
 	let send_socket = socket;
-	tokio::spawn(async move {
-		while let Some(msg) = rx.recv().await {
-			if let Ok(bytes) = serde_json::to_vec(&msg) {
-				for peer in &peers {
-					let _ = send_socket.send_to(&bytes, peer).await;
+	let send_scores = Arc::clone(&scores);
+	let fanout_seed = RandomState::new();
+	let next_msg_id = AtomicU64::new(
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_nanos() as u64,
+	);
+	let sender = tokio::spawn(async move {
+		while let Some(Outgoing { msg, to }) = rx.recv().await {
+			let envelope = Envelope::seal(&config.keypair, &msg, wire_format);
+			let Some(bytes) = wire_format.encode(&envelope) else {
+				continue;
+			};
+			if bytes.len() > compression::MAX_DECOMPRESSED {
+				// Peers would refuse to expand it.
+				continue;
+			}
+			let uncompressed_len = bytes.len();
+			let bytes = compression::encode(bytes, config.compression_level);
+			let compressed_len = bytes.len();
+			if !fragment::fits(bytes.len()) {
+				// Too large even for the maximum number of fragments.
+				continue;
+			}
+			let msg_id = next_msg_id.fetch_add(1, Ordering::Relaxed);
+			let datagrams = fragment::encode(msg_id, bytes);
+			let targets: Vec<SocketAddr> = {
+				let mut scores = send_scores.lock().unwrap();
+				let now = Instant::now();
+				match to {
+					Some(peer) => vec![peer],
+					None => peers.clone(),
+				}
+				.into_iter()
+				.filter(|peer| !scores.is_banned(*peer, now))
+				.collect()
+			};
+			let targets = match (to, &msg) {
+				(Some(_), _) | (None, GossipMessage::HaveTxs(_)) => targets,
+				(None, GossipMessage::Tx(tx)) => {
+					// Best effort: on a false positive the peer misses
+					// this forward.
+					let id = tx.id();
+					let peer_txs = peer_txs.lock().unwrap();
+					let targets = targets
+						.into_iter()
+						.filter(|peer| !peer_txs.get(peer).is_some_and(|f| f.contains(&id)))
+						.collect();
+					pick_peers(targets, config.fanout, fanout_seed.hash_one(msg_id))
+				}
+				(None, _) => pick_peers(targets, config.fanout, fanout_seed.hash_one(msg_id)),
+			};
+			for peer in &targets {
+				let mut sent = true;
+				for datagram in &datagrams {
+					sent &= send_socket.send_to(datagram, peer).await.is_ok();
+				}
+				if sent {
+					sequencer_metrics::record_gossip_sent(msg.kind());
+					if let GossipMessage::Block(_) = msg {
+						sequencer_metrics::record_gossip_block_bytes(
+							uncompressed_len,
+							compressed_len,
+						);
+					}
 				}
 			}
 		}
 	});
 
-	NetworkHandle { tx }
+	NetworkHandle {
+		tx,
+		peers: config.peers,
+		peer_timeout: config.peer_timeout,
+		last_seen,
+		peer_versions,
+		scores,
+		status: status_rx,
+		tasks: Arc::new(vec![receiver, heartbeat, sender]),
+	}
+}
+
+/// Up to `fanout` of `peers`, chosen uniformly at random from `seed`; all
+/// of them when `fanout` covers the list.
+fn pick_peers(mut peers: Vec<SocketAddr>, fanout: usize, seed: u64) -> Vec<SocketAddr> {
+	if fanout >= peers.len() {
+		return peers;
+	}
+	// Partial Fisher-Yates shuffle driven by splitmix64.
+	let mut state = seed;
+	for i in 0..fanout {
+		state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^= z >> 31;
+		let j = i + (z % (peers.len() - i) as u64) as usize;
+		peers.swap(i, j);
+	}
+	peers.truncate(fanout);
+	peers
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::time::sleep;
+
+	/// Reserve a free local UDP port.
+	fn free_addr() -> SocketAddr {
+		let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket.local_addr().unwrap()
+	}
+
+	fn key(seed: u8) -> SigningKey {
+		SigningKey::from_bytes(&[seed; 32])
+	}
+
+	fn config(listen_addr: SocketAddr, peer: SocketAddr) -> NetworkConfig {
+		NetworkConfig {
+			listen_addr,
+			peers: vec![peer],
+			keypair: key(1),
+			trusted_peers: vec![key(1).verifying_key()],
+			heartbeat_interval: Duration::from_millis(20),
+			peer_timeout: Duration::from_millis(200),
+			fragment_timeout: DEFAULT_FRAGMENT_TIMEOUT,
+			compression_level: None,
+			wire_format: WireFormat::Json,
+			ban_threshold: DEFAULT_BAN_THRESHOLD,
+			ban_cooldown: DEFAULT_BAN_COOLDOWN,
+			fanout: DEFAULT_FANOUT,
+			subscribed_namespaces: None,
+			rate_limit: None,
+			recv_backoff: RecvBackoff::default(),
+		}
+	}
+
+	fn tx_message(nonce: u64) -> GossipMessage {
+		GossipMessage::Tx(Transaction {
+			chain_id: 0,
+			from: types::Address::default(),
+			namespace: types::NamespaceId(1),
+			gas_price: 1,
+			nonce,
+			valid_until_height: None,
+			priority: false,
+			payload: vec![],
+			signature: vec![],
+			pow_nonce: 0,
+		})
+	}
+
+	/// Start a node that trusts only `trusted` and forwards received
+	/// messages to a channel.
+	async fn listening_node(
+		trusted: SigningKey,
+	) -> (
+		SocketAddr,
+		NetworkHandle,
+		mpsc::UnboundedReceiver<GossipMessage>,
+	) {
+		listening_node_in(trusted, WireFormat::Json).await
+	}
+
+	/// [`listening_node`] speaking `wire_format`.
+	async fn listening_node_in(
+		trusted: SigningKey,
+		wire_format: WireFormat,
+	) -> (
+		SocketAddr,
+		NetworkHandle,
+		mpsc::UnboundedReceiver<GossipMessage>,
+	) {
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		cfg.trusted_peers = vec![trusted.verifying_key()];
+		cfg.wire_format = wire_format;
+		let (tx, rx) = mpsc::unbounded_channel();
+		let handle = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+		(addr, handle, rx)
+	}
+
+	/// Serialize `envelope` as a JSON node would send it.
+	fn encode(envelope: &Envelope) -> Vec<u8> {
+		WireFormat::Json.encode(envelope).unwrap()
+	}
+
+	async fn send_raw(to: SocketAddr, bytes: &[u8]) {
+		let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		socket.send_to(bytes, to).await.unwrap();
+	}
+
+	fn nonce_of(msg: GossipMessage) -> u64 {
+		match msg {
+			GossipMessage::Tx(tx) => tx.nonce,
+			other => panic!("unexpected message {other:?}"),
+		}
+	}
+
+	async fn wait_for(handle: &NetworkHandle, alive: bool) -> bool {
+		for _ in 0..100 {
+			if handle.peers().iter().all(|p| p.alive == alive) {
+				return true;
+			}
+			sleep(Duration::from_millis(20)).await;
+		}
+		false
+	}
+
+	#[tokio::test]
+	async fn peers_see_each_other_and_detect_shutdown() {
+		let (addr_a, addr_b) = (free_addr(), free_addr());
+		let a = start_network(config(addr_a, addr_b), |_, _| {}).await;
+		let b = start_network(config(addr_b, addr_a), |_, _| {}).await;
+
+		assert!(wait_for(&a, true).await, "a should see b as alive");
+		assert!(wait_for(&b, true).await, "b should see a as alive");
+		assert_eq!(a.peers()[0].addr, addr_b);
+		assert!(a.peers()[0].last_seen_ms.is_some());
+
+		b.shutdown();
+		assert!(wait_for(&a, false).await, "a should mark b as dead");
+	}
+
+	#[tokio::test]
+	async fn signed_message_from_trusted_peer_is_delivered() {
+		let (addr, _node, mut rx) = listening_node(key(1)).await;
+
+		let envelope = Envelope::seal(&key(1), &tx_message(7), WireFormat::Json);
+		send_raw(addr, &encode(&envelope)).await;
+
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 7);
+	}
+
+	#[tokio::test]
+	async fn forged_and_unsigned_messages_are_dropped() {
+		let (addr, _node, mut rx) = listening_node(key(1)).await;
+
+		// Unsigned legacy message.
+		send_raw(addr, &serde_json::to_vec(&tx_message(1)).unwrap()).await;
+		// Signed by an untrusted key.
+		let untrusted = Envelope::seal(&key(2), &tx_message(2), WireFormat::Json);
+		send_raw(addr, &encode(&untrusted)).await;
+		// Claims the trusted node id but is signed by another key.
+		let mut forged = Envelope::seal(&key(2), &tx_message(3), WireFormat::Json);
+		forged.node_id = key(1).verifying_key().to_bytes();
+		send_raw(addr, &encode(&forged)).await;
+		// Valid signature, tampered payload.
+		let mut tampered = Envelope::seal(&key(1), &tx_message(4), WireFormat::Json);
+		tampered.payload = serde_json::to_vec(&tx_message(5)).unwrap();
+		send_raw(addr, &encode(&tampered)).await;
+		// Finally a genuine message, which must be the first one delivered.
+		let genuine = Envelope::seal(&key(1), &tx_message(6), WireFormat::Json);
+		send_raw(addr, &encode(&genuine)).await;
+
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 6);
+		sleep(Duration::from_millis(50)).await;
+		assert!(rx.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn peer_sending_malformed_datagrams_is_banned() {
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		cfg.ban_threshold = -30;
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		for _ in 0..4 {
+			peer.send_to(b"not an envelope", addr).await.unwrap();
+		}
+		let mut banned = Vec::new();
+		for _ in 0..100 {
+			banned = node.banned_peers();
+			if !banned.is_empty() {
+				break;
+			}
+			sleep(Duration::from_millis(20)).await;
+		}
+		assert_eq!(banned.len(), 1);
+		assert_eq!(banned[0].addr, peer.local_addr().unwrap());
+		assert!(banned[0].remaining_ms <= DEFAULT_BAN_COOLDOWN.as_millis() as u64);
+
+		// Even a genuine message from the banned peer is now ignored.
+		let genuine = Envelope::seal(&key(1), &tx_message(1), WireFormat::Json);
+		peer.send_to(&encode(&genuine), addr)
+			.await
+			.unwrap();
+		sleep(Duration::from_millis(100)).await;
+		assert!(rx.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn full_send_queue_reports_channel_full() {
+		let node = start_network(config(free_addr(), free_addr()), |_, _| {}).await;
+		let tx = |nonce| match tx_message(nonce) {
+			GossipMessage::Tx(tx) => tx,
+			_ => unreachable!(),
+		};
+
+		// The single-threaded test runtime cannot run the sender loop until
+		// this task yields, so nothing drains the queue meanwhile.
+		for nonce in 0..SEND_QUEUE_CAPACITY as u64 {
+			assert_eq!(node.try_broadcast_tx(tx(nonce)), Ok(()));
+		}
+		assert_eq!(node.try_broadcast_tx(tx(0)), Err(NetworkError::ChannelFull));
+
+		node.shutdown();
+		sleep(Duration::from_millis(50)).await;
+		assert_eq!(node.broadcast_tx(tx(0)).await, Err(NetworkError::Closed));
+		assert_eq!(node.try_broadcast_tx(tx(0)), Err(NetworkError::Closed));
+	}
+
+	#[tokio::test]
+	async fn messages_reach_at_most_fanout_peers() {
+		let mut sockets = Vec::new();
+		for _ in 0..8 {
+			sockets.push(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+		}
+		let mut cfg = config(free_addr(), free_addr());
+		cfg.peers = sockets.iter().map(|s| s.local_addr().unwrap()).collect();
+		cfg.heartbeat_interval = Duration::from_secs(60);
+		cfg.fanout = 3;
+		let node = start_network(cfg, |_, _| {}).await;
+
+		for nonce in 0..20 {
+			node.broadcast_tx(match tx_message(nonce) {
+				GossipMessage::Tx(tx) => tx,
+				_ => unreachable!(),
+			})
+			.await
+			.unwrap();
+		}
+
+		let mut receivers: HashMap<u64, usize> = HashMap::new();
+		let mut buf = vec![0u8; 65_536];
+		for socket in &sockets {
+			while let Ok(Ok((len, _))) =
+				tokio::time::timeout(Duration::from_millis(200), socket.recv_from(&mut buf)).await
+			{
+				let envelope: Envelope = WireFormat::Json.decode(&buf[..len]).unwrap();
+				match envelope.open(&[key(1).verifying_key()], WireFormat::Json).unwrap() {
+					GossipMessage::Tx(tx) => *receivers.entry(tx.nonce).or_default() += 1,
+					// The first heartbeat still goes to every peer.
+					GossipMessage::Ping | GossipMessage::Hello { .. } => {}
+					other => panic!("unexpected message {other:?}"),
+				}
+			}
+		}
+		assert_eq!(receivers.len(), 20);
+		assert!(receivers.values().all(|count| (1..=3).contains(count)));
+		node.shutdown();
+	}
+
+	#[tokio::test]
+	async fn get_blocks_reply_goes_only_to_the_requester() {
+		let requester = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let bystander = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let mut cfg = config(free_addr(), requester.local_addr().unwrap());
+		cfg.peers.push(bystander.local_addr().unwrap());
+		cfg.heartbeat_interval = Duration::from_secs(60);
+		cfg.wire_format = WireFormat::Bincode;
+		let addr = cfg.listen_addr;
+		// Answer each request with the first block asked for.
+		let handle: Arc<std::sync::OnceLock<NetworkHandle>> = Arc::default();
+		let replies = Arc::clone(&handle);
+		let node = start_network(cfg, move |from, msg| {
+			if let GossipMessage::GetBlocks { from_height, .. } = msg {
+				let block = Block {
+					header: types::BlockHeader {
+						height: from_height,
+						parent: None,
+						tx_root: merkle_root(&[]),
+						gas_used: 0,
+						fees: 0,
+						state_root: types::Hash([0; 32]),
+						timestamp_ms: 0,
+						proposer: [0; 32],
+					},
+					txs: vec![],
+				};
+				let handle = replies.get().unwrap();
+				handle.try_send_to(from, GossipMessage::Block(block)).unwrap();
+			}
+		})
+		.await;
+		assert!(handle.set(node.clone()).is_ok());
+
+		let request = GossipMessage::GetBlocks {
+			from_height: 5,
+			limit: 10,
+		};
+		let envelope = Envelope::seal(&key(1), &request, WireFormat::Bincode);
+		let bytes = WireFormat::Bincode.encode(&envelope).unwrap();
+		requester.send_to(&bytes, addr).await.unwrap();
+
+		// Both peers get the first heartbeat; only the requester gets the
+		// reply.
+		let mut buf = vec![0u8; 65_536];
+		let mut received = HashMap::new();
+		for (name, socket) in [("requester", &requester), ("bystander", &bystander)] {
+			while let Ok(Ok((len, _))) =
+				tokio::time::timeout(Duration::from_millis(300), socket.recv_from(&mut buf)).await
+			{
+				let envelope: Envelope = WireFormat::Bincode.decode(&buf[..len]).unwrap();
+				match envelope.open(&[key(1).verifying_key()], WireFormat::Bincode).unwrap() {
+					GossipMessage::Ping | GossipMessage::Hello { .. } => {}
+					GossipMessage::Block(block) => {
+						received.insert(name, block.header.height);
+					}
+					other => panic!("unexpected message {other:?}"),
+				}
+			}
+		}
+		assert_eq!(received, HashMap::from([("requester", 5)]));
+		node.shutdown();
+	}
+
+	#[tokio::test]
+	async fn transactions_a_peer_announced_are_not_forwarded_to_it() {
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let mut cfg = config(free_addr(), peer.local_addr().unwrap());
+		cfg.heartbeat_interval = Duration::from_secs(60);
+		let addr = cfg.listen_addr;
+		let node = start_network(cfg, |_, _| {}).await;
+
+		let txs: Vec<Transaction> = (0..20)
+			.map(|nonce| match tx_message(nonce) {
+				GossipMessage::Tx(tx) => tx,
+				_ => unreachable!(),
+			})
+			.collect();
+		let ids: Vec<TxId> = txs.iter().map(Transaction::id).collect();
+		let have = GossipMessage::HaveTxs(BloomFilter::for_txs(ids.iter()));
+		let envelope = Envelope::seal(&key(1), &have, WireFormat::Json);
+		peer.send_to(&encode(&envelope), addr).await.unwrap();
+		while node.peers()[0].last_seen_ms.is_none() {
+			sleep(Duration::from_millis(5)).await;
+		}
+
+		for tx in txs {
+			node.broadcast_tx(tx).await.unwrap();
+		}
+		let fresh = match tx_message(99) {
+			GossipMessage::Tx(tx) => tx,
+			_ => unreachable!(),
+		};
+		node.broadcast_tx(fresh).await.unwrap();
+
+		let mut forwarded = Vec::new();
+		let mut buf = vec![0u8; 65_536];
+		while let Ok(Ok((len, _))) =
+			tokio::time::timeout(Duration::from_millis(300), peer.recv_from(&mut buf)).await
+		{
+			let envelope: Envelope = WireFormat::Json.decode(&buf[..len]).unwrap();
+			match envelope.open(&[key(1).verifying_key()], WireFormat::Json).unwrap() {
+				GossipMessage::Tx(tx) => forwarded.push(tx.nonce),
+				GossipMessage::Ping | GossipMessage::Hello { .. } => {}
+				other => panic!("unexpected message {other:?}"),
+			}
+		}
+		assert_eq!(forwarded, vec![99]);
+		node.shutdown();
+	}
+
+	#[tokio::test]
+	async fn peer_on_an_incompatible_version_is_ignored() {
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let mut cfg = config(free_addr(), peer.local_addr().unwrap());
+		cfg.keypair = key(9);
+		cfg.heartbeat_interval = Duration::from_secs(60);
+		let addr = cfg.listen_addr;
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+		let send = |msg: GossipMessage| {
+			let bytes = encode(&Envelope::seal(&key(1), &msg, WireFormat::Json));
+			let peer = &peer;
+			async move { peer.send_to(&bytes, addr).await.unwrap() }
+		};
+		let hello = |major| GossipMessage::Hello {
+			protocol_version: ProtocolVersion { major, minor: 0 },
+			node_id: key(1).verifying_key().to_bytes(),
+		};
+
+		send(hello(PROTOCOL_VERSION.major + 1)).await;
+		send(tx_message(1)).await;
+		while node.peers()[0].protocol_version.is_none() {
+			sleep(Duration::from_millis(5)).await;
+		}
+		assert!(!node.peers()[0].is_compatible());
+		sleep(Duration::from_millis(100)).await;
+		assert!(rx.try_recv().is_err());
+
+		// The node introduced itself in return.
+		let mut buf = vec![0u8; 65_536];
+		let mut answered = false;
+		while let Ok(Ok((len, _))) =
+			tokio::time::timeout(Duration::from_millis(200), peer.recv_from(&mut buf)).await
+		{
+			let envelope: Envelope = WireFormat::Json.decode(&buf[..len]).unwrap();
+			if let Some(GossipMessage::Hello { protocol_version, .. }) =
+				envelope.open(&[key(9).verifying_key()], WireFormat::Json)
+			{
+				answered |= protocol_version == PROTOCOL_VERSION;
+			}
+		}
+		assert!(answered);
+
+		// Once upgraded, the peer is heard again.
+		send(hello(PROTOCOL_VERSION.major)).await;
+		send(tx_message(2)).await;
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 2);
+		assert!(node.peers()[0].is_compatible());
+		node.shutdown();
+	}
+
+	#[test]
+	fn pick_peers_keeps_all_when_fanout_covers_them() {
+		let peers: Vec<SocketAddr> = (0..5)
+			.map(|i| SocketAddr::from(([127, 0, 0, 1], 9000 + i)))
+			.collect();
+		assert_eq!(pick_peers(peers.clone(), 5, 7), peers);
+		assert_eq!(pick_peers(peers.clone(), DEFAULT_FANOUT, 7), peers);
+
+		let picked = pick_peers(peers.clone(), 2, 7);
+		assert_eq!(picked.len(), 2);
+		assert_ne!(picked[0], picked[1]);
+		assert!(picked.iter().all(|p| peers.contains(p)));
+	}
+
+	/// A block whose envelope needs well over ten fragments.
+	fn large_block() -> Block {
+		let txs: Vec<_> = (0..200)
+			.map(|nonce| match tx_message(nonce) {
+				GossipMessage::Tx(tx) => tx.id(),
+				_ => unreachable!(),
+			})
+			.collect();
+		Block {
+			header: types::BlockHeader {
+				height: 1,
+				parent: None,
+				tx_root: types::merkle_root(&txs),
+				gas_used: 0,
+				fees: 0,
+				state_root: types::Hash([0u8; 32]),
+				timestamp_ms: 0,
+				proposer: [0u8; 32],
+			},
+			txs,
+		}
+	}
+
+	/// Gossip `block` from a node using `compression_level` to one that
+	/// trusts it, and return what arrived.
+	async fn gossip_block(block: Block, compression_level: Option<i32>) -> GossipMessage {
+		let (addr, _node, mut rx) = listening_node(key(1)).await;
+		let mut cfg = config(free_addr(), addr);
+		cfg.compression_level = compression_level;
+		// Keep heartbeats out of the way of the block.
+		cfg.heartbeat_interval = Duration::from_secs(60);
+		let sender = start_network(cfg, |_, _| {}).await;
+
+		sender.broadcast_block(block).await.unwrap();
+
+		tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap()
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn large_block_roundtrips_through_fragments() {
+		let block = large_block();
+		let encoded = encode(&Envelope::seal(
+			&key(1),
+			&GossipMessage::Block(block.clone()),
+			WireFormat::Json,
+		));
+		assert!(fragment::encode(0, encoded).len() > 10);
+
+		match gossip_block(block.clone(), None).await {
+			GossipMessage::Block(received) => assert_eq!(received, block),
+			other => panic!("unexpected message {other:?}"),
+		}
+	}
+
+	#[tokio::test]
+	async fn compressed_block_is_decoded_by_receiver() {
+		let block = large_block();
+		let encoded = encode(&Envelope::seal(
+			&key(1),
+			&GossipMessage::Block(block.clone()),
+			WireFormat::Json,
+		));
+		let plain_fragments = fragment::encode(0, encoded.clone()).len();
+		let compressed = compression::encode(encoded, Some(3));
+		assert!(fragment::encode(0, compressed).len() < plain_fragments);
+
+		// The receiver has compression off and still accepts it.
+		match gossip_block(block.clone(), Some(3)).await {
+			GossipMessage::Block(received) => assert_eq!(received, block),
+			other => panic!("unexpected message {other:?}"),
+		}
+	}
+
+	#[tokio::test]
+	async fn block_gossip_records_bytes_before_and_after_compression() {
+		fn counter(rendered: &str, name: &str) -> u64 {
+			rendered
+				.lines()
+				.find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+				.unwrap_or_else(|| panic!("{name} not rendered"))
+		}
+
+		let metrics = sequencer_metrics::reset_metrics_for_test();
+		gossip_block(large_block(), Some(3)).await;
+
+		let rendered = metrics.render();
+		let uncompressed = counter(&rendered, "sequencer_gossip_block_bytes_uncompressed");
+		let compressed = counter(&rendered, "sequencer_gossip_block_bytes_compressed");
+		assert!(compressed > 0);
+		assert!(compressed < uncompressed, "{compressed} >= {uncompressed}");
+	}
+
+	#[tokio::test]
+	async fn tx_and_block_roundtrip_in_each_wire_format() {
+		for format in [WireFormat::Json, WireFormat::Bincode] {
+			let (addr, _node, mut rx) = listening_node_in(key(1), format).await;
+			let mut cfg = config(free_addr(), addr);
+			cfg.wire_format = format;
+			cfg.heartbeat_interval = Duration::from_secs(60);
+			let sender = start_network(cfg, |_, _| {}).await;
+
+			let block = large_block();
+			sender.broadcast_tx(match tx_message(4) {
+				GossipMessage::Tx(tx) => tx,
+				_ => unreachable!(),
+			})
+			.await
+			.unwrap();
+			sender.broadcast_block(block.clone()).await.unwrap();
+
+			let (mut nonce, mut received_block) = (None, None);
+			for _ in 0..2 {
+				match tokio::time::timeout(Duration::from_secs(2), rx.recv())
+					.await
+					.unwrap()
+					.unwrap()
+				{
+					GossipMessage::Tx(tx) => nonce = Some(tx.nonce),
+					GossipMessage::Block(received) => received_block = Some(received),
+					other => panic!("unexpected message {other:?}"),
+				}
+			}
+			assert_eq!(nonce, Some(4), "{format:?}");
+			assert_eq!(received_block, Some(block), "{format:?}");
+			sender.shutdown();
+		}
+	}
+
+	#[tokio::test]
+	async fn envelopes_in_another_wire_format_are_dropped_without_penalty() {
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		cfg.ban_threshold = -30;
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let bincode = Envelope::seal(&key(1), &tx_message(1), WireFormat::Bincode);
+		for _ in 0..4 {
+			peer.send_to(&WireFormat::Bincode.encode(&bincode).unwrap(), addr)
+				.await
+				.unwrap();
+		}
+		let genuine = Envelope::seal(&key(1), &tx_message(2), WireFormat::Json);
+		peer.send_to(&encode(&genuine), addr).await.unwrap();
+
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 2);
+		assert!(node.banned_peers().is_empty());
+	}
+
+	#[tokio::test]
+	async fn malformed_datagrams_are_counted_per_peer() {
+		let metrics = sequencer_metrics::reset_metrics_for_test();
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let _node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		peer.send_to(b"not an envelope", addr).await.unwrap();
+		// Datagrams from one socket are handled in order, so once the
+		// genuine message arrives the malformed one has been counted.
+		let genuine = Envelope::seal(&key(1), &tx_message(1), WireFormat::Json);
+		peer.send_to(&encode(&genuine), addr).await.unwrap();
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 1);
+
+		let expected = format!(
+			"sequencer_gossip_decode_errors_total{{peer=\"{}\"}} 1",
+			peer.local_addr().unwrap()
+		);
+		assert!(metrics.render().contains(&expected));
+	}
+
+	#[tokio::test]
+	async fn flooding_peer_is_throttled_without_affecting_others() {
+		let metrics = sequencer_metrics::reset_metrics_for_test();
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		cfg.rate_limit = Some(PeerRateLimit {
+			messages_per_second: 1,
+			burst: 3,
+		});
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+
+		let flooder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let quiet = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		for nonce in 0..10 {
+			let envelope = Envelope::seal(&key(1), &tx_message(nonce), WireFormat::Json);
+			flooder.send_to(&encode(&envelope), addr).await.unwrap();
+		}
+		for nonce in [100, 101] {
+			let envelope = Envelope::seal(&key(1), &tx_message(nonce), WireFormat::Json);
+			quiet.send_to(&encode(&envelope), addr).await.unwrap();
+		}
+
+		let mut nonces = Vec::new();
+		while let Ok(Some(msg)) =
+			tokio::time::timeout(Duration::from_millis(300), rx.recv()).await
+		{
+			nonces.push(nonce_of(msg));
+		}
+		let flooded = nonces.iter().filter(|n| **n < 100).count();
+		// The burst, plus at most one refill on a slow machine.
+		assert!((3..=4).contains(&flooded), "processed {flooded} of 10");
+		assert_eq!(nonces.iter().filter(|n| **n >= 100).count(), 2);
+		assert!(node.banned_peers().is_empty());
+
+		let dropped = format!(
+			"sequencer_gossip_rate_limited_total{{peer=\"{}\"}} {}",
+			flooder.local_addr().unwrap(),
+			10 - flooded
+		);
+		assert!(metrics.render().contains(&dropped));
+	}
+
+	#[tokio::test]
+	async fn transactions_outside_subscribed_namespaces_are_ignored() {
+		let addr = free_addr();
+		let mut cfg = config(addr, free_addr());
+		cfg.keypair = key(9);
+		cfg.subscribed_namespaces = Some(HashSet::from([NamespaceId(2)]));
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let _node = start_network(cfg, move |_, msg| {
+			let _ = tx.send(msg);
+		})
+		.await;
+
+		let in_namespace = |nonce, namespace| {
+			let GossipMessage::Tx(tx) = tx_message(nonce) else {
+				unreachable!()
+			};
+			GossipMessage::Tx(Transaction {
+				namespace: NamespaceId(namespace),
+				..tx
+			})
+		};
+		let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		for msg in [in_namespace(1, 1), in_namespace(2, 2)] {
+			let envelope = Envelope::seal(&key(1), &msg, WireFormat::Json);
+			peer.send_to(&encode(&envelope), addr).await.unwrap();
+		}
+
+		let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+			.await
+			.unwrap();
+		assert_eq!(nonce_of(msg.unwrap()), 2);
+		sleep(Duration::from_millis(100)).await;
+		assert!(rx.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn unknown_peer_is_not_alive() {
+		let a = start_network(config(free_addr(), free_addr()), |_, _| {}).await;
+
+		let peers = a.peers();
+		assert_eq!(peers.len(), 1);
+		assert_eq!(peers[0].last_seen_ms, None);
+		assert!(!peers[0].alive);
+		assert!(a.is_healthy());
+	}
 }
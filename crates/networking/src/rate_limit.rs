@@ -0,0 +1,108 @@
+//! Per-peer throttling of incoming gossip.
+//!
+//! Each source address gets a token bucket holding up to `burst` tokens
+//! that refills at `messages_per_second`. Every complete message takes one
+//! token; with none left it is dropped before decoding. Unlike
+//! [`crate::scoring`], this never bans: a chatty but honest peer is only
+//! slowed down.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use tokio::time::Instant;
+
+/// Buckets kept before full ones are pruned.
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Limit on messages accepted from one peer address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerRateLimit {
+	/// Sustained messages per second processed per peer.
+	pub messages_per_second: u32,
+	/// Messages a peer may send back to back before being throttled.
+	pub burst: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct PeerRateLimiter {
+	limit: PeerRateLimit,
+	buckets: HashMap<SocketAddr, Bucket>,
+}
+
+impl PeerRateLimiter {
+	pub(crate) fn new(limit: PeerRateLimit) -> Self {
+		Self {
+			limit,
+			buckets: HashMap::new(),
+		}
+	}
+
+	/// Take a token for `addr` at `now`. Returns whether the message may be
+	/// processed.
+	pub(crate) fn allow(&mut self, addr: SocketAddr, now: Instant) -> bool {
+		let burst = f64::from(self.limit.burst.max(1));
+		let rate = f64::from(self.limit.messages_per_second);
+		if self.buckets.len() >= MAX_TRACKED_PEERS {
+			self.buckets
+				.retain(|_, bucket| refill(bucket, now, rate, burst) < burst);
+		}
+
+		let bucket = self.buckets.entry(addr).or_insert(Bucket {
+			tokens: burst,
+			updated: now,
+		});
+		if refill(bucket, now, rate, burst) < 1.0 {
+			return false;
+		}
+		bucket.tokens -= 1.0;
+		true
+	}
+}
+
+/// Bring `bucket` up to date at `now` and return its tokens.
+fn refill(bucket: &mut Bucket, now: Instant, rate: f64, burst: f64) -> f64 {
+	let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+	bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+	bucket.updated = now;
+	bucket.tokens
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::time::Duration;
+
+	#[test]
+	fn bucket_refills_at_the_configured_rate() {
+		let mut limiter = PeerRateLimiter::new(PeerRateLimit {
+			messages_per_second: 2,
+			burst: 3,
+		});
+		let peer: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+		let other: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+		let now = Instant::now();
+
+		for _ in 0..3 {
+			assert!(limiter.allow(peer, now));
+		}
+		assert!(!limiter.allow(peer, now));
+		// Other peers have their own bucket.
+		assert!(limiter.allow(other, now));
+
+		let later = now + Duration::from_millis(500);
+		assert!(limiter.allow(peer, later));
+		assert!(!limiter.allow(peer, later));
+		// Refills never exceed the burst.
+		let much_later = later + Duration::from_secs(60);
+		for _ in 0..3 {
+			assert!(limiter.allow(peer, much_later));
+		}
+		assert!(!limiter.allow(peer, much_later));
+	}
+}
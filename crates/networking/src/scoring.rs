@@ -0,0 +1,136 @@
+//! Misbehaviour scoring and temporary bans of gossip peers.
+//!
+//! Peers are tracked by address. Each starts at a score of zero and loses
+//! points for every datagram that fails to decode, fails the signature check
+//! or carries an inconsistent block. A peer whose score falls below the
+//! threshold is banned for a cooldown period, after which it starts again
+//! from zero.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use tokio::time::{Duration, Instant};
+
+/// Ways a peer can misbehave.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Offence {
+	/// A datagram that is not a well-formed fragment, compressed frame or
+	/// envelope.
+	Malformed,
+	/// An envelope from an untrusted key or with a bad signature.
+	BadSignature,
+	/// A block whose `tx_root` does not commit to its transactions.
+	InvalidBlock,
+}
+
+impl Offence {
+	/// Points deducted from the peer's score.
+	pub fn penalty(self) -> i64 {
+		match self {
+			Offence::Malformed => 10,
+			Offence::BadSignature => 25,
+			Offence::InvalidBlock => 50,
+		}
+	}
+}
+
+/// A currently banned peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BannedPeer {
+	pub addr: SocketAddr,
+	/// Milliseconds until the ban is lifted.
+	pub remaining_ms: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct PeerScores {
+	threshold: i64,
+	cooldown: Duration,
+	scores: HashMap<SocketAddr, i64>,
+	banned_until: HashMap<SocketAddr, Instant>,
+}
+
+impl PeerScores {
+	pub(crate) fn new(threshold: i64, cooldown: Duration) -> Self {
+		Self {
+			threshold,
+			cooldown,
+			scores: HashMap::new(),
+			banned_until: HashMap::new(),
+		}
+	}
+
+	/// Whether `addr` is banned at `now`. Lifts the ban once it expires.
+	pub(crate) fn is_banned(&mut self, addr: SocketAddr, now: Instant) -> bool {
+		match self.banned_until.get(&addr) {
+			Some(until) if *until > now => true,
+			Some(_) => {
+				self.banned_until.remove(&addr);
+				false
+			}
+			None => false,
+		}
+	}
+
+	/// Deduct the penalty for `offence`, banning the peer if its score drops
+	/// below the threshold. Returns whether this banned it.
+	pub(crate) fn penalize(&mut self, addr: SocketAddr, offence: Offence, now: Instant) -> bool {
+		if self.is_banned(addr, now) {
+			return false;
+		}
+		let score = self.scores.entry(addr).or_insert(0);
+		*score -= offence.penalty();
+		if *score >= self.threshold {
+			return false;
+		}
+		self.scores.remove(&addr);
+		self.banned_until.insert(addr, now + self.cooldown);
+		true
+	}
+
+	pub(crate) fn banned(&self, now: Instant) -> Vec<BannedPeer> {
+		let mut banned: Vec<BannedPeer> = self
+			.banned_until
+			.iter()
+			.filter(|(_, until)| **until > now)
+			.map(|(addr, until)| BannedPeer {
+				addr: *addr,
+				remaining_ms: (*until - now).as_millis() as u64,
+			})
+			.collect();
+		banned.sort_by_key(|peer| peer.addr);
+		banned
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn peer_is_banned_below_threshold_until_cooldown_ends() {
+		let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+		let mut scores = PeerScores::new(-30, Duration::from_secs(10));
+		let now = Instant::now();
+
+		assert!(!scores.penalize(addr, Offence::Malformed, now));
+		assert!(!scores.penalize(addr, Offence::Malformed, now));
+		// -30 is not below the threshold yet.
+		assert!(!scores.penalize(addr, Offence::Malformed, now));
+		assert!(scores.penalize(addr, Offence::Malformed, now));
+		assert!(scores.is_banned(addr, now));
+		assert_eq!(
+			scores.banned(now),
+			vec![BannedPeer {
+				addr,
+				remaining_ms: 10_000
+			}]
+		);
+
+		let later = now + Duration::from_secs(10);
+		assert!(!scores.is_banned(addr, later));
+		assert!(scores.banned(later).is_empty());
+		// The score starts over after the ban.
+		assert!(!scores.penalize(addr, Offence::BadSignature, later));
+	}
+}
@@ -0,0 +1,140 @@
+//! Serialization format of gossip envelopes.
+//!
+//! Every serialized envelope starts with a one-byte tag naming its
+//! [`WireFormat`]; the tags are distinct from the compression and fragment
+//! magics. A node only decodes its own format. Messages tagged with another
+//! one are dropped without penalizing the sender, since a misconfigured
+//! peer is not a misbehaving one; untagged ones count as malformed.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// First byte of a JSON envelope.
+const JSON_TAG: u8 = 0xF5;
+
+/// First byte of a bincode envelope.
+const BINCODE_TAG: u8 = 0xF6;
+
+/// Encoding of envelopes and the messages inside them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WireFormat {
+	/// Human-readable, and readable by tools like `tcpdump`.
+	#[default]
+	Json,
+	/// Compact binary encoding for high-throughput gossip.
+	Bincode,
+}
+
+/// Why a datagram could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+	/// Tagged with a format other than ours.
+	Mismatch(WireFormat),
+	/// Untagged, or tagged correctly but not decodable.
+	Malformed,
+}
+
+impl WireFormat {
+	fn tag(self) -> u8 {
+		match self {
+			WireFormat::Json => JSON_TAG,
+			WireFormat::Bincode => BINCODE_TAG,
+		}
+	}
+
+	fn from_tag(tag: u8) -> Option<Self> {
+		match tag {
+			JSON_TAG => Some(WireFormat::Json),
+			BINCODE_TAG => Some(WireFormat::Bincode),
+			_ => None,
+		}
+	}
+
+	/// Serialize `value` without a tag.
+	pub fn serialize<T: Serialize>(self, value: &T) -> Option<Vec<u8>> {
+		match self {
+			WireFormat::Json => serde_json::to_vec(value).ok(),
+			WireFormat::Bincode => bincode::serialize(value).ok(),
+		}
+	}
+
+	/// Undo [`WireFormat::serialize`].
+	pub fn deserialize<T: DeserializeOwned>(self, bytes: &[u8]) -> Option<T> {
+		match self {
+			WireFormat::Json => serde_json::from_slice(bytes).ok(),
+			WireFormat::Bincode => bincode::deserialize(bytes).ok(),
+		}
+	}
+
+	/// Serialize `value` behind this format's tag.
+	pub fn encode<T: Serialize>(self, value: &T) -> Option<Vec<u8>> {
+		let mut out = vec![self.tag()];
+		out.extend(self.serialize(value)?);
+		Some(out)
+	}
+
+	/// Undo [`WireFormat::encode`], refusing bytes tagged with another
+	/// format.
+	pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, DecodeError> {
+		let (&tag, rest) = bytes.split_first().ok_or(DecodeError::Malformed)?;
+		match WireFormat::from_tag(tag) {
+			Some(format) if format == self => {
+				self.deserialize(rest).ok_or(DecodeError::Malformed)
+			}
+			Some(other) => Err(DecodeError::Mismatch(other)),
+			None => Err(DecodeError::Malformed),
+		}
+	}
+}
+
+/// Serde helper for signed payloads: a string in JSON, whose payloads are
+/// UTF-8, and raw bytes in binary formats.
+pub mod payload {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+		if serializer.is_human_readable() {
+			let text = std::str::from_utf8(bytes).map_err(serde::ser::Error::custom)?;
+			serializer.serialize_str(text)
+		} else {
+			serializer.serialize_bytes(bytes)
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		if deserializer.is_human_readable() {
+			String::deserialize(deserializer).map(String::into_bytes)
+		} else {
+			Vec::<u8>::deserialize(deserializer)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decode_checks_the_tag() {
+		let json = WireFormat::Json.encode(&vec![1u32, 2, 3]).unwrap();
+		let bincode = WireFormat::Bincode.encode(&vec![1u32, 2, 3]).unwrap();
+		assert_eq!(json[0], JSON_TAG);
+		assert_eq!(bincode[0], BINCODE_TAG);
+
+		assert_eq!(WireFormat::Json.decode::<Vec<u32>>(&json), Ok(vec![1, 2, 3]));
+		assert_eq!(
+			WireFormat::Bincode.decode::<Vec<u32>>(&bincode),
+			Ok(vec![1, 2, 3])
+		);
+		assert_eq!(
+			WireFormat::Json.decode::<Vec<u32>>(&bincode),
+			Err(DecodeError::Mismatch(WireFormat::Bincode))
+		);
+		// Untagged JSON from an older node.
+		assert_eq!(
+			WireFormat::Json.decode::<Vec<u32>>(b"[1,2,3]"),
+			Err(DecodeError::Malformed)
+		);
+		assert_eq!(WireFormat::Json.decode::<Vec<u32>>(&[]), Err(DecodeError::Malformed));
+	}
+}
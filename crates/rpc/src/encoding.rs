@@ -0,0 +1,158 @@
+//! Response encodings picked from the `Accept` header.
+//!
+//! REST handlers answer JSON unless the client asks for one of the binary
+//! codecs compiled in with cargo features: `application/x-bincode` (the
+//! `bincode` feature) or `application/msgpack` (`msgpack`). MessagePack
+//! keeps field names like JSON; bincode is positional, so clients decode
+//! it into structs with the documented fields in order. Error bodies are
+//! always JSON.
+
+#[cfg(feature = "bincode")]
+use std::cell::Cell;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{ErrorResponse, HandlerError};
+
+/// A response body encoding a client may ask for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Json,
+    #[cfg(feature = "bincode")]
+    Bincode,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+impl Encoding {
+    /// Every encoding this build can produce, JSON first.
+    pub const ALL: &'static [Encoding] = &[
+        Encoding::Json,
+        #[cfg(feature = "bincode")]
+        Encoding::Bincode,
+        #[cfg(feature = "msgpack")]
+        Encoding::MsgPack,
+    ];
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            #[cfg(feature = "bincode")]
+            Encoding::Bincode => "application/x-bincode",
+            #[cfg(feature = "msgpack")]
+            Encoding::MsgPack => "application/msgpack",
+        }
+    }
+
+    /// The encoding to answer an `Accept` header with: the supported media
+    /// type with the highest `q`, the earliest listed among equals. `*/*`
+    /// and `application/*` mean JSON. `None` if nothing listed is
+    /// supported.
+    pub fn negotiate(accept: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+        for range in accept.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let encoding = match media_type.as_str() {
+                "*/*" | "application/*" => Some(Encoding::Json),
+                other => Self::ALL
+                    .iter()
+                    .copied()
+                    .find(|e| e.content_type() == other),
+            };
+            if let Some(encoding) = encoding.filter(|_| q > 0.0) {
+                if best.is_none_or(|(best_q, _)| q > best_q) {
+                    best = Some((q, encoding));
+                }
+            }
+        }
+        best.map(|(_, encoding)| encoding)
+    }
+
+    fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "bincode")]
+            Encoding::Bincode => {
+                POSITIONAL.with(|positional| positional.set(true));
+                let bytes = bincode::serialize(value);
+                POSITIONAL.with(|positional| positional.set(false));
+                bytes.map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "msgpack")]
+            Encoding::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Encoding {
+    type Rejection = HandlerError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let Some(accept) = parts.headers.get(header::ACCEPT) else {
+            return Ok(Encoding::Json);
+        };
+        let accept = accept.to_str().unwrap_or_default();
+        if accept.trim().is_empty() {
+            return Ok(Encoding::Json);
+        }
+        Encoding::negotiate(accept).ok_or_else(|| {
+            let supported: Vec<_> = Self::ALL.iter().map(|e| e.content_type()).collect();
+            (
+                StatusCode::NOT_ACCEPTABLE,
+                Json(ErrorResponse {
+                    error: format!(
+                        "unsupported Accept type `{accept}`; supported: {}",
+                        supported.join(", ")
+                    ),
+                }),
+            )
+        })
+    }
+}
+
+/// A handler result serialized in the negotiated [`Encoding`].
+pub(crate) struct Encoded<T>(pub Encoding, pub T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded(encoding, value) = self;
+        match encoding.encode(&value) {
+            Ok(bytes) => ([(header::CONTENT_TYPE, encoding.content_type())], bytes).into_response(),
+            Err(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error }),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[cfg(feature = "bincode")]
+thread_local! {
+    /// Set while encoding bincode, which has no way to mark a field as
+    /// missing.
+    static POSITIONAL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// `skip_serializing_if` for optional response fields: they are left out
+/// of JSON and MessagePack when `None`, but always present in bincode.
+pub(crate) fn omit_none<T>(value: &Option<T>) -> bool {
+    #[cfg(feature = "bincode")]
+    if POSITIONAL.with(Cell::get) {
+        return false;
+    }
+    value.is_none()
+}
@@ -1,26 +1,229 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, routing::post, Json, Router};
-use consensus::ConsensusEngine;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::Bytes,
+    error_handling::HandleErrorLayer,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::{ConnectInfo, DefaultBodyLimit, MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    routing::post,
+    Extension, Json, Router,
+};
+use consensus::{ConsensusEngine, FinalityEvent, L1BatchLog, L1BatchRecord, L1BatchStatus};
 use networking::NetworkHandle;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
-use tracing::info;
-use types::{NamespaceId, Transaction};
+use serde_json::Value;
+use tokio::net::TcpSocket;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{watch, Mutex};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::{BoxError, ServiceBuilder};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::{info, info_span, warn, Instrument};
+use types::{
+    Address, Block, BlockHeader, ExecStatus, Hash, NamespaceId, Receipt, Transaction, TxId,
+};
+
+mod encoding;
+mod rate_limit;
+
+use encoding::{Encoded, Encoding};
+pub use rate_limit::RateLimit;
+use rate_limit::RateLimiter;
 
 pub struct RpcInnerState<E> {
     pub engine: Arc<Mutex<E>>,
     pub network: Option<NetworkHandle>,
+    /// Finality events published by the consensus loop; each WebSocket
+    /// subscriber holds its own receiver.
+    pub events: broadcast::Sender<FinalityEvent>,
+    pub config: RpcConfig,
+    /// Batches of the node's L1 batcher, served under `/l1/batches`. `None`
+    /// when the node posts no batches, in which case those routes return 404.
+    pub l1_batches: Option<L1BatchLog>,
 }
 
 pub type RpcState<E> = Arc<RpcInnerState<E>>;
 
+/// Blocks returned by `GET /blocks` when no `limit` is given.
+pub const DEFAULT_BLOCKS_PAGE: usize = 20;
+
+/// Default for [`RpcConfig::max_blocks_page`].
+pub const DEFAULT_MAX_BLOCKS_PAGE: usize = 100;
+
+/// Default for [`RpcConfig::max_body_bytes`], axum's own default.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Default for [`RpcConfig::accept_backlog`], tokio's own default.
+pub const DEFAULT_ACCEPT_BACKLOG: u32 = 1024;
+
+/// Settings for the HTTP server built by [`router`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpcConfig {
+    /// Answer cross-origin requests from browsers.
+    pub enable_cors: bool,
+    /// Origins allowed to call the server when CORS is enabled, e.g.
+    /// `https://dashboard.example`. Empty allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Largest request body accepted; larger ones get
+    /// `413 Payload Too Large`.
+    pub max_body_bytes: usize,
+    /// Largest `limit` honoured by `GET /blocks`.
+    pub max_blocks_page: usize,
+    /// Per-client limit on `POST /tx` and JSON-RPC
+    /// `seq_submitTransaction`, which share one budget; `None` disables it.
+    pub tx_rate_limit: Option<RateLimit>,
+    /// Requests served at once across all connections. Further requests
+    /// get `503 Service Unavailable` instead of queueing; `None` disables
+    /// the limit.
+    pub max_connections: Option<usize>,
+    /// Connections the OS queues before [`run_rpc_server`] accepts them.
+    pub accept_backlog: u32,
+    /// Age of the head block, by its timestamp, beyond which `GET /health`
+    /// reports the node degraded. `None` never does.
+    pub max_block_age_ms: Option<u64>,
+    /// Operator token that authorizes submissions setting `priority`, sent
+    /// as `Authorization: Bearer <token>`. Without it every such submission
+    /// is refused, so public clients cannot jump the fee order.
+    pub priority_token: Option<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            enable_cors: false,
+            allowed_origins: Vec::new(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_blocks_page: DEFAULT_MAX_BLOCKS_PAGE,
+            tx_rate_limit: None,
+            max_connections: None,
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            max_block_age_ms: None,
+            priority_token: None,
+        }
+    }
+}
+
+impl RpcConfig {
+    /// The CORS layer for these settings, or `None` if CORS is disabled.
+    /// Origins that are not valid header values are skipped.
+    fn cors_layer(&self) -> Option<CorsLayer> {
+        if !self.enable_cors {
+            return None;
+        }
+        let allow_origin = if self.allowed_origins.is_empty() {
+            AllowOrigin::any()
+        } else {
+            let origins = self.allowed_origins.iter().filter_map(|origin| {
+                let value = HeaderValue::from_str(origin);
+                if value.is_err() {
+                    warn!(%origin, "ignoring invalid CORS origin");
+                }
+                value.ok()
+            });
+            AllowOrigin::list(origins)
+        };
+        Some(
+            CorsLayer::new()
+                .allow_origin(allow_origin)
+                .allow_methods([Method::GET, Method::POST])
+                .allow_headers([header::CONTENT_TYPE]),
+        )
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SubmitTxRequest {
+    #[serde(default)]
+    pub chain_id: u64,
+    /// Hex-encoded sender address; defaults to the zero address.
+    #[serde(default)]
+    pub from: Option<String>,
     pub namespace: u64,
     pub gas_price: u64,
     pub nonce: u64,
+    /// Last block height the transaction may be included at.
+    #[serde(default)]
+    pub valid_until_height: Option<u64>,
+    /// Include ahead of fee-ordered transactions. Requires the operator's
+    /// [`RpcConfig::priority_token`].
+    #[serde(default)]
+    pub priority: bool,
     pub payload: String,
+    /// How `payload` is encoded; defaults to hex.
+    #[serde(default)]
+    pub payload_encoding: PayloadEncoding,
+    /// Optional hex-encoded signature.
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Anti-spam proof-of-work nonce, when the node requires one.
+    #[serde(default)]
+    pub pow_nonce: u64,
+}
+
+/// Encoding of `SubmitTxRequest::payload`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    Utf8,
+    #[default]
+    Hex,
+}
+
+/// Why a submitted transaction was not accepted.
+#[derive(Debug)]
+enum SubmitError {
+    /// The request itself is malformed (e.g. invalid hex).
+    InvalidRequest(String),
+    /// The engine rejected or failed to store the transaction.
+    Engine(String),
+    /// The request sets `priority` without the operator's token.
+    Forbidden(String),
+}
+
+/// Decode a hex string, tolerating an optional `0x` prefix.
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, SubmitError> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(digits)
+        .map_err(|e| SubmitError::InvalidRequest(format!("invalid hex in `{field}`: {e}")))
+}
+
+impl SubmitTxRequest {
+    fn into_transaction(self) -> Result<Transaction, SubmitError> {
+        let payload = match self.payload_encoding {
+            PayloadEncoding::Utf8 => self.payload.into_bytes(),
+            PayloadEncoding::Hex => decode_hex("payload", &self.payload)?,
+        };
+        let signature = match &self.signature {
+            Some(sig) => decode_hex("signature", sig)?,
+            None => Vec::new(),
+        };
+        let from = match &self.from {
+            Some(addr) => addr
+                .parse()
+                .map_err(|e| SubmitError::InvalidRequest(format!("invalid `from`: {e}")))?,
+            None => Address::default(),
+        };
+        Ok(Transaction {
+            chain_id: self.chain_id,
+            from,
+            namespace: NamespaceId(self.namespace),
+            gas_price: self.gas_price,
+            nonce: self.nonce,
+            valid_until_height: self.valid_until_height,
+            priority: self.priority,
+            payload,
+            signature,
+            pow_nonce: self.pow_nonce,
+        })
+    }
 }
 
 #[derive(Serialize)]
@@ -28,57 +231,1012 @@ pub struct SubmitTxResponse {
     pub tx_id: String,
 }
 
+/// Result of a dry-run submission. `reason` is set only when the
+/// transaction would be rejected.
+#[derive(Serialize)]
+pub struct SimulateTxResponse {
+    pub would_accept: bool,
+    #[serde(skip_serializing_if = "encoding::omit_none")]
+    pub reason: Option<String>,
+    pub estimated_gas: u64,
+}
+
+/// Where a transaction is in its lifecycle, as seen by this node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxStatus {
+    /// Waiting in the mempool.
+    Pending,
+    /// Included in a committed block.
+    Committed,
+    /// Neither pending nor committed, e.g. never submitted or dropped.
+    Unknown,
+}
+
 #[derive(Serialize)]
 pub struct TxStatusResponse {
-    pub found: bool,
+    pub status: TxStatus,
 }
 
 #[derive(Serialize)]
 struct ErrorResponse {
-	error: String,
+    error: String,
 }
 
 type AppState<E> = RpcState<E>;
 
-#[tracing::instrument(skip(state, req))]
-async fn submit_tx_handler<E: ConsensusEngine + Send + Sync + 'static>(
-    State(state): State<AppState<E>>,
-    Json(req): Json<SubmitTxRequest>,
-) -> Result<Json<SubmitTxResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let tx = Transaction {
-        namespace: NamespaceId(req.namespace),
-        gas_price: req.gas_price,
-        nonce: req.nonce,
-        payload: req.payload.into_bytes(),
-        signature: vec![],
+/// Whether `headers` carry the operator's [`RpcConfig::priority_token`].
+fn priority_authorized(config: &RpcConfig, headers: &HeaderMap) -> bool {
+    let Some(token) = &config.priority_token else {
+        return false;
+    };
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
     };
+    // Compare every byte so the time taken does not leak the token.
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Build a transaction from a submit request, insert it through the engine
+/// and gossip it to peers. Shared by the REST and JSON-RPC front ends.
+/// `authorized` says whether the caller presented the priority token.
+async fn submit_transaction<E: ConsensusEngine + Send + Sync + 'static>(
+    state: &AppState<E>,
+    req: SubmitTxRequest,
+    authorized: bool,
+) -> Result<TxId, SubmitError> {
+    if req.priority && !authorized {
+        return Err(SubmitError::Forbidden(
+            "`priority` requires the operator token".into(),
+        ));
+    }
+    let tx = req.into_transaction()?;
 
     let tx_clone = tx.clone();
     let mut engine = state.engine.lock().await;
     let tx_id = engine
         .submit_tx(tx)
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                        error: format!("submit_tx failed: {e}"),
-                    }),
-            )
-        })?;
+        .map_err(|e| SubmitError::Engine(format!("submit_tx failed: {e}")))?;
     drop(engine);
 
     if let Some(net) = &state.network {
         // Fire-and-forget gossip; if the channel is full, we just drop.
-        net.broadcast_tx(tx_clone).await;
+        // Priority transactions stay local, since peers refuse them.
+        if !tx_clone.priority {
+            let _ = net.try_broadcast_tx(tx_clone);
+        }
+    }
+
+    Ok(tx_id)
+}
+
+#[tracing::instrument(skip(state, req))]
+async fn submit_tx_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    headers: HeaderMap,
+    Json(req): Json<SubmitTxRequest>,
+) -> Result<Encoded<SubmitTxResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let authorized = priority_authorized(&state.config, &headers);
+    let tx_id = submit_transaction(&state, req, authorized)
+        .await
+        .map_err(|e| match e {
+            SubmitError::InvalidRequest(error) => {
+                (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }))
+            }
+            SubmitError::Forbidden(error) => (StatusCode::FORBIDDEN, Json(ErrorResponse { error })),
+            SubmitError::Engine(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error }),
+            ),
+        })?;
+
+    Ok(Encoded(
+        encoding,
+        SubmitTxResponse {
+            tx_id: hex::encode(tx_id.0 .0),
+        },
+    ))
+}
+
+/// Run a submit request through the engine's checks without inserting or
+/// gossiping the transaction.
+#[tracing::instrument(skip(state, req))]
+async fn simulate_tx_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Json(req): Json<SubmitTxRequest>,
+) -> Result<Encoded<SimulateTxResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let tx = req.into_transaction().map_err(|e| match e {
+        SubmitError::InvalidRequest(error)
+        | SubmitError::Engine(error)
+        | SubmitError::Forbidden(error) => (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })),
+    })?;
+
+    let outcome = state.engine.lock().await.simulate_tx(&tx);
+    Ok(Encoded(
+        encoding,
+        SimulateTxResponse {
+            would_accept: outcome.is_ok(),
+            reason: outcome.err().map(|e| e.to_string()),
+            estimated_gas: tx.gas_used(),
+        },
+    ))
+}
+
+type HandlerError = (StatusCode, Json<ErrorResponse>);
+
+/// Parse a transaction id from a URL path segment.
+fn path_tx_id(id: &str) -> Result<TxId, HandlerError> {
+    parse_hash(id.strip_prefix("0x").unwrap_or(id))
+        .map(TxId)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse { error: e.message }),
+            )
+        })
+}
+
+fn internal_error(e: consensus::ConsensusError) -> HandlerError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+}
+
+fn not_found(what: &str) -> HandlerError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: format!("{what} not found"),
+        }),
+    )
+}
+
+#[tracing::instrument(skip(state))]
+async fn tx_status_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Path(id): Path<String>,
+) -> Result<Encoded<TxStatusResponse>, HandlerError> {
+    let tx_id = path_tx_id(&id)?;
+    let engine = state.engine.lock().await;
+    let committed = engine.get_tx(tx_id).map_err(internal_error)?;
+    let status = if committed.is_some() {
+        TxStatus::Committed
+    } else if engine.is_pending(tx_id) {
+        TxStatus::Pending
+    } else {
+        TxStatus::Unknown
+    };
+    Ok(Encoded(encoding, TxStatusResponse { status }))
+}
+
+#[tracing::instrument(skip(state))]
+async fn tx_receipt_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Path(id): Path<String>,
+) -> Result<Encoded<ReceiptView>, HandlerError> {
+    let tx_id = path_tx_id(&id)?;
+    let receipt = state
+        .engine
+        .lock()
+        .await
+        .get_receipt(tx_id)
+        .map_err(internal_error)?;
+    match receipt {
+        Some(receipt) => Ok(Encoded(encoding, ReceiptView::from(&receipt))),
+        None => Err(not_found("receipt")),
+    }
+}
+
+/// Prove a committed transaction is in its block. The block is found
+/// through the transaction's receipt.
+#[tracing::instrument(skip(state))]
+async fn tx_proof_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Path(id): Path<String>,
+) -> Result<Encoded<TxProofResponse>, HandlerError> {
+    let tx_id = path_tx_id(&id)?;
+    let engine = state.engine.lock().await;
+    let Some(receipt) = engine.get_receipt(tx_id).map_err(internal_error)? else {
+        return Err(not_found("transaction"));
+    };
+    let Some(block) = engine.get_block(receipt.block_id).map_err(internal_error)? else {
+        return Err(not_found("block"));
+    };
+    // Reverted transactions have a receipt but are not part of the block.
+    let Some(index) = block.txs.iter().position(|id| *id == tx_id) else {
+        return Err(not_found("transaction"));
+    };
+    let proof = types::merkle_proof(&block.txs, index)
+        .filter(|proof| types::verify_merkle_proof(block.header.tx_root, tx_id, proof));
+    let Some(proof) = proof else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!(
+                    "inclusion proof for {id} does not verify against its block's tx_root"
+                ),
+            }),
+        ));
+    };
+    Ok(Encoded(
+        encoding,
+        TxProofResponse {
+            tx_id: hex::encode(tx_id.0 .0),
+            block_id: hex::encode(receipt.block_id.0 .0),
+            height: block.header.height,
+            tx_root: hex::encode(block.header.tx_root.0),
+            index: proof.index,
+            siblings: proof.siblings.iter().map(|h| hex::encode(h.0)).collect(),
+        },
+    ))
+}
+
+/// Query parameters of `GET /blocks`.
+#[derive(Debug, Deserialize)]
+pub struct BlocksQuery {
+    /// Height of the first (highest) block to return; defaults to the
+    /// latest block.
+    pub from: Option<u64>,
+    /// Number of blocks to return, capped at the server's maximum.
+    pub limit: Option<usize>,
+}
+
+/// Page through committed blocks, newest first.
+#[tracing::instrument(skip(state))]
+async fn blocks_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Query(query): Query<BlocksQuery>,
+) -> Result<Encoded<Vec<BlockSummary>>, HandlerError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_BLOCKS_PAGE)
+        .min(state.config.max_blocks_page);
+    let engine = state.engine.lock().await;
+    let Some(latest) = engine.latest_block().map_err(internal_error)? else {
+        return Ok(Encoded(encoding, Vec::new()));
+    };
+    if limit == 0 {
+        return Ok(Encoded(encoding, Vec::new()));
+    }
+    let top = query
+        .from
+        .unwrap_or(latest.header.height)
+        .min(latest.header.height);
+    let bottom = top.saturating_sub(limit as u64 - 1);
+    let blocks = engine
+        .blocks_in_range(bottom, top)
+        .map_err(internal_error)?;
+    Ok(Encoded(
+        encoding,
+        blocks.iter().rev().map(BlockSummary::from).collect(),
+    ))
+}
+
+/// Recent L1 batches, newest first.
+#[tracing::instrument(skip(state))]
+async fn l1_batches_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+) -> Result<Encoded<L1BatchesResponse>, HandlerError> {
+    let Some(log) = &state.l1_batches else {
+        return Err(not_found("L1 batcher"));
+    };
+    let batches = log.recent().iter().map(L1BatchView::from).collect();
+    Ok(Encoded(encoding, L1BatchesResponse { batches }))
+}
+
+#[tracing::instrument(skip(state))]
+async fn l1_batch_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Path(number): Path<u64>,
+) -> Result<Encoded<L1BatchView>, HandlerError> {
+    let record = state.l1_batches.as_ref().and_then(|log| log.get(number));
+    let Some(record) = record else {
+        return Err(not_found("L1 batch"));
+    };
+    Ok(Encoded(encoding, L1BatchView::from(&record)))
+}
+
+/// Query parameters of `GET /state/proof`.
+#[derive(Debug, Deserialize)]
+pub struct StateProofQuery {
+    pub height: u64,
+}
+
+/// Prove the state root at a height is the one committed in its block.
+#[tracing::instrument(skip(state))]
+async fn state_proof_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+    Query(query): Query<StateProofQuery>,
+) -> Result<Encoded<StateProofResponse>, HandlerError> {
+    let engine = state.engine.lock().await;
+    let Some(block) = engine
+        .get_block_by_height(query.height)
+        .map_err(internal_error)?
+    else {
+        return Err(not_found("block"));
+    };
+    let Some(root) = engine
+        .state_root_at_height(query.height)
+        .map_err(internal_error)?
+    else {
+        return Err(not_found("state root"));
+    };
+    if root != block.header.state_root {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!(
+                    "stored state root at height {} disagrees with its block",
+                    query.height
+                ),
+            }),
+        ));
+    }
+    Ok(Encoded(encoding, StateProofResponse::new(&block)))
+}
+
+/// JSON-RPC 2.0 request envelope.
+#[derive(Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
+}
+
+/// JSON-RPC 2.0 response envelope. Exactly one of `result` and `error`
+/// is present.
+#[derive(Serialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+    pub id: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl JsonRpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// A `seq_submitTransaction` sets `priority` without the operator's
+    /// token.
+    pub const FORBIDDEN: i64 = -32001;
+    /// A `seq_submitTransaction` from a client over
+    /// [`RpcConfig::tx_rate_limit`].
+    pub const RATE_LIMITED: i64 = -32005;
+
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+}
+
+impl JsonRpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    fn error(id: Value, error: JsonRpcError) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            result: None,
+            error: Some(error),
+            id,
+        }
+    }
+}
+
+/// JSON view of a transaction with binary fields hex-encoded.
+#[derive(Serialize)]
+pub struct TransactionView {
+    pub tx_id: String,
+    pub chain_id: u64,
+    pub from: String,
+    pub namespace: u64,
+    pub gas_price: u64,
+    pub nonce: u64,
+    pub valid_until_height: Option<u64>,
+    pub priority: bool,
+    pub payload: String,
+    pub signature: String,
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            tx_id: hex::encode(tx.id().0 .0),
+            chain_id: tx.chain_id,
+            from: tx.from.to_string(),
+            namespace: tx.namespace.0,
+            gas_price: tx.gas_price,
+            nonce: tx.nonce,
+            valid_until_height: tx.valid_until_height,
+            priority: tx.priority,
+            payload: hex::encode(&tx.payload),
+            signature: hex::encode(&tx.signature),
+        }
+    }
+}
+
+/// JSON view of an execution receipt with binary fields hex-encoded.
+/// `reason` is set only for reverted transactions.
+#[derive(Serialize)]
+pub struct ReceiptView {
+    pub tx_id: String,
+    pub block_id: String,
+    pub index: u32,
+    /// `"success"` or `"reverted"`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "encoding::omit_none")]
+    pub reason: Option<String>,
+    pub gas_used: u64,
+    pub fee: u64,
+    pub logs: Vec<LogView>,
+}
+
+#[derive(Serialize)]
+pub struct LogView {
+    pub namespace: u64,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+impl From<&Receipt> for ReceiptView {
+    fn from(receipt: &Receipt) -> Self {
+        let (status, reason) = match &receipt.status {
+            ExecStatus::Success => ("success", None),
+            ExecStatus::Reverted { reason } => ("reverted", Some(reason.clone())),
+        };
+        Self {
+            tx_id: hex::encode(receipt.tx_id.0 .0),
+            block_id: hex::encode(receipt.block_id.0 .0),
+            index: receipt.index,
+            status,
+            reason,
+            gas_used: receipt.gas_used,
+            fee: receipt.fee,
+            logs: receipt
+                .logs
+                .iter()
+                .map(|log| LogView {
+                    namespace: log.namespace.0,
+                    topics: log.topics.iter().map(|t| hex::encode(t.0)).collect(),
+                    data: hex::encode(&log.data),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// JSON view of a block with hashes hex-encoded.
+#[derive(Serialize)]
+pub struct BlockView {
+    pub block_id: String,
+    pub height: u64,
+    pub parent: Option<String>,
+    pub tx_root: String,
+    pub gas_used: u64,
+    pub fees: u64,
+    pub state_root: String,
+    pub timestamp_ms: u64,
+    pub proposer: String,
+    pub txs: Vec<String>,
+}
+
+impl From<&Block> for BlockView {
+    fn from(block: &Block) -> Self {
+        let header = &block.header;
+        Self {
+            block_id: hex::encode(header.id().0 .0),
+            height: header.height,
+            parent: header.parent.map(|p| hex::encode(p.0 .0)),
+            tx_root: hex::encode(header.tx_root.0),
+            gas_used: header.gas_used,
+            fees: header.fees,
+            state_root: hex::encode(header.state_root.0),
+            timestamp_ms: header.timestamp_ms,
+            proposer: hex::encode(header.proposer),
+            txs: block.txs.iter().map(|id| hex::encode(id.0 .0)).collect(),
+        }
     }
+}
 
-    Ok(Json(SubmitTxResponse {
-        tx_id: hex::encode(tx_id.0 .0),
-    }))
+/// Header fields of a block, hex-encoded, plus the block id.
+#[derive(Serialize)]
+pub struct HeaderView {
+    pub block_id: String,
+    pub height: u64,
+    pub parent: Option<String>,
+    pub tx_root: String,
+    pub gas_used: u64,
+    pub fees: u64,
+    pub state_root: String,
+    pub timestamp_ms: u64,
+    pub proposer: String,
 }
 
-async fn health_handler() -> &'static str {
-    "ok"
+impl From<&BlockHeader> for HeaderView {
+    fn from(header: &BlockHeader) -> Self {
+        Self {
+            block_id: hex::encode(header.id().0 .0),
+            height: header.height,
+            parent: header.parent.map(|p| hex::encode(p.0 .0)),
+            tx_root: hex::encode(header.tx_root.0),
+            gas_used: header.gas_used,
+            fees: header.fees,
+            state_root: hex::encode(header.state_root.0),
+            timestamp_ms: header.timestamp_ms,
+            proposer: hex::encode(header.proposer),
+        }
+    }
+}
+
+/// Evidence linking a state root to a block id.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StateRootProof {
+    /// The root is the header's own `state_root` field: hashing
+    /// `encoded_header` gives `block_id`, and decoding it gives the root.
+    Header {
+        block_id: String,
+        encoded_header: String,
+    },
+}
+
+/// Response of `GET /state/proof`.
+#[derive(Serialize)]
+pub struct StateProofResponse {
+    pub height: u64,
+    pub state_root: String,
+    pub header: HeaderView,
+    pub proof: StateRootProof,
+}
+
+impl StateProofResponse {
+    fn new(block: &Block) -> Self {
+        let header = &block.header;
+        Self {
+            height: header.height,
+            state_root: hex::encode(header.state_root.0),
+            header: HeaderView::from(header),
+            proof: StateRootProof::Header {
+                block_id: hex::encode(header.id().0 .0),
+                encoded_header: hex::encode(header.encode()),
+            },
+        }
+    }
+}
+
+/// Response of `GET /tx/:id/proof`: a Merkle proof of the transaction
+/// against its block's `tx_root`.
+#[derive(Serialize)]
+pub struct TxProofResponse {
+    pub tx_id: String,
+    pub block_id: String,
+    pub height: u64,
+    pub tx_root: String,
+    /// Leaf position in `Block::txs`.
+    pub index: u32,
+    /// Sibling hashes from the leaf up to the root.
+    pub siblings: Vec<String>,
+}
+
+/// Explorer listing entry for a block.
+#[derive(Serialize)]
+pub struct BlockSummary {
+    pub block_id: String,
+    pub height: u64,
+    pub timestamp_ms: u64,
+    pub tx_count: usize,
+    pub state_root: String,
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        let header = &block.header;
+        Self {
+            block_id: hex::encode(header.id().0 .0),
+            height: header.height,
+            timestamp_ms: header.timestamp_ms,
+            tx_count: block.txs.len(),
+            state_root: hex::encode(header.state_root.0),
+        }
+    }
+}
+
+/// Response of `GET /l1/batches`.
+#[derive(Serialize)]
+pub struct L1BatchesResponse {
+    pub batches: Vec<L1BatchView>,
+}
+
+/// An L1 batch and whether it has been posted.
+#[derive(Serialize)]
+pub struct L1BatchView {
+    pub batch_number: u64,
+    pub first_height: u64,
+    pub last_height: u64,
+    pub block_ids: Vec<String>,
+    pub commitment_hash: String,
+    /// `pending`, `posted` or `failed`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "encoding::omit_none")]
+    pub l1_tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "encoding::omit_none")]
+    pub error: Option<String>,
+}
+
+impl From<&L1BatchRecord> for L1BatchView {
+    fn from(record: &L1BatchRecord) -> Self {
+        let commitment = &record.commitment;
+        let (status, l1_tx_hash, error) = match &record.status {
+            L1BatchStatus::Pending => ("pending", None, None),
+            L1BatchStatus::Posted(hash) => ("posted", Some(hex::encode(hash.0 .0)), None),
+            L1BatchStatus::Failed { error } => ("failed", None, Some(error.clone())),
+        };
+        Self {
+            batch_number: commitment.batch_number,
+            first_height: record.first_height,
+            last_height: record.last_height,
+            block_ids: commitment
+                .block_ids
+                .iter()
+                .map(|id| hex::encode(id.0 .0))
+                .collect(),
+            commitment_hash: hex::encode(commitment.hash().0),
+            status,
+            l1_tx_hash,
+            error,
+        }
+    }
+}
+
+/// Extract a single parameter passed either positionally (`[value]`) or
+/// by name (`{"<name>": value}`).
+fn single_param<T: DeserializeOwned>(params: Value, name: &str) -> Result<T, JsonRpcError> {
+    let value = match params {
+        Value::Array(mut items) if items.len() == 1 => items.remove(0),
+        Value::Object(mut fields) => fields
+            .remove(name)
+            .ok_or_else(|| JsonRpcError::invalid_params(format!("missing param `{name}`")))?,
+        _ => {
+            return Err(JsonRpcError::invalid_params(format!(
+                "expected `[{name}]` or `{{\"{name}\": ...}}`"
+            )))
+        }
+    };
+    serde_json::from_value(value)
+        .map_err(|e| JsonRpcError::invalid_params(format!("invalid `{name}`: {e}")))
+}
+
+fn parse_hash(s: &str) -> Result<Hash, JsonRpcError> {
+    let bytes =
+        hex::decode(s).map_err(|e| JsonRpcError::invalid_params(format!("invalid hex: {e}")))?;
+    let arr: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| JsonRpcError::invalid_params("expected a 32-byte hex hash"))?;
+    Ok(Hash(arr))
+}
+
+async fn dispatch_rpc<E: ConsensusEngine + Send + Sync + 'static>(
+    state: &AppState<E>,
+    method: &str,
+    params: Value,
+    authorized: bool,
+) -> Result<Value, JsonRpcError> {
+    let internal = |e: consensus::ConsensusError| {
+        JsonRpcError::new(JsonRpcError::INTERNAL_ERROR, e.to_string())
+    };
+    match method {
+        "seq_submitTransaction" => {
+            let req = match params {
+                Value::Array(mut items) if items.len() == 1 => items.remove(0),
+                other => other,
+            };
+            let req: SubmitTxRequest = serde_json::from_value(req)
+                .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+            let tx_id = submit_transaction(state, req, authorized)
+                .await
+                .map_err(|e| match e {
+                    SubmitError::InvalidRequest(msg) => JsonRpcError::invalid_params(msg),
+                    SubmitError::Engine(msg) => {
+                        JsonRpcError::new(JsonRpcError::INTERNAL_ERROR, msg)
+                    }
+                    SubmitError::Forbidden(msg) => JsonRpcError::new(JsonRpcError::FORBIDDEN, msg),
+                })?;
+            Ok(serde_json::json!({ "tx_id": hex::encode(tx_id.0 .0) }))
+        }
+        "seq_getTransaction" => {
+            let tx_id: String = single_param(params, "tx_id")?;
+            let tx_id = TxId(parse_hash(&tx_id)?);
+            let tx = state.engine.lock().await.get_tx(tx_id).map_err(internal)?;
+            Ok(serde_json::to_value(tx.as_ref().map(TransactionView::from)).unwrap_or(Value::Null))
+        }
+        "seq_getBlockByHeight" => {
+            let height: u64 = single_param(params, "height")?;
+            let block = state
+                .engine
+                .lock()
+                .await
+                .get_block_by_height(height)
+                .map_err(internal)?;
+            Ok(serde_json::to_value(block.as_ref().map(BlockView::from)).unwrap_or(Value::Null))
+        }
+        _ => Err(JsonRpcError::new(
+            JsonRpcError::METHOD_NOT_FOUND,
+            format!("method not found: {method}"),
+        )),
+    }
+}
+
+/// `POST /rpc`: JSON-RPC 2.0 front end. Errors are reported inside the
+/// envelope, so the HTTP status is always `200 OK`.
+#[tracing::instrument(skip(state, body))]
+async fn json_rpc_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    limiter: Option<Extension<Arc<RateLimiter>>>,
+    peer: Option<ConnectInfo<std::net::SocketAddr>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<JsonRpcResponse> {
+    let value: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(e) => {
+            return Json(JsonRpcResponse::error(
+                Value::Null,
+                JsonRpcError::new(JsonRpcError::PARSE_ERROR, format!("parse error: {e}")),
+            ))
+        }
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let req: JsonRpcRequest = match serde_json::from_value(value) {
+        Ok(req) => req,
+        Err(e) => {
+            return Json(JsonRpcResponse::error(
+                id,
+                JsonRpcError::new(
+                    JsonRpcError::INVALID_REQUEST,
+                    format!("invalid request: {e}"),
+                ),
+            ))
+        }
+    };
+    if req.jsonrpc != "2.0" {
+        return Json(JsonRpcResponse::error(
+            req.id,
+            JsonRpcError::new(JsonRpcError::INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+        ));
+    }
+
+    if let (Some(Extension(limiter)), "seq_submitTransaction") = (&limiter, req.method.as_str()) {
+        let client = limiter.client_ip(&headers, peer.map(|ConnectInfo(addr)| addr));
+        if let Err(wait) = limiter.acquire(client, Instant::now()) {
+            let retry_after = rate_limit::retry_after_secs(wait);
+            return Json(JsonRpcResponse::error(
+                req.id,
+                JsonRpcError::new(
+                    JsonRpcError::RATE_LIMITED,
+                    format!("rate limit exceeded, retry in {retry_after}s"),
+                ),
+            ));
+        }
+    }
+
+    let authorized = priority_authorized(&state.config, &headers);
+    match dispatch_rpc(&state, &req.method, req.params, authorized).await {
+        Ok(result) => Json(JsonRpcResponse::result(req.id, result)),
+        Err(error) => Json(JsonRpcResponse::error(req.id, error)),
+    }
+}
+
+/// Notification pushed to `GET /ws` subscribers.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsNotification {
+    BlockCommitted {
+        view: u64,
+        block: BlockView,
+    },
+    /// A block previously announced as committed was dropped by a reorg.
+    BlockReverted {
+        block_id: String,
+        height: u64,
+    },
+    /// Consensus went the configured number of views or time without
+    /// producing a block.
+    ViewTimeout {
+        view: u64,
+    },
+}
+
+/// `GET /ws`: stream committed blocks to the client as JSON text frames.
+async fn ws_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState<E>>,
+) -> impl IntoResponse {
+    // Subscribe before upgrading so no commit between the handshake and
+    // the socket task starting is missed.
+    let events = state.events.subscribe();
+    ws.on_upgrade(move |socket| stream_finality_events(socket, events))
+}
+
+async fn stream_finality_events(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<FinalityEvent>,
+) {
+    loop {
+        let notification = match events.recv().await {
+            Ok(FinalityEvent::BlockCommitted { block, qc }) => WsNotification::BlockCommitted {
+                view: qc.view.0,
+                block: BlockView::from(&block),
+            },
+            Ok(FinalityEvent::BlockReverted { block_id, height }) => {
+                WsNotification::BlockReverted {
+                    block_id: hex::encode(block_id.0 .0),
+                    height,
+                }
+            }
+            Ok(FinalityEvent::ViewTimeout { view }) => WsNotification::ViewTimeout { view: view.0 },
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "websocket subscriber lagged; dropping events");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let Ok(text) = serde_json::to_string(&notification) else {
+            continue;
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            // Client went away.
+            break;
+        }
+    }
+}
+
+/// Overall verdict of `GET /health`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    /// The head block is older than [`RpcConfig::max_block_age_ms`],
+    /// storage could not be read, or the gossip socket failed.
+    Degraded,
+}
+
+/// Response of `GET /health`.
+#[derive(Serialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    /// Height of the head block; 0 before the first block.
+    pub last_block_height: u64,
+    /// Time since the head block's timestamp; `None` before the first
+    /// block or when storage could not be read.
+    pub last_block_age_ms: Option<u64>,
+    /// Configured peers heard from recently; 0 without networking.
+    pub peers_alive: usize,
+}
+
+/// Report liveness, answering `503 Service Unavailable` when degraded so
+/// load balancers stop routing to the node. Reading the head block from
+/// storage doubles as the storage probe.
+#[tracing::instrument(skip(state))]
+async fn health_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let (height, head) = {
+        let engine = state.engine.lock().await;
+        let height = engine.head().map_or(0, |(height, _)| height);
+        (height, engine.latest_block())
+    };
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let age_ms = match &head {
+        Ok(head) => head
+            .as_ref()
+            .map(|block| now_ms.saturating_sub(block.header.timestamp_ms)),
+        Err(e) => {
+            warn!(error = %e, "health check could not read the head block");
+            None
+        }
+    };
+    let stalled = state
+        .config
+        .max_block_age_ms
+        .zip(age_ms)
+        .is_some_and(|(max, age)| age > max);
+    let network_failed = state.network.as_ref().is_some_and(|net| !net.is_healthy());
+    let peers_alive = state.network.as_ref().map_or(0, |net| {
+        net.peers().iter().filter(|peer| peer.alive).count()
+    });
+
+    let (code, status) = if head.is_err() || stalled || network_failed {
+        (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Degraded)
+    } else {
+        (StatusCode::OK, HealthStatus::Ok)
+    };
+    (
+        code,
+        Json(HealthResponse {
+            status,
+            last_block_height: height,
+            last_block_age_ms: age_ms,
+            peers_alive,
+        }),
+    )
+}
+
+/// Response of `GET /status`.
+#[derive(Serialize)]
+pub struct StatusResponse {
+    /// Height of the head block; 0 before the first block.
+    pub height: u64,
+    /// Id of the head block, absent before the first block.
+    pub head: Option<String>,
+    /// Transactions waiting in the mempool.
+    pub pending: usize,
+}
+
+#[tracing::instrument(skip(state))]
+async fn status_handler<E: ConsensusEngine + Send + Sync + 'static>(
+    State(state): State<AppState<E>>,
+    encoding: Encoding,
+) -> Encoded<StatusResponse> {
+    let engine = state.engine.lock().await;
+    let head = engine.head();
+    Encoded(
+        encoding,
+        StatusResponse {
+            height: head.map_or(0, |(height, _)| height),
+            head: head.map(|(_, id)| hex::encode(id.0 .0)),
+            pending: engine.pending_count(),
+        },
+    )
 }
 
 #[tracing::instrument(skip_all)]
@@ -87,36 +1245,1426 @@ async fn metrics_handler() -> impl IntoResponse {
     ([("Content-Type", "text/plain; version=0.0.4")], body)
 }
 
+/// Header carrying the id of a request, taken from the client if it sent
+/// one and echoed on the response.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Middleware recording request count and latency per route, and running
+/// the request in a `rpc_request` span tagged with its request id. Requests
+/// that match no route are grouped under `unmatched` to bound label
+/// cardinality.
+async fn track_requests(req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).to_string());
+
+    let span = info_span!("rpc_request", %request_id, %method, %path);
+    let mut response = next.run(req).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    metrics::record_rpc_request(method, path.clone(), response.status().as_u16());
+    metrics::record_rpc_request_duration_ms(path, elapsed);
+    response
+}
+
 pub fn router<E>(state: RpcState<E>) -> Router
 where
     E: ConsensusEngine + Send + Sync + 'static,
 {
-    Router::new()
-        .route("/health", get(health_handler))
+    let config = state.config.clone();
+    let mut submit_tx = post(submit_tx_handler::<E>);
+    let mut json_rpc = post(json_rpc_handler::<E>);
+    if let Some(limit) = config.tx_rate_limit.clone() {
+        let limiter = Arc::new(RateLimiter::new(limit));
+        submit_tx = submit_tx.layer(middleware::from_fn_with_state(
+            Arc::clone(&limiter),
+            rate_limit::limit_rate,
+        ));
+        // `seq_submitTransaction` spends the same tokens as `POST /tx`.
+        json_rpc = json_rpc.layer(Extension(limiter));
+    }
+    let mut app = Router::new()
+        .route("/health", get(health_handler::<E>))
         .route("/metrics", get(metrics_handler))
-        .route("/tx", post(submit_tx_handler::<E>))
-        .with_state(state)
+        .route("/status", get(status_handler::<E>))
+        .route("/tx", submit_tx)
+        .route("/tx/simulate", post(simulate_tx_handler::<E>))
+        .route("/tx/:id/status", get(tx_status_handler::<E>))
+        .route("/tx/:id/receipt", get(tx_receipt_handler::<E>))
+        .route("/tx/:id/proof", get(tx_proof_handler::<E>))
+        .route("/blocks", get(blocks_handler::<E>))
+        .route("/state/proof", get(state_proof_handler::<E>))
+        .route("/l1/batches", get(l1_batches_handler::<E>))
+        .route("/l1/batches/:number", get(l1_batch_handler::<E>))
+        .route("/rpc", json_rpc)
+        .route("/ws", get(ws_handler::<E>))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes));
+    if let Some(max) = config.max_connections {
+        // One semaphore shared by every route; a request that finds no
+        // permit is shed instead of waiting for one.
+        app = app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(overloaded))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(max)),
+        );
+    }
+    let app = app
+        .layer(middleware::from_fn(track_requests))
+        .with_state(state);
+    match config.cors_layer() {
+        Some(cors) => app.layer(cors),
+        None => app,
+    }
 }
 
-/// Helper to spawn the Axum server on the given address.
-#[tracing::instrument(skip(state))]
+/// Response to a request shed by [`RpcConfig::max_connections`].
+async fn overloaded(_: BoxError) -> HandlerError {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(ErrorResponse {
+            error: "server is busy, retry later".into(),
+        }),
+    )
+}
+
+/// Cloneable trigger for a graceful shutdown. Every clone shares the same
+/// signal, so an embedder can keep one and hand others to the RPC server
+/// and its own loops.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { tx: Arc::new(tx) }
+    }
+
+    /// Request shutdown. Idempotent.
+    pub fn shutdown(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Resolves once shutdown has been requested, immediately if it
+    /// already was.
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        // The sender lives as long as `self`, so this cannot fail.
+        let _ = rx.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// Bind `addr` with room for `backlog` connections waiting to be accepted.
+fn bind_listener(
+    addr: std::net::SocketAddr,
+    backlog: u32,
+) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // Like `TcpListener::bind`, so a restarted node can rebind at once.
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// Serve the RPC API on the given address until `shutdown` fires, then
+/// stop accepting connections and let in-flight requests finish.
+#[tracing::instrument(skip(state, shutdown))]
 pub async fn run_rpc_server<E>(
     state: RpcState<E>,
     addr: std::net::SocketAddr,
+    shutdown: ShutdownHandle,
 ) -> Result<(), std::convert::Infallible>
 where
     E: ConsensusEngine + Send + Sync + 'static,
 {
+    let backlog = state.config.accept_backlog;
     let app = router(state);
     info!(%addr, "starting RPC server");
-    let listener = tokio::net::TcpListener::bind(addr)
+    let listener = bind_listener(addr, backlog).expect("failed to bind RPC listener");
+    // Connection info lets the rate limiter tell clients apart.
+    let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.wait().await })
         .await
-        .expect("failed to bind RPC listener");
-    axum::serve(listener, app).await.expect("RPC server failed");
+        .expect("RPC server failed");
+    info!("RPC server stopped");
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    // RPC tests can be added later when we wire a test engine.
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use consensus::{
+        ConsensusConfig, InMemoryL1Sink, L1Batcher, L1BatcherConfig, SingleNodeConsensus,
+    };
+    use mempool::{Mempool, SimpleMempool};
+    use storage::InMemoryStorage;
+    use tower::ServiceExt;
+
+    type TestEngine = SingleNodeConsensus<SimpleMempool, InMemoryStorage>;
+
+    /// Engine where the zero address, the sender of test txs without a
+    /// `from`, is funded.
+    fn test_engine() -> TestEngine {
+        let config = ConsensusConfig {
+            genesis_balances: vec![(Address::default(), 1_000_000_000)],
+            ..Default::default()
+        };
+        SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap()
+    }
+
+    fn test_state() -> RpcState<TestEngine> {
+        state_with_config(RpcConfig::default())
+    }
+
+    fn state_with_config(config: RpcConfig) -> RpcState<TestEngine> {
+        let (events, _) = broadcast::channel(16);
+        Arc::new(RpcInnerState {
+            engine: Arc::new(Mutex::new(test_engine())),
+            network: None,
+            events,
+            config,
+            l1_batches: None,
+        })
+    }
+
+    async fn post_json(state: RpcState<TestEngine>, path: &str, body: &str) -> (StatusCode, Value) {
+        let response = router(state)
+            .oneshot(
+                Request::post(path)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    async fn get_json(state: RpcState<TestEngine>, path: &str) -> (StatusCode, Value) {
+        get_from(router(state), path).await
+    }
+
+    async fn get_from(app: Router, path: &str) -> (StatusCode, Value) {
+        let response = app
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    async fn call_rpc(state: RpcState<TestEngine>, body: &str) -> Value {
+        let (status, json) = post_json(state, "/rpc", body).await;
+        assert_eq!(status, StatusCode::OK);
+        json
+    }
+
+    async fn committed_tx(state: &RpcState<TestEngine>, tx_id: &str) -> Transaction {
+        let mut engine = state.engine.lock().await;
+        engine.step().unwrap();
+        engine
+            .get_tx(TxId(parse_hash(tx_id).unwrap()))
+            .unwrap()
+            .expect("tx committed")
+    }
+
+    #[tokio::test]
+    async fn requests_are_counted_in_metrics() {
+        // The recorder is process-global; another test may have installed it.
+        let _ = metrics::init_metrics();
+        let app = router(test_state());
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let rendered = metrics::render_metrics();
+        assert!(rendered.contains(
+            r#"sequencer_rpc_requests_total{method="GET",path="/health",status="200"} 2"#
+        ));
+        assert!(rendered.contains(r#"sequencer_rpc_request_duration_ms_count{path="/health"} 2"#));
+    }
+
+    #[tokio::test]
+    async fn submit_tx_decodes_hex_payload_and_signature() {
+        let state = test_state();
+        let (status, json) = post_json(
+            Arc::clone(&state),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"0x00ff10","signature":"abcd"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let tx = committed_tx(&state, json["tx_id"].as_str().unwrap()).await;
+        assert_eq!(tx.payload, vec![0x00, 0xff, 0x10]);
+        assert_eq!(tx.signature, vec![0xab, 0xcd]);
+    }
+
+    #[tokio::test]
+    async fn submit_tx_accepts_utf8_payloads() {
+        let state = test_state();
+        let (status, json) = post_json(
+            Arc::clone(&state),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"hello","payload_encoding":"utf8"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let tx = committed_tx(&state, json["tx_id"].as_str().unwrap()).await;
+        assert_eq!(tx.payload, b"hello".to_vec());
+        assert!(tx.signature.is_empty());
+    }
+
+    #[tokio::test]
+    async fn submit_tx_rejects_malformed_hex() {
+        let (status, json) = post_json(
+            test_state(),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"zz"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(json["error"].as_str().unwrap().contains("payload"));
+
+        let (status, _) = post_json(
+            test_state(),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00","signature":"abc"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn submit_tx_parses_sender_address() {
+        let state = test_state();
+        let from = Address::from_pubkey(&[3u8; 32]);
+        let body = format!(
+            r#"{{"from":"0x{from}","namespace":1,"gas_price":1,"nonce":0,"payload":"00"}}"#
+        );
+        let (status, _) = post_json(Arc::clone(&state), "/tx", &body).await;
+        assert_eq!(status, StatusCode::OK);
+        // Unfunded, so it stays pending.
+        let pending = state.engine.lock().await.mempool_mut().get_batch(1);
+        assert_eq!(pending[0].1.from, from);
+
+        let (status, json) = post_json(
+            test_state(),
+            "/tx",
+            r#"{"from":"abcd","namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(json["error"].as_str().unwrap().contains("from"));
+    }
+
+    #[tokio::test]
+    async fn simulate_tx_accepts_without_inserting() {
+        let state = test_state();
+        let (status, json) = post_json(
+            Arc::clone(&state),
+            "/tx/simulate",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00ff"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["would_accept"], true);
+        assert!(json.get("reason").is_none());
+        assert_eq!(json["estimated_gas"], types::TX_BASE_GAS + 2);
+        assert!(state.engine.lock().await.step().unwrap().is_none());
+
+        // A follow-up nonce is accepted on top of the sender's pending tx.
+        post_json(
+            Arc::clone(&state),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#,
+        )
+        .await;
+        let (_, json) = post_json(
+            Arc::clone(&state),
+            "/tx/simulate",
+            r#"{"namespace":1,"gas_price":1,"nonce":1,"payload":"00"}"#,
+        )
+        .await;
+        assert_eq!(json["would_accept"], true);
+    }
+
+    #[tokio::test]
+    async fn simulate_tx_reports_each_rejection_reason() {
+        let state = test_state();
+        // Commit nonce 0, and leave nonce 1 pending at gas price 10.
+        for (nonce, gas_price) in [(0, 1), (1, 10)] {
+            let body = format!(
+                r#"{{"namespace":1,"gas_price":{gas_price},"nonce":{nonce},"payload":"01"}}"#
+            );
+            let (status, _) = post_json(Arc::clone(&state), "/tx", &body).await;
+            assert_eq!(status, StatusCode::OK);
+            if nonce == 0 {
+                state.engine.lock().await.step().unwrap();
+            }
+        }
+
+        let cases = [
+            (
+                r#""chain_id":7,"namespace":1,"gas_price":1,"nonce":2"#,
+                "chain",
+            ),
+            (r#""namespace":1,"gas_price":0,"nonce":2"#, "gas price"),
+            (r#""namespace":1,"gas_price":10,"nonce":1"#, "underpriced"),
+            (r#""namespace":1,"gas_price":1,"nonce":0"#, "already used"),
+            (r#""namespace":1,"gas_price":1,"nonce":5"#, "skips ahead"),
+            (
+                r#""from":"0202020202020202020202020202020202020202","namespace":1,"gas_price":1,"nonce":0"#,
+                "insufficient balance",
+            ),
+        ];
+        for (fields, reason) in cases {
+            let body = format!(r#"{{{fields},"payload":"00"}}"#);
+            let (status, json) = post_json(Arc::clone(&state), "/tx/simulate", &body).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(json["would_accept"], false, "{fields}");
+            let got = json["reason"].as_str().unwrap();
+            assert!(got.contains(reason), "{fields}: {got}");
+        }
+        // Only the pending nonce-1 tx is in the mempool.
+        assert_eq!(state.engine.lock().await.mempool_mut().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn tx_status_moves_from_pending_to_committed() {
+        let state = test_state();
+        let unknown = format!("/tx/{}/status", "00".repeat(32));
+        let (status, json) = get_json(Arc::clone(&state), &unknown).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["status"], "unknown");
+
+        let (_, submitted) = post_json(
+            Arc::clone(&state),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#,
+        )
+        .await;
+        let path = format!("/tx/{}/status", submitted["tx_id"].as_str().unwrap());
+        let (_, json) = get_json(Arc::clone(&state), &path).await;
+        assert_eq!(json["status"], "pending");
+
+        state.engine.lock().await.step().unwrap();
+        let (_, json) = get_json(Arc::clone(&state), &path).await;
+        assert_eq!(json["status"], "committed");
+
+        let (status, _) = get_json(state, "/tx/zz/status").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn tx_receipt_reports_success_and_revert() {
+        let state = test_state();
+        let submit = |body: &'static str| {
+            let state = Arc::clone(&state);
+            async move {
+                let (_, json) = post_json(state, "/tx", body).await;
+                json["tx_id"].as_str().unwrap().to_string()
+            }
+        };
+
+        let ok = submit(r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#).await;
+        let (status, _) = get_json(Arc::clone(&state), &format!("/tx/{ok}/receipt")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        state.engine.lock().await.step().unwrap();
+
+        let (status, json) = get_json(Arc::clone(&state), &format!("/tx/{ok}/receipt")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["tx_id"], ok);
+        assert_eq!(json["status"], "success");
+        assert!(json.get("reason").is_none());
+        assert_eq!(json["index"], 0);
+        assert_eq!(json["gas_used"], types::TX_BASE_GAS + 1);
+        assert_eq!(json["logs"], serde_json::json!([]));
+
+        // Reusing nonce 0 reverts once the next block is built.
+        let stale = submit(r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"01"}"#).await;
+        submit(r#"{"namespace":1,"gas_price":1,"nonce":1,"payload":"00"}"#).await;
+        state.engine.lock().await.step().unwrap();
+
+        let (status, json) = get_json(Arc::clone(&state), &format!("/tx/{stale}/receipt")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["status"], "reverted");
+        assert!(json["reason"].as_str().unwrap().contains("already used"));
+        assert_eq!(json["index"], 1);
+        assert_eq!(json["gas_used"], 0);
+    }
+
+    /// State whose chain holds empty blocks at heights `1..=blocks`.
+    fn state_with_blocks(blocks: u64, max_blocks_page: usize) -> RpcState<TestEngine> {
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            produce_empty_blocks: true,
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+        for _ in 0..blocks {
+            engine.step().unwrap().expect("empty block produced");
+        }
+        let (events, _) = broadcast::channel(16);
+        Arc::new(RpcInnerState {
+            engine: Arc::new(Mutex::new(engine)),
+            network: None,
+            events,
+            config: RpcConfig {
+                max_blocks_page,
+                ..RpcConfig::default()
+            },
+            l1_batches: None,
+        })
+    }
+
+    fn heights(json: &Value) -> Vec<u64> {
+        json.as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b["height"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn blocks_pages_back_from_the_latest_block() {
+        let (status, json) =
+            get_json(state_with_blocks(0, DEFAULT_MAX_BLOCKS_PAGE), "/blocks").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json, serde_json::json!([]));
+
+        let state = state_with_blocks(25, DEFAULT_MAX_BLOCKS_PAGE);
+        let (status, json) = get_json(Arc::clone(&state), "/blocks").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(heights(&json), (6..=25).rev().collect::<Vec<_>>());
+
+        let latest = state.engine.lock().await.latest_block().unwrap().unwrap();
+        let entry = &json[0];
+        assert_eq!(
+            entry["block_id"].as_str().unwrap(),
+            hex::encode(latest.header.id().0 .0)
+        );
+        assert_eq!(entry["timestamp_ms"], latest.header.timestamp_ms);
+        assert_eq!(entry["tx_count"], 0);
+        assert_eq!(
+            entry["state_root"].as_str().unwrap(),
+            hex::encode(latest.header.state_root.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn blocks_honours_an_explicit_range() {
+        let state = state_with_blocks(12, DEFAULT_MAX_BLOCKS_PAGE);
+        let (_, json) = get_json(Arc::clone(&state), "/blocks?from=10&limit=3").await;
+        assert_eq!(heights(&json), vec![10, 9, 8]);
+
+        // The page stops at the first block...
+        let (_, json) = get_json(Arc::clone(&state), "/blocks?from=2&limit=5").await;
+        assert_eq!(heights(&json), vec![2, 1]);
+        // ...and never starts above the latest one.
+        let (_, json) = get_json(Arc::clone(&state), "/blocks?from=99&limit=2").await;
+        assert_eq!(heights(&json), vec![12, 11]);
+
+        let response = router(state)
+            .oneshot(
+                Request::get("/blocks?from=abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn blocks_clamps_the_limit() {
+        let state = state_with_blocks(12, 5);
+        let (_, json) = get_json(Arc::clone(&state), "/blocks?limit=50").await;
+        assert_eq!(heights(&json), vec![12, 11, 10, 9, 8]);
+
+        let (_, json) = get_json(state, "/blocks?limit=0").await;
+        assert_eq!(json, serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn l1_batches_follow_the_batcher() {
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            produce_empty_blocks: true,
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+        let (events, rx) = broadcast::channel(16);
+        let batcher = L1Batcher::new(
+            Arc::new(InMemoryL1Sink::default()),
+            L1BatcherConfig {
+                batch_size: 2,
+                ..Default::default()
+            },
+        );
+        let log = batcher.log();
+        batcher.spawn(rx);
+
+        let mut block_ids = Vec::new();
+        for _ in 0..5 {
+            let event = engine.step().unwrap().expect("empty block produced");
+            if let FinalityEvent::BlockCommitted { block, .. } = &event {
+                block_ids.push(hex::encode(block.header.id().0 .0));
+            }
+            events.send(event).unwrap();
+        }
+        while log.recent().len() < 2
+            || log
+                .recent()
+                .iter()
+                .any(|r| r.status == L1BatchStatus::Pending)
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        let state = Arc::new(RpcInnerState {
+            engine: Arc::new(Mutex::new(engine)),
+            network: None,
+            events,
+            config: RpcConfig::default(),
+            l1_batches: Some(log.clone()),
+        });
+        let (status, json) = get_json(Arc::clone(&state), "/l1/batches").await;
+        assert_eq!(status, StatusCode::OK);
+        let batches = json["batches"].as_array().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0]["batch_number"], 1);
+        assert_eq!(batches[0]["first_height"], 3);
+        assert_eq!(batches[0]["last_height"], 4);
+        assert_eq!(batches[0]["block_ids"], serde_json::json!(block_ids[2..4]));
+        assert_eq!(batches[1]["block_ids"], serde_json::json!(block_ids[..2]));
+
+        let (status, json) = get_json(Arc::clone(&state), "/l1/batches/0").await;
+        assert_eq!(status, StatusCode::OK);
+        let record = log.get(0).unwrap();
+        assert_eq!(json["status"], "posted");
+        assert_eq!(
+            json["commitment_hash"],
+            hex::encode(record.commitment.hash().0)
+        );
+        assert!(json["l1_tx_hash"].is_string());
+
+        let (status, _) = get_json(Arc::clone(&state), "/l1/batches/2").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get_json(test_state(), "/l1/batches").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn status_reports_head_and_pending_count() {
+        let state = test_state();
+        let (status, json) = get_json(Arc::clone(&state), "/status").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["height"], 0);
+        assert_eq!(json["head"], Value::Null);
+        assert_eq!(json["pending"], 0);
+
+        for nonce in 0..3 {
+            let body = format!(r#"{{"namespace":1,"gas_price":1,"nonce":{nonce},"payload":"00"}}"#);
+            post_json(Arc::clone(&state), "/tx", &body).await;
+            if nonce < 2 {
+                state.engine.lock().await.step().unwrap();
+            }
+        }
+        let head = state.engine.lock().await.latest_block().unwrap().unwrap();
+
+        let (_, json) = get_json(state, "/status").await;
+        assert_eq!(json["height"], 2);
+        assert_eq!(json["head"], hex::encode(head.header.id().0 .0));
+        assert_eq!(json["pending"], 1);
+    }
+
+    /// State whose engine seals one empty block per step, stamped by
+    /// `clock`, with `/health` degraded past a minute without a block.
+    fn health_state(clock: impl consensus::Clock + 'static) -> RpcState<TestEngine> {
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            produce_empty_blocks: true,
+            ..Default::default()
+        };
+        let engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap()
+        .with_clock(clock);
+        let (events, _) = broadcast::channel(16);
+        Arc::new(RpcInnerState {
+            engine: Arc::new(Mutex::new(engine)),
+            network: None,
+            events,
+            config: RpcConfig {
+                max_block_age_ms: Some(60_000),
+                ..RpcConfig::default()
+            },
+            l1_batches: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn health_is_ok_while_blocks_are_recent() {
+        let state = health_state(consensus::SystemClock);
+        state.engine.lock().await.step().unwrap();
+
+        let (status, json) = get_json(state, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["last_block_height"], 1);
+        assert!(json["last_block_age_ms"].as_u64().unwrap() < 60_000);
+        assert_eq!(json["peers_alive"], 0);
+    }
+
+    #[tokio::test]
+    async fn health_is_degraded_once_the_last_block_is_too_old() {
+        // Before any block there is nothing to be stale.
+        let state = health_state(consensus::MockClock::new(1_000));
+        let (status, json) = get_json(Arc::clone(&state), "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["last_block_age_ms"], Value::Null);
+
+        // A block stamped long ago: consensus has stalled since.
+        state.engine.lock().await.step().unwrap();
+        let (status, json) = get_json(state, "/health").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json["status"], "degraded");
+        assert_eq!(json["last_block_height"], 1);
+        assert!(json["last_block_age_ms"].as_u64().unwrap() > 60_000);
+    }
+
+    /// GET `path` with an `Accept` header; returns the status, the
+    /// `Content-Type` and the raw body.
+    async fn get_accepting(
+        state: RpcState<TestEngine>,
+        path: &str,
+        accept: &str,
+    ) -> (StatusCode, String, Bytes) {
+        let response = router(state)
+            .oneshot(
+                Request::get(path)
+                    .header("Accept", accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, content_type, bytes)
+    }
+
+    /// Client-side mirrors of the responses decoded from binary codecs.
+    #[cfg(any(feature = "bincode", feature = "msgpack"))]
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct StatusMirror {
+        height: u64,
+        head: Option<String>,
+        pending: usize,
+    }
+
+    #[cfg(any(feature = "bincode", feature = "msgpack"))]
+    #[derive(Debug, Deserialize)]
+    struct ReceiptMirror {
+        tx_id: String,
+        block_id: String,
+        index: u32,
+        status: String,
+        reason: Option<String>,
+        gas_used: u64,
+        /// No test receipt has logs.
+        logs: Vec<Value>,
+    }
+
+    /// Submit one transaction and commit it; returns its hex id.
+    #[cfg(any(feature = "bincode", feature = "msgpack"))]
+    async fn commit_one_tx(state: &RpcState<TestEngine>) -> String {
+        let (_, json) = post_json(
+            Arc::clone(state),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#,
+        )
+        .await;
+        state.engine.lock().await.step().unwrap();
+        json["tx_id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn responses_honour_the_accept_header() {
+        let state = test_state();
+        for accept in ["application/json", "*/*", "text/html, application/*;q=0.5"] {
+            let (status, content_type, body) =
+                get_accepting(Arc::clone(&state), "/status", accept).await;
+            assert_eq!(status, StatusCode::OK, "{accept}");
+            assert_eq!(content_type, "application/json", "{accept}");
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["height"], 0);
+        }
+
+        for accept in ["text/html", "application/json;q=0", "application/xml"] {
+            let (status, content_type, body) =
+                get_accepting(Arc::clone(&state), "/status", accept).await;
+            assert_eq!(status, StatusCode::NOT_ACCEPTABLE, "{accept}");
+            assert_eq!(content_type, "application/json");
+            let json: Value = serde_json::from_slice(&body).unwrap();
+            assert!(json["error"].as_str().unwrap().contains(accept));
+        }
+
+        // Refused before the handler runs, so nothing is submitted.
+        let response = router(Arc::clone(&state))
+            .oneshot(
+                Request::post("/tx")
+                    .header("Content-Type", "application/json")
+                    .header("Accept", "text/plain")
+                    .body(Body::from(
+                        r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(state.engine.lock().await.pending_count(), 0);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn bincode_responses_decode() {
+        let state = test_state();
+        let tx_id = commit_one_tx(&state).await;
+
+        let (status, content_type, body) =
+            get_accepting(Arc::clone(&state), "/status", "application/x-bincode").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/x-bincode");
+        let head: StatusMirror = bincode::deserialize(&body).unwrap();
+        let (_, json) = get_json(Arc::clone(&state), "/status").await;
+        assert_eq!(head.height, 1);
+        assert_eq!(head.head.as_deref(), json["head"].as_str());
+        assert_eq!(head.pending, 0);
+
+        // `reason` is left out of JSON but kept in place for bincode.
+        let path = format!("/tx/{tx_id}/receipt");
+        let (status, _, body) =
+            get_accepting(Arc::clone(&state), &path, "application/x-bincode").await;
+        assert_eq!(status, StatusCode::OK);
+        let receipt: ReceiptMirror = bincode::deserialize(&body).unwrap();
+        assert_eq!(receipt.tx_id, tx_id);
+        assert_eq!(receipt.status, "success");
+        assert_eq!(receipt.reason, None);
+        assert!(receipt.logs.is_empty());
+        let (_, json) = get_json(state, &path).await;
+        assert!(json.get("reason").is_none());
+        assert_eq!(receipt.block_id, json["block_id"].as_str().unwrap());
+        assert_eq!(receipt.gas_used, json["gas_used"].as_u64().unwrap());
+        assert_eq!(u64::from(receipt.index), json["index"].as_u64().unwrap());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[tokio::test]
+    async fn msgpack_responses_decode() {
+        let state = test_state();
+        let tx_id = commit_one_tx(&state).await;
+
+        let (status, content_type, body) =
+            get_accepting(Arc::clone(&state), "/status", "application/msgpack").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/msgpack");
+        let head: StatusMirror = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(head.height, 1);
+        assert_eq!(head.pending, 0);
+
+        let path = format!("/tx/{tx_id}/receipt");
+        let (status, _, body) = get_accepting(
+            Arc::clone(&state),
+            &path,
+            "text/html;q=0.9, application/msgpack",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let receipt: ReceiptMirror = rmp_serde::from_slice(&body).unwrap();
+        let (_, json) = get_json(state, &path).await;
+        assert_eq!(receipt.tx_id, tx_id);
+        assert_eq!(receipt.block_id, json["block_id"].as_str().unwrap());
+        assert_eq!(receipt.status, "success");
+        assert_eq!(receipt.reason, None);
+        assert_eq!(receipt.gas_used, json["gas_used"].as_u64().unwrap());
+        assert_eq!(u64::from(receipt.index), json["index"].as_u64().unwrap());
+        assert!(receipt.logs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn state_proof_ties_the_root_to_its_block() {
+        let state = state_with_blocks(3, DEFAULT_MAX_BLOCKS_PAGE);
+        let block = state
+            .engine
+            .lock()
+            .await
+            .get_block_by_height(2)
+            .unwrap()
+            .unwrap();
+        let block_id = hex::encode(block.header.id().0 .0);
+
+        let (status, json) = get_json(Arc::clone(&state), "/state/proof?height=2").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["height"], 2);
+        assert_eq!(json["state_root"], hex::encode(block.header.state_root.0));
+        assert_eq!(json["header"]["block_id"], block_id);
+        assert_eq!(json["header"]["state_root"], json["state_root"]);
+        assert_eq!(json["proof"]["kind"], "header");
+        assert_eq!(json["proof"]["block_id"], block_id);
+        let encoded = hex::decode(json["proof"]["encoded_header"].as_str().unwrap()).unwrap();
+        assert_eq!(hex::encode(types::hash_bytes(&encoded).0), block_id);
+
+        let (status, json) = get_json(state, "/state/proof?height=9").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(json["error"], "block not found");
+    }
+
+    #[tokio::test]
+    async fn tx_proof_verifies_against_the_block_tx_root() {
+        let state = test_state();
+        let mut ids = Vec::new();
+        for nonce in 0..2 {
+            let body = format!(r#"{{"namespace":1,"gas_price":1,"nonce":{nonce},"payload":"00"}}"#);
+            let (_, json) = post_json(Arc::clone(&state), "/tx", &body).await;
+            ids.push(json["tx_id"].as_str().unwrap().to_string());
+        }
+        committed_tx(&state, &ids[1]).await;
+
+        let (status, json) = get_json(Arc::clone(&state), &format!("/tx/{}/proof", ids[1])).await;
+        assert_eq!(status, StatusCode::OK);
+        let block = state
+            .engine
+            .lock()
+            .await
+            .get_block_by_height(1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(json["block_id"], hex::encode(block.header.id().0 .0));
+        assert_eq!(json["height"], 1);
+        assert_eq!(json["tx_root"], hex::encode(block.header.tx_root.0));
+        let proof = types::MerkleProof {
+            index: json["index"].as_u64().unwrap() as u32,
+            siblings: json["siblings"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|h| parse_hash(h.as_str().unwrap()).unwrap())
+                .collect(),
+        };
+        let tx_id = TxId(parse_hash(&ids[1]).unwrap());
+        assert!(types::verify_merkle_proof(
+            block.header.tx_root,
+            tx_id,
+            &proof
+        ));
+
+        // Submitted after the block was built, so still pending.
+        let body = r#"{"namespace":1,"gas_price":1,"nonce":2,"payload":"00"}"#;
+        let (_, json) = post_json(Arc::clone(&state), "/tx", body).await;
+        let (status, _) = get_json(
+            Arc::clone(&state),
+            &format!("/tx/{}/proof", json["tx_id"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let unknown = format!("/tx/{}/proof", "00".repeat(32));
+        let (status, json) = get_json(state, &unknown).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(json["error"], "transaction not found");
+    }
+
+    #[tokio::test]
+    async fn cors_headers_follow_the_config() {
+        const ORIGIN: &str = "https://dashboard.example";
+        let allow_origin = |config: RpcConfig| async move {
+            let response = router(state_with_config(config))
+                .oneshot(
+                    Request::get("/health")
+                        .header(header::ORIGIN, ORIGIN)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .cloned()
+        };
+
+        assert_eq!(allow_origin(RpcConfig::default()).await, None);
+        let enabled = RpcConfig {
+            enable_cors: true,
+            allowed_origins: vec![ORIGIN.to_string()],
+            ..RpcConfig::default()
+        };
+        assert_eq!(
+            allow_origin(enabled).await,
+            Some(HeaderValue::from_static(ORIGIN))
+        );
+        let other_origin = RpcConfig {
+            enable_cors: true,
+            allowed_origins: vec!["https://elsewhere.example".to_string()],
+            ..RpcConfig::default()
+        };
+        assert_eq!(allow_origin(other_origin).await, None);
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_echoed_or_assigned() {
+        let request = |id: Option<&str>| {
+            let mut builder = Request::get("/health");
+            if let Some(id) = id {
+                builder = builder.header(REQUEST_ID_HEADER, id);
+            }
+            router(test_state()).oneshot(builder.body(Body::empty()).unwrap())
+        };
+
+        let response = request(Some("client-42")).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-42");
+
+        let first = request(None).await.unwrap();
+        let second = request(None).await.unwrap();
+        assert_ne!(
+            first.headers()[REQUEST_ID_HEADER],
+            second.headers()[REQUEST_ID_HEADER]
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let state = state_with_config(RpcConfig {
+            max_body_bytes: 64,
+            ..RpcConfig::default()
+        });
+        let body = format!(
+            r#"{{"namespace":1,"gas_price":1,"nonce":0,"payload":"{}"}}"#,
+            "ab".repeat(64)
+        );
+        let response = router(state)
+            .oneshot(
+                Request::post("/tx")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn priority_submissions_require_the_operator_token() {
+        let state = state_with_config(RpcConfig {
+            priority_token: Some("s3cret".into()),
+            ..RpcConfig::default()
+        });
+        let body = r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00","priority":true}"#;
+        let submit = |authorization: Option<&str>| {
+            let mut request = Request::post("/tx").header("Content-Type", "application/json");
+            if let Some(value) = authorization {
+                request = request.header("Authorization", value);
+            }
+            router(Arc::clone(&state)).oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        for authorization in [None, Some("Bearer wrong"), Some("s3cret")] {
+            let response = submit(authorization).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        let rpc = call_rpc(
+            Arc::clone(&state),
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"seq_submitTransaction","params":{body},"id":1}}"#
+            ),
+        )
+        .await;
+        assert_eq!(rpc["error"]["code"], JsonRpcError::FORBIDDEN);
+        assert_eq!(state.engine.lock().await.pending_count(), 0);
+
+        let response = submit(Some("Bearer s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.engine.lock().await.pending_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn priority_submissions_are_refused_without_a_configured_token() {
+        let (status, json) = post_json(
+            test_state(),
+            "/tx",
+            r#"{"namespace":1,"gas_price":1,"nonce":0,"payload":"00","priority":true}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["error"], "`priority` requires the operator token");
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_max_connections_are_shed() {
+        let app = router(state_with_config(RpcConfig {
+            max_connections: Some(2),
+            ..RpcConfig::default()
+        }));
+        // Submissions whose bodies arrive only when released hold their
+        // permit until then.
+        let mut release = Vec::new();
+        let mut slow = Vec::new();
+        for _ in 0..2 {
+            let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+            release.push(tx);
+            let body = futures_util::stream::once(async move {
+                let _ = rx.await;
+                Ok::<_, std::io::Error>(Bytes::from_static(b"{}"))
+            });
+            let request = Request::post("/tx")
+                .header("Content-Type", "application/json")
+                .body(Body::from_stream(body))
+                .unwrap();
+            slow.push(tokio::spawn(app.clone().oneshot(request)));
+        }
+        tokio::task::yield_now().await;
+
+        let (status, json) = get_from(app.clone(), "/status").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json["error"], "server is busy, retry later");
+
+        drop(release);
+        for request in slow {
+            let response = request.await.unwrap().unwrap();
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let (status, _) = get_from(app.clone(), "/status").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn tx_submissions_are_rate_limited_per_client() {
+        let app = router(state_with_config(RpcConfig {
+            tx_rate_limit: Some(RateLimit {
+                requests_per_second: 20,
+                burst: 2,
+                trust_forwarded_for: true,
+            }),
+            ..RpcConfig::default()
+        }));
+        let submit = |nonce: u64, client: &str| {
+            let body = format!(r#"{{"namespace":1,"gas_price":1,"nonce":{nonce},"payload":"00"}}"#);
+            app.clone().oneshot(
+                Request::post("/tx")
+                    .header("Content-Type", "application/json")
+                    .header("X-Forwarded-For", client)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        for nonce in 0..2 {
+            let response = submit(nonce, "10.0.0.1").await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let limited = submit(2, "10.0.0.1").await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "1");
+        // Other clients and other routes are unaffected.
+        let other = submit(0, "10.0.0.2, 10.0.0.1").await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+        let health = app
+            .clone()
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let recovered = submit(2, "10.0.0.1").await.unwrap();
+        assert_eq!(recovered.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn json_rpc_submissions_share_the_tx_rate_limit() {
+        let app = router(state_with_config(RpcConfig {
+            tx_rate_limit: Some(RateLimit {
+                requests_per_second: 1,
+                burst: 2,
+                trust_forwarded_for: true,
+            }),
+            ..RpcConfig::default()
+        }));
+        let call = |method: &str, params: String| {
+            let body =
+                format!(r#"{{"jsonrpc":"2.0","method":"{method}","params":{params},"id":1}}"#);
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::post("/rpc")
+                            .header("Content-Type", "application/json")
+                            .header("X-Forwarded-For", "10.0.0.1")
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<Value>(&bytes).unwrap()
+            }
+        };
+        let submit = |nonce: u64| {
+            call(
+                "seq_submitTransaction",
+                format!(r#"{{"namespace":1,"gas_price":1,"nonce":{nonce},"payload":"00"}}"#),
+            )
+        };
+
+        assert!(submit(0).await.get("result").is_some());
+        // `POST /tx` takes the second token from the same bucket.
+        let rest = app
+            .clone()
+            .oneshot(
+                Request::post("/tx")
+                    .header("Content-Type", "application/json")
+                    .header("X-Forwarded-For", "10.0.0.1")
+                    .body(Body::from(
+                        r#"{"namespace":1,"gas_price":1,"nonce":1,"payload":"00"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rest.status(), StatusCode::OK);
+
+        let limited = submit(2).await;
+        assert_eq!(limited["error"]["code"], JsonRpcError::RATE_LIMITED);
+        assert_eq!(
+            limited["error"]["message"],
+            "rate limit exceeded, retry in 1s"
+        );
+        // Reads are not limited.
+        let read = call("seq_getBlockByHeight", "[1]".into()).await;
+        assert!(read.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn json_rpc_submit_then_query_block() {
+        let state = test_state();
+        let submitted = call_rpc(
+            Arc::clone(&state),
+            r#"{"jsonrpc":"2.0","method":"seq_submitTransaction","params":{"namespace":1,"gas_price":1,"nonce":0,"payload":"6869"},"id":1}"#,
+        )
+        .await;
+        assert_eq!(submitted["id"], 1);
+        let tx_id = submitted["result"]["tx_id"].as_str().unwrap().to_string();
+        assert_eq!(tx_id.len(), 64);
+
+        state.engine.lock().await.step().unwrap();
+
+        let block = call_rpc(
+            Arc::clone(&state),
+            r#"{"jsonrpc":"2.0","method":"seq_getBlockByHeight","params":[1],"id":"b"}"#,
+        )
+        .await;
+        assert_eq!(block["id"], "b");
+        assert_eq!(block["result"]["height"], 1);
+        assert_eq!(block["result"]["txs"][0], tx_id.as_str());
+
+        let tx = call_rpc(
+            state,
+            &format!(
+                r#"{{"jsonrpc":"2.0","method":"seq_getTransaction","params":["{tx_id}"],"id":2}}"#
+            ),
+        )
+        .await;
+        assert_eq!(tx["result"]["nonce"], 0);
+        assert!(tx.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn json_rpc_unknown_method_and_bad_params() {
+        let resp = call_rpc(
+            test_state(),
+            r#"{"jsonrpc":"2.0","method":"seq_nope","params":[],"id":7}"#,
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], JsonRpcError::METHOD_NOT_FOUND);
+        assert_eq!(resp["id"], 7);
+        assert!(resp.get("result").is_none());
+
+        let resp = call_rpc(
+            test_state(),
+            r#"{"jsonrpc":"2.0","method":"seq_getBlockByHeight","params":["tall"],"id":8}"#,
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], JsonRpcError::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn json_rpc_malformed_requests() {
+        let resp = call_rpc(test_state(), "{not json").await;
+        assert_eq!(resp["error"]["code"], JsonRpcError::PARSE_ERROR);
+        assert_eq!(resp["id"], Value::Null);
+
+        let resp = call_rpc(
+            test_state(),
+            r#"{"jsonrpc":"1.0","method":"seq_getTransaction","id":3}"#,
+        )
+        .await;
+        assert_eq!(resp["error"]["code"], JsonRpcError::INVALID_REQUEST);
+        assert_eq!(resp["id"], 3);
+
+        let resp = call_rpc(test_state(), r#"{"jsonrpc":"2.0","id":4}"#).await;
+        assert_eq!(resp["error"]["code"], JsonRpcError::INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn websocket_streams_committed_blocks() {
+        use futures_util::StreamExt;
+
+        let state = test_state();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(Arc::clone(&state));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+            .await
+            .unwrap();
+
+        // Drive a commit the same way the node's consensus loop does.
+        let mut engine = state.engine.lock().await;
+        engine
+            .submit_tx(Transaction {
+                chain_id: 0,
+                from: Address::default(),
+                namespace: NamespaceId(1),
+                gas_price: 1,
+                nonce: 0,
+                valid_until_height: None,
+                priority: false,
+                payload: vec![],
+                signature: vec![],
+                pow_nonce: 0,
+            })
+            .unwrap();
+        let event = engine.step().unwrap().expect("block committed");
+        drop(engine);
+        let FinalityEvent::BlockCommitted { block, .. } = event.clone() else {
+            panic!("expected committed block");
+        };
+        state.events.send(event).unwrap();
+
+        let msg = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("notification before timeout")
+            .unwrap()
+            .unwrap();
+        let json: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        assert_eq!(json["type"], "block_committed");
+        assert_eq!(json["block"]["height"], 1);
+        assert_eq!(
+            json["block"]["block_id"],
+            hex::encode(block.header.id().0 .0).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn server_stops_on_shutdown_signal() {
+        let shutdown = ShutdownHandle::new();
+        let server = tokio::spawn(run_rpc_server(
+            test_state(),
+            "127.0.0.1:0".parse().unwrap(),
+            shutdown.clone(),
+        ));
+        // Let the server bind before signalling.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!server.is_finished());
+
+        shutdown.shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server stopped before timeout")
+            .unwrap()
+            .unwrap();
+        assert!(shutdown.is_shutdown());
+    }
 }
@@ -0,0 +1,234 @@
+//! Per-client token buckets limiting how fast transactions can be
+//! submitted.
+//!
+//! Each client IP gets a bucket holding up to `burst` tokens that refills
+//! at `requests_per_second`. A request takes one token; with none left it
+//! is answered `429 Too Many Requests` with a `Retry-After` header.
+//! JSON-RPC `seq_submitTransaction` calls draw on the same buckets.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::ErrorResponse;
+
+/// Most buckets kept at once. A new client past it first prunes full
+/// buckets, then evicts the least recently used ones.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Rate limit settings for `POST /tx` and `seq_submitTransaction`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained requests per second allowed per client.
+    pub requests_per_second: u32,
+    /// Requests a client may make back to back before being limited.
+    pub burst: u32,
+    /// Identify clients by the first `X-Forwarded-For` address instead of
+    /// the connection's peer address. Only enable this behind a proxy that
+    /// sets the header, since clients can forge it.
+    pub trust_forwarded_for: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimit,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    max_clients: usize,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimit) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            max_clients: MAX_TRACKED_CLIENTS,
+        }
+    }
+
+    /// Take a token for `client` at `now`, or return how long until one is
+    /// available.
+    pub(crate) fn acquire(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.config.burst.max(1));
+        let rate = f64::from(self.config.requests_per_second);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= self.max_clients && !buckets.contains_key(&client) {
+            // A full bucket is what a forgotten client starts with anyway.
+            buckets.retain(|_, bucket| tokens_at(bucket, now, rate, burst) < burst);
+            // Evict a tenth at once so a flood of new clients does not
+            // sort the map on every request.
+            let keep = self.max_clients - self.max_clients.div_ceil(10);
+            if buckets.len() > keep {
+                let mut by_age: Vec<(Instant, IpAddr)> = buckets
+                    .iter()
+                    .map(|(ip, bucket)| (bucket.updated, *ip))
+                    .collect();
+                let evict = buckets.len() - keep;
+                by_age.select_nth_unstable(evict - 1);
+                for (_, ip) in &by_age[..evict] {
+                    buckets.remove(ip);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        if refill(bucket, now, rate, burst) >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        if rate == 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+
+    /// The client a request with `headers` from `peer` is counted against.
+    pub(crate) fn client_ip(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> IpAddr {
+        let forwarded = self
+            .config
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|first| first.trim().parse().ok());
+        forwarded
+            .or_else(|| peer.map(|addr| addr.ip()))
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+/// Whole seconds to wait before retrying, rounded up so a retry is never
+/// early.
+pub(crate) fn retry_after_secs(wait: Duration) -> u32 {
+    wait.as_secs_f64().ceil().clamp(1.0, u32::MAX as f64) as u32
+}
+
+/// The tokens `bucket` holds at `now`.
+fn tokens_at(bucket: &Bucket, now: Instant, rate: f64, burst: f64) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * rate).min(burst)
+}
+
+/// Bring `bucket` up to date at `now` and return its tokens.
+fn refill(bucket: &mut Bucket, now: Instant, rate: f64, burst: f64) -> f64 {
+    bucket.tokens = tokens_at(bucket, now, rate, burst);
+    bucket.updated = now;
+    bucket.tokens
+}
+
+/// Middleware rejecting requests from clients that are over their limit.
+pub(crate) async fn limit_rate(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let client = limiter.client_ip(req.headers(), peer);
+    match limiter.acquire(client, Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            let retry_after = retry_after_secs(wait);
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse {
+                    error: "rate limit exceeded".to_string(),
+                }),
+            )
+                .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_at_the_configured_rate() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 2,
+            burst: 3,
+            trust_forwarded_for: false,
+        });
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire(client, now), Ok(()));
+        }
+        assert_eq!(
+            limiter.acquire(client, now),
+            Err(Duration::from_millis(500))
+        );
+        // Other clients have their own bucket.
+        assert_eq!(limiter.acquire(other, now), Ok(()));
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.acquire(client, later), Ok(()));
+        assert!(limiter.acquire(client, later).is_err());
+        // Refills never exceed the burst.
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire(client, much_later), Ok(()));
+        }
+        assert!(limiter.acquire(client, much_later).is_err());
+    }
+
+    #[test]
+    fn least_recently_used_buckets_are_evicted_at_the_cap() {
+        let limiter = RateLimiter {
+            max_clients: 10,
+            ..RateLimiter::new(RateLimit {
+                requests_per_second: 0,
+                burst: 1,
+                trust_forwarded_for: false,
+            })
+        };
+        let client = |n: u8| IpAddr::from([10, 0, 0, n]);
+        let start = Instant::now();
+        // Ten clients drain their buckets, which never refill.
+        for n in 0..10 {
+            let now = start + Duration::from_secs(u64::from(n));
+            assert_eq!(limiter.acquire(client(n), now), Ok(()));
+        }
+        let now = start + Duration::from_secs(10);
+        assert!(limiter.acquire(client(9), now).is_err());
+
+        // A new client evicts the oldest one rather than growing the map.
+        assert_eq!(limiter.acquire(client(10), now), Ok(()));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 10);
+        assert_eq!(limiter.acquire(client(0), now), Ok(()));
+        assert!(limiter.acquire(client(9), now).is_err());
+
+        for n in 11..100 {
+            limiter.acquire(client(n), now).unwrap();
+            assert!(limiter.buckets.lock().unwrap().len() <= 10);
+        }
+    }
+}
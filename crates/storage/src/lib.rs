@@ -1,22 +1,110 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::Arc;
 use std::time::Instant;
 
-use thiserror::Error;
-use types::{Block, BlockId, Hash, Transaction, TxId};
 use metrics as sequencer_metrics;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError,
+    TransactionalTree,
+};
+use sled::Transactional;
+use thiserror::Error;
+use types::{merkle_root, Block, BlockId, Hash, Receipt, Transaction, TxId};
+
+#[cfg(feature = "rocksdb")]
+mod rocks;
+#[cfg(feature = "rocksdb")]
+pub use rocks::RocksStorage;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
     #[error("not found")]
     NotFound,
-    #[error("backend error: {0}")]
-    Backend(String),
+    /// A value could not be encoded for storage.
+    #[error("serialization error: {0}")]
+    Serialization(String),
+    /// The backend failed to read or write; retrying may succeed.
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// Stored or imported data does not decode or is inconsistent; retrying
+    /// will not help.
+    #[error("corrupted data: {0}")]
+    Corruption(String),
+    /// A reorg or prune was committed after a [`ReadView`] was captured,
+    /// so it can no longer answer consistently; capture a new one.
+    #[error("read view is stale")]
+    Stale,
+}
+
+impl From<sled::Error> for StorageError {
+    fn from(e: sled::Error) -> Self {
+        match e {
+            sled::Error::Corruption { .. } => Self::Corruption(e.to_string()),
+            _ => Self::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<TransactionError<sled::Error>> for StorageError {
+    fn from(e: TransactionError<sled::Error>) -> Self {
+        match e {
+            TransactionError::Abort(e) | TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
+
+impl From<bincode::Error> for StorageError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) => e.into(),
+            _ => Self::Serialization(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+/// Map a failure to decode a stored value.
+fn corrupt(e: impl std::fmt::Display) -> StorageError {
+    StorageError::Corruption(e.to_string())
+}
+
+/// Decode a state-root entry stored as a big-endian height key and a raw
+/// hash value.
+fn decode_state_root(key: &[u8], value: &[u8]) -> Result<(u64, Hash), StorageError> {
+    let height: [u8; 8] = key.try_into().map_err(corrupt)?;
+    let root: [u8; 32] = value.try_into().map_err(corrupt)?;
+    Ok((u64::from_be_bytes(height), Hash(root)))
+}
+
+/// The entry with the greatest height among `roots`, shared by every
+/// backend's `latest_state_root`. Roots are keyed by height, so no two
+/// entries tie and the result does not depend on iteration order.
+fn highest_state_root(
+    roots: impl IntoIterator<Item = (u64, Hash)>,
+) -> Result<(u64, Hash), StorageError> {
+    roots
+        .into_iter()
+        .max_by_key(|(height, _)| *height)
+        .ok_or(StorageError::NotFound)
 }
 
 pub trait BlockStore {
     fn put_block(&mut self, block: Block) -> Result<(), StorageError>;
     fn get_block(&self, id: BlockId) -> Result<Block, StorageError>;
     fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError>;
+    /// The block at the highest indexed height, or `NotFound` if the store
+    /// is empty.
+    fn latest_block(&self) -> Result<Block, StorageError>;
+    /// Indexed blocks with heights in `from..=to`, in ascending height
+    /// order. Heights without a block, e.g. pruned ones, are skipped.
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError>;
 }
 
 pub trait TxStore {
@@ -24,319 +112,2631 @@ pub trait TxStore {
     fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError>;
 }
 
+/// Storage for execution receipts, keyed by transaction id.
+pub trait ReceiptStore {
+    fn put_receipt(&mut self, receipt: Receipt) -> Result<(), StorageError>;
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError>;
+}
+
 pub trait StateStore {
     fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError>;
     fn latest_state_root(&self) -> Result<(u64, Hash), StorageError>;
+    /// The state root recorded for `height`, or `NotFound` if there is none.
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError>;
+    /// Recorded `(height, root)` pairs with heights in `from..=to`, in
+    /// ascending height order. Heights without a root are skipped.
+    fn state_roots_in_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Hash)>, StorageError>;
+    /// The account state recorded with [`StorageBatch::put_accounts`] for
+    /// `block`, or `NotFound` if there is none.
+    fn accounts_at(&self, block: BlockId) -> Result<Vec<u8>, StorageError>;
 }
 
-/// A simple in-memory storage implementation used for testing and as a
-/// reference for the sled-backed implementation.
-#[derive(Default)]
-pub struct InMemoryStorage {
-    blocks_by_id: HashMap<BlockId, Block>,
-    blocks_by_height: HashMap<u64, BlockId>,
-    txs: HashMap<TxId, Transaction>,
-    state_roots: HashMap<u64, Hash>,
+/// Storage that tracks the head of the canonical chain.
+pub trait HeadStore {
+    /// The current head, or `NotFound` if no head has been recorded.
+    fn head(&self) -> Result<BlockId, StorageError>;
+
+    /// A counter bumped by every commit that changes history already
+    /// written: a reorg ([`StorageBatch::set_canonical`]) or pruning.
+    /// [`StorageSnapshot`] checks it to notice it went stale. Backends
+    /// whose snapshots are isolated copies never bump it.
+    fn generation(&self) -> Result<u64, StorageError> {
+        Ok(0)
+    }
 }
 
-impl BlockStore for InMemoryStorage {
-    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
-        let id = block.header.id();
-        let height = block.header.height;
-        self.blocks_by_height.insert(height, id);
-        self.blocks_by_id.insert(id, block);
-        Ok(())
+/// Key under which the head block id is stored in the metadata tree.
+const HEAD_KEY: &[u8] = b"head";
+
+/// Key under which [`HeadStore::generation`] is stored in the metadata
+/// tree.
+const GENERATION_KEY: &[u8] = b"generation";
+
+/// A set of block, transaction, receipt and state-root writes that are
+/// applied together by [`BatchStore::commit_batch`].
+#[derive(Clone, Debug, Default)]
+pub struct StorageBatch {
+    blocks: Vec<Block>,
+    side_blocks: Vec<Block>,
+    canonical: Vec<(u64, BlockId)>,
+    head: Option<BlockId>,
+    txs: Vec<Transaction>,
+    receipts: Vec<Receipt>,
+    state_roots: Vec<(u64, Hash)>,
+    accounts: Vec<(BlockId, Vec<u8>)>,
+}
+
+impl StorageBatch {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
-        self.blocks_by_id
-            .get(&id)
-            .cloned()
-            .ok_or(StorageError::NotFound)
+    /// Store a block and index it as the canonical block at its height.
+    pub fn put_block(&mut self, block: Block) {
+        self.blocks.push(block);
     }
 
-    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
-        let id = self
-            .blocks_by_height
-            .get(&height)
-            .copied()
-            .ok_or(StorageError::NotFound)?;
-        self.get_block(id)
+    /// Store a block by id only, leaving the height index untouched. Used
+    /// for blocks on a non-canonical branch.
+    pub fn put_side_block(&mut self, block: Block) {
+        self.side_blocks.push(block);
     }
-}
 
-impl TxStore for InMemoryStorage {
-    fn put_tx(&mut self, tx: Transaction) -> Result<TxId, StorageError> {
+    /// Point the height index at `id`, e.g. when a reorg switches branches.
+    pub fn set_canonical(&mut self, height: u64, id: BlockId) {
+        self.canonical.push((height, id));
+    }
+
+    /// Record `id` as the head of the canonical chain.
+    pub fn set_head(&mut self, id: BlockId) {
+        self.head = Some(id);
+    }
+
+    pub fn put_tx(&mut self, tx: Transaction) -> TxId {
         let id = tx.id();
-        self.txs.insert(id, tx);
-        Ok(id)
+        self.txs.push(tx);
+        id
     }
 
-    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
-        self.txs.get(&id).cloned().ok_or(StorageError::NotFound)
+    pub fn put_receipt(&mut self, receipt: Receipt) {
+        self.receipts.push(receipt);
     }
-}
 
-impl StateStore for InMemoryStorage {
-    fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError> {
-        self.state_roots.insert(height, root);
-        Ok(())
+    pub fn put_state_root(&mut self, height: u64, root: Hash) {
+        self.state_roots.push((height, root));
     }
 
-    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
-        self.state_roots
-            .iter()
-            .max_by_key(|(h, _)| *h)
-            .map(|(h, r)| (*h, *r))
-            .ok_or(StorageError::NotFound)
+    /// Record the account state after `block`, encoded by the caller.
+    /// Kept for canonical and side blocks alike, and pruned with the block.
+    pub fn put_accounts(&mut self, block: BlockId, accounts: Vec<u8>) {
+        self.accounts.push((block, accounts));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+            && self.side_blocks.is_empty()
+            && self.canonical.is_empty()
+            && self.head.is_none()
+            && self.txs.is_empty()
+            && self.receipts.is_empty()
+            && self.state_roots.is_empty()
+            && self.accounts.is_empty()
     }
 }
 
-/// Sled-backed storage implementation intended for production use.
-pub struct SledStorage {
-    db: sled::Db,
-    blocks: sled::Tree,
-    blocks_by_height: sled::Tree,
-    txs: sled::Tree,
-    state_roots: sled::Tree,
+/// Storage that can reclaim space by deleting historical data.
+pub trait Prunable {
+    /// Delete blocks, their transaction bodies, receipts and account
+    /// states, and state roots strictly below `height`, returning the
+    /// number of blocks removed. The latest state root is always retained,
+    /// even if it is below `height`.
+    fn prune_below(&mut self, height: u64) -> Result<u64, StorageError>;
 }
 
-impl SledStorage {
-    pub fn open(path: &std::path::Path) -> Result<Self, StorageError> {
-        let db = sled::open(path).map_err(|e| StorageError::Backend(e.to_string()))?;
-        let blocks = db
-            .open_tree("blocks")
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let blocks_by_height = db
-            .open_tree("blocks_by_height")
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let txs = db
-            .open_tree("txs")
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let state_roots = db
-            .open_tree("state_roots")
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
+/// Storage that can apply a [`StorageBatch`] atomically: either every
+/// write in the batch lands or none does.
+pub trait BatchStore {
+    fn commit_batch(&mut self, batch: StorageBatch) -> Result<(), StorageError>;
+}
 
-        Ok(Self {
-            db,
-            blocks,
-            blocks_by_height,
-            txs,
-            state_roots,
-        })
-    }
+/// Everything a consensus engine needs from storage, as one trait so a
+/// backend chosen at runtime can be boxed as a [`BoxedStorage`].
+pub trait Storage:
+    BlockStore + StateStore + TxStore + ReceiptStore + BatchStore + HeadStore + ReadSnapshot
+{
 }
 
-impl BlockStore for SledStorage {
+impl<T> Storage for T where
+    T: BlockStore
+        + StateStore
+        + TxStore
+        + ReceiptStore
+        + BatchStore
+        + HeadStore
+        + ReadSnapshot
+        + ?Sized
+{
+}
+
+/// A storage backend chosen at runtime, e.g. from the node config.
+pub type BoxedStorage = Box<dyn Storage + Send>;
+
+impl<S: BlockStore + ?Sized> BlockStore for Box<S> {
     fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
-        let start = Instant::now();
-        let id = block.header.id();
-        let height = block.header.height;
-        let key_id = id.0 .0;
-        let key_height = height.to_be_bytes();
-        let value = bincode::serialize(&block).map_err(|e| StorageError::Backend(e.to_string()))?;
-
-        self.blocks
-            .insert(key_id, value)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        self.blocks_by_height
-            .insert(key_height, &id.0 .0)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_put_block", elapsed);
-        Ok(())
+        (**self).put_block(block)
     }
 
     fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
-        let start = Instant::now();
-        let key_id = id.0 .0;
-        let Some(bytes) = self
-            .blocks
-            .get(key_id)
-            .map_err(|e| StorageError::Backend(e.to_string()))? else {
-            return Err(StorageError::NotFound);
-        };
-        let block: Block = bincode::deserialize(&bytes)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_get_block", elapsed);
-        Ok(block)
+        (**self).get_block(id)
     }
 
     fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
-        let start = Instant::now();
-        let key_height = height.to_be_bytes();
-        let Some(id_bytes) = self
-            .blocks_by_height
-            .get(key_height)
-            .map_err(|e| StorageError::Backend(e.to_string()))? else {
-            return Err(StorageError::NotFound);
-        };
-        let mut id_arr = [0u8; 32];
-        id_arr.copy_from_slice(&id_bytes);
-        let id = BlockId(Hash(id_arr));
-        let block = self.get_block(id)?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_get_block_by_height", elapsed);
-        Ok(block)
+        (**self).get_block_by_height(height)
+    }
+
+    fn latest_block(&self) -> Result<Block, StorageError> {
+        (**self).latest_block()
+    }
+
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError> {
+        (**self).blocks_in_range(from, to)
     }
 }
 
-impl TxStore for SledStorage {
+impl<S: TxStore + ?Sized> TxStore for Box<S> {
     fn put_tx(&mut self, tx: Transaction) -> Result<TxId, StorageError> {
-        let start = Instant::now();
-        let id = tx.id();
-        let key_id = id.0 .0;
-        let value = bincode::serialize(&tx).map_err(|e| StorageError::Backend(e.to_string()))?;
-        self.txs
-            .insert(key_id, value)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_put_tx", elapsed);
-        Ok(id)
+        (**self).put_tx(tx)
     }
 
     fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
-        let start = Instant::now();
-        let key_id = id.0 .0;
-        let Some(bytes) = self
-            .txs
-            .get(key_id)
-            .map_err(|e| StorageError::Backend(e.to_string()))? else {
-            return Err(StorageError::NotFound);
-        };
-        let tx: Transaction = bincode::deserialize(&bytes)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_get_tx", elapsed);
-        Ok(tx)
+        (**self).get_tx(id)
     }
 }
 
-impl StateStore for SledStorage {
+impl<S: ReceiptStore + ?Sized> ReceiptStore for Box<S> {
+    fn put_receipt(&mut self, receipt: Receipt) -> Result<(), StorageError> {
+        (**self).put_receipt(receipt)
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError> {
+        (**self).get_receipt(id)
+    }
+}
+
+impl<S: StateStore + ?Sized> StateStore for Box<S> {
     fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError> {
-        let start = Instant::now();
-        let key_height = height.to_be_bytes();
-        self.state_roots
-            .insert(key_height, &root.0)
-            .map_err(|e| StorageError::Backend(e.to_string()))?;
-        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-        sequencer_metrics::record_storage_op_duration_ms("sled_put_state_root", elapsed);
-        Ok(())
+        (**self).put_state_root(height, root)
     }
 
     fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
-        let start = Instant::now();
-        let mut latest: Option<(u64, Hash)> = None;
-        for res in self.state_roots.iter() {
-            let (k, v) = res.map_err(|e| StorageError::Backend(e.to_string()))?;
-            let mut height_bytes = [0u8; 8];
-            height_bytes.copy_from_slice(&k);
-            let height = u64::from_be_bytes(height_bytes);
-            let mut root_bytes = [0u8; 32];
-            root_bytes.copy_from_slice(&v);
-            let candidate = (height, Hash(root_bytes));
-            if let Some((best_h, _)) = latest {
-                if height > best_h {
-                    latest = Some(candidate);
-                }
-            } else {
-                latest = Some(candidate);
-            }
-        }
-        let result = latest.ok_or(StorageError::NotFound);
-        if result.is_ok() {
-            let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-            sequencer_metrics::record_storage_op_duration_ms("sled_latest_state_root", elapsed);
-        }
-        result
+        (**self).latest_state_root()
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError> {
+        (**self).state_root_at_height(height)
+    }
+
+    fn state_roots_in_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Hash)>, StorageError> {
+        (**self).state_roots_in_range(from, to)
+    }
+
+    fn accounts_at(&self, block: BlockId) -> Result<Vec<u8>, StorageError> {
+        (**self).accounts_at(block)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use types::{BlockHeader, NamespaceId, Transaction};
-    use proptest::prelude::*;
+impl<S: HeadStore + ?Sized> HeadStore for Box<S> {
+    fn head(&self) -> Result<BlockId, StorageError> {
+        (**self).head()
+    }
+}
 
-    fn make_block(height: u64) -> Block {
-        let header = BlockHeader {
-            height,
-            parent: None,
-            tx_root: Hash([0u8; 32]),
-            state_root: Hash([0u8; 32]),
-            timestamp_ms: 0,
-            proposer: [0u8; 32],
-        };
-        Block {
-            header,
-            txs: Vec::new(),
-        }
+impl<S: BatchStore + ?Sized> BatchStore for Box<S> {
+    fn commit_batch(&mut self, batch: StorageBatch) -> Result<(), StorageError> {
+        (**self).commit_batch(batch)
     }
+}
 
-    fn make_tx(nonce: u64) -> Transaction {
-        Transaction {
-            namespace: NamespaceId(1),
-            gas_price: 1,
-            nonce,
-            payload: vec![],
-            signature: vec![],
-        }
+impl<S: ReadSnapshot + ?Sized> ReadSnapshot for Box<S> {
+    fn snapshot(&self) -> Result<Box<dyn ReadView>, StorageError> {
+        (**self).snapshot()
     }
+}
 
-    proptest! {
-        #[test]
-        fn in_memory_tx_roundtrip_holds(nonces in proptest::collection::vec(0u64..1000, 0..32)) {
-            let mut store = InMemoryStorage::default();
-            let mut ids = Vec::new();
-            for nonce in nonces {
-                let tx = make_tx(nonce);
-                let id = store.put_tx(tx.clone()).unwrap();
-                ids.push((id, tx));
-            }
+/// Read-only access to storage as of a fixed point in time, obtained from
+/// [`ReadSnapshot::snapshot`]. Methods behave like their namesakes on the
+/// store traits.
+pub trait ReadView {
+    fn get_block(&self, id: BlockId) -> Result<Block, StorageError>;
+    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError>;
+    fn latest_block(&self) -> Result<Block, StorageError>;
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError>;
+    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError>;
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError>;
+    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError>;
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError>;
+    fn head(&self) -> Result<BlockId, StorageError>;
+}
 
-            for (id, original) in ids {
-                let loaded = store.get_tx(id).unwrap();
-                prop_assert_eq!(loaded.id(), original.id());
-            }
+/// Storage that can freeze a [`ReadView`], so a reader sees a head, latest
+/// block and state root that belong together however many batches are
+/// committed while it reads.
+pub trait ReadSnapshot {
+    fn snapshot(&self) -> Result<Box<dyn ReadView>, StorageError>;
+}
+
+/// A [`ReadView`] pinned to the head a store had when it was captured.
+///
+/// The store is not copied: backends whose clones share a database (sled,
+/// RocksDB) keep writing underneath the snapshot. It hides every block
+/// above the captured height, and the transactions and receipts of those
+/// blocks. Blocks, transactions and receipts never change once stored, so
+/// only a reorg or prune can change what the view sees; once
+/// [`HeadStore::generation`] moves, reads fail with
+/// [`StorageError::Stale`] rather than mix two versions of the chain.
+pub struct StorageSnapshot<S> {
+    store: S,
+    /// [`HeadStore::generation`] at capture time.
+    generation: u64,
+    /// Head id at capture time.
+    head: Option<BlockId>,
+    /// Head block at capture time, or the latest block if no head was
+    /// recorded.
+    tip: Option<Block>,
+}
+
+impl<S> StorageSnapshot<S>
+where
+    S: BlockStore + TxStore + ReceiptStore + HeadStore,
+{
+    pub fn capture(store: S) -> Result<Self, StorageError> {
+        // Read first, so a reorg racing the head read below is caught by
+        // the first check.
+        let generation = store.generation()?;
+        // A head is written in the same batch as its block, so the block is
+        // readable once the head is.
+        let (head, tip) = match store.head() {
+            Ok(id) => (Some(id), Some(store.get_block(id)?)),
+            Err(StorageError::NotFound) => match store.latest_block() {
+                Ok(block) => (None, Some(block)),
+                Err(StorageError::NotFound) => (None, None),
+                Err(e) => return Err(e),
+            },
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            store,
+            generation,
+            head,
+            tip,
+        })
+    }
+
+    /// `read`, unless history changed since the capture, in which case it
+    /// may have read the new version.
+    fn checked<T>(&self, read: Result<T, StorageError>) -> Result<T, StorageError> {
+        if self.store.generation()? != self.generation {
+            return Err(StorageError::Stale);
         }
+        read
     }
 
-    #[test]
-    fn block_roundtrip_by_id_and_height() {
-        let mut store = InMemoryStorage::default();
-        let block = make_block(1);
-        let id = block.header.id();
+    fn covers(&self, height: u64) -> bool {
+        self.tip
+            .as_ref()
+            .is_some_and(|tip| height <= tip.header.height)
+    }
 
-        BlockStore::put_block(&mut store, block.clone()).unwrap();
+    /// `NotFound` if `block` was committed after the capture.
+    fn visible(&self, block: Block) -> Result<Block, StorageError> {
+        if self.covers(block.header.height) {
+            Ok(block)
+        } else {
+            Err(StorageError::NotFound)
+        }
+    }
+}
 
-        let fetched_by_id = BlockStore::get_block(&store, id).unwrap();
-        let fetched_by_height = BlockStore::get_block_by_height(&store, 1).unwrap();
+impl<S> ReadView for StorageSnapshot<S>
+where
+    S: BlockStore + TxStore + ReceiptStore + StateStore + HeadStore,
+{
+    fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
+        self.checked(self.store.get_block(id))
+            .and_then(|block| self.visible(block))
+    }
 
-        assert_eq!(fetched_by_id.header.height, 1);
-        assert_eq!(fetched_by_height.header.height, 1);
-        assert_eq!(fetched_by_id.header.id(), id);
+    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
+        match &self.tip {
+            Some(tip) if tip.header.height == height => Ok(tip.clone()),
+            _ if self.covers(height) => self.checked(self.store.get_block_by_height(height)),
+            _ => Err(StorageError::NotFound),
+        }
     }
 
-    #[test]
-    fn tx_roundtrip() {
-        let mut store = InMemoryStorage::default();
-        let tx = make_tx(1);
-        let id = TxStore::put_tx(&mut store, tx.clone()).unwrap();
+    fn latest_block(&self) -> Result<Block, StorageError> {
+        self.tip.clone().ok_or(StorageError::NotFound)
+    }
 
-        let fetched = TxStore::get_tx(&store, id).unwrap();
-        assert_eq!(fetched.nonce, tx.nonce);
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError> {
+        let Some(tip) = &self.tip else {
+            return Ok(Vec::new());
+        };
+        let to = to.min(tip.header.height);
+        if from > to {
+            return Ok(Vec::new());
+        }
+        self.checked(self.store.blocks_in_range(from, to))
     }
 
-    #[test]
-    fn state_root_latest_tracks_highest_height() {
+    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
+        let tx = self.checked(self.store.get_tx(id))?;
+        match self.checked(self.store.get_receipt(id)) {
+            Ok(receipt) => {
+                self.get_block(receipt.block_id)?;
+            }
+            // Stored without a receipt, so not tied to any block.
+            Err(StorageError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(tx)
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError> {
+        let receipt = self.checked(self.store.get_receipt(id))?;
+        self.get_block(receipt.block_id)?;
+        Ok(receipt)
+    }
+
+    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
+        self.tip
+            .as_ref()
+            .map(|tip| (tip.header.height, tip.header.state_root))
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError> {
+        match &self.tip {
+            Some(tip) if tip.header.height == height => Ok(tip.header.state_root),
+            _ if self.covers(height) => self.checked(self.store.state_root_at_height(height)),
+            _ => Err(StorageError::NotFound),
+        }
+    }
+
+    fn head(&self) -> Result<BlockId, StorageError> {
+        self.head.ok_or(StorageError::NotFound)
+    }
+}
+
+/// First bytes of every snapshot stream: a tag plus a format version.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"SEQSNAP\x01";
+
+/// One entry in a snapshot stream. Each record is written as a big-endian
+/// `u32` length followed by its bincode encoding.
+#[derive(Debug, Serialize, Deserialize)]
+enum SnapshotRecord {
+    /// A stored block, canonical or not.
+    Block(Block),
+    /// A height index entry.
+    Canonical {
+        height: u64,
+        id: BlockId,
+    },
+    Tx(Transaction),
+    StateRoot {
+        height: u64,
+        root: Hash,
+    },
+    Head(BlockId),
+    Receipt(Receipt),
+    Accounts {
+        block: BlockId,
+        accounts: Vec<u8>,
+    },
+}
+
+/// Storage that can be dumped to and restored from a portable byte stream,
+/// e.g. to seed a new node or move between backends.
+pub trait Snapshotable: BatchStore {
+    /// Write every block, height index entry, transaction, state root,
+    /// receipt, account state and the head to `w`.
+    fn export_snapshot<W: Write>(&self, w: W) -> Result<(), StorageError>;
+
+    /// Read a stream produced by [`Snapshotable::export_snapshot`] and apply
+    /// it as a single batch, so a truncated or corrupt stream writes nothing.
+    fn import_snapshot<R: Read>(&mut self, mut r: R) -> Result<(), StorageError> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(StorageError::Corruption("not a snapshot stream".into()));
+        }
+
+        let mut batch = StorageBatch::new();
+        while let Some(record) = read_snapshot_record(&mut r)? {
+            match record {
+                SnapshotRecord::Block(block) => batch.put_side_block(block),
+                SnapshotRecord::Canonical { height, id } => batch.set_canonical(height, id),
+                SnapshotRecord::Tx(tx) => {
+                    batch.put_tx(tx);
+                }
+                SnapshotRecord::StateRoot { height, root } => batch.put_state_root(height, root),
+                SnapshotRecord::Head(id) => batch.set_head(id),
+                SnapshotRecord::Receipt(receipt) => batch.put_receipt(receipt),
+                SnapshotRecord::Accounts { block, accounts } => batch.put_accounts(block, accounts),
+            }
+        }
+        self.commit_batch(batch)
+    }
+}
+
+/// Writes the snapshot header, then length-prefixed records.
+struct SnapshotWriter<W> {
+    w: W,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    fn new(mut w: W) -> Result<Self, StorageError> {
+        w.write_all(SNAPSHOT_MAGIC)?;
+        Ok(Self { w })
+    }
+
+    fn write(&mut self, record: &SnapshotRecord) -> Result<(), StorageError> {
+        let bytes = bincode::serialize(record)?;
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| StorageError::Serialization("snapshot record too large".into()))?;
+        self.w
+            .write_all(&len.to_be_bytes())
+            .and_then(|_| self.w.write_all(&bytes))
+            .map_err(StorageError::from)
+    }
+
+    fn finish(mut self) -> Result<(), StorageError> {
+        self.w.flush().map_err(StorageError::from)
+    }
+}
+
+/// Read the next record, or `None` at a clean end of stream.
+fn read_snapshot_record<R: Read>(r: &mut R) -> Result<Option<SnapshotRecord>, StorageError> {
+    let mut len_bytes = [0u8; 4];
+    let mut filled = 0;
+    while filled < len_bytes.len() {
+        match r.read(&mut len_bytes[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(StorageError::Corruption("truncated snapshot".into())),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // Read through `take` rather than preallocating, so a corrupt length
+    // cannot trigger a huge allocation.
+    let len = u64::from(u32::from_be_bytes(len_bytes));
+    let mut bytes = Vec::new();
+    r.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(StorageError::Corruption("truncated snapshot".into()));
+    }
+    let record = bincode::deserialize(&bytes).map_err(corrupt)?;
+    Ok(Some(record))
+}
+
+/// A simple in-memory storage implementation used for testing and as a
+/// reference for the sled-backed implementation.
+///
+/// Each map is shared between clones until one of them writes to it, so
+/// [`ReadSnapshot::snapshot`] copies nothing unless the snapshot outlives
+/// a write.
+#[derive(Clone, Default)]
+pub struct InMemoryStorage {
+    blocks_by_id: Arc<HashMap<BlockId, Block>>,
+    blocks_by_height: Arc<HashMap<u64, BlockId>>,
+    txs: Arc<HashMap<TxId, Transaction>>,
+    receipts: Arc<HashMap<TxId, Receipt>>,
+    state_roots: Arc<HashMap<u64, Hash>>,
+    accounts: Arc<HashMap<BlockId, Vec<u8>>>,
+    head: Option<BlockId>,
+}
+
+impl BlockStore for InMemoryStorage {
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        let id = block.header.id();
+        let height = block.header.height;
+        Arc::make_mut(&mut self.blocks_by_height).insert(height, id);
+        Arc::make_mut(&mut self.blocks_by_id).insert(id, block);
+        Ok(())
+    }
+
+    fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
+        self.blocks_by_id
+            .get(&id)
+            .cloned()
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
+        let id = self
+            .blocks_by_height
+            .get(&height)
+            .copied()
+            .ok_or(StorageError::NotFound)?;
+        self.get_block(id)
+    }
+
+    fn latest_block(&self) -> Result<Block, StorageError> {
+        let height = self
+            .blocks_by_height
+            .keys()
+            .max()
+            .copied()
+            .ok_or(StorageError::NotFound)?;
+        self.get_block_by_height(height)
+    }
+
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError> {
+        let mut heights: Vec<u64> = self
+            .blocks_by_height
+            .keys()
+            .copied()
+            .filter(|height| (from..=to).contains(height))
+            .collect();
+        heights.sort_unstable();
+        heights
+            .into_iter()
+            .map(|height| self.get_block_by_height(height))
+            .collect()
+    }
+}
+
+impl TxStore for InMemoryStorage {
+    fn put_tx(&mut self, tx: Transaction) -> Result<TxId, StorageError> {
+        let id = tx.id();
+        Arc::make_mut(&mut self.txs).insert(id, tx);
+        Ok(id)
+    }
+
+    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
+        self.txs.get(&id).cloned().ok_or(StorageError::NotFound)
+    }
+}
+
+impl ReceiptStore for InMemoryStorage {
+    fn put_receipt(&mut self, receipt: Receipt) -> Result<(), StorageError> {
+        Arc::make_mut(&mut self.receipts).insert(receipt.tx_id, receipt);
+        Ok(())
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError> {
+        self.receipts
+            .get(&id)
+            .cloned()
+            .ok_or(StorageError::NotFound)
+    }
+}
+
+impl StateStore for InMemoryStorage {
+    fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError> {
+        Arc::make_mut(&mut self.state_roots).insert(height, root);
+        Ok(())
+    }
+
+    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
+        highest_state_root(self.state_roots.iter().map(|(h, r)| (*h, *r)))
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError> {
+        self.state_roots
+            .get(&height)
+            .copied()
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn state_roots_in_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Hash)>, StorageError> {
+        let mut roots: Vec<(u64, Hash)> = self
+            .state_roots
+            .iter()
+            .filter(|(height, _)| (from..=to).contains(*height))
+            .map(|(height, root)| (*height, *root))
+            .collect();
+        roots.sort_unstable_by_key(|(height, _)| *height);
+        Ok(roots)
+    }
+
+    fn accounts_at(&self, block: BlockId) -> Result<Vec<u8>, StorageError> {
+        self.accounts
+            .get(&block)
+            .cloned()
+            .ok_or(StorageError::NotFound)
+    }
+}
+
+impl BatchStore for InMemoryStorage {
+    fn commit_batch(&mut self, batch: StorageBatch) -> Result<(), StorageError> {
+        for block in batch.blocks {
+            self.put_block(block)?;
+        }
+        for block in batch.side_blocks {
+            Arc::make_mut(&mut self.blocks_by_id).insert(block.header.id(), block);
+        }
+        for (height, id) in batch.canonical {
+            Arc::make_mut(&mut self.blocks_by_height).insert(height, id);
+        }
+        if let Some(head) = batch.head {
+            self.head = Some(head);
+        }
+        for tx in batch.txs {
+            self.put_tx(tx)?;
+        }
+        for receipt in batch.receipts {
+            self.put_receipt(receipt)?;
+        }
+        for (height, root) in batch.state_roots {
+            self.put_state_root(height, root)?;
+        }
+        for (block, accounts) in batch.accounts {
+            Arc::make_mut(&mut self.accounts).insert(block, accounts);
+        }
+        Ok(())
+    }
+}
+
+impl ReadSnapshot for InMemoryStorage {
+    fn snapshot(&self) -> Result<Box<dyn ReadView>, StorageError> {
+        Ok(Box::new(StorageSnapshot::capture(self.clone())?))
+    }
+}
+
+impl HeadStore for InMemoryStorage {
+    fn head(&self) -> Result<BlockId, StorageError> {
+        self.head.ok_or(StorageError::NotFound)
+    }
+}
+
+impl Snapshotable for InMemoryStorage {
+    fn export_snapshot<W: Write>(&self, w: W) -> Result<(), StorageError> {
+        // Sort by key so the output matches the key order of the on-disk
+        // backends and two exports of the same data are byte-identical.
+        let mut writer = SnapshotWriter::new(w)?;
+        let mut blocks: Vec<&Block> = self.blocks_by_id.values().collect();
+        blocks.sort_by_key(|b| b.header.id().0 .0);
+        for block in blocks {
+            writer.write(&SnapshotRecord::Block(block.clone()))?;
+        }
+        let mut canonical: Vec<(u64, BlockId)> = self
+            .blocks_by_height
+            .iter()
+            .map(|(h, id)| (*h, *id))
+            .collect();
+        canonical.sort_by_key(|(h, _)| *h);
+        for (height, id) in canonical {
+            writer.write(&SnapshotRecord::Canonical { height, id })?;
+        }
+        let mut txs: Vec<(&TxId, &Transaction)> = self.txs.iter().collect();
+        txs.sort_by_key(|(id, _)| id.0 .0);
+        for (_, tx) in txs {
+            writer.write(&SnapshotRecord::Tx(tx.clone()))?;
+        }
+        let mut receipts: Vec<&Receipt> = self.receipts.values().collect();
+        receipts.sort_by_key(|r| r.tx_id.0 .0);
+        for receipt in receipts {
+            writer.write(&SnapshotRecord::Receipt(receipt.clone()))?;
+        }
+        let mut state_roots: Vec<(u64, Hash)> =
+            self.state_roots.iter().map(|(h, r)| (*h, *r)).collect();
+        state_roots.sort_by_key(|(h, _)| *h);
+        for (height, root) in state_roots {
+            writer.write(&SnapshotRecord::StateRoot { height, root })?;
+        }
+        let mut accounts: Vec<(&BlockId, &Vec<u8>)> = self.accounts.iter().collect();
+        accounts.sort_by_key(|(block, _)| block.0 .0);
+        for (block, accounts) in accounts {
+            writer.write(&SnapshotRecord::Accounts {
+                block: *block,
+                accounts: accounts.clone(),
+            })?;
+        }
+        if let Some(head) = self.head {
+            writer.write(&SnapshotRecord::Head(head))?;
+        }
+        writer.finish()
+    }
+}
+
+impl Prunable for InMemoryStorage {
+    /// Side blocks below `height` are deleted too.
+    fn prune_below(&mut self, height: u64) -> Result<u64, StorageError> {
+        let latest = self.latest_state_root().ok().map(|(h, _)| h);
+        let pruned: HashSet<BlockId> = self
+            .blocks_by_id
+            .iter()
+            .filter(|(_, block)| block.header.height < height)
+            .map(|(id, _)| *id)
+            .collect();
+        // A transaction re-included by a block that stays keeps its body
+        // and receipt.
+        let kept_elsewhere = |tx_id: &TxId| {
+            self.receipts
+                .get(tx_id)
+                .is_some_and(|receipt| !pruned.contains(&receipt.block_id))
+        };
+        let dropped_txs: Vec<TxId> = pruned
+            .iter()
+            .flat_map(|id| &self.blocks_by_id[id].txs)
+            .filter(|tx_id| !kept_elsewhere(tx_id))
+            .copied()
+            .collect();
+        if !pruned.is_empty() {
+            let txs = Arc::make_mut(&mut self.txs);
+            for tx_id in &dropped_txs {
+                txs.remove(tx_id);
+            }
+            Arc::make_mut(&mut self.blocks_by_id).retain(|id, _| !pruned.contains(id));
+            Arc::make_mut(&mut self.receipts).retain(|_, r| !pruned.contains(&r.block_id));
+            Arc::make_mut(&mut self.accounts).retain(|block, _| !pruned.contains(block));
+        }
+        Arc::make_mut(&mut self.blocks_by_height).retain(|h, _| *h >= height);
+        Arc::make_mut(&mut self.state_roots).retain(|h, _| *h >= height || Some(*h) == latest);
+        Ok(pruned.len() as u64)
+    }
+}
+
+/// Version byte prefixed to zstd-compressed block values.
+const BLOCK_ZSTD_V1: u8 = 1;
+
+/// Every zstd frame starts with this magic. Together with the version byte it
+/// tells compressed values apart from legacy plain bincode, which would need
+/// a block height near 2^40 to look the same.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Settings for [`SledStorage::open_with_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SledConfig {
+    /// zstd level for block values; `None` stores plain bincode. Values
+    /// written either way can always be read back.
+    pub compression_level: Option<i32>,
+    /// Period of sled's background flush, in milliseconds; `None` disables
+    /// it, leaving flushing to `flush_on_block` and explicit calls.
+    pub flush_every_ms: Option<u64>,
+    /// Flush to disk after every write that stores a block, so a crash never
+    /// loses a committed block. Costs an fsync per block.
+    pub flush_on_block: bool,
+}
+
+impl Default for SledConfig {
+    fn default() -> Self {
+        Self {
+            compression_level: None,
+            // sled's own default.
+            flush_every_ms: Some(500),
+            flush_on_block: false,
+        }
+    }
+}
+
+fn encode_block(block: &Block, compression_level: Option<i32>) -> Result<Vec<u8>, StorageError> {
+    let bytes = bincode::serialize(block)?;
+    let Some(level) = compression_level else {
+        return Ok(bytes);
+    };
+    let mut value = vec![BLOCK_ZSTD_V1];
+    value.extend(
+        zstd::bulk::compress(&bytes, level)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?,
+    );
+    Ok(value)
+}
+
+fn decode_block(value: &[u8]) -> Result<Block, StorageError> {
+    let compressed = value.first() == Some(&BLOCK_ZSTD_V1) && value.get(1..5) == Some(&ZSTD_MAGIC);
+    if !compressed {
+        return bincode::deserialize(value).map_err(corrupt);
+    }
+    let bytes = zstd::stream::decode_all(&value[1..]).map_err(corrupt)?;
+    bincode::deserialize(&bytes).map_err(corrupt)
+}
+
+/// Sled-backed storage implementation intended for production use.
+///
+/// Reads take `&self`, and clones share the same database, so a clone can
+/// serve reads from another thread while the original keeps writing. Each
+/// read sees every batch committed before it, never part of one.
+#[derive(Clone)]
+pub struct SledStorage {
+    db: sled::Db,
+    compression_level: Option<i32>,
+    flush_on_block: bool,
+    blocks: sled::Tree,
+    blocks_by_height: sled::Tree,
+    txs: sled::Tree,
+    receipts: sled::Tree,
+    state_roots: sled::Tree,
+    meta: sled::Tree,
+    /// Every stored block, canonical or not, keyed by height then id, so
+    /// pruning finds side blocks without scanning `blocks`.
+    block_heights: sled::Tree,
+    /// Receipts keyed by block id then tx id, so pruning a block finds its
+    /// reverted receipts too without scanning `receipts`.
+    block_receipts: sled::Tree,
+    /// Encoded account state after each block, keyed by block id.
+    accounts: sled::Tree,
+}
+
+/// Key of `block` in `block_heights`.
+fn block_height_key(block: &Block) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&block.header.height.to_be_bytes());
+    key[8..].copy_from_slice(&block.header.id().0 .0);
+    key
+}
+
+/// Key of `receipt` in `block_receipts`.
+fn block_receipt_key(receipt: &Receipt) -> [u8; 64] {
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(&receipt.block_id.0 .0);
+    key[32..].copy_from_slice(&receipt.tx_id.0 .0);
+    key
+}
+
+impl SledStorage {
+    pub fn open(path: &std::path::Path) -> Result<Self, StorageError> {
+        Self::open_with_config(path, SledConfig::default())
+    }
+
+    pub fn open_with_config(
+        path: &std::path::Path,
+        config: SledConfig,
+    ) -> Result<Self, StorageError> {
+        let db = sled::Config::new()
+            .path(path)
+            .flush_every_ms(config.flush_every_ms)
+            .open()?;
+        let blocks = db.open_tree("blocks")?;
+        let blocks_by_height = db.open_tree("blocks_by_height")?;
+        let txs = db.open_tree("txs")?;
+        let receipts = db.open_tree("receipts")?;
+        let state_roots = db.open_tree("state_roots")?;
+        let meta = db.open_tree("meta")?;
+        let block_heights = db.open_tree("block_heights")?;
+        let block_receipts = db.open_tree("block_receipts")?;
+        let accounts = db.open_tree("accounts")?;
+
+        let store = Self {
+            db,
+            compression_level: config.compression_level,
+            flush_on_block: config.flush_on_block,
+            blocks,
+            blocks_by_height,
+            txs,
+            receipts,
+            state_roots,
+            meta,
+            block_heights,
+            block_receipts,
+            accounts,
+        };
+        if store.block_heights.is_empty() && !store.blocks.is_empty() {
+            store.rebuild_prune_indexes()?;
+        }
+        Ok(store)
+    }
+
+    /// Fill `block_heights` and `block_receipts` for a database written
+    /// before they existed.
+    fn rebuild_prune_indexes(&self) -> Result<(), StorageError> {
+        for res in self.blocks.iter() {
+            let (_, value) = res?;
+            self.block_heights
+                .insert(block_height_key(&decode_block(&value)?), &[])?;
+        }
+        for res in self.receipts.iter() {
+            let (_, value) = res?;
+            let receipt: Receipt = bincode::deserialize(&value).map_err(corrupt)?;
+            self.block_receipts
+                .insert(block_receipt_key(&receipt), &[])?;
+        }
+        Ok(())
+    }
+
+    /// Bytes the database occupies on disk, as estimated by sled.
+    pub fn size_on_disk(&self) -> Result<u64, StorageError> {
+        Ok(self.db.size_on_disk()?)
+    }
+
+    /// Number of stored blocks, canonical or not.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Report [`SledStorage::size_on_disk`] and
+    /// [`SledStorage::block_count`] to their gauges.
+    pub fn record_size_metrics(&self) -> Result<(), StorageError> {
+        sequencer_metrics::record_storage_bytes(self.size_on_disk()?);
+        sequencer_metrics::record_block_count(self.block_count());
+        Ok(())
+    }
+
+    /// Write all buffered changes to disk, blocking until they are durable.
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Write all buffered changes to disk. Call before shutting down so the
+    /// last committed blocks survive a restart.
+    pub async fn flush_async(&self) -> Result<(), StorageError> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    /// Check that every stored block is self-consistent: its key matches
+    /// `header.id()`, its `tx_root` matches the merkle root of its `txs`, and
+    /// every height index entry points at a stored block of that height.
+    pub fn verify_integrity(&self) -> Result<IntegrityReport, StorageError> {
+        let start = Instant::now();
+        let mut report = IntegrityReport::default();
+
+        for entry in self.blocks.iter() {
+            let (key, value) = entry?;
+            let key_id = <[u8; 32]>::try_from(key.as_ref())
+                .ok()
+                .map(|k| BlockId(Hash(k)));
+            let consistent = decode_block(&value).is_ok_and(|block| {
+                Some(block.header.id()) == key_id && merkle_root(&block.txs) == block.header.tx_root
+            });
+            report.record(consistent, key_id);
+        }
+
+        for entry in self.blocks_by_height.iter() {
+            let (key, value) = entry?;
+            let height = <[u8; 8]>::try_from(key.as_ref())
+                .ok()
+                .map(u64::from_be_bytes);
+            let id = <[u8; 32]>::try_from(value.as_ref())
+                .ok()
+                .map(|v| BlockId(Hash(v)));
+            let indexed = match id {
+                Some(id) => self.blocks.get(id.0 .0)?,
+                None => None,
+            };
+            let consistent = indexed
+                .and_then(|bytes| decode_block(&bytes).ok())
+                .is_some_and(|block| Some(block.header.height) == height);
+            report.record(consistent, id);
+        }
+
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_verify_integrity", elapsed);
+        Ok(report)
+    }
+}
+
+/// Result of [`SledStorage::verify_integrity`]. Each block record and each
+/// height index entry counts as one entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub ok: u64,
+    pub corrupt: u64,
+    /// Ids of corrupt blocks, or of blocks a corrupt index entry points at.
+    pub bad_ids: Vec<BlockId>,
+}
+
+impl IntegrityReport {
+    fn record(&mut self, consistent: bool, id: Option<BlockId>) {
+        if consistent {
+            self.ok += 1;
+        } else {
+            self.corrupt += 1;
+            self.bad_ids.extend(id);
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.corrupt == 0
+    }
+}
+
+impl BlockStore for SledStorage {
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let id = block.header.id();
+        let height = block.header.height;
+        let key_id = id.0 .0;
+        let key_height = height.to_be_bytes();
+        let value = encode_block(&block, self.compression_level)?;
+
+        self.blocks.insert(key_id, value)?;
+        self.blocks_by_height.insert(key_height, &id.0 .0)?;
+        self.block_heights.insert(block_height_key(&block), &[])?;
+        if self.flush_on_block {
+            self.db.flush()?;
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_put_block", elapsed);
+        Ok(())
+    }
+
+    fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        let key_id = id.0 .0;
+        let Some(bytes) = self.blocks.get(key_id)? else {
+            return Err(StorageError::NotFound);
+        };
+        let block = decode_block(&bytes)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_get_block", elapsed);
+        Ok(block)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        let key_height = height.to_be_bytes();
+        let Some(id_bytes) = self.blocks_by_height.get(key_height)? else {
+            return Err(StorageError::NotFound);
+        };
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&id_bytes);
+        let id = BlockId(Hash(id_arr));
+        let block = self.get_block(id)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_get_block_by_height", elapsed);
+        Ok(block)
+    }
+
+    fn latest_block(&self) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        // Heights are stored big-endian, so the last key is the highest.
+        let Some((_, id_bytes)) = self.blocks_by_height.last()? else {
+            return Err(StorageError::NotFound);
+        };
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&id_bytes);
+        let block = self.get_block(BlockId(Hash(id_arr)))?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_latest_block", elapsed);
+        Ok(block)
+    }
+
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError> {
+        if from > to {
+            return Ok(Vec::new());
+        }
+        let start = Instant::now();
+        let mut blocks = Vec::new();
+        for entry in self
+            .blocks_by_height
+            .range(from.to_be_bytes()..=to.to_be_bytes())
+        {
+            let (_, id_bytes) = entry?;
+            let mut id_arr = [0u8; 32];
+            id_arr.copy_from_slice(&id_bytes);
+            blocks.push(self.get_block(BlockId(Hash(id_arr)))?);
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_blocks_in_range", elapsed);
+        Ok(blocks)
+    }
+}
+
+impl TxStore for SledStorage {
+    fn put_tx(&mut self, tx: Transaction) -> Result<TxId, StorageError> {
+        let start = Instant::now();
+        let id = tx.id();
+        let key_id = id.0 .0;
+        let value = tx.encode();
+        self.txs.insert(key_id, value)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_put_tx", elapsed);
+        Ok(id)
+    }
+
+    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
+        let start = Instant::now();
+        let key_id = id.0 .0;
+        let Some(bytes) = self.txs.get(key_id)? else {
+            return Err(StorageError::NotFound);
+        };
+        let tx = Transaction::decode(&bytes).map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_get_tx", elapsed);
+        Ok(tx)
+    }
+}
+
+impl ReceiptStore for SledStorage {
+    fn put_receipt(&mut self, receipt: Receipt) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let value = bincode::serialize(&receipt)?;
+        self.receipts.insert(receipt.tx_id.0 .0, value)?;
+        self.block_receipts
+            .insert(block_receipt_key(&receipt), &[])?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_put_receipt", elapsed);
+        Ok(())
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self.receipts.get(id.0 .0)? else {
+            return Err(StorageError::NotFound);
+        };
+        let receipt: Receipt = bincode::deserialize(&bytes).map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_get_receipt", elapsed);
+        Ok(receipt)
+    }
+}
+
+impl StateStore for SledStorage {
+    fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let key_height = height.to_be_bytes();
+        self.state_roots.insert(key_height, &root.0)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_put_state_root", elapsed);
+        Ok(())
+    }
+
+    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
+        let start = Instant::now();
+        // Heights are stored big-endian, so the last key is the highest; a
+        // single read also cannot mix entries from concurrent writes.
+        let last = match self.state_roots.last()? {
+            Some((k, v)) => Some(decode_state_root(&k, &v)?),
+            None => None,
+        };
+        let latest = highest_state_root(last)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_latest_state_root", elapsed);
+        Ok(latest)
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self.state_roots.get(height.to_be_bytes())? else {
+            return Err(StorageError::NotFound);
+        };
+        let root: [u8; 32] = bytes.as_ref().try_into().map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_state_root_at_height", elapsed);
+        Ok(Hash(root))
+    }
+
+    fn state_roots_in_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Hash)>, StorageError> {
+        if from > to {
+            return Ok(Vec::new());
+        }
+        let start = Instant::now();
+        let mut roots = Vec::new();
+        for entry in self
+            .state_roots
+            .range(from.to_be_bytes()..=to.to_be_bytes())
+        {
+            let (key, value) = entry?;
+            roots.push(decode_state_root(&key, &value)?);
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_state_roots_in_range", elapsed);
+        Ok(roots)
+    }
+
+    fn accounts_at(&self, block: BlockId) -> Result<Vec<u8>, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self.accounts.get(block.0 .0)? else {
+            return Err(StorageError::NotFound);
+        };
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_accounts_at", elapsed);
+        Ok(bytes.to_vec())
+    }
+}
+
+impl BatchStore for SledStorage {
+    fn commit_batch(&mut self, batch: StorageBatch) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let mut blocks = sled::Batch::default();
+        let mut blocks_by_height = sled::Batch::default();
+        let mut txs = sled::Batch::default();
+        let mut receipts = sled::Batch::default();
+        let mut state_roots = sled::Batch::default();
+        let mut meta = sled::Batch::default();
+        let mut block_heights = sled::Batch::default();
+        let mut block_receipts = sled::Batch::default();
+        let mut accounts = sled::Batch::default();
+
+        for block in &batch.blocks {
+            let id = block.header.id();
+            let value = encode_block(block, self.compression_level)?;
+            blocks.insert(&id.0 .0, value);
+            blocks_by_height.insert(&block.header.height.to_be_bytes(), &id.0 .0);
+            block_heights.insert(&block_height_key(block)[..], &[]);
+        }
+        for block in &batch.side_blocks {
+            let value = encode_block(block, self.compression_level)?;
+            blocks.insert(&block.header.id().0 .0, value);
+            block_heights.insert(&block_height_key(block)[..], &[]);
+        }
+        for (height, id) in &batch.canonical {
+            blocks_by_height.insert(&height.to_be_bytes(), &id.0 .0);
+        }
+        let reorg = !batch.canonical.is_empty();
+        if let Some(head) = batch.head {
+            meta.insert(HEAD_KEY, &head.0 .0);
+        }
+        for tx in &batch.txs {
+            let value = tx.encode();
+            txs.insert(&tx.id().0 .0, value);
+        }
+        for receipt in &batch.receipts {
+            let value = bincode::serialize(receipt)?;
+            receipts.insert(&receipt.tx_id.0 .0, value);
+            block_receipts.insert(&block_receipt_key(receipt)[..], &[]);
+        }
+        for (height, root) in &batch.state_roots {
+            state_roots.insert(&height.to_be_bytes(), &root.0);
+        }
+        for (block, state) in &batch.accounts {
+            accounts.insert(&block.0 .0, state.as_slice());
+        }
+
+        (
+            &self.blocks,
+            &self.blocks_by_height,
+            &self.txs,
+            &self.receipts,
+            &self.state_roots,
+            &self.meta,
+            &self.block_heights,
+            &self.block_receipts,
+            &self.accounts,
+        )
+            .transaction(
+                |(
+                    t_blocks,
+                    t_by_height,
+                    t_txs,
+                    t_receipts,
+                    t_roots,
+                    t_meta,
+                    t_heights,
+                    t_block_receipts,
+                    t_accounts,
+                )| {
+                    t_blocks.apply_batch(&blocks)?;
+                    t_by_height.apply_batch(&blocks_by_height)?;
+                    t_txs.apply_batch(&txs)?;
+                    t_receipts.apply_batch(&receipts)?;
+                    t_roots.apply_batch(&state_roots)?;
+                    t_meta.apply_batch(&meta)?;
+                    t_heights.apply_batch(&block_heights)?;
+                    t_block_receipts.apply_batch(&block_receipts)?;
+                    t_accounts.apply_batch(&accounts)?;
+                    if reorg {
+                        bump_generation(t_meta)?;
+                    }
+                    Ok::<_, ConflictableTransactionError<sled::Error>>(())
+                },
+            )?;
+        if self.flush_on_block && !(batch.blocks.is_empty() && batch.side_blocks.is_empty()) {
+            self.db.flush()?;
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_commit_batch", elapsed);
+        Ok(())
+    }
+}
+
+impl ReadSnapshot for SledStorage {
+    fn snapshot(&self) -> Result<Box<dyn ReadView>, StorageError> {
+        Ok(Box::new(StorageSnapshot::capture(self.clone())?))
+    }
+}
+
+impl HeadStore for SledStorage {
+    fn head(&self) -> Result<BlockId, StorageError> {
+        let Some(bytes) = self.meta.get(HEAD_KEY)? else {
+            return Err(StorageError::NotFound);
+        };
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&bytes);
+        Ok(BlockId(Hash(id_arr)))
+    }
+
+    fn generation(&self) -> Result<u64, StorageError> {
+        self.meta
+            .get(GENERATION_KEY)?
+            .map_or(Ok(0), |bytes| decode_generation(&bytes))
+    }
+}
+
+fn decode_generation(bytes: &[u8]) -> Result<u64, StorageError> {
+    Ok(u64::from_be_bytes(bytes.try_into().map_err(corrupt)?))
+}
+
+/// Bump the generation inside a transaction that rewrites history.
+fn bump_generation(meta: &TransactionalTree) -> ConflictableTransactionResult<(), sled::Error> {
+    let generation = match meta.get(GENERATION_KEY)? {
+        Some(bytes) => decode_generation(&bytes).map_err(|e| {
+            ConflictableTransactionError::Abort(sled::Error::Unsupported(e.to_string()))
+        })?,
+        None => 0,
+    };
+    meta.insert(GENERATION_KEY, &(generation + 1).to_be_bytes())?;
+    Ok(())
+}
+
+impl Snapshotable for SledStorage {
+    fn export_snapshot<W: Write>(&self, w: W) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let mut writer = SnapshotWriter::new(w)?;
+        for res in self.blocks.iter() {
+            let (_, v) = res?;
+            writer.write(&SnapshotRecord::Block(decode_block(&v)?))?;
+        }
+        for res in self.blocks_by_height.iter() {
+            let (k, v) = res?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&k);
+            let mut id_arr = [0u8; 32];
+            id_arr.copy_from_slice(&v);
+            writer.write(&SnapshotRecord::Canonical {
+                height: u64::from_be_bytes(height_bytes),
+                id: BlockId(Hash(id_arr)),
+            })?;
+        }
+        for res in self.txs.iter() {
+            let (_, v) = res?;
+            let tx = Transaction::decode(&v).map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Tx(tx))?;
+        }
+        for res in self.receipts.iter() {
+            let (_, v) = res?;
+            let receipt: Receipt = bincode::deserialize(&v).map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Receipt(receipt))?;
+        }
+        for res in self.state_roots.iter() {
+            let (k, v) = res?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&k);
+            let mut root_bytes = [0u8; 32];
+            root_bytes.copy_from_slice(&v);
+            writer.write(&SnapshotRecord::StateRoot {
+                height: u64::from_be_bytes(height_bytes),
+                root: Hash(root_bytes),
+            })?;
+        }
+        for res in self.accounts.iter() {
+            let (k, v) = res?;
+            let block: [u8; 32] = k.as_ref().try_into().map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Accounts {
+                block: BlockId(Hash(block)),
+                accounts: v.to_vec(),
+            })?;
+        }
+        match self.head() {
+            Ok(head) => writer.write(&SnapshotRecord::Head(head))?,
+            Err(StorageError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        writer.finish()?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_export_snapshot", elapsed);
+        Ok(())
+    }
+}
+
+impl Prunable for SledStorage {
+    /// Side blocks below `height` are deleted too. Work is proportional
+    /// to what is pruned.
+    fn prune_below(&mut self, height: u64) -> Result<u64, StorageError> {
+        let start = Instant::now();
+        let height_be = height.to_be_bytes();
+        let latest = match self.latest_state_root() {
+            Ok((h, _)) => Some(h),
+            Err(StorageError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut blocks = sled::Batch::default();
+        let mut blocks_by_height = sled::Batch::default();
+        let mut txs = sled::Batch::default();
+        let mut receipts = sled::Batch::default();
+        let mut state_roots = sled::Batch::default();
+        let mut block_heights = sled::Batch::default();
+        let mut block_receipts = sled::Batch::default();
+        let mut accounts = sled::Batch::default();
+
+        let mut pruned = HashSet::new();
+        let mut pruned_blocks = Vec::new();
+        for res in self.block_heights.range(..&height_be[..]) {
+            let (key, _) = res?;
+            let id = key[8..].to_vec();
+            if let Some(bytes) = self.blocks.get(&id)? {
+                pruned_blocks.push(decode_block(&bytes)?);
+            }
+            blocks.remove(id.as_slice());
+            accounts.remove(id.as_slice());
+            block_heights.remove(key);
+            pruned.insert(id);
+        }
+        // A transaction re-included by a block that stays keeps its body
+        // and receipt.
+        let kept_elsewhere = |tx_id: &[u8]| -> Result<bool, StorageError> {
+            let Some(value) = self.receipts.get(tx_id)? else {
+                return Ok(false);
+            };
+            let receipt: Receipt = bincode::deserialize(&value).map_err(corrupt)?;
+            Ok(!pruned.contains(&receipt.block_id.0 .0[..]))
+        };
+        for block in &pruned_blocks {
+            for tx_id in &block.txs {
+                if !kept_elsewhere(&tx_id.0 .0)? {
+                    txs.remove(&tx_id.0 .0);
+                }
+            }
+            // Reverted receipts are not reachable from the block's txs.
+            for res in self.block_receipts.scan_prefix(block.header.id().0 .0) {
+                let (key, _) = res?;
+                if !kept_elsewhere(&key[32..])? {
+                    receipts.remove(&key[32..]);
+                }
+                block_receipts.remove(key);
+            }
+        }
+        for res in self.blocks_by_height.range(..height_be) {
+            let (key_height, _) = res?;
+            blocks_by_height.remove(key_height);
+        }
+        let mut changed = !pruned.is_empty();
+        for res in self.state_roots.range(..height_be) {
+            let (key_height, _) = res?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&key_height);
+            if Some(u64::from_be_bytes(height_bytes)) != latest {
+                state_roots.remove(key_height);
+                changed = true;
+            }
+        }
+
+        (
+            &self.blocks,
+            &self.blocks_by_height,
+            &self.txs,
+            &self.receipts,
+            &self.state_roots,
+            &self.meta,
+            &self.block_heights,
+            &self.block_receipts,
+            &self.accounts,
+        )
+            .transaction(
+                |(
+                    t_blocks,
+                    t_by_height,
+                    t_txs,
+                    t_receipts,
+                    t_roots,
+                    t_meta,
+                    t_heights,
+                    t_block_receipts,
+                    t_accounts,
+                )| {
+                    t_blocks.apply_batch(&blocks)?;
+                    t_by_height.apply_batch(&blocks_by_height)?;
+                    t_txs.apply_batch(&txs)?;
+                    t_receipts.apply_batch(&receipts)?;
+                    t_roots.apply_batch(&state_roots)?;
+                    t_heights.apply_batch(&block_heights)?;
+                    t_block_receipts.apply_batch(&block_receipts)?;
+                    t_accounts.apply_batch(&accounts)?;
+                    if changed {
+                        bump_generation(t_meta)?;
+                    }
+                    Ok::<_, ConflictableTransactionError<sled::Error>>(())
+                },
+            )?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("sled_prune_below", elapsed);
+        Ok(pruned.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use types::{Address, BlockHeader, ExecStatus, NamespaceId, Transaction};
+
+    fn make_block(height: u64) -> Block {
+        let header = BlockHeader {
+            height,
+            parent: None,
+            tx_root: Hash([0u8; 32]),
+            gas_used: 0,
+            fees: 0,
+            state_root: Hash([0u8; 32]),
+            timestamp_ms: 0,
+            proposer: [0u8; 32],
+        };
+        Block {
+            header,
+            txs: Vec::new(),
+        }
+    }
+
+    fn make_tx(nonce: u64) -> Transaction {
+        Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn backends_agree_on_latest_state_root(
+            puts in proptest::collection::vec((0u64..20, any::<u8>()), 0..16),
+        ) {
+            let dir = tempfile::tempdir().unwrap();
+            let mut sled = SledStorage::open(dir.path()).unwrap();
+            let mut memory = InMemoryStorage::default();
+            for (height, byte) in puts {
+                sled.put_state_root(height, Hash([byte; 32])).unwrap();
+                memory.put_state_root(height, Hash([byte; 32])).unwrap();
+            }
+
+            match (memory.latest_state_root(), sled.latest_state_root()) {
+                (Ok(expected), Ok(actual)) => prop_assert_eq!(expected, actual),
+                (Err(StorageError::NotFound), Err(StorageError::NotFound)) => {}
+                (expected, actual) => prop_assert!(false, "{expected:?} vs {actual:?}"),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn in_memory_tx_roundtrip_holds(nonces in proptest::collection::vec(0u64..1000, 0..32)) {
+            let mut store = InMemoryStorage::default();
+            let mut ids = Vec::new();
+            for nonce in nonces {
+                let tx = make_tx(nonce);
+                let id = store.put_tx(tx.clone()).unwrap();
+                ids.push((id, tx));
+            }
+
+            for (id, original) in ids {
+                let loaded = store.get_tx(id).unwrap();
+                prop_assert_eq!(loaded.id(), original.id());
+            }
+        }
+    }
+
+    #[test]
+    fn block_roundtrip_by_id_and_height() {
+        let mut store = InMemoryStorage::default();
+        let block = make_block(1);
+        let id = block.header.id();
+
+        BlockStore::put_block(&mut store, block.clone()).unwrap();
+
+        let fetched_by_id = BlockStore::get_block(&store, id).unwrap();
+        let fetched_by_height = BlockStore::get_block_by_height(&store, 1).unwrap();
+
+        assert_eq!(fetched_by_id.header.height, 1);
+        assert_eq!(fetched_by_height.header.height, 1);
+        assert_eq!(fetched_by_id.header.id(), id);
+    }
+
+    #[test]
+    fn tx_roundtrip() {
+        let mut store = InMemoryStorage::default();
+        let tx = make_tx(1);
+        let id = TxStore::put_tx(&mut store, tx.clone()).unwrap();
+
+        let fetched = TxStore::get_tx(&store, id).unwrap();
+        assert_eq!(fetched.nonce, tx.nonce);
+    }
+
+    #[test]
+    fn state_root_latest_tracks_highest_height() {
+        let mut store = InMemoryStorage::default();
+        StateStore::put_state_root(&mut store, 1, Hash([1u8; 32])).unwrap();
+        StateStore::put_state_root(&mut store, 5, Hash([5u8; 32])).unwrap();
+
+        let (height, root) = StateStore::latest_state_root(&store).unwrap();
+        assert_eq!(height, 5);
+        assert_eq!(root, Hash([5u8; 32]));
+    }
+
+    #[test]
+    fn state_root_at_height_looks_up_exact_heights() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sled = SledStorage::open(dir.path()).unwrap();
+        let mut memory = InMemoryStorage::default();
+        for store in [&mut sled as &mut dyn StateStore, &mut memory] {
+            store.put_state_root(1, Hash([1u8; 32])).unwrap();
+            store.put_state_root(5, Hash([5u8; 32])).unwrap();
+
+            assert_eq!(store.state_root_at_height(1).unwrap(), Hash([1u8; 32]));
+            assert_eq!(store.state_root_at_height(5).unwrap(), Hash([5u8; 32]));
+            assert!(matches!(
+                store.state_root_at_height(3),
+                Err(StorageError::NotFound)
+            ));
+        }
+    }
+
+    fn make_receipt(tx_id: TxId, block: &Block, index: u32, status: ExecStatus) -> Receipt {
+        let gas_used = match status {
+            ExecStatus::Success => types::TX_BASE_GAS,
+            ExecStatus::Reverted { .. } => 0,
+        };
+        Receipt {
+            tx_id,
+            block_id: block.header.id(),
+            index,
+            status,
+            gas_used,
+            fee: 0,
+            logs: Vec::new(),
+        }
+    }
+
+    /// Receipt suite shared by every backend: a successful and a reverted
+    /// receipt, written directly and through a batch.
+    fn backend_receipts<S: ReceiptStore + BatchStore>(store: &mut S) {
+        let block = make_block(1);
+        let ok_id = make_tx(0).id();
+        let reverted_id = make_tx(1).id();
+        assert!(matches!(
+            store.get_receipt(ok_id),
+            Err(StorageError::NotFound)
+        ));
+
+        let mut ok = make_receipt(ok_id, &block, 0, ExecStatus::Success);
+        ok.logs.push(types::Log {
+            namespace: NamespaceId(1),
+            topics: vec![Hash([1u8; 32])],
+            data: vec![1, 2, 3],
+        });
+        store.put_receipt(ok.clone()).unwrap();
+
+        let reverted = make_receipt(
+            reverted_id,
+            &block,
+            1,
+            ExecStatus::Reverted {
+                reason: "nonce 1 already used (expected 2)".into(),
+            },
+        );
+        let mut batch = StorageBatch::new();
+        batch.put_receipt(reverted.clone());
+        store.commit_batch(batch).unwrap();
+
+        assert_eq!(store.get_receipt(ok_id).unwrap(), ok);
+        assert_eq!(store.get_receipt(reverted_id).unwrap(), reverted);
+    }
+
+    #[test]
+    fn in_memory_receipts_roundtrip() {
+        backend_receipts(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_receipts_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_receipts(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_receipts_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_receipts(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    /// Round-trip suite shared by every backend.
+    fn backend_roundtrip<S: BlockStore + TxStore + StateStore>(store: &mut S) {
+        let block = make_block(4);
+        let block_id = block.header.id();
+        BlockStore::put_block(store, block).unwrap();
+        assert_eq!(
+            BlockStore::get_block(store, block_id).unwrap().header.id(),
+            block_id
+        );
+        assert_eq!(
+            BlockStore::get_block_by_height(store, 4)
+                .unwrap()
+                .header
+                .id(),
+            block_id
+        );
+        assert!(matches!(
+            BlockStore::get_block_by_height(store, 5),
+            Err(StorageError::NotFound)
+        ));
+
+        let tx = make_tx(9);
+        let tx_id = TxStore::put_tx(store, tx.clone()).unwrap();
+        assert_eq!(TxStore::get_tx(store, tx_id).unwrap(), tx);
+
+        assert!(matches!(
+            StateStore::latest_state_root(store),
+            Err(StorageError::NotFound)
+        ));
+        StateStore::put_state_root(store, 2, Hash([2u8; 32])).unwrap();
+        StateStore::put_state_root(store, 300, Hash([3u8; 32])).unwrap();
+        StateStore::put_state_root(store, 1, Hash([1u8; 32])).unwrap();
+        assert_eq!(
+            StateStore::latest_state_root(store).unwrap(),
+            (300, Hash([3u8; 32]))
+        );
+    }
+
+    /// `latest_block` suite shared by every backend.
+    fn backend_latest_block<S: BlockStore + BatchStore>(store: &mut S) {
+        assert!(matches!(store.latest_block(), Err(StorageError::NotFound)));
+
+        // 256 sorts before 2 as a little-endian key, not as a big-endian one.
+        for height in [1, 256, 2] {
+            let mut batch = StorageBatch::new();
+            batch.put_block(make_block(height));
+            store.commit_batch(batch).unwrap();
+        }
+        assert_eq!(store.latest_block().unwrap(), make_block(256));
+    }
+
+    #[test]
+    fn in_memory_latest_block() {
+        backend_latest_block(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_latest_block() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_latest_block(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_latest_block() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_latest_block(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    /// `blocks_in_range` suite shared by every backend.
+    fn backend_blocks_in_range<S: BlockStore + BatchStore>(store: &mut S) {
+        assert!(store.blocks_in_range(0, 10).unwrap().is_empty());
+
+        for height in [1, 2, 3, 5, 256] {
+            let mut batch = StorageBatch::new();
+            batch.put_block(make_block(height));
+            store.commit_batch(batch).unwrap();
+        }
+        let heights =
+            |blocks: Vec<Block>| blocks.iter().map(|b| b.header.height).collect::<Vec<_>>();
+        assert_eq!(heights(store.blocks_in_range(2, 5).unwrap()), vec![2, 3, 5]);
+        assert!(store.blocks_in_range(4, 4).unwrap().is_empty());
+        assert_eq!(
+            heights(store.blocks_in_range(0, u64::MAX).unwrap()),
+            vec![1, 2, 3, 5, 256]
+        );
+        assert!(store.blocks_in_range(5, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn in_memory_blocks_in_range() {
+        backend_blocks_in_range(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_blocks_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_blocks_in_range(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_blocks_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_blocks_in_range(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    /// `state_roots_in_range` suite shared by every backend.
+    fn backend_state_roots_in_range<S: StateStore>(store: &mut S) {
+        assert!(store.state_roots_in_range(0, 10).unwrap().is_empty());
+
+        let root = |height: u64| Hash([height as u8; 32]);
+        for height in [1, 2, 3, 5, 256] {
+            store.put_state_root(height, root(height)).unwrap();
+        }
+        let expected = |heights: &[u64]| heights.iter().map(|h| (*h, root(*h))).collect::<Vec<_>>();
+        // Both bounds are inclusive and the gap at 4 is skipped.
+        assert_eq!(
+            store.state_roots_in_range(2, 5).unwrap(),
+            expected(&[2, 3, 5])
+        );
+        assert!(store.state_roots_in_range(4, 4).unwrap().is_empty());
+        assert_eq!(store.state_roots_in_range(5, 5).unwrap(), expected(&[5]));
+        assert_eq!(
+            store.state_roots_in_range(0, u64::MAX).unwrap(),
+            expected(&[1, 2, 3, 5, 256])
+        );
+        assert!(store.state_roots_in_range(5, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn in_memory_state_roots_in_range() {
+        backend_state_roots_in_range(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_state_roots_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_state_roots_in_range(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_state_roots_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_state_roots_in_range(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    /// Batch suite shared by every backend.
+    fn backend_commit_batch<S: BlockStore + TxStore + StateStore + BatchStore>(store: &mut S) {
+        let block = make_block(3);
+        let block_id = block.header.id();
+        let mut batch = StorageBatch::new();
+        batch.put_block(block);
+        let tx_id = batch.put_tx(make_tx(5));
+        batch.put_state_root(3, Hash([7u8; 32]));
+        store.commit_batch(batch).unwrap();
+
+        assert_eq!(
+            BlockStore::get_block(store, block_id)
+                .unwrap()
+                .header
+                .height,
+            3
+        );
+        assert_eq!(
+            BlockStore::get_block_by_height(store, 3)
+                .unwrap()
+                .header
+                .id(),
+            block_id
+        );
+        assert_eq!(TxStore::get_tx(store, tx_id).unwrap().nonce, 5);
+        assert_eq!(
+            StateStore::latest_state_root(store).unwrap(),
+            (3, Hash([7u8; 32]))
+        );
+    }
+
+    /// Pruning suite shared by every backend: a 20-block chain with one
+    /// tx and receipt per block and state roots only for the first five
+    /// heights.
+    fn backend_prune<S>(store: &mut S)
+    where
+        S: BlockStore + TxStore + ReceiptStore + StateStore + BatchStore + Prunable,
+    {
+        let mut tx_ids = Vec::new();
+        for height in 1..=20 {
+            let mut batch = StorageBatch::new();
+            let tx_id = batch.put_tx(make_tx(height));
+            let mut block = make_block(height);
+            block.txs.push(tx_id);
+            batch.put_receipt(make_receipt(tx_id, &block, 0, ExecStatus::Success));
+            batch.put_accounts(block.header.id(), vec![height as u8]);
+            batch.put_block(block);
+            if height <= 5 {
+                batch.put_state_root(height, Hash([height as u8; 32]));
+            }
+            store.commit_batch(batch).unwrap();
+            tx_ids.push(tx_id);
+        }
+
+        assert_eq!(store.prune_below(10).unwrap(), 9);
+
+        for height in 1..10u64 {
+            assert!(matches!(
+                BlockStore::get_block_by_height(store, height),
+                Err(StorageError::NotFound)
+            ));
+            assert!(matches!(
+                TxStore::get_tx(store, tx_ids[height as usize - 1]),
+                Err(StorageError::NotFound)
+            ));
+            assert!(matches!(
+                store.get_receipt(tx_ids[height as usize - 1]),
+                Err(StorageError::NotFound)
+            ));
+            assert!(matches!(
+                store.accounts_at(make_block(height).header.id()),
+                Err(StorageError::NotFound)
+            ));
+        }
+        for height in 10..=20u64 {
+            let block = BlockStore::get_block_by_height(store, height).unwrap();
+            assert_eq!(block.header.height, height);
+            assert!(TxStore::get_tx(store, block.txs[0]).is_ok());
+            assert!(store.get_receipt(block.txs[0]).is_ok());
+            assert_eq!(
+                store.accounts_at(make_block(height).header.id()).unwrap(),
+                vec![height as u8]
+            );
+        }
+        assert_eq!(
+            StateStore::latest_state_root(store).unwrap(),
+            (5, Hash([5u8; 32]))
+        );
+
+        // Pruning again below the same height is a no-op.
+        assert_eq!(store.prune_below(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn in_memory_prune_below_removes_old_blocks() {
+        backend_prune(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_prune_below_removes_old_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_prune(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    fn backend_prune_side_blocks_and_reincluded_txs<S>(store: &mut S)
+    where
+        S: BlockStore + TxStore + ReceiptStore + StateStore + BatchStore + Prunable,
+    {
+        let moved = make_tx(100).id();
+        let reverted = make_tx(200).id();
+
+        // A side block at height 2 first includes `moved`, which the
+        // canonical chain only picks up at height 5.
+        let mut side = make_block(2);
+        side.header.timestamp_ms = 1;
+        side.txs.push(moved);
+        let mut batch = StorageBatch::new();
+        batch.put_tx(make_tx(100));
+        batch.put_receipt(make_receipt(moved, &side, 0, ExecStatus::Success));
+        batch.put_side_block(side.clone());
+        store.commit_batch(batch).unwrap();
+        for height in 1..=5 {
+            let mut block = make_block(height);
+            let mut batch = StorageBatch::new();
+            if height == 2 {
+                batch.put_tx(make_tx(200));
+                let status = ExecStatus::Reverted {
+                    reason: "nonce too low".into(),
+                };
+                batch.put_receipt(make_receipt(reverted, &block, 0, status));
+            }
+            if height == 5 {
+                block.txs.push(moved);
+                batch.put_receipt(make_receipt(moved, &block, 0, ExecStatus::Success));
+            }
+            batch.put_block(block);
+            store.commit_batch(batch).unwrap();
+        }
+
+        // Canonical heights 1 to 3 and the side block.
+        assert_eq!(store.prune_below(4).unwrap(), 4);
+        assert!(matches!(
+            BlockStore::get_block(store, side.header.id()),
+            Err(StorageError::NotFound)
+        ));
+        assert!(matches!(
+            store.get_receipt(reverted),
+            Err(StorageError::NotFound)
+        ));
+        assert!(TxStore::get_tx(store, moved).is_ok());
+        let receipt = store.get_receipt(moved).unwrap();
+        assert_eq!(
+            receipt.block_id,
+            BlockStore::get_block_by_height(store, 5)
+                .unwrap()
+                .header
+                .id()
+        );
+        for height in 4..=5 {
+            assert!(BlockStore::get_block_by_height(store, height).is_ok());
+        }
+    }
+
+    #[test]
+    fn in_memory_prune_below_removes_side_blocks_and_keeps_reincluded_txs() {
+        backend_prune_side_blocks_and_reincluded_txs(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_prune_below_removes_side_blocks_and_keeps_reincluded_txs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        backend_prune_side_blocks_and_reincluded_txs(&mut store);
+        assert_eq!(store.block_count(), 2);
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_prune_below_removes_side_blocks_and_keeps_reincluded_txs() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_prune_side_blocks_and_reincluded_txs(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn sled_prune_indexes_are_rebuilt_for_an_older_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        for height in 1..=3 {
+            store.put_block(make_block(height)).unwrap();
+        }
+        // As written before the index existed; `open` rebuilds it then.
+        store.block_heights.clear().unwrap();
+        store.rebuild_prune_indexes().unwrap();
+
+        assert_eq!(store.prune_below(3).unwrap(), 2);
+        assert_eq!(store.block_count(), 1);
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_prune_below_removes_old_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_prune(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    /// Fork-aware batch suite shared by every backend: side blocks do not
+    /// touch the height index until they are made canonical.
+    fn backend_side_blocks_and_head<S: BlockStore + BatchStore + HeadStore>(store: &mut S) {
+        assert!(matches!(store.head(), Err(StorageError::NotFound)));
+
+        let main = make_block(1);
+        let main_id = main.header.id();
+        let mut side = make_block(1);
+        side.header.timestamp_ms = 1;
+        let side_id = side.header.id();
+
+        let mut batch = StorageBatch::new();
+        batch.put_block(main);
+        batch.put_side_block(side);
+        batch.set_head(main_id);
+        store.commit_batch(batch).unwrap();
+
+        assert_eq!(store.head().unwrap(), main_id);
+        assert!(BlockStore::get_block(store, side_id).is_ok());
+        assert_eq!(
+            BlockStore::get_block_by_height(store, 1)
+                .unwrap()
+                .header
+                .id(),
+            main_id
+        );
+
+        let mut batch = StorageBatch::new();
+        batch.set_canonical(1, side_id);
+        batch.set_head(side_id);
+        store.commit_batch(batch).unwrap();
+
+        assert_eq!(store.head().unwrap(), side_id);
+        assert_eq!(
+            BlockStore::get_block_by_height(store, 1)
+                .unwrap()
+                .header
+                .id(),
+            side_id
+        );
+    }
+
+    /// Snapshot suite shared by every backend: a snapshot of `src` imported
+    /// into an empty `dst` reproduces every record, including side blocks,
+    /// the height index and the head.
+    fn backend_snapshot_roundtrip<S>(src: &mut S, dst: &mut S)
+    where
+        S: BlockStore + TxStore + ReceiptStore + StateStore + HeadStore + Snapshotable,
+    {
+        let mut batch = StorageBatch::new();
+        let mut blocks = Vec::new();
+        for height in 1..=3 {
+            let block = make_block(height);
+            blocks.push(block.clone());
+            batch.put_block(block);
+            batch.put_state_root(height, Hash([height as u8; 32]));
+        }
+        let mut side = make_block(3);
+        side.header.timestamp_ms = 1;
+        batch.put_side_block(side.clone());
+        let tx_ids: Vec<TxId> = (0..4).map(|nonce| batch.put_tx(make_tx(nonce))).collect();
+        batch.put_receipt(make_receipt(tx_ids[0], &blocks[0], 0, ExecStatus::Success));
+        let reverted = ExecStatus::Reverted {
+            reason: "stale".into(),
+        };
+        batch.put_receipt(make_receipt(tx_ids[1], &blocks[0], 1, reverted));
+        let head = blocks[2].header.id();
+        batch.set_head(head);
+        src.commit_batch(batch).unwrap();
+
+        let mut snapshot = Vec::new();
+        src.export_snapshot(&mut snapshot).unwrap();
+        dst.import_snapshot(snapshot.as_slice()).unwrap();
+
+        for block in blocks.iter().chain([&side]) {
+            assert_eq!(
+                BlockStore::get_block(dst, block.header.id()).unwrap(),
+                *block
+            );
+        }
+        for block in &blocks {
+            assert_eq!(
+                BlockStore::get_block_by_height(dst, block.header.height).unwrap(),
+                *block
+            );
+        }
+        for id in &tx_ids {
+            assert_eq!(
+                TxStore::get_tx(dst, *id).unwrap(),
+                TxStore::get_tx(src, *id).unwrap()
+            );
+        }
+        for id in &tx_ids[..2] {
+            assert_eq!(dst.get_receipt(*id).unwrap(), src.get_receipt(*id).unwrap());
+        }
+        assert_eq!(dst.latest_state_root().unwrap(), (3, Hash([3; 32])));
+        assert_eq!(dst.head().unwrap(), head);
+
+        // Re-exporting the restored store yields the same stream.
+        let mut again = Vec::new();
+        dst.export_snapshot(&mut again).unwrap();
+        assert_eq!(again, snapshot);
+
+        // A truncated stream is rejected without writing anything.
+        let truncated = &snapshot[..snapshot.len() - 1];
+        let mut empty = InMemoryStorage::default();
+        assert!(matches!(
+            empty.import_snapshot(truncated),
+            Err(StorageError::Corruption(_))
+        ));
+        assert!(matches!(empty.head(), Err(StorageError::NotFound)));
+    }
+
+    #[test]
+    fn in_memory_snapshot_roundtrip() {
+        backend_snapshot_roundtrip(
+            &mut InMemoryStorage::default(),
+            &mut InMemoryStorage::default(),
+        );
+    }
+
+    #[test]
+    fn sled_snapshot_roundtrip() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let dst_dir = tempfile::tempdir().unwrap();
+        backend_snapshot_roundtrip(
+            &mut SledStorage::open(src_dir.path()).unwrap(),
+            &mut SledStorage::open(dst_dir.path()).unwrap(),
+        );
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_snapshot_roundtrip() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let dst_dir = tempfile::tempdir().unwrap();
+        backend_snapshot_roundtrip(
+            &mut RocksStorage::open(src_dir.path()).unwrap(),
+            &mut RocksStorage::open(dst_dir.path()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn in_memory_side_blocks_and_head() {
+        backend_side_blocks_and_head(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_side_blocks_and_head() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_side_blocks_and_head(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn sled_integrity_check_passes_on_clean_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        for height in 1..=3 {
+            store.put_block(make_block(height)).unwrap();
+        }
+
+        let report = store.verify_integrity().unwrap();
+        assert!(report.is_clean());
+        // Three block records plus three height index entries.
+        assert_eq!(report.ok, 6);
+        assert!(report.bad_ids.is_empty());
+    }
+
+    #[test]
+    fn sled_undecodable_values_are_reported_as_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        let block = make_block(1);
+        let block_id = block.header.id();
+        store.put_block(block).unwrap();
+        let tx_id = store.put_tx(make_tx(1)).unwrap();
+
+        store.blocks.insert(block_id.0 .0, &[0xde, 0xad]).unwrap();
+        store.txs.insert(tx_id.0 .0, &[0xbe, 0xef]).unwrap();
+
+        assert!(matches!(
+            store.get_block(block_id),
+            Err(StorageError::Corruption(_))
+        ));
+        assert!(matches!(
+            store.get_block_by_height(1),
+            Err(StorageError::Corruption(_))
+        ));
+        assert!(matches!(
+            store.get_tx(tx_id),
+            Err(StorageError::Corruption(_))
+        ));
+    }
+
+    #[test]
+    fn sled_integrity_check_detects_corrupted_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        store.put_block(make_block(1)).unwrap();
+        let victim = make_block(2);
+        let victim_id = victim.header.id();
+        store.put_block(victim.clone()).unwrap();
+
+        // Overwrite the stored value with a block whose header no longer
+        // hashes to its key.
+        let mut tampered = victim;
+        tampered.header.timestamp_ms = 42;
+        store
+            .blocks
+            .insert(victim_id.0 .0, bincode::serialize(&tampered).unwrap())
+            .unwrap();
+
+        let report = store.verify_integrity().unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.corrupt, 1);
+        assert_eq!(report.bad_ids, vec![victim_id]);
+        assert_eq!(report.ok, 3);
+    }
+
+    fn compressed_config() -> SledConfig {
+        SledConfig {
+            compression_level: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sled_size_metrics_track_stored_blocks() {
+        let metrics = sequencer_metrics::reset_metrics_for_test();
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open(dir.path()).unwrap();
+        store.record_size_metrics().unwrap();
+        assert!(metrics.render().contains("sequencer_block_count 0"));
+
+        for height in 1..=3 {
+            store.put_block(make_block(height)).unwrap();
+        }
+        store.flush().unwrap();
+        store.record_size_metrics().unwrap();
+        let rendered = metrics.render();
+        assert!(rendered.contains("sequencer_block_count 3"));
+        assert!(!rendered.contains("sequencer_storage_bytes 0\n"));
+        assert!(store.size_on_disk().unwrap() > 0);
+    }
+
+    #[test]
+    fn sled_flushed_blocks_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let manual = SledConfig {
+            flush_every_ms: None,
+            ..Default::default()
+        };
+        {
+            let mut store = SledStorage::open_with_config(dir.path(), manual).unwrap();
+            store.put_block(make_block(1)).unwrap();
+            store.flush().unwrap();
+        }
+        {
+            let config = SledConfig {
+                flush_on_block: true,
+                ..manual
+            };
+            let mut store = SledStorage::open_with_config(dir.path(), config).unwrap();
+            let mut batch = StorageBatch::default();
+            batch.put_block(make_block(2));
+            store.commit_batch(batch).unwrap();
+        }
+
+        let store = SledStorage::open_with_config(dir.path(), manual).unwrap();
+        assert_eq!(store.get_block_by_height(1).unwrap(), make_block(1));
+        assert_eq!(store.get_block_by_height(2).unwrap(), make_block(2));
+    }
+
+    #[test]
+    fn sled_compressed_block_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SledStorage::open_with_config(dir.path(), compressed_config()).unwrap();
+        let mut block = make_block(1);
+        block.txs = (0..200).map(|n| make_tx(n).id()).collect();
+        block.header.tx_root = merkle_root(&block.txs);
+        let id = block.header.id();
+        store.put_block(block.clone()).unwrap();
+        let mut batched = make_block(2);
+        batched.txs = block.txs.clone();
+        batched.header.tx_root = block.header.tx_root;
+        let mut batch = StorageBatch::default();
+        batch.put_block(batched.clone());
+        store.commit_batch(batch).unwrap();
+
+        let raw = store.blocks.get(id.0 .0).unwrap().unwrap();
+        assert_eq!(raw[0], BLOCK_ZSTD_V1);
+        assert_eq!(store.get_block(id).unwrap(), block);
+        assert_eq!(store.get_block_by_height(2).unwrap(), batched);
+        assert!(store.verify_integrity().unwrap().is_clean());
+    }
+
+    #[test]
+    fn sled_compressed_store_reads_legacy_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = make_block(1);
+        {
+            let mut store = SledStorage::open(dir.path()).unwrap();
+            store.put_block(legacy.clone()).unwrap();
+        }
+
+        let mut store = SledStorage::open_with_config(dir.path(), compressed_config()).unwrap();
+        store.put_block(make_block(2)).unwrap();
+
+        assert_eq!(store.get_block_by_height(1).unwrap(), legacy);
+        assert_eq!(store.get_block_by_height(2).unwrap(), make_block(2));
+        assert!(store.verify_integrity().unwrap().is_clean());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_side_blocks_and_head() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_side_blocks_and_head(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn in_memory_commit_batch_writes_all_records() {
+        backend_commit_batch(&mut InMemoryStorage::default());
+    }
+
+    /// Commit block `height` with one transaction, its receipt and a state
+    /// root, and make it the head.
+    fn commit_block_with_tx<S: BatchStore>(store: &mut S, height: u64) -> (Block, TxId) {
+        let mut block = make_block(height);
+        block.header.state_root = Hash([height as u8; 32]);
+        let tx = make_tx(height);
+        let tx_id = tx.id();
+        block.txs.push(tx_id);
+        let mut batch = StorageBatch::new();
+        batch.put_block(block.clone());
+        batch.put_tx(tx);
+        batch.put_receipt(make_receipt(tx_id, &block, 0, ExecStatus::Success));
+        batch.put_state_root(height, block.header.state_root);
+        batch.set_head(block.header.id());
+        store.commit_batch(batch).unwrap();
+        (block, tx_id)
+    }
+
+    /// Snapshot suite shared by every backend: a snapshot taken before a
+    /// batch does not observe it.
+    fn backend_snapshot_ignores_later_writes<S: Storage>(store: &mut S) {
+        let empty = store.snapshot().unwrap();
+        let (first, first_tx) = commit_block_with_tx(store, 1);
+        let snapshot = store.snapshot().unwrap();
+        let (second, second_tx) = commit_block_with_tx(store, 2);
+
+        assert!(matches!(empty.latest_block(), Err(StorageError::NotFound)));
+        assert!(matches!(empty.head(), Err(StorageError::NotFound)));
+
+        assert_eq!(snapshot.head().unwrap(), first.header.id());
+        assert_eq!(snapshot.latest_block().unwrap(), first);
+        assert_eq!(snapshot.latest_state_root().unwrap(), (1, Hash([1u8; 32])));
+        assert_eq!(
+            snapshot.blocks_in_range(0, 10).unwrap(),
+            vec![first.clone()]
+        );
+        assert!(snapshot.get_tx(first_tx).is_ok());
+        assert!(snapshot.get_receipt(first_tx).is_ok());
+        assert!(matches!(
+            snapshot.get_block(second.header.id()),
+            Err(StorageError::NotFound)
+        ));
+        assert!(matches!(
+            snapshot.get_block_by_height(2),
+            Err(StorageError::NotFound)
+        ));
+        assert!(matches!(
+            snapshot.state_root_at_height(2),
+            Err(StorageError::NotFound)
+        ));
+        assert!(matches!(
+            snapshot.get_tx(second_tx),
+            Err(StorageError::NotFound)
+        ));
+        assert!(matches!(
+            snapshot.get_receipt(second_tx),
+            Err(StorageError::NotFound)
+        ));
+
+        // The store, and snapshots taken from now on, see the new block.
+        assert_eq!(store.head().unwrap(), second.header.id());
+        assert_eq!(store.snapshot().unwrap().latest_block().unwrap(), second);
+    }
+
+    #[test]
+    fn in_memory_snapshot_ignores_later_writes() {
+        backend_snapshot_ignores_later_writes(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_snapshot_ignores_later_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_snapshot_ignores_later_writes(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_snapshot_ignores_later_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_snapshot_ignores_later_writes(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn in_memory_snapshot_shares_maps_until_a_write() {
         let mut store = InMemoryStorage::default();
-        StateStore::put_state_root(&mut store, 1, Hash([1u8; 32])).unwrap();
-        StateStore::put_state_root(&mut store, 5, Hash([5u8; 32])).unwrap();
+        commit_block_with_tx(&mut store, 1);
+        let frozen = store.clone();
+        assert!(Arc::ptr_eq(&store.blocks_by_id, &frozen.blocks_by_id));
+        assert!(Arc::ptr_eq(&store.receipts, &frozen.receipts));
 
-        let (height, root) = StateStore::latest_state_root(&store).unwrap();
-        assert_eq!(height, 5);
-        assert_eq!(root, Hash([5u8; 32]));
+        commit_block_with_tx(&mut store, 2);
+        assert!(!Arc::ptr_eq(&store.blocks_by_id, &frozen.blocks_by_id));
+        assert_eq!(frozen.blocks_by_id.len(), 1);
+        assert_eq!(store.blocks_by_id.len(), 2);
+    }
+
+    fn backend_snapshot_goes_stale_after_a_reorg_or_prune<S: Storage + Prunable>(store: &mut S) {
+        let (first, _) = commit_block_with_tx(store, 1);
+        commit_block_with_tx(store, 2);
+
+        // New blocks on top leave a snapshot usable.
+        let snapshot = store.snapshot().unwrap();
+        commit_block_with_tx(store, 3);
+        assert_eq!(snapshot.get_block_by_height(1).unwrap(), first);
+
+        // A reorg at height 2 does not.
+        let mut side = make_block(2);
+        side.header.timestamp_ms = 1;
+        let mut batch = StorageBatch::new();
+        batch.put_side_block(side.clone());
+        batch.set_canonical(2, side.header.id());
+        store.commit_batch(batch).unwrap();
+        assert!(matches!(
+            snapshot.get_block_by_height(1),
+            Err(StorageError::Stale)
+        ));
+
+        let snapshot = store.snapshot().unwrap();
+        store.prune_below(2).unwrap();
+        assert!(matches!(
+            snapshot.get_block(first.header.id()),
+            Err(StorageError::Stale)
+        ));
+        // Pruning nothing changes nothing.
+        let snapshot = store.snapshot().unwrap();
+        store.prune_below(2).unwrap();
+        assert!(snapshot.get_block_by_height(2).is_ok());
+    }
+
+    #[test]
+    fn sled_snapshot_goes_stale_after_a_reorg_or_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_snapshot_goes_stale_after_a_reorg_or_prune(
+            &mut SledStorage::open(dir.path()).unwrap(),
+        );
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_snapshot_goes_stale_after_a_reorg_or_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_snapshot_goes_stale_after_a_reorg_or_prune(
+            &mut RocksStorage::open(dir.path()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn sled_clones_read_while_another_handle_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SledStorage::open(dir.path()).unwrap();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = writer.clone();
+                std::thread::spawn(move || {
+                    let mut seen = 0;
+                    while seen < 50 {
+                        let height = match reader.latest_block() {
+                            Ok(block) => block.header.height,
+                            Err(StorageError::NotFound) => 0,
+                            Err(e) => panic!("read failed: {e}"),
+                        };
+                        assert!(height >= seen, "head went back from {seen} to {height}");
+                        if height > 0 {
+                            // The block and its state root were committed
+                            // together.
+                            assert_eq!(
+                                reader.state_root_at_height(height).unwrap(),
+                                Hash([height as u8; 32])
+                            );
+                        }
+                        seen = height;
+                    }
+                })
+            })
+            .collect();
+
+        for height in 1..=50 {
+            let mut batch = StorageBatch::new();
+            batch.put_block(make_block(height));
+            batch.put_state_root(height, Hash([height as u8; 32]));
+            writer.commit_batch(batch).unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn sled_commit_batch_writes_all_records() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_commit_batch(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_commit_batch_writes_all_records() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_commit_batch(&mut RocksStorage::open(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn in_memory_backend_roundtrip() {
+        backend_roundtrip(&mut InMemoryStorage::default());
+    }
+
+    #[test]
+    fn sled_backend_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_roundtrip(&mut SledStorage::open(dir.path()).unwrap());
+    }
+
+    #[cfg(feature = "rocksdb")]
+    #[test]
+    fn rocks_backend_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        backend_roundtrip(&mut RocksStorage::open(dir.path()).unwrap());
     }
 
     #[test]
@@ -0,0 +1,590 @@
+//! RocksDB-backed storage, enabled with the `rocksdb` cargo feature.
+//!
+//! Uses the same logical layout and value encodings as [`SledStorage`],
+//! with each sled tree mapped onto a column family.
+//!
+//! [`SledStorage`]: crate::SledStorage
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+use rocksdb::{ColumnFamily, Direction, ErrorKind, IteratorMode, Options, WriteBatch, DB};
+use types::{Block, BlockId, Hash, Receipt, Transaction, TxId};
+
+use crate::{
+    block_height_key, block_receipt_key, corrupt, decode_generation, decode_state_root,
+    highest_state_root, BatchStore, BlockStore, HeadStore, Prunable, ReadSnapshot, ReadView,
+    ReceiptStore, SnapshotRecord, SnapshotWriter, Snapshotable, StateStore, StorageBatch,
+    StorageError, StorageSnapshot, TxStore, GENERATION_KEY, HEAD_KEY,
+};
+use metrics as sequencer_metrics;
+
+const CF_BLOCKS: &str = "blocks";
+const CF_BLOCKS_BY_HEIGHT: &str = "blocks_by_height";
+const CF_TXS: &str = "txs";
+const CF_RECEIPTS: &str = "receipts";
+const CF_STATE_ROOTS: &str = "state_roots";
+const CF_META: &str = "meta";
+const CF_ACCOUNTS: &str = "accounts";
+const CF_BLOCK_HEIGHTS: &str = "block_heights";
+const CF_BLOCK_RECEIPTS: &str = "block_receipts";
+
+impl From<rocksdb::Error> for StorageError {
+    fn from(e: rocksdb::Error) -> Self {
+        match e.kind() {
+            ErrorKind::Corruption => Self::Corruption(e.to_string()),
+            _ => Self::Io(e.to_string()),
+        }
+    }
+}
+
+/// RocksDB-backed storage implementation. Clones share the database.
+#[derive(Clone)]
+pub struct RocksStorage {
+    db: Arc<DB>,
+}
+
+impl RocksStorage {
+    pub fn open(path: &std::path::Path) -> Result<Self, StorageError> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(
+            &opts,
+            path,
+            [
+                CF_BLOCKS,
+                CF_BLOCKS_BY_HEIGHT,
+                CF_TXS,
+                CF_RECEIPTS,
+                CF_STATE_ROOTS,
+                CF_META,
+                CF_ACCOUNTS,
+                CF_BLOCK_HEIGHTS,
+                CF_BLOCK_RECEIPTS,
+            ],
+        )?;
+        let store = Self { db: Arc::new(db) };
+        let empty = |name| -> Result<bool, StorageError> {
+            let first = store
+                .db
+                .iterator_cf(store.cf(name)?, IteratorMode::Start)
+                .next();
+            Ok(first.transpose()?.is_none())
+        };
+        if empty(CF_BLOCK_HEIGHTS)? && !empty(CF_BLOCKS)? {
+            store.rebuild_prune_indexes()?;
+        }
+        Ok(store)
+    }
+
+    /// Fill `block_heights` and `block_receipts` for a database written
+    /// before they existed.
+    fn rebuild_prune_indexes(&self) -> Result<(), StorageError> {
+        let mut write = WriteBatch::default();
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start)
+        {
+            let (_, value) = entry?;
+            let block: Block = bincode::deserialize(&value).map_err(corrupt)?;
+            write.put_cf(self.cf(CF_BLOCK_HEIGHTS)?, block_height_key(&block), b"");
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_RECEIPTS)?, IteratorMode::Start)
+        {
+            let (_, value) = entry?;
+            let receipt: Receipt = bincode::deserialize(&value).map_err(corrupt)?;
+            write.put_cf(
+                self.cf(CF_BLOCK_RECEIPTS)?,
+                block_receipt_key(&receipt),
+                b"",
+            );
+        }
+        self.db.write(write)?;
+        Ok(())
+    }
+
+    fn cf(&self, name: &str) -> Result<&ColumnFamily, StorageError> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| StorageError::Corruption(format!("missing column family {name}")))
+    }
+
+    /// Add a bump of [`HeadStore::generation`] to `write`, a batch that
+    /// rewrites history. Writes go through `&mut self`, so no other write
+    /// can bump it between the read and the batch.
+    fn bump_generation(&self, write: &mut WriteBatch) -> Result<(), StorageError> {
+        let generation = self.generation()?;
+        write.put_cf(
+            self.cf(CF_META)?,
+            GENERATION_KEY,
+            (generation + 1).to_be_bytes(),
+        );
+        Ok(())
+    }
+}
+
+impl BlockStore for RocksStorage {
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let id = block.header.id();
+        let height = block.header.height;
+        let key_id = id.0 .0;
+        let key_height = height.to_be_bytes();
+        let value = bincode::serialize(&block)?;
+
+        let mut write = WriteBatch::default();
+        write.put_cf(self.cf(CF_BLOCKS)?, key_id, value);
+        write.put_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, key_height, id.0 .0);
+        write.put_cf(self.cf(CF_BLOCK_HEIGHTS)?, block_height_key(&block), b"");
+        self.db.write(write)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_put_block", elapsed);
+        Ok(())
+    }
+
+    fn get_block(&self, id: BlockId) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        let key_id = id.0 .0;
+        let Some(bytes) = self.db.get_cf(self.cf(CF_BLOCKS)?, key_id)? else {
+            return Err(StorageError::NotFound);
+        };
+        let block: Block = bincode::deserialize(&bytes).map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_get_block", elapsed);
+        Ok(block)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        let key_height = height.to_be_bytes();
+        let Some(id_bytes) = self.db.get_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, key_height)? else {
+            return Err(StorageError::NotFound);
+        };
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&id_bytes);
+        let id = BlockId(Hash(id_arr));
+        let block = self.get_block(id)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_get_block_by_height", elapsed);
+        Ok(block)
+    }
+
+    fn latest_block(&self) -> Result<Block, StorageError> {
+        let start = Instant::now();
+        // Heights are stored big-endian, so the last key is the highest.
+        let Some(entry) = self
+            .db
+            .iterator_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, IteratorMode::End)
+            .next()
+        else {
+            return Err(StorageError::NotFound);
+        };
+        let (_, id_bytes) = entry?;
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&id_bytes);
+        let block = self.get_block(BlockId(Hash(id_arr)))?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_latest_block", elapsed);
+        Ok(block)
+    }
+
+    fn blocks_in_range(&self, from: u64, to: u64) -> Result<Vec<Block>, StorageError> {
+        let start = Instant::now();
+        let from_key = from.to_be_bytes();
+        let to_key = to.to_be_bytes();
+        let mut blocks = Vec::new();
+        let mode = IteratorMode::From(&from_key, Direction::Forward);
+        for entry in self.db.iterator_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, mode) {
+            let (key, id_bytes) = entry?;
+            if key.as_ref() > to_key.as_slice() {
+                break;
+            }
+            let mut id_arr = [0u8; 32];
+            id_arr.copy_from_slice(&id_bytes);
+            blocks.push(self.get_block(BlockId(Hash(id_arr)))?);
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_blocks_in_range", elapsed);
+        Ok(blocks)
+    }
+}
+
+impl TxStore for RocksStorage {
+    fn put_tx(&mut self, tx: Transaction) -> Result<TxId, StorageError> {
+        let start = Instant::now();
+        let id = tx.id();
+        let key_id = id.0 .0;
+        let value = tx.encode();
+        self.db.put_cf(self.cf(CF_TXS)?, key_id, value)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_put_tx", elapsed);
+        Ok(id)
+    }
+
+    fn get_tx(&self, id: TxId) -> Result<Transaction, StorageError> {
+        let start = Instant::now();
+        let key_id = id.0 .0;
+        let Some(bytes) = self.db.get_cf(self.cf(CF_TXS)?, key_id)? else {
+            return Err(StorageError::NotFound);
+        };
+        let tx = Transaction::decode(&bytes).map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_get_tx", elapsed);
+        Ok(tx)
+    }
+}
+
+impl ReceiptStore for RocksStorage {
+    fn put_receipt(&mut self, receipt: Receipt) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let value = bincode::serialize(&receipt)?;
+        let mut write = WriteBatch::default();
+        write.put_cf(self.cf(CF_RECEIPTS)?, receipt.tx_id.0 .0, value);
+        write.put_cf(
+            self.cf(CF_BLOCK_RECEIPTS)?,
+            block_receipt_key(&receipt),
+            b"",
+        );
+        self.db.write(write)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_put_receipt", elapsed);
+        Ok(())
+    }
+
+    fn get_receipt(&self, id: TxId) -> Result<Receipt, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self.db.get_cf(self.cf(CF_RECEIPTS)?, id.0 .0)? else {
+            return Err(StorageError::NotFound);
+        };
+        let receipt: Receipt = bincode::deserialize(&bytes).map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_get_receipt", elapsed);
+        Ok(receipt)
+    }
+}
+
+impl StateStore for RocksStorage {
+    fn put_state_root(&mut self, height: u64, root: Hash) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let key_height = height.to_be_bytes();
+        self.db
+            .put_cf(self.cf(CF_STATE_ROOTS)?, key_height, root.0)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_put_state_root", elapsed);
+        Ok(())
+    }
+
+    fn latest_state_root(&self) -> Result<(u64, Hash), StorageError> {
+        let start = Instant::now();
+        // Heights are stored big-endian, so the last key is the highest.
+        let Some(entry) = self
+            .db
+            .iterator_cf(self.cf(CF_STATE_ROOTS)?, IteratorMode::End)
+            .next()
+        else {
+            return Err(StorageError::NotFound);
+        };
+        let (k, v) = entry?;
+        let latest = highest_state_root([decode_state_root(&k, &v)?])?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_latest_state_root", elapsed);
+        Ok(latest)
+    }
+
+    fn state_root_at_height(&self, height: u64) -> Result<Hash, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self
+            .db
+            .get_cf(self.cf(CF_STATE_ROOTS)?, height.to_be_bytes())?
+        else {
+            return Err(StorageError::NotFound);
+        };
+        let root: [u8; 32] = bytes.as_slice().try_into().map_err(corrupt)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_state_root_at_height", elapsed);
+        Ok(Hash(root))
+    }
+
+    fn state_roots_in_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Hash)>, StorageError> {
+        let start = Instant::now();
+        let from_key = from.to_be_bytes();
+        let to_key = to.to_be_bytes();
+        let mut roots = Vec::new();
+        let mode = IteratorMode::From(&from_key, Direction::Forward);
+        for entry in self.db.iterator_cf(self.cf(CF_STATE_ROOTS)?, mode) {
+            let (key, value) = entry?;
+            if key.as_ref() > to_key.as_slice() {
+                break;
+            }
+            roots.push(decode_state_root(&key, &value)?);
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_state_roots_in_range", elapsed);
+        Ok(roots)
+    }
+
+    fn accounts_at(&self, block: BlockId) -> Result<Vec<u8>, StorageError> {
+        let start = Instant::now();
+        let Some(bytes) = self.db.get_cf(self.cf(CF_ACCOUNTS)?, block.0 .0)? else {
+            return Err(StorageError::NotFound);
+        };
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_accounts_at", elapsed);
+        Ok(bytes)
+    }
+}
+
+impl BatchStore for RocksStorage {
+    fn commit_batch(&mut self, batch: StorageBatch) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let mut write = WriteBatch::default();
+        for block in &batch.blocks {
+            let id = block.header.id();
+            let value = bincode::serialize(block)?;
+            write.put_cf(self.cf(CF_BLOCKS)?, id.0 .0, value);
+            write.put_cf(
+                self.cf(CF_BLOCKS_BY_HEIGHT)?,
+                block.header.height.to_be_bytes(),
+                id.0 .0,
+            );
+            write.put_cf(self.cf(CF_BLOCK_HEIGHTS)?, block_height_key(block), b"");
+        }
+        for block in &batch.side_blocks {
+            let value = bincode::serialize(block)?;
+            write.put_cf(self.cf(CF_BLOCKS)?, block.header.id().0 .0, value);
+            write.put_cf(self.cf(CF_BLOCK_HEIGHTS)?, block_height_key(block), b"");
+        }
+        for (height, id) in &batch.canonical {
+            write.put_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, height.to_be_bytes(), id.0 .0);
+        }
+        if let Some(head) = batch.head {
+            write.put_cf(self.cf(CF_META)?, HEAD_KEY, head.0 .0);
+        }
+        for tx in &batch.txs {
+            let value = tx.encode();
+            write.put_cf(self.cf(CF_TXS)?, tx.id().0 .0, value);
+        }
+        for receipt in &batch.receipts {
+            let value = bincode::serialize(receipt)?;
+            write.put_cf(self.cf(CF_RECEIPTS)?, receipt.tx_id.0 .0, value);
+            write.put_cf(self.cf(CF_BLOCK_RECEIPTS)?, block_receipt_key(receipt), b"");
+        }
+        for (height, root) in &batch.state_roots {
+            write.put_cf(self.cf(CF_STATE_ROOTS)?, height.to_be_bytes(), root.0);
+        }
+        for (block, accounts) in &batch.accounts {
+            write.put_cf(self.cf(CF_ACCOUNTS)?, block.0 .0, accounts);
+        }
+        if !batch.canonical.is_empty() {
+            self.bump_generation(&mut write)?;
+        }
+        self.db.write(write)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_commit_batch", elapsed);
+        Ok(())
+    }
+}
+
+impl Prunable for RocksStorage {
+    /// Side blocks below `height` are deleted too. Work is proportional
+    /// to what is pruned.
+    fn prune_below(&mut self, height: u64) -> Result<u64, StorageError> {
+        let start = Instant::now();
+        let height_be = height.to_be_bytes();
+        let latest = match self.latest_state_root() {
+            Ok((h, _)) => Some(h),
+            Err(StorageError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut write = WriteBatch::default();
+        let mut pruned = HashSet::new();
+        let mut pruned_blocks = Vec::new();
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_BLOCK_HEIGHTS)?, IteratorMode::Start)
+        {
+            let (key, _) = entry?;
+            if key[..8] >= height_be[..] {
+                break;
+            }
+            let id = key[8..].to_vec();
+            if let Some(bytes) = self.db.get_cf(self.cf(CF_BLOCKS)?, &id)? {
+                let block: Block = bincode::deserialize(&bytes).map_err(corrupt)?;
+                pruned_blocks.push(block);
+            }
+            write.delete_cf(self.cf(CF_BLOCKS)?, &id);
+            write.delete_cf(self.cf(CF_ACCOUNTS)?, &id);
+            write.delete_cf(self.cf(CF_BLOCK_HEIGHTS)?, &key);
+            pruned.insert(id);
+        }
+        // A transaction re-included by a block that stays keeps its body
+        // and receipt.
+        let kept_elsewhere = |tx_id: &[u8]| -> Result<bool, StorageError> {
+            let Some(value) = self.db.get_cf(self.cf(CF_RECEIPTS)?, tx_id)? else {
+                return Ok(false);
+            };
+            let receipt: Receipt = bincode::deserialize(&value).map_err(corrupt)?;
+            Ok(!pruned.contains(&receipt.block_id.0 .0[..]))
+        };
+        for block in &pruned_blocks {
+            for tx_id in &block.txs {
+                if !kept_elsewhere(&tx_id.0 .0)? {
+                    write.delete_cf(self.cf(CF_TXS)?, tx_id.0 .0);
+                }
+            }
+            // Reverted receipts are not reachable from the block's txs.
+            let prefix = block.header.id().0 .0;
+            let mode = IteratorMode::From(&prefix, Direction::Forward);
+            for entry in self.db.iterator_cf(self.cf(CF_BLOCK_RECEIPTS)?, mode) {
+                let (key, _) = entry?;
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                if !kept_elsewhere(&key[32..])? {
+                    write.delete_cf(self.cf(CF_RECEIPTS)?, &key[32..]);
+                }
+                write.delete_cf(self.cf(CF_BLOCK_RECEIPTS)?, &key);
+            }
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, IteratorMode::Start)
+        {
+            let (key_height, _) = entry?;
+            if key_height[..] >= height_be[..] {
+                break;
+            }
+            write.delete_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, &key_height);
+        }
+        let mut changed = !pruned.is_empty();
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_STATE_ROOTS)?, IteratorMode::Start)
+        {
+            let (key_height, _) = entry?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&key_height);
+            let root_height = u64::from_be_bytes(height_bytes);
+            if root_height >= height {
+                break;
+            }
+            if Some(root_height) != latest {
+                write.delete_cf(self.cf(CF_STATE_ROOTS)?, &key_height);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.bump_generation(&mut write)?;
+        }
+        self.db.write(write)?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_prune_below", elapsed);
+        Ok(pruned.len() as u64)
+    }
+}
+
+impl ReadSnapshot for RocksStorage {
+    fn snapshot(&self) -> Result<Box<dyn ReadView>, StorageError> {
+        Ok(Box::new(StorageSnapshot::capture(self.clone())?))
+    }
+}
+
+impl HeadStore for RocksStorage {
+    fn head(&self) -> Result<BlockId, StorageError> {
+        let Some(bytes) = self.db.get_cf(self.cf(CF_META)?, HEAD_KEY)? else {
+            return Err(StorageError::NotFound);
+        };
+        let mut id_arr = [0u8; 32];
+        id_arr.copy_from_slice(&bytes);
+        Ok(BlockId(Hash(id_arr)))
+    }
+
+    fn generation(&self) -> Result<u64, StorageError> {
+        self.db
+            .get_cf(self.cf(CF_META)?, GENERATION_KEY)?
+            .map_or(Ok(0), |bytes| decode_generation(&bytes))
+    }
+}
+
+impl Snapshotable for RocksStorage {
+    fn export_snapshot<W: Write>(&self, w: W) -> Result<(), StorageError> {
+        let start = Instant::now();
+        let mut writer = SnapshotWriter::new(w)?;
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_BLOCKS)?, IteratorMode::Start)
+        {
+            let (_, v) = entry?;
+            let block: Block = bincode::deserialize(&v).map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Block(block))?;
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_BLOCKS_BY_HEIGHT)?, IteratorMode::Start)
+        {
+            let (k, v) = entry?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&k);
+            let mut id_arr = [0u8; 32];
+            id_arr.copy_from_slice(&v);
+            writer.write(&SnapshotRecord::Canonical {
+                height: u64::from_be_bytes(height_bytes),
+                id: BlockId(Hash(id_arr)),
+            })?;
+        }
+        for entry in self.db.iterator_cf(self.cf(CF_TXS)?, IteratorMode::Start) {
+            let (_, v) = entry?;
+            let tx = Transaction::decode(&v).map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Tx(tx))?;
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_RECEIPTS)?, IteratorMode::Start)
+        {
+            let (_, v) = entry?;
+            let receipt: Receipt = bincode::deserialize(&v).map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Receipt(receipt))?;
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_STATE_ROOTS)?, IteratorMode::Start)
+        {
+            let (k, v) = entry?;
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&k);
+            let mut root_bytes = [0u8; 32];
+            root_bytes.copy_from_slice(&v);
+            writer.write(&SnapshotRecord::StateRoot {
+                height: u64::from_be_bytes(height_bytes),
+                root: Hash(root_bytes),
+            })?;
+        }
+        for entry in self
+            .db
+            .iterator_cf(self.cf(CF_ACCOUNTS)?, IteratorMode::Start)
+        {
+            let (k, v) = entry?;
+            let block: [u8; 32] = k.as_ref().try_into().map_err(corrupt)?;
+            writer.write(&SnapshotRecord::Accounts {
+                block: BlockId(Hash(block)),
+                accounts: v.to_vec(),
+            })?;
+        }
+        match self.head() {
+            Ok(head) => writer.write(&SnapshotRecord::Head(head))?,
+            Err(StorageError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        writer.finish()?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        sequencer_metrics::record_storage_op_duration_ms("rocks_export_snapshot", elapsed);
+        Ok(())
+    }
+}
@@ -1,10 +1,29 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
 use blake3::Hasher;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 /// Fixed-size hash used across the sequencer
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Hash(#[serde(with = "serde_bytes_array")] pub [u8; 32]);
 
+impl Hash {
+    /// Number of leading zero bits, reading the bytes in order.
+    pub fn leading_zero_bits(&self) -> u32 {
+        let mut bits = 0;
+        for byte in self.0 {
+            bits += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+}
+
 /// Transaction identifier
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TxId(pub Hash);
@@ -17,6 +36,123 @@ pub struct BlockId(pub Hash);
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NamespaceId(pub u64);
 
+impl NamespaceId {
+    /// The name registered for this namespace in `registry`, or its
+    /// numeric id if it has none.
+    pub fn name(&self, registry: &NamespaceRegistry) -> String {
+        registry
+            .name_of(*self)
+            .map_or_else(|| self.0.to_string(), str::to_string)
+    }
+}
+
+/// Human-readable names for namespaces, for logs and metric labels.
+///
+/// The numeric [`NamespaceId`] stays the canonical identifier; names are
+/// only ever looked up from it. Names must not be all digits so they can
+/// never be mistaken for the numeric fallback of another namespace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NamespaceRegistry {
+    names: HashMap<NamespaceId, String>,
+    ids: HashMap<String, NamespaceId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum NamespaceRegistryError {
+    #[error("namespace name `{0}` must be non-empty and not all digits")]
+    InvalidName(String),
+    #[error("namespace name `{0}` is already registered")]
+    DuplicateName(String),
+    #[error("namespace {0} already has a name")]
+    DuplicateId(u64),
+}
+
+impl NamespaceRegistry {
+    /// Name `id`. Each id and each name can be registered once.
+    pub fn register(
+        &mut self,
+        id: NamespaceId,
+        name: impl Into<String>,
+    ) -> Result<(), NamespaceRegistryError> {
+        let name = name.into();
+        if name.is_empty() || name.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(NamespaceRegistryError::InvalidName(name));
+        }
+        if self.ids.contains_key(&name) {
+            return Err(NamespaceRegistryError::DuplicateName(name));
+        }
+        if self.names.contains_key(&id) {
+            return Err(NamespaceRegistryError::DuplicateId(id.0));
+        }
+        self.ids.insert(name.clone(), id);
+        self.names.insert(id, name);
+        Ok(())
+    }
+
+    pub fn name_of(&self, id: NamespaceId) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<NamespaceId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Account address: the last 20 bytes of the hash of a public key.
+///
+/// Displayed and parsed as 40 hex digits; parsing also accepts a `0x`
+/// prefix.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Address(pub [u8; 20]);
+
+impl Address {
+    /// Derive the address of an ed25519 public key.
+    pub fn from_pubkey(pubkey: &[u8; 32]) -> Self {
+        let hash = hash_bytes(pubkey);
+        let mut out = [0u8; 20];
+        out.copy_from_slice(&hash.0[12..]);
+        Address(out)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum AddressParseError {
+    #[error("invalid hex: {0}")]
+    InvalidHex(String),
+    #[error("address must be 20 bytes, got {0}")]
+    InvalidLength(usize),
+}
+
+impl FromStr for Address {
+    type Err = AddressParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        let bytes =
+            hex::decode(digits).map_err(|e| AddressParseError::InvalidHex(e.to_string()))?;
+        let bytes: [u8; 20] = bytes
+            .try_into()
+            .map_err(|b: Vec<u8>| AddressParseError::InvalidLength(b.len()))?;
+        Ok(Address(bytes))
+    }
+}
+
 /// Basic transaction status for RPC and storage
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TransactionStatus {
@@ -26,29 +162,301 @@ pub enum TransactionStatus {
 }
 
 /// Core transaction type used by the sequencer
+///
+/// `chain_id` and `from` were added after the initial release. JSON peers
+/// that omit them are treated as chain `0` and the zero address;
+/// bincode-encoded transactions written by older nodes are not readable and
+/// must be re-imported (blocks only reference `TxId`s and are unaffected).
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
+    /// Deployment the transaction is intended for; prevents replaying a
+    /// signed transaction against a different chain.
+    #[serde(default)]
+    pub chain_id: u64,
+    /// Sender account.
+    #[serde(default)]
+    pub from: Address,
     pub namespace: NamespaceId,
     pub gas_price: u64,
     pub nonce: u64,
+    /// Last block height the transaction may be included at; `None` never
+    /// expires.
+    #[serde(default)]
+    pub valid_until_height: Option<u64>,
+    /// Forced inclusion, e.g. a system transaction: block building takes
+    /// priority transactions first, in arrival order, regardless of fee.
+    #[serde(default)]
+    pub priority: bool,
     #[serde(with = "serde_bytes_vec")]
     pub payload: Vec<u8>,
     #[serde(with = "serde_bytes_vec")]
     pub signature: Vec<u8>,
+    /// Anti-spam proof of work: a value the sender varies until
+    /// [`Transaction::pow_bits`] meets the mempool's difficulty. Not
+    /// signed and not part of the id, so it cannot be used to give a
+    /// signed transaction a second id.
+    #[serde(default)]
+    pub pow_nonce: u64,
+}
+
+/// Admission rules checked by [`Transaction::validate_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxValidationRules {
+    /// Accept transactions with `gas_price == 0`.
+    pub allow_zero_gas_price: bool,
+    /// Maximum payload length in bytes.
+    pub max_payload_bytes: usize,
+    /// Namespaces transactions may target; `None` allows any namespace.
+    pub allowed_namespaces: Option<Vec<NamespaceId>>,
+}
+
+impl Default for TxValidationRules {
+    fn default() -> Self {
+        Self {
+            allow_zero_gas_price: false,
+            max_payload_bytes: 32 * 1024,
+            allowed_namespaces: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum TxValidationError {
+    #[error("gas price must be non-zero")]
+    ZeroGasPrice,
+    #[error("payload of {len} bytes exceeds maximum of {max}")]
+    PayloadTooLarge { len: usize, max: usize },
+    #[error("namespace {0} is not allowed")]
+    NamespaceNotAllowed(u64),
+}
+
+/// Fixed gas charged for every transaction, on top of its payload bytes.
+pub const TX_BASE_GAS: u64 = 1_000;
+
+/// Version byte leading every [`Transaction::encode`] output.
+pub const TX_ENCODING_VERSION: u8 = 1;
+
+/// Bytes of [`Transaction::encode`] that do not depend on the payload,
+/// signature or `pow_nonce`: the version byte, five fixed-width fields,
+/// `valid_until_height` with its flag, the `priority` flag and two length
+/// prefixes.
+const TX_FIXED_BYTES: usize = 1 + 8 + 20 + 8 + 8 + 8 + (1 + 8) + 1 + 4 + 4;
+
+/// Why bytes could not be read by [`Transaction::decode`].
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum CodecError {
+    #[error("unsupported transaction encoding version {0}")]
+    UnsupportedVersion(u8),
+    #[error("transaction encoding ends early")]
+    Truncated,
+    #[error("invalid transaction encoding: {0}")]
+    Invalid(&'static str),
+}
+
+/// Cursor over an encoded transaction.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        if self.0.len() < len {
+            return Err(CodecError::Truncated);
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        Ok(self.take(N)?.try_into().expect("length was checked"))
+    }
+
+    fn u64(&mut self) -> Result<u64, CodecError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn flag(&mut self) -> Result<bool, CodecError> {
+        match self.array::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(CodecError::Invalid("flag is neither 0 nor 1")),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, CodecError> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        self.take(len).map(<[u8]>::to_vec)
+    }
 }
 
 impl Transaction {
+    /// Gas consumed by including this transaction in a block: a fixed base
+    /// cost plus one unit per payload byte.
+    pub fn gas_used(&self) -> u64 {
+        TX_BASE_GAS + self.payload.len() as u64
+    }
+
+    /// Whether the transaction can no longer be included in a block at
+    /// `height`.
+    pub fn is_expired_at(&self, height: u64) -> bool {
+        self.valid_until_height.is_some_and(|until| until < height)
+    }
+
+    /// Check the transaction against the default [`TxValidationRules`].
+    pub fn validate(&self) -> Result<(), TxValidationError> {
+        self.validate_with(&TxValidationRules::default())
+    }
+
+    /// Check the transaction against `rules`.
+    pub fn validate_with(&self, rules: &TxValidationRules) -> Result<(), TxValidationError> {
+        if self.gas_price == 0 && !rules.allow_zero_gas_price {
+            return Err(TxValidationError::ZeroGasPrice);
+        }
+        if self.payload.len() > rules.max_payload_bytes {
+            return Err(TxValidationError::PayloadTooLarge {
+                len: self.payload.len(),
+                max: rules.max_payload_bytes,
+            });
+        }
+        if let Some(allowed) = &rules.allowed_namespaces {
+            if !allowed.contains(&self.namespace) {
+                return Err(TxValidationError::NamespaceNotAllowed(self.namespace.0));
+            }
+        }
+        Ok(())
+    }
+
+    /// The hash of the canonical [`Transaction::encode`] bytes without
+    /// `pow_nonce`.
     pub fn id(&self) -> TxId {
-        let encoded = bincode::serialize(self).expect("transaction should serialize");
-        TxId(hash_bytes(&encoded))
+        self.id_with(HashAlgo::default())
+    }
+
+    /// The transaction id under `algo`.
+    pub fn id_with(&self, algo: HashAlgo) -> TxId {
+        let mut bytes = self.encode();
+        bytes.truncate(TX_FIXED_BYTES + self.payload.len() + self.signature.len());
+        TxId(algo.hash(&bytes))
+    }
+
+    /// Canonical encoding used for ids, storage and binary gossip, written
+    /// by hand so it does not change with serde or bincode.
+    ///
+    /// Layout: [`TX_ENCODING_VERSION`], then `chain_id`, `from`,
+    /// `namespace`, `gas_price` and `nonce`; `valid_until_height` as a
+    /// presence flag and a value (zero when absent); the `priority` flag;
+    /// `payload` and `signature` each behind a `u32` length; a non-zero
+    /// `pow_nonce` last. Integers are little-endian and flags are one byte,
+    /// `0` or `1`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.size_bytes());
+        out.push(TX_ENCODING_VERSION);
+        out.extend(self.chain_id.to_le_bytes());
+        out.extend(self.from.0);
+        out.extend(self.namespace.0.to_le_bytes());
+        out.extend(self.gas_price.to_le_bytes());
+        out.extend(self.nonce.to_le_bytes());
+        out.push(u8::from(self.valid_until_height.is_some()));
+        out.extend(self.valid_until_height.unwrap_or(0).to_le_bytes());
+        out.push(u8::from(self.priority));
+        for bytes in [&self.payload, &self.signature] {
+            let len = u32::try_from(bytes.len()).expect("field shorter than 4 GiB");
+            out.extend(len.to_le_bytes());
+            out.extend(bytes);
+        }
+        if self.pow_nonce != 0 {
+            out.extend(self.pow_nonce.to_le_bytes());
+        }
+        out
+    }
+
+    /// Length of [`Transaction::encode`], computed without encoding.
+    pub fn size_bytes(&self) -> usize {
+        let pow_nonce = if self.pow_nonce != 0 { 8 } else { 0 };
+        TX_FIXED_BYTES + self.payload.len() + self.signature.len() + pow_nonce
+    }
+
+    /// Undo [`Transaction::encode`]. Other versions and trailing bytes are
+    /// rejected.
+    pub fn decode(bytes: &[u8]) -> Result<Transaction, CodecError> {
+        let mut r = Reader(bytes);
+        let [version] = r.array()?;
+        if version != TX_ENCODING_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        let chain_id = r.u64()?;
+        let from = Address(r.array()?);
+        let namespace = NamespaceId(r.u64()?);
+        let gas_price = r.u64()?;
+        let nonce = r.u64()?;
+        let has_expiry = r.flag()?;
+        let until = r.u64()?;
+        let valid_until_height = match (has_expiry, until) {
+            (true, until) => Some(until),
+            (false, 0) => None,
+            (false, _) => return Err(CodecError::Invalid("expiry set without its flag")),
+        };
+        let tx = Transaction {
+            chain_id,
+            from,
+            namespace,
+            gas_price,
+            nonce,
+            valid_until_height,
+            priority: r.flag()?,
+            payload: r.bytes()?,
+            signature: r.bytes()?,
+            pow_nonce: match r.0.len() {
+                0 => 0,
+                8 => match r.u64()? {
+                    0 => return Err(CodecError::Invalid("zero pow_nonce is never encoded")),
+                    pow_nonce => pow_nonce,
+                },
+                _ => return Err(CodecError::Invalid("trailing bytes")),
+            },
+        };
+        Ok(tx)
+    }
+
+    /// Leading zero bits of the blake3 hash of the encoding, whatever
+    /// [`HashAlgo`] ids use. Each bit doubles the expected number of
+    /// `pow_nonce` values a sender has to try.
+    pub fn pow_bits(&self) -> u32 {
+        HashAlgo::Blake3.hash(&self.encode()).leading_zero_bits()
+    }
+
+    /// Canonical bytes covered by the transaction signature: every field
+    /// except the signature itself.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&(
+            self.chain_id,
+            self.from,
+            self.namespace,
+            self.gas_price,
+            self.nonce,
+            self.valid_until_height,
+            self.priority,
+            &self.payload,
+        ))
+        .expect("transaction should serialize")
     }
 }
 
+/// Block header.
+///
+/// `gas_used` and `fees` were added after the initial release; like
+/// [`Transaction::chain_id`] they default to `0` for JSON peers, while
+/// bincode-encoded headers from older nodes are not readable.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BlockHeader {
     pub height: u64,
     pub parent: Option<BlockId>,
     pub tx_root: Hash,
+    /// Total gas used by the block's transactions.
+    #[serde(default)]
+    pub gas_used: u64,
+    /// Total fees the block's transactions paid to the fee recipient.
+    #[serde(default)]
+    pub fees: u64,
     pub state_root: Hash,
     pub timestamp_ms: u64,
     #[serde(with = "serde_bytes_array")]
@@ -57,9 +465,63 @@ pub struct BlockHeader {
 
 impl BlockHeader {
     pub fn id(&self) -> BlockId {
-        let encoded = bincode::serialize(self).expect("header should serialize");
-        BlockId(hash_bytes(&encoded))
+        BlockId(hash_bytes(&self.encode()))
     }
+
+    /// The bincode encoding hashed into the block id.
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("header should serialize")
+    }
+}
+
+/// Why a header cannot follow the parent it was checked against.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum HeaderError {
+    #[error("height {actual} does not follow parent height, expected {expected}")]
+    UnexpectedHeight { expected: u64, actual: u64 },
+    #[error("timestamp {timestamp_ms} is not after parent timestamp {parent_timestamp_ms}")]
+    TimestampNotAfterParent {
+        timestamp_ms: u64,
+        parent_timestamp_ms: u64,
+    },
+    #[error("parent {actual:?} does not match expected parent {expected:?}")]
+    ParentMismatch {
+        expected: Option<BlockId>,
+        actual: Option<BlockId>,
+    },
+}
+
+/// Check that `header` can extend `parent`, or start the chain at height 1
+/// when there is no parent. Only the links between the two headers are
+/// checked, so this needs no storage; the transactions and state root are
+/// the importer's concern.
+pub fn validate_block_header(
+    header: &BlockHeader,
+    parent: Option<&BlockHeader>,
+) -> Result<(), HeaderError> {
+    let expected_parent = parent.map(BlockHeader::id);
+    if header.parent != expected_parent {
+        return Err(HeaderError::ParentMismatch {
+            expected: expected_parent,
+            actual: header.parent,
+        });
+    }
+    let expected_height = parent.map_or(1, |p| p.height + 1);
+    if header.height != expected_height {
+        return Err(HeaderError::UnexpectedHeight {
+            expected: expected_height,
+            actual: header.height,
+        });
+    }
+    if let Some(parent) = parent {
+        if header.timestamp_ms <= parent.timestamp_ms {
+            return Err(HeaderError::TimestampNotAfterParent {
+                timestamp_ms: header.timestamp_ms,
+                parent_timestamp_ms: parent.timestamp_ms,
+            });
+        }
+    }
+    Ok(())
 }
 
 /// Block consisting of a header and list of transaction IDs.
@@ -69,6 +531,51 @@ pub struct Block {
     pub txs: Vec<TxId>,
 }
 
+impl Block {
+    /// Length of the block's bincode encoding, the form blocks are stored
+    /// and batched to L1 in, computed without encoding.
+    pub fn size_bytes(&self) -> usize {
+        bincode::serialized_size(self).expect("block should serialize") as usize
+    }
+}
+
+/// Outcome of executing a transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecStatus {
+    Success,
+    /// The transaction was dropped without taking effect, e.g. because its
+    /// nonce was already used.
+    Reverted {
+        reason: String,
+    },
+}
+
+/// An event emitted while executing a transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Log {
+    pub namespace: NamespaceId,
+    pub topics: Vec<Hash>,
+    pub data: Vec<u8>,
+}
+
+/// Execution result of a transaction, recorded with the block that
+/// processed it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub tx_id: TxId,
+    pub block_id: BlockId,
+    /// Position in `Block::txs`. Reverted transactions are not part of the
+    /// block and are numbered after the included ones.
+    pub index: u32,
+    pub status: ExecStatus,
+    /// Gas charged; zero for a reverted transaction.
+    pub gas_used: u64,
+    /// `gas_price * gas_used` paid by the sender.
+    #[serde(default)]
+    pub fee: u64,
+    pub logs: Vec<Log>,
+}
+
 /// A logical batch of L2 blocks that a sequencer would commit to an
 /// L1 settlement contract. The on-chain commitment is typically a
 /// hash of this structure.
@@ -97,111 +604,351 @@ pub struct MerkleProof {
     pub siblings: Vec<Hash>,
 }
 
-/// Compute a Merkle root from a list of transaction IDs.
-/// Empty input yields a zero hash.
-pub fn merkle_root(txs: &[TxId]) -> Hash {
-    if txs.is_empty() {
-        return Hash([0u8; 32]);
+/// A Merkle tree over transaction IDs with every layer cached, so the root
+/// and any number of proofs can be served without rehashing.
+///
+/// Odd nodes are paired with themselves, matching [`merkle_root`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// `layers[0]` holds the leaves and the last layer holds the root.
+    /// Empty for a tree without leaves.
+    layers: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn build(txs: &[TxId]) -> Self {
+        Self::build_with(HashAlgo::default(), txs)
     }
 
-    let mut layer: Vec<Hash> = txs.iter().map(|TxId(h)| *h).collect();
+    /// Build the tree hashing internal nodes with `algo`.
+    pub fn build_with(algo: HashAlgo, txs: &[TxId]) -> Self {
+        if txs.is_empty() {
+            return Self { layers: Vec::new() };
+        }
 
-    while layer.len() > 1 {
-        let mut next = Vec::with_capacity((layer.len() + 1) / 2);
-        for chunk in layer.chunks(2) {
-            let combined = if chunk.len() == 2 {
-                let mut data = Vec::with_capacity(64);
-                data.extend_from_slice(&chunk[0].0);
-                data.extend_from_slice(&chunk[1].0);
-                data
-            } else {
-                let mut data = Vec::with_capacity(64);
-                data.extend_from_slice(&chunk[0].0);
-                data.extend_from_slice(&chunk[0].0);
-                data
-            };
-            next.push(hash_bytes(&combined));
+        let mut layers = vec![txs.iter().map(|TxId(h)| *h).collect::<Vec<Hash>>()];
+        while layers[layers.len() - 1].len() > 1 {
+            let next = layers[layers.len() - 1]
+                .chunks(2)
+                .map(|chunk| hash_pair(algo, &chunk[0], chunk.get(1).unwrap_or(&chunk[0])))
+                .collect();
+            layers.push(next);
         }
-        layer = next;
+        Self { layers }
     }
 
-    layer[0]
-}
+    pub fn leaf_count(&self) -> usize {
+        self.layers.first().map_or(0, Vec::len)
+    }
 
-/// Build a Merkle proof for the leaf at `index`.
-pub fn merkle_proof(txs: &[TxId], index: usize) -> Option<MerkleProof> {
-    if txs.is_empty() || index >= txs.len() {
-        return None;
+    /// The root hash, or a zero hash for an empty tree.
+    pub fn root(&self) -> Hash {
+        self.layers.last().map_or(Hash([0u8; 32]), |layer| layer[0])
     }
 
-    let mut idx = index;
-    let mut layer: Vec<Hash> = txs.iter().map(|TxId(h)| *h).collect();
-    let mut siblings = Vec::new();
+    /// Proof for the leaf at `index`, or `None` if out of range.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
 
-    while layer.len() > 1 {
-        let is_right = idx % 2 == 1;
-        let sibling_idx = if is_right { idx - 1 } else { idx + 1 };
+        let mut idx = index;
+        let mut siblings = Vec::with_capacity(self.layers.len() - 1);
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let sibling_idx = if idx % 2 == 1 { idx - 1 } else { idx + 1 };
+            siblings.push(*layer.get(sibling_idx).unwrap_or(&layer[idx]));
+            idx /= 2;
+        }
 
-        let sibling_hash = if sibling_idx < layer.len() {
-            layer[sibling_idx]
-        } else {
-            layer[idx]
-        };
-        siblings.push(sibling_hash);
+        Some(MerkleProof {
+            index: index as u32,
+            siblings,
+        })
+    }
 
-        idx /= 2;
+    /// Multiproof for the leaves at `indices`.
+    ///
+    /// Returns `None` if the tree or `indices` is empty or any index is out
+    /// of range. Duplicate indices are collapsed.
+    pub fn multiproof(&self, indices: &[usize]) -> Option<MerkleMultiProof> {
+        let leaf_count = self.leaf_count();
+        if leaf_count == 0 || indices.is_empty() || indices.iter().any(|&i| i >= leaf_count) {
+            return None;
+        }
 
-        let mut next = Vec::with_capacity((layer.len() + 1) / 2);
-        for chunk in layer.chunks(2) {
-            let combined = if chunk.len() == 2 {
-                let mut data = Vec::with_capacity(64);
-                data.extend_from_slice(&chunk[0].0);
-                data.extend_from_slice(&chunk[1].0);
-                data
-            } else {
-                let mut data = Vec::with_capacity(64);
-                data.extend_from_slice(&chunk[0].0);
-                data.extend_from_slice(&chunk[0].0);
-                data
-            };
-            next.push(hash_bytes(&combined));
+        let mut known: Vec<usize> = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        let proven: Vec<u32> = known.iter().map(|&i| i as u32).collect();
+        let mut siblings = Vec::new();
+
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let mut next_known = Vec::with_capacity(known.len());
+            let mut pos = 0;
+            while pos < known.len() {
+                let idx = known[pos];
+                if idx.is_multiple_of(2) {
+                    let right = idx + 1;
+                    if pos + 1 < known.len() && known[pos + 1] == right {
+                        // Both children are known; nothing to emit.
+                        pos += 1;
+                    } else if right < layer.len() {
+                        siblings.push(layer[right]);
+                    }
+                } else {
+                    siblings.push(layer[idx - 1]);
+                }
+                next_known.push(idx / 2);
+                pos += 1;
+            }
+            known = next_known;
         }
-        layer = next;
+
+        Some(MerkleMultiProof {
+            leaf_count: leaf_count as u32,
+            indices: proven,
+            siblings,
+        })
     }
+}
 
-    Some(MerkleProof {
-        index: index as u32,
-        siblings,
-    })
+/// Compute a Merkle root from a list of transaction IDs.
+/// Empty input yields a zero hash.
+///
+/// Build a [`MerkleTree`] instead when serving proofs for the same block.
+pub fn merkle_root(txs: &[TxId]) -> Hash {
+    merkle_root_with(HashAlgo::default(), txs)
+}
+
+/// [`merkle_root`] hashing internal nodes with `algo`.
+pub fn merkle_root_with(algo: HashAlgo, txs: &[TxId]) -> Hash {
+    merkle_root_from_iter_with(algo, txs.iter().copied())
+}
+
+/// [`merkle_root`] over ids as they are produced, holding one pending node
+/// per tree level instead of the whole bottom layer.
+pub fn merkle_root_from_iter<I: ExactSizeIterator<Item = TxId>>(iter: I) -> Hash {
+    merkle_root_from_iter_with(HashAlgo::default(), iter)
+}
+
+/// [`merkle_root_from_iter`] hashing internal nodes with `algo`.
+pub fn merkle_root_from_iter_with<I: ExactSizeIterator<Item = TxId>>(
+    algo: HashAlgo,
+    iter: I,
+) -> Hash {
+    // Hashing rounds until a layer of `len` nodes is down to the root.
+    let mut depth = 0;
+    let mut len = iter.len();
+    while len > 1 {
+        len = len.div_ceil(2);
+        depth += 1;
+    }
+
+    // `pending[level]` is a left node at `level` still waiting for its
+    // right sibling, like the digits of a binary counter.
+    let mut pending: Vec<Option<Hash>> = Vec::with_capacity(depth + 1);
+    for TxId(leaf) in iter {
+        let mut node = leaf;
+        let mut level = 0;
+        loop {
+            if level == pending.len() {
+                pending.push(None);
+            }
+            match pending[level].take() {
+                Some(left) => {
+                    node = hash_pair(algo, &left, &node);
+                    level += 1;
+                }
+                None => {
+                    pending[level] = Some(node);
+                    break;
+                }
+            }
+        }
+    }
+
+    // What is left is the last node of each odd-length layer, paired with
+    // itself, carried up together with anything pending above it.
+    let mut carry: Option<Hash> = None;
+    for level in 0..depth {
+        carry = match (pending.get(level).copied().flatten(), carry) {
+            (Some(left), Some(right)) => Some(hash_pair(algo, &left, &right)),
+            (Some(lone), None) | (None, Some(lone)) => Some(hash_pair(algo, &lone, &lone)),
+            (None, None) => None,
+        };
+    }
+    carry
+        .or_else(|| pending.get(depth).copied().flatten())
+        .unwrap_or(Hash([0u8; 32]))
+}
+
+/// Build a Merkle proof for the leaf at `index`.
+pub fn merkle_proof(txs: &[TxId], index: usize) -> Option<MerkleProof> {
+    merkle_proof_with(HashAlgo::default(), txs, index)
+}
+
+/// [`merkle_proof`] hashing internal nodes with `algo`.
+pub fn merkle_proof_with(algo: HashAlgo, txs: &[TxId], index: usize) -> Option<MerkleProof> {
+    MerkleTree::build_with(algo, txs).proof(index)
 }
 
 /// Verify that a transaction ID is included in a tree with the given root.
 pub fn verify_merkle_proof(root: Hash, leaf: TxId, proof: &MerkleProof) -> bool {
+    verify_merkle_proof_with(HashAlgo::default(), root, leaf, proof)
+}
+
+/// [`verify_merkle_proof`] for a tree built with `algo`.
+pub fn verify_merkle_proof_with(
+    algo: HashAlgo,
+    root: Hash,
+    leaf: TxId,
+    proof: &MerkleProof,
+) -> bool {
     let mut hash = leaf.0;
     let mut idx = proof.index as usize;
 
     for sibling in &proof.siblings {
         let mut data = Vec::with_capacity(64);
-        if idx % 2 == 0 {
+        if idx.is_multiple_of(2) {
             data.extend_from_slice(&hash.0);
             data.extend_from_slice(&sibling.0);
         } else {
             data.extend_from_slice(&sibling.0);
             data.extend_from_slice(&hash.0);
         }
-        hash = hash_bytes(&data);
+        hash = algo.hash(&data);
         idx /= 2;
     }
 
     hash == root
 }
 
+/// Merkle proof for the inclusion of several transactions in the same
+/// block. Internal nodes shared between the requested leaves are only
+/// included once.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleMultiProof {
+    /// Number of leaves in the tree the proof was built from.
+    pub leaf_count: u32,
+    /// Proven leaf indices, sorted ascending and deduplicated.
+    pub indices: Vec<u32>,
+    /// Sibling hashes not derivable from the proven leaves, in the order
+    /// the verifier consumes them (bottom layer first, left to right).
+    pub siblings: Vec<Hash>,
+}
+
+/// Build a Merkle multiproof for the leaves at `indices`.
+///
+/// Returns `None` if `txs` or `indices` is empty or any index is out of
+/// range. Duplicate indices are collapsed.
+pub fn merkle_multiproof(txs: &[TxId], indices: &[usize]) -> Option<MerkleMultiProof> {
+    MerkleTree::build(txs).multiproof(indices)
+}
+
+/// Verify that `leaves` are included in a tree with the given root.
+///
+/// `leaves` must be supplied in the same order as `proof.indices`.
+pub fn verify_merkle_multiproof(root: Hash, leaves: &[TxId], proof: &MerkleMultiProof) -> bool {
+    verify_merkle_multiproof_with(HashAlgo::default(), root, leaves, proof)
+}
+
+/// [`verify_merkle_multiproof`] for a tree built with `algo`.
+pub fn verify_merkle_multiproof_with(
+    algo: HashAlgo,
+    root: Hash,
+    leaves: &[TxId],
+    proof: &MerkleMultiProof,
+) -> bool {
+    if leaves.is_empty() || leaves.len() != proof.indices.len() {
+        return false;
+    }
+    let mut layer_len = proof.leaf_count as usize;
+    let sorted = proof.indices.windows(2).all(|w| w[0] < w[1]);
+    if !sorted || proof.indices.iter().any(|&i| i as usize >= layer_len) {
+        return false;
+    }
+
+    let mut known: Vec<(usize, Hash)> = proof
+        .indices
+        .iter()
+        .zip(leaves)
+        .map(|(&i, TxId(h))| (i as usize, *h))
+        .collect();
+    let mut siblings = proof.siblings.iter();
+
+    while layer_len > 1 {
+        let mut next_known = Vec::with_capacity(known.len());
+        let mut pos = 0;
+        while pos < known.len() {
+            let (idx, hash) = known[pos];
+            let parent = if idx.is_multiple_of(2) {
+                let right = idx + 1;
+                if pos + 1 < known.len() && known[pos + 1].0 == right {
+                    pos += 1;
+                    hash_pair(algo, &hash, &known[pos].1)
+                } else if right < layer_len {
+                    let Some(sibling) = siblings.next() else {
+                        return false;
+                    };
+                    hash_pair(algo, &hash, sibling)
+                } else {
+                    hash_pair(algo, &hash, &hash)
+                }
+            } else {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash_pair(algo, sibling, &hash)
+            };
+            next_known.push((idx / 2, parent));
+            pos += 1;
+        }
+        known = next_known;
+        layer_len = layer_len.div_ceil(2);
+    }
+
+    siblings.next().is_none() && known.len() == 1 && known[0].1 == root
+}
+
+fn hash_pair(algo: HashAlgo, left: &Hash, right: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(&left.0);
+    data.extend_from_slice(&right.0);
+    algo.hash(&data)
+}
+
+/// Hash function behind transaction ids and Merkle trees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgo {
+    #[default]
+    Blake3,
+    /// Keccak-256 as used by the EVM. Requires the `keccak` feature.
+    #[cfg(feature = "keccak")]
+    Keccak256,
+}
+
+impl HashAlgo {
+    pub fn hash(self, data: &[u8]) -> Hash {
+        let mut out = [0u8; 32];
+        match self {
+            HashAlgo::Blake3 => {
+                let mut hasher = Hasher::new();
+                hasher.update(data);
+                out.copy_from_slice(hasher.finalize().as_bytes());
+            }
+            #[cfg(feature = "keccak")]
+            HashAlgo::Keccak256 => {
+                use sha3::Digest;
+                out.copy_from_slice(&sha3::Keccak256::digest(data));
+            }
+        }
+        Hash(out)
+    }
+}
+
+/// Hash `data` with the default [`HashAlgo`].
 pub fn hash_bytes(data: &[u8]) -> Hash {
-    let mut hasher = Hasher::new();
-    hasher.update(data);
-    let mut out = [0u8; 32];
-    out.copy_from_slice(hasher.finalize().as_bytes());
-    Hash(out)
+    HashAlgo::default().hash(data)
 }
 
 mod serde_bytes_array {
@@ -238,6 +985,23 @@ mod serde_bytes_array {
                 out.copy_from_slice(v);
                 Ok(out)
             }
+
+            // Self-describing formats such as JSON encode bytes as a sequence.
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut out = [0u8; 32];
+                for (i, byte) in out.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(serde::de::Error::invalid_length(33, &self));
+                }
+                Ok(out)
+            }
         }
 
         deserializer.deserialize_bytes(Visitor)
@@ -247,7 +1011,7 @@ mod serde_bytes_array {
 mod serde_bytes_vec {
     use serde::{Deserializer, Serializer};
 
-    pub fn serialize<S>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
+    pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
@@ -273,6 +1037,18 @@ mod serde_bytes_vec {
             {
                 Ok(v.to_vec())
             }
+
+            // Self-describing formats such as JSON encode bytes as a sequence.
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    out.push(byte);
+                }
+                Ok(out)
+            }
         }
 
         deserializer.deserialize_bytes(Visitor)
@@ -302,22 +1078,381 @@ mod tests {
     #[test]
     fn transaction_id_stable_for_same_content() {
         let tx1 = Transaction {
+            chain_id: 0,
+            from: Address::default(),
             namespace: NamespaceId(1),
             gas_price: 10,
             nonce: 1,
+            valid_until_height: None,
+            priority: false,
             payload: b"abc".to_vec(),
             signature: vec![],
+            pow_nonce: 0,
         };
         let tx2 = Transaction { ..tx1.clone() };
         assert_eq!(tx1.id(), tx2.id());
     }
 
+    #[test]
+    fn chain_id_changes_id_and_signing_bytes() {
+        let tx1 = Transaction {
+            chain_id: 1,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 10,
+            nonce: 1,
+            valid_until_height: None,
+            priority: false,
+            payload: b"abc".to_vec(),
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        let tx2 = Transaction {
+            chain_id: 2,
+            ..tx1.clone()
+        };
+        assert_ne!(tx1.id(), tx2.id());
+        assert_ne!(tx1.signing_bytes(), tx2.signing_bytes());
+
+        let signed = Transaction {
+            signature: vec![1, 2, 3],
+            ..tx1.clone()
+        };
+        assert_eq!(tx1.signing_bytes(), signed.signing_bytes());
+    }
+
+    #[test]
+    fn address_derivation_is_deterministic() {
+        let a = Address::from_pubkey(&[1u8; 32]);
+        assert_eq!(a, Address::from_pubkey(&[1u8; 32]));
+        assert_ne!(a, Address::from_pubkey(&[2u8; 32]));
+        assert_eq!(a.0, hash_bytes(&[1u8; 32]).0[12..]);
+    }
+
+    #[test]
+    fn address_hex_roundtrip() {
+        let addr = Address::from_pubkey(&[7u8; 32]);
+        let text = addr.to_string();
+        assert_eq!(text.len(), 40);
+        assert_eq!(text.parse::<Address>(), Ok(addr));
+        assert_eq!(format!("0x{text}").parse::<Address>(), Ok(addr));
+
+        assert_eq!(
+            "abcd".parse::<Address>(),
+            Err(AddressParseError::InvalidLength(2))
+        );
+        assert!(matches!(
+            "zz".parse::<Address>(),
+            Err(AddressParseError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn namespace_registry_maps_names_both_ways() {
+        let mut registry = NamespaceRegistry::default();
+        registry.register(NamespaceId(1), "payments").unwrap();
+        registry.register(NamespaceId(7), "games").unwrap();
+
+        assert_eq!(registry.name_of(NamespaceId(7)), Some("games"));
+        assert_eq!(registry.id_of("payments"), Some(NamespaceId(1)));
+        assert_eq!(registry.id_of("unknown"), None);
+        assert_eq!(NamespaceId(1).name(&registry), "payments");
+        // Unregistered namespaces fall back to their numeric id.
+        assert_eq!(NamespaceId(2).name(&registry), "2");
+        assert_eq!(NamespaceId(2).name(&NamespaceRegistry::default()), "2");
+    }
+
+    #[test]
+    fn namespace_registry_rejects_duplicates_and_numeric_names() {
+        let mut registry = NamespaceRegistry::default();
+        registry.register(NamespaceId(1), "payments").unwrap();
+
+        assert_eq!(
+            registry.register(NamespaceId(2), "payments"),
+            Err(NamespaceRegistryError::DuplicateName("payments".into()))
+        );
+        assert_eq!(
+            registry.register(NamespaceId(1), "other"),
+            Err(NamespaceRegistryError::DuplicateId(1))
+        );
+        for name in ["", "3"] {
+            assert_eq!(
+                registry.register(NamespaceId(3), name),
+                Err(NamespaceRegistryError::InvalidName(name.into()))
+            );
+        }
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn sender_changes_id_and_signing_bytes() {
+        let tx1 = Transaction {
+            chain_id: 0,
+            from: Address::from_pubkey(&[1u8; 32]),
+            namespace: NamespaceId(1),
+            gas_price: 10,
+            nonce: 1,
+            valid_until_height: None,
+            priority: false,
+            payload: b"abc".to_vec(),
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        let tx2 = Transaction {
+            from: Address::from_pubkey(&[2u8; 32]),
+            ..tx1.clone()
+        };
+        assert_ne!(tx1.id(), tx2.id());
+        assert_ne!(tx1.signing_bytes(), tx2.signing_bytes());
+    }
+
+    #[test]
+    fn encoding_roundtrips_and_rejects_other_versions() {
+        let tx = Transaction {
+            chain_id: 7,
+            from: Address([3u8; 20]),
+            namespace: NamespaceId(2),
+            gas_price: 10,
+            nonce: 4,
+            valid_until_height: Some(0),
+            priority: true,
+            payload: b"hello".to_vec(),
+            signature: vec![9; 64],
+            pow_nonce: 0,
+        };
+        let open_ended = Transaction {
+            valid_until_height: None,
+            ..tx.clone()
+        };
+        for tx in [tx, open_ended] {
+            let encoded = tx.encode();
+            assert_eq!(encoded[0], TX_ENCODING_VERSION);
+            assert_eq!(Transaction::decode(&encoded), Ok(tx.clone()));
+            assert_eq!(tx.id(), TxId(hash_bytes(&encoded)));
+
+            let mut future = encoded.clone();
+            future[0] = TX_ENCODING_VERSION + 1;
+            assert_eq!(
+                Transaction::decode(&future),
+                Err(CodecError::UnsupportedVersion(TX_ENCODING_VERSION + 1))
+            );
+            // Bincode from before the version byte starts with the chain id.
+            let legacy = bincode::serialize(&tx).unwrap();
+            assert!(Transaction::decode(&legacy).is_err());
+            assert_eq!(
+                Transaction::decode(&encoded[..encoded.len() - 1]),
+                Err(CodecError::Truncated)
+            );
+            let mut trailing = encoded;
+            trailing.push(0);
+            assert!(matches!(
+                Transaction::decode(&trailing),
+                Err(CodecError::Invalid(_))
+            ));
+        }
+        assert_eq!(Transaction::decode(&[]), Err(CodecError::Truncated));
+    }
+
+    #[test]
+    fn pow_nonce_is_encoded_only_when_set() {
+        let tx = Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: None,
+            priority: false,
+            payload: b"hello".to_vec(),
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        let worked = Transaction {
+            pow_nonce: 42,
+            ..tx.clone()
+        };
+        let encoded = worked.encode();
+        assert_eq!(encoded.len(), tx.encode().len() + 8);
+        assert_eq!(Transaction::decode(&encoded), Ok(worked.clone()));
+        assert_eq!(worked.signing_bytes(), tx.signing_bytes());
+
+        let mut zero = tx.encode();
+        zero.extend(0u64.to_le_bytes());
+        assert!(matches!(
+            Transaction::decode(&zero),
+            Err(CodecError::Invalid(_))
+        ));
+
+        assert_eq!(Hash([0; 32]).leading_zero_bits(), 256);
+        let mut hash = Hash([0xff; 32]);
+        hash.0[0] = 0;
+        hash.0[1] = 0b0001_0000;
+        assert_eq!(hash.leading_zero_bits(), 11);
+    }
+
+    #[test]
+    fn pow_nonce_does_not_change_the_id() {
+        let tx = Transaction {
+            chain_id: 1,
+            from: Address([3; 20]),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: Some(9),
+            priority: false,
+            payload: b"hello".to_vec(),
+            signature: vec![7; 64],
+            pow_nonce: 0,
+        };
+        // The signature covers the same bytes, so the id must not move
+        // either: otherwise anyone could re-id a signed transaction.
+        for pow_nonce in [1, 42, u64::MAX] {
+            let worked = Transaction {
+                pow_nonce,
+                ..tx.clone()
+            };
+            assert_eq!(worked.signing_bytes(), tx.signing_bytes());
+            assert_eq!(worked.id(), tx.id());
+            assert_ne!(worked.encode(), tx.encode());
+        }
+    }
+
+    #[test]
+    fn size_bytes_match_the_encodings() {
+        let tx = Transaction {
+            chain_id: 1,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: Some(9),
+            priority: false,
+            payload: b"hello".to_vec(),
+            signature: vec![7; 64],
+            pow_nonce: 0,
+        };
+        let worked = Transaction {
+            pow_nonce: 3,
+            ..tx.clone()
+        };
+        assert_eq!(tx.size_bytes(), tx.encode().len());
+        assert_eq!(worked.size_bytes(), worked.encode().len());
+
+        let mut block = Block {
+            header: BlockHeader {
+                height: 1,
+                parent: None,
+                tx_root: Hash([4u8; 32]),
+                gas_used: 0,
+                fees: 0,
+                state_root: Hash([5u8; 32]),
+                timestamp_ms: 0,
+                proposer: [6u8; 32],
+            },
+            txs: vec![],
+        };
+        assert_eq!(
+            block.size_bytes(),
+            bincode::serialize(&block).unwrap().len()
+        );
+        block.header.parent = Some(BlockId(Hash([3u8; 32])));
+        block.txs = vec![tx.id(), worked.id()];
+        assert_eq!(
+            block.size_bytes(),
+            bincode::serialize(&block).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn priority_flag_is_committed_to() {
+        let tx = Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        let urgent = Transaction {
+            priority: true,
+            ..tx.clone()
+        };
+        assert_ne!(tx.id(), urgent.id());
+        assert_ne!(tx.signing_bytes(), urgent.signing_bytes());
+    }
+
+    #[test]
+    fn expiry_is_committed_to_and_inclusive() {
+        let tx = Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: Some(5),
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        };
+        let open_ended = Transaction {
+            valid_until_height: None,
+            ..tx.clone()
+        };
+        assert_ne!(tx.id(), open_ended.id());
+        assert_ne!(tx.signing_bytes(), open_ended.signing_bytes());
+
+        assert!(!tx.is_expired_at(5));
+        assert!(tx.is_expired_at(6));
+        assert!(!open_ended.is_expired_at(u64::MAX));
+    }
+
+    #[test]
+    fn transaction_and_block_roundtrip_through_json() {
+        let tx = Transaction {
+            chain_id: 7,
+            from: Address([2u8; 20]),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 2,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![0, 1, 255],
+            signature: vec![9; 64],
+            pow_nonce: 0,
+        };
+        let json = serde_json::to_string(&tx).unwrap();
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), tx);
+
+        let block = Block {
+            header: BlockHeader {
+                height: 1,
+                parent: Some(BlockId(Hash([3u8; 32]))),
+                tx_root: Hash([4u8; 32]),
+                gas_used: 0,
+                fees: 0,
+                state_root: Hash([5u8; 32]),
+                timestamp_ms: 0,
+                proposer: [6u8; 32],
+            },
+            txs: vec![tx.id()],
+        };
+        let json = serde_json::to_string(&block).unwrap();
+        assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), block);
+    }
+
     #[test]
     fn block_header_id_changes_with_height() {
         let header1 = BlockHeader {
             height: 1,
             parent: None,
             tx_root: hash_bytes(b"tx_root"),
+            gas_used: 0,
+            fees: 0,
             state_root: hash_bytes(b"state_root"),
             timestamp_ms: 0,
             proposer: [0u8; 32],
@@ -329,22 +1464,117 @@ mod tests {
         assert_ne!(header1.id(), header2.id());
     }
 
+    #[test]
+    fn header_validation_checks_links_to_parent() {
+        let genesis = BlockHeader {
+            height: 1,
+            parent: None,
+            tx_root: hash_bytes(b"tx_root"),
+            gas_used: 0,
+            fees: 0,
+            state_root: hash_bytes(b"state_root"),
+            timestamp_ms: 1_000,
+            proposer: [0u8; 32],
+        };
+        let child = BlockHeader {
+            height: 2,
+            parent: Some(genesis.id()),
+            timestamp_ms: 1_001,
+            ..genesis.clone()
+        };
+        assert_eq!(validate_block_header(&genesis, None), Ok(()));
+        assert_eq!(validate_block_header(&child, Some(&genesis)), Ok(()));
+
+        let orphan = BlockHeader {
+            height: 2,
+            ..genesis.clone()
+        };
+        assert_eq!(
+            validate_block_header(&orphan, None),
+            Err(HeaderError::UnexpectedHeight {
+                expected: 1,
+                actual: 2
+            })
+        );
+        let skipped = BlockHeader {
+            height: 3,
+            ..child.clone()
+        };
+        assert_eq!(
+            validate_block_header(&skipped, Some(&genesis)),
+            Err(HeaderError::UnexpectedHeight {
+                expected: 2,
+                actual: 3
+            })
+        );
+
+        let stale = BlockHeader {
+            timestamp_ms: 1_000,
+            ..child.clone()
+        };
+        assert_eq!(
+            validate_block_header(&stale, Some(&genesis)),
+            Err(HeaderError::TimestampNotAfterParent {
+                timestamp_ms: 1_000,
+                parent_timestamp_ms: 1_000
+            })
+        );
+
+        // The parent field must name the supplied parent, and be empty
+        // without one.
+        let other_parent = BlockHeader {
+            state_root: hash_bytes(b"other"),
+            ..genesis.clone()
+        };
+        assert_eq!(
+            validate_block_header(&child, Some(&other_parent)),
+            Err(HeaderError::ParentMismatch {
+                expected: Some(other_parent.id()),
+                actual: Some(genesis.id())
+            })
+        );
+        assert_eq!(
+            validate_block_header(&child, None),
+            Err(HeaderError::ParentMismatch {
+                expected: None,
+                actual: Some(genesis.id())
+            })
+        );
+    }
+
     #[test]
     fn merkle_root_empty_is_zero() {
         let root = merkle_root(&[]);
         assert_eq!(root, Hash([0u8; 32]));
     }
 
+    #[test]
+    fn merkle_root_from_iter_matches_the_slice_root() {
+        for len in 0..=40u32 {
+            let txs: Vec<TxId> = (0..len)
+                .map(|i| TxId(hash_bytes(&i.to_le_bytes())))
+                .collect();
+            let root = merkle_root_from_iter(txs.iter().copied());
+            assert_eq!(root, merkle_root(&txs), "{len} leaves");
+            assert_eq!(root, MerkleTree::build(&txs).root(), "{len} leaves");
+        }
+    }
+
     #[test]
     fn merkle_proof_roundtrip() {
         let txs: Vec<_> = (0u8..4)
             .map(|i| {
                 let tx = Transaction {
+                    chain_id: 0,
+                    from: Address::default(),
                     namespace: NamespaceId(1),
                     gas_price: 1,
                     nonce: i as u64,
+                    valid_until_height: None,
+                    priority: false,
                     payload: vec![i],
                     signature: vec![],
+                    pow_nonce: 0,
                 };
                 tx.id()
             })
@@ -357,17 +1587,105 @@ mod tests {
         }
     }
 
+    const ALL_ALGOS: &[HashAlgo] = &[
+        HashAlgo::Blake3,
+        #[cfg(feature = "keccak")]
+        HashAlgo::Keccak256,
+    ];
+
+    #[test]
+    fn merkle_proofs_roundtrip_under_each_algo() {
+        for &algo in ALL_ALGOS {
+            let txs: Vec<TxId> = (0u8..5)
+                .map(|i| {
+                    let tx = Transaction {
+                        chain_id: 0,
+                        from: Address::default(),
+                        namespace: NamespaceId(1),
+                        gas_price: 1,
+                        nonce: i as u64,
+                        valid_until_height: None,
+                        priority: false,
+                        payload: vec![i],
+                        signature: vec![],
+                        pow_nonce: 0,
+                    };
+                    tx.id_with(algo)
+                })
+                .collect();
+
+            let root = merkle_root_with(algo, &txs);
+            for (idx, tx_id) in txs.iter().enumerate() {
+                let proof = merkle_proof_with(algo, &txs, idx).expect("proof exists");
+                assert!(verify_merkle_proof_with(algo, root, *tx_id, &proof));
+            }
+            let multi = MerkleTree::build_with(algo, &txs)
+                .multiproof(&[1, 4])
+                .expect("multiproof exists");
+            assert!(verify_merkle_multiproof_with(
+                algo,
+                root,
+                &[txs[1], txs[4]],
+                &multi
+            ));
+        }
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn keccak_ids_differ_from_blake3() {
+        // Keccak-256 of the empty string, not SHA3-256.
+        assert_eq!(
+            hex::encode(HashAlgo::Keccak256.hash(b"").0),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+
+        let tx = Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+        };
+        assert_eq!(tx.id(), tx.id_with(HashAlgo::Blake3));
+        assert_ne!(
+            tx.id_with(HashAlgo::Blake3),
+            tx.id_with(HashAlgo::Keccak256)
+        );
+
+        // A proof from one algorithm does not verify under the other.
+        let txs = make_tx_ids(3);
+        let root = merkle_root_with(HashAlgo::Keccak256, &txs);
+        let proof = merkle_proof_with(HashAlgo::Keccak256, &txs, 1).expect("proof exists");
+        assert!(verify_merkle_proof_with(
+            HashAlgo::Keccak256,
+            root,
+            txs[1],
+            &proof
+        ));
+        assert!(!verify_merkle_proof(root, txs[1], &proof));
+    }
+
     proptest! {
         #[test]
         fn merkle_proof_holds_for_random_txs(data in proptest::collection::vec(any::<u8>(), 0..32)) {
             // Build a list of TxIds from arbitrary payloads.
             let txs: Vec<TxId> = data.iter().enumerate().map(|(i, b)| {
                 let tx = Transaction {
+                    chain_id: 0,
+                    from: Address::default(),
                     namespace: NamespaceId(1),
                     gas_price: 1,
                     nonce: i as u64,
+                    valid_until_height: None,
+                    priority: false,
                     payload: vec![*b],
                     signature: vec![],
+                    pow_nonce: 0,
                 };
                 tx.id()
             }).collect();
@@ -381,6 +1699,260 @@ mod tests {
         }
     }
 
+    fn make_tx_ids(n: u8) -> Vec<TxId> {
+        (0..n)
+            .map(|i| {
+                let tx = Transaction {
+                    chain_id: 0,
+                    from: Address::default(),
+                    namespace: NamespaceId(1),
+                    gas_price: 1,
+                    nonce: i as u64,
+                    valid_until_height: None,
+                    priority: false,
+                    payload: vec![i],
+                    signature: vec![],
+                    pow_nonce: 0,
+                };
+                tx.id()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merkle_multiproof_agrees_with_single_proofs() {
+        let txs = make_tx_ids(7);
+        let root = merkle_root(&txs);
+        let indices = [0usize, 3, 4, 6];
+
+        let multi = merkle_multiproof(&txs, &indices).expect("multiproof exists");
+        let leaves: Vec<TxId> = indices.iter().map(|&i| txs[i]).collect();
+        assert!(verify_merkle_multiproof(root, &leaves, &multi));
+
+        let single_siblings: usize = indices
+            .iter()
+            .map(|&i| {
+                let proof = merkle_proof(&txs, i).expect("proof exists");
+                assert!(verify_merkle_proof(root, txs[i], &proof));
+                proof.siblings.len()
+            })
+            .sum();
+        assert!(multi.siblings.len() < single_siblings);
+    }
+
+    #[test]
+    fn merkle_multiproof_rejects_out_of_range_and_tampering() {
+        let txs = make_tx_ids(4);
+        let root = merkle_root(&txs);
+        assert!(merkle_multiproof(&txs, &[1, 4]).is_none());
+        assert!(merkle_multiproof(&txs, &[]).is_none());
+
+        let proof = merkle_multiproof(&txs, &[1, 2]).expect("multiproof exists");
+        assert!(verify_merkle_multiproof(root, &[txs[1], txs[2]], &proof));
+        assert!(!verify_merkle_multiproof(root, &[txs[2], txs[1]], &proof));
+        assert!(!verify_merkle_multiproof(root, &[txs[1]], &proof));
+    }
+
+    proptest! {
+        #[test]
+        fn merkle_multiproof_holds_for_random_subsets(
+            n in 1u8..32,
+            picks in proptest::collection::vec(any::<u8>(), 1..8),
+        ) {
+            let txs = make_tx_ids(n);
+            let root = merkle_root(&txs);
+            let indices: Vec<usize> = picks.iter().map(|p| *p as usize % txs.len()).collect();
+
+            let proof = merkle_multiproof(&txs, &indices).expect("multiproof exists");
+            let leaves: Vec<TxId> = proof.indices.iter().map(|&i| txs[i as usize]).collect();
+            prop_assert!(verify_merkle_multiproof(root, &leaves, &proof));
+        }
+    }
+
+    // The pre-`MerkleTree` implementations, which rebuild every layer per
+    // call. Kept as references for the cached tree.
+    fn reference_root(txs: &[TxId]) -> Hash {
+        if txs.is_empty() {
+            return Hash([0u8; 32]);
+        }
+
+        let mut layer: Vec<Hash> = txs.iter().map(|TxId(h)| *h).collect();
+
+        while layer.len() > 1 {
+            let mut next = Vec::with_capacity(layer.len().div_ceil(2));
+            for chunk in layer.chunks(2) {
+                let combined = if chunk.len() == 2 {
+                    let mut data = Vec::with_capacity(64);
+                    data.extend_from_slice(&chunk[0].0);
+                    data.extend_from_slice(&chunk[1].0);
+                    data
+                } else {
+                    let mut data = Vec::with_capacity(64);
+                    data.extend_from_slice(&chunk[0].0);
+                    data.extend_from_slice(&chunk[0].0);
+                    data
+                };
+                next.push(hash_bytes(&combined));
+            }
+            layer = next;
+        }
+
+        layer[0]
+    }
+
+    fn reference_proof(txs: &[TxId], index: usize) -> Option<MerkleProof> {
+        if txs.is_empty() || index >= txs.len() {
+            return None;
+        }
+
+        let mut idx = index;
+        let mut layer: Vec<Hash> = txs.iter().map(|TxId(h)| *h).collect();
+        let mut siblings = Vec::new();
+
+        while layer.len() > 1 {
+            let is_right = idx % 2 == 1;
+            let sibling_idx = if is_right { idx - 1 } else { idx + 1 };
+
+            let sibling_hash = if sibling_idx < layer.len() {
+                layer[sibling_idx]
+            } else {
+                layer[idx]
+            };
+            siblings.push(sibling_hash);
+
+            idx /= 2;
+
+            let mut next = Vec::with_capacity(layer.len().div_ceil(2));
+            for chunk in layer.chunks(2) {
+                let combined = if chunk.len() == 2 {
+                    let mut data = Vec::with_capacity(64);
+                    data.extend_from_slice(&chunk[0].0);
+                    data.extend_from_slice(&chunk[1].0);
+                    data
+                } else {
+                    let mut data = Vec::with_capacity(64);
+                    data.extend_from_slice(&chunk[0].0);
+                    data.extend_from_slice(&chunk[0].0);
+                    data
+                };
+                next.push(hash_bytes(&combined));
+            }
+            layer = next;
+        }
+
+        Some(MerkleProof {
+            index: index as u32,
+            siblings,
+        })
+    }
+
+    fn reference_multiproof(txs: &[TxId], indices: &[usize]) -> Option<MerkleMultiProof> {
+        if txs.is_empty() || indices.is_empty() || indices.iter().any(|&i| i >= txs.len()) {
+            return None;
+        }
+
+        let mut known: Vec<usize> = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        let proven: Vec<u32> = known.iter().map(|&i| i as u32).collect();
+
+        let mut layer: Vec<Hash> = txs.iter().map(|TxId(h)| *h).collect();
+        let mut siblings = Vec::new();
+
+        while layer.len() > 1 {
+            let mut next_known = Vec::with_capacity(known.len());
+            let mut pos = 0;
+            while pos < known.len() {
+                let idx = known[pos];
+                if idx.is_multiple_of(2) {
+                    let right = idx + 1;
+                    if pos + 1 < known.len() && known[pos + 1] == right {
+                        // Both children are known; nothing to emit.
+                        pos += 1;
+                    } else if right < layer.len() {
+                        siblings.push(layer[right]);
+                    }
+                } else {
+                    siblings.push(layer[idx - 1]);
+                }
+                next_known.push(idx / 2);
+                pos += 1;
+            }
+
+            let next = layer
+                .chunks(2)
+                .map(|chunk| {
+                    hash_pair(
+                        HashAlgo::Blake3,
+                        &chunk[0],
+                        chunk.get(1).unwrap_or(&chunk[0]),
+                    )
+                })
+                .collect();
+            layer = next;
+            known = next_known;
+        }
+
+        Some(MerkleMultiProof {
+            leaf_count: txs.len() as u32,
+            indices: proven,
+            siblings,
+        })
+    }
+
+    fn leaf_ids(seeds: &[u32]) -> Vec<TxId> {
+        seeds
+            .iter()
+            .map(|i| TxId(hash_bytes(&i.to_be_bytes())))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn merkle_tree_matches_reference(
+            seeds in proptest::collection::vec(any::<u32>(), 0..64),
+            picks in proptest::collection::vec(any::<u8>(), 1..8),
+        ) {
+            let txs = leaf_ids(&seeds);
+            let tree = MerkleTree::build(&txs);
+
+            prop_assert_eq!(tree.root(), reference_root(&txs));
+            for idx in 0..=txs.len() {
+                prop_assert_eq!(tree.proof(idx), reference_proof(&txs, idx));
+            }
+            let indices: Vec<usize> = picks
+                .iter()
+                .map(|p| *p as usize % txs.len().max(1))
+                .collect();
+            prop_assert_eq!(tree.multiproof(&indices), reference_multiproof(&txs, &indices));
+        }
+    }
+
+    #[test]
+    fn merkle_tree_serves_every_proof_for_a_large_block() {
+        // One build serves all 4096 proofs; the reference would rehash the
+        // whole tree per proof, so it is only spot-checked.
+        let seeds: Vec<u32> = (0..4096).collect();
+        let txs = leaf_ids(&seeds);
+        let tree = MerkleTree::build(&txs);
+        let root = tree.root();
+        assert_eq!(root, reference_root(&txs));
+
+        for (idx, tx_id) in txs.iter().enumerate() {
+            let proof = tree.proof(idx).expect("proof exists");
+            assert!(verify_merkle_proof(root, *tx_id, &proof));
+            if idx % 512 == 0 {
+                assert_eq!(Some(proof), reference_proof(&txs, idx));
+            }
+        }
+
+        let indices: Vec<usize> = (0..txs.len()).step_by(7).collect();
+        let multi = tree.multiproof(&indices).expect("multiproof exists");
+        assert_eq!(Some(multi.clone()), reference_multiproof(&txs, &indices));
+        let leaves: Vec<TxId> = indices.iter().map(|&i| txs[i]).collect();
+        assert!(verify_merkle_multiproof(root, &leaves, &multi));
+    }
+
     #[test]
     fn l1_batch_commitment_hash_is_deterministic() {
         let batch = L1BatchCommitment {
@@ -0,0 +1,119 @@
+//! Command-line interface: running the node, and one-off commands that
+//! read a stopped node's sled data directory.
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use metrics as sequencer_metrics;
+use storage::{BlockStore, SledStorage, StorageError};
+use types::Block;
+
+use crate::config::NodeConfig;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Rollup sequencer node")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Arguments of `run`, so the subcommand can be omitted.
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the node (the default).
+    Run(RunArgs),
+    /// Print the latest block as JSON.
+    Head(DataDirArgs),
+    /// Print the block at a height as JSON.
+    GetBlock {
+        #[arg(long)]
+        height: u64,
+        #[command(flatten)]
+        data: DataDirArgs,
+    },
+    /// Print the storage gauges in Prometheus text format.
+    DumpMetrics(DataDirArgs),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct RunArgs {
+    /// TOML node config. Without one the node uses the `NODE_ID` demo
+    /// defaults.
+    pub config: Option<PathBuf>,
+}
+
+impl RunArgs {
+    pub fn node_config(&self) -> Result<NodeConfig, Box<dyn std::error::Error>> {
+        match &self.config {
+            Some(path) => Ok(NodeConfig::load(path)?),
+            None => Ok(NodeConfig::from_env()),
+        }
+    }
+}
+
+/// Where the one-off commands find the node's data.
+#[derive(Args, Debug)]
+pub struct DataDirArgs {
+    /// Sled data directory. Defaults to the `data_dir` of `--config`, or
+    /// of the `NODE_ID` demo defaults.
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// TOML node config to take the data directory from.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+impl DataDirArgs {
+    /// Open the data directory. Sled holds a lock on it, so this fails
+    /// while a node is running on the same directory.
+    fn open(&self) -> Result<SledStorage, Box<dyn std::error::Error>> {
+        let config = RunArgs {
+            config: self.config.clone(),
+        }
+        .node_config()?;
+        let path = self
+            .data_dir
+            .clone()
+            .unwrap_or_else(|| config.data_dir.clone());
+        if !path.exists() {
+            return Err(format!("data directory {} does not exist", path.display()).into());
+        }
+        Ok(SledStorage::open_with_config(&path, config.sled_config())?)
+    }
+}
+
+pub fn head(args: &DataDirArgs) -> Result<(), Box<dyn std::error::Error>> {
+    print_block(args.open()?.latest_block(), "no blocks committed yet")
+}
+
+pub fn get_block(height: u64, args: &DataDirArgs) -> Result<(), Box<dyn std::error::Error>> {
+    print_block(
+        args.open()?.get_block_by_height(height),
+        &format!("no block at height {height}"),
+    )
+}
+
+fn print_block(
+    block: Result<Block, StorageError>,
+    missing: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match block {
+        Ok(block) => {
+            println!("{}", serde_json::to_string_pretty(&block)?);
+            Ok(())
+        }
+        Err(StorageError::NotFound) => Err(missing.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn dump_metrics(args: &DataDirArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = args.open()?;
+    sequencer_metrics::init_metrics()?;
+    storage.record_size_metrics()?;
+    print!("{}", sequencer_metrics::render_metrics());
+    Ok(())
+}
@@ -0,0 +1,777 @@
+//! Node configuration, loaded from a TOML file or derived from the
+//! `NODE_ID` environment variable for the two-node demo.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+/// Mempool limits.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolSettings {
+    pub max_tx: usize,
+    pub replacement_bump_percent: u64,
+    /// Drop pending transactions older than this many seconds.
+    pub ttl_secs: Option<u64>,
+    pub ordering: OrderingSetting,
+    pub overflow: OverflowSetting,
+    /// Reject transactions priced below this; off when absent.
+    pub min_gas_price: Option<u64>,
+    /// Leading zero bits required of each transaction's proof of work;
+    /// off when absent.
+    pub pow_difficulty_bits: Option<u32>,
+    /// Log pending transactions to `mempool.wal` in the data directory and
+    /// replay them after a crash.
+    pub wal: bool,
+}
+
+/// TOML form of [`mempool::OrderingStrategy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderingSetting {
+    #[default]
+    GasPriceThenFifo,
+    Fifo,
+    /// Seeded from the OS; use the library directly for reproducible runs.
+    RandomWeightedByFee,
+}
+
+impl OrderingSetting {
+    fn to_strategy(self) -> mempool::OrderingStrategy {
+        match self {
+            Self::GasPriceThenFifo => mempool::OrderingStrategy::GasPriceThenFifo,
+            Self::Fifo => mempool::OrderingStrategy::Fifo,
+            Self::RandomWeightedByFee => {
+                mempool::OrderingStrategy::RandomWeightedByFee { seed: None }
+            }
+        }
+    }
+}
+
+/// TOML form of [`mempool::OverflowPolicy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowSetting {
+    #[default]
+    Reject,
+    EvictLowestFee,
+}
+
+impl OverflowSetting {
+    fn to_policy(self) -> mempool::OverflowPolicy {
+        match self {
+            Self::Reject => mempool::OverflowPolicy::Reject,
+            Self::EvictLowestFee => mempool::OverflowPolicy::EvictLowestFee,
+        }
+    }
+}
+
+impl Default for MempoolSettings {
+    fn default() -> Self {
+        let defaults = mempool::MempoolConfig::default();
+        Self {
+            max_tx: defaults.max_tx,
+            replacement_bump_percent: defaults.replacement_bump_percent,
+            ttl_secs: None,
+            ordering: OrderingSetting::default(),
+            overflow: OverflowSetting::default(),
+            min_gas_price: None,
+            pow_difficulty_bits: None,
+            wal: false,
+        }
+    }
+}
+
+impl MempoolSettings {
+    pub fn to_mempool_config(&self) -> mempool::MempoolConfig {
+        mempool::MempoolConfig {
+            max_tx: self.max_tx,
+            replacement_bump_percent: self.replacement_bump_percent,
+            ordering: self.ordering.to_strategy(),
+            overflow: self.overflow.to_policy(),
+            anti_spam: mempool::AntiSpamConfig {
+                min_gas_price: self.min_gas_price,
+                pow_difficulty_bits: self.pow_difficulty_bits,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// HTTP server options.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcSettings {
+    /// Send CORS headers so browser dashboards can call the server.
+    pub enable_cors: bool,
+    /// Origins allowed when CORS is enabled; empty allows any origin.
+    pub allowed_origins: Vec<String>,
+    pub max_body_bytes: usize,
+    /// Largest page served by `GET /blocks`.
+    pub max_blocks_page: usize,
+    /// Per-client limit on `POST /tx` and `seq_submitTransaction`;
+    /// unlimited when absent.
+    pub tx_rate_limit: Option<RateLimitSettings>,
+    /// Requests served at once; more get 503. Unlimited when absent.
+    pub max_connections: Option<usize>,
+    /// Connections queued by the OS before being accepted.
+    pub accept_backlog: u32,
+    /// Age of the last block past which `GET /health` answers 503; never
+    /// stale when absent. Requires `produce_empty_blocks`, since an idle
+    /// node otherwise stops producing blocks.
+    pub max_block_age_ms: Option<u64>,
+    /// Bearer token required to submit `priority` transactions; without one
+    /// they are always refused.
+    pub priority_token: Option<String>,
+}
+
+/// TOML form of [`rpc::RateLimit`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub requests_per_second: u32,
+    pub burst: u32,
+    /// Key clients by `X-Forwarded-For`; only safe behind a proxy.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RpcSettings {
+    fn default() -> Self {
+        let defaults = rpc::RpcConfig::default();
+        Self {
+            enable_cors: defaults.enable_cors,
+            allowed_origins: defaults.allowed_origins,
+            max_body_bytes: defaults.max_body_bytes,
+            max_blocks_page: defaults.max_blocks_page,
+            tx_rate_limit: None,
+            max_connections: defaults.max_connections,
+            accept_backlog: defaults.accept_backlog,
+            max_block_age_ms: defaults.max_block_age_ms,
+            priority_token: defaults.priority_token,
+        }
+    }
+}
+
+impl RpcSettings {
+    pub fn to_rpc_config(&self) -> rpc::RpcConfig {
+        rpc::RpcConfig {
+            enable_cors: self.enable_cors,
+            allowed_origins: self.allowed_origins.clone(),
+            max_body_bytes: self.max_body_bytes,
+            max_blocks_page: self.max_blocks_page,
+            tx_rate_limit: self.tx_rate_limit.as_ref().map(|limit| rpc::RateLimit {
+                requests_per_second: limit.requests_per_second,
+                burst: limit.burst,
+                trust_forwarded_for: limit.trust_forwarded_for,
+            }),
+            max_connections: self.max_connections,
+            accept_backlog: self.accept_backlog,
+            max_block_age_ms: self.max_block_age_ms,
+            priority_token: self.priority_token.clone(),
+        }
+    }
+}
+
+/// TOML form of [`networking::PeerRateLimit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GossipRateLimitSettings {
+    pub messages_per_second: u32,
+    pub burst: u32,
+}
+
+impl GossipRateLimitSettings {
+    pub fn to_peer_rate_limit(self) -> networking::PeerRateLimit {
+        networking::PeerRateLimit {
+            messages_per_second: self.messages_per_second,
+            burst: self.burst,
+        }
+    }
+}
+
+/// TOML form of [`networking::WireFormat`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormatSetting {
+    #[default]
+    Json,
+    Bincode,
+}
+
+impl WireFormatSetting {
+    pub fn to_wire_format(self) -> networking::WireFormat {
+        match self {
+            Self::Json => networking::WireFormat::Json,
+            Self::Bincode => networking::WireFormat::Bincode,
+        }
+    }
+}
+
+/// Initial balance for one sender at genesis.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisAccount {
+    #[serde(deserialize_with = "hex_address")]
+    pub address: types::Address,
+    pub balance: u64,
+}
+
+/// An address written as hex, as in `POST /tx`.
+fn hex_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<types::Address, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn optional_hex_address<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<types::Address>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|address| address.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Everything a node needs to start.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    /// UDP gossip listen address.
+    pub listen_addr: SocketAddr,
+    /// Gossip peers.
+    #[serde(default)]
+    pub peers: Vec<SocketAddr>,
+    /// HTTP RPC listen address.
+    pub rpc_addr: SocketAddr,
+    /// Directory for the sled database.
+    pub data_dir: PathBuf,
+    /// Hex-encoded 32-byte ed25519 secret key used to sign gossip.
+    pub node_key: String,
+    /// Hex-encoded ed25519 public keys of peers whose gossip is accepted.
+    #[serde(default)]
+    pub trusted_peers: Vec<String>,
+    #[serde(default)]
+    pub mempool: MempoolSettings,
+    #[serde(default)]
+    pub rpc: RpcSettings,
+    /// Delay between consensus steps, in milliseconds.
+    #[serde(default = "default_block_time_ms")]
+    pub block_time_ms: u64,
+    /// Seal header-only blocks when the mempool is empty.
+    #[serde(default)]
+    pub produce_empty_blocks: bool,
+    /// Transactions a block waits for before it is sealed, unless it is
+    /// full or `max_wait_ms` has passed.
+    #[serde(default = "default_min_txs_per_block")]
+    pub min_txs_per_block: usize,
+    /// Longest pending transactions wait for `min_txs_per_block`, in
+    /// milliseconds.
+    #[serde(default = "default_max_wait_ms")]
+    pub max_wait_ms: u64,
+    /// Report a view timeout after this many consecutive steps without a
+    /// block; off when absent.
+    #[serde(default)]
+    pub view_timeout_views: Option<u64>,
+    /// Report a view timeout after this many milliseconds without a block;
+    /// off when absent.
+    #[serde(default)]
+    pub view_timeout_ms: Option<u64>,
+    /// zstd level (1-22) for stored blocks and outgoing gossip. Unset
+    /// disables compression; compressed data is always readable.
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+    /// Period of the storage background flush, in milliseconds; 0 disables
+    /// it.
+    #[serde(default = "default_flush_every_ms")]
+    pub flush_every_ms: u64,
+    /// Flush storage to disk after every block, trading throughput for
+    /// never losing a committed block on a crash.
+    #[serde(default)]
+    pub flush_on_block: bool,
+    /// Keep only this many of the most recent blocks, pruning older ones
+    /// with their transactions and receipts. Unset keeps everything.
+    #[serde(default)]
+    pub retain_blocks: Option<u64>,
+    /// Encoding of gossip messages; every peer must use the same one.
+    #[serde(default)]
+    pub wire_format: WireFormatSetting,
+    /// Namespaces whose gossiped transactions this node accepts. Unset
+    /// accepts every namespace; blocks are always accepted.
+    #[serde(default)]
+    pub subscribed_namespaces: Option<Vec<u64>>,
+    /// Per-peer limit on gossip messages processed; unlimited when absent.
+    #[serde(default)]
+    pub gossip_rate_limit: Option<GossipRateLimitSettings>,
+    /// Funded accounts at genesis. Transactions from any other sender
+    /// cannot pay fees and are never included in a block.
+    #[serde(default)]
+    pub genesis: Vec<GenesisAccount>,
+    /// Address credited with block fees. Unset credits each block's
+    /// proposer.
+    #[serde(default, deserialize_with = "optional_hex_address")]
+    pub fee_recipient: Option<types::Address>,
+    /// Human-readable namespace names, `name = id`, shown in logs and
+    /// metric labels instead of the numeric id.
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, u64>,
+}
+
+fn default_block_time_ms() -> u64 {
+    500
+}
+
+fn default_min_txs_per_block() -> usize {
+    consensus::ConsensusConfig::default().min_txs_per_block
+}
+
+fn default_max_wait_ms() -> u64 {
+    consensus::ConsensusConfig::default().max_wait_ms
+}
+
+fn default_flush_every_ms() -> u64 {
+    500
+}
+
+impl NodeConfig {
+    /// Load and validate a config from a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    /// Parse and validate a config from TOML text.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The very simple two-node demo configuration based on the `NODE_ID`
+    /// env var (`1` or anything else), with an optional
+    /// `MEMPOOL_TTL_SECS`.
+    pub fn from_env() -> Self {
+        let node_id = env::var("NODE_ID").unwrap_or_else(|_| "1".to_string());
+        let (listen_addr, peer, rpc_addr, own_key, peer_key) = if node_id == "1" {
+            ("127.0.0.1:9001", "127.0.0.1:9002", "127.0.0.1:8080", 1, 2)
+        } else {
+            ("127.0.0.1:9002", "127.0.0.1:9001", "127.0.0.1:8081", 2, 1)
+        };
+
+        // Demo-only gossip keys derived from the node id: each node signs
+        // with its own key and trusts the other node's.
+        let demo_key = |id: u8| SigningKey::from_bytes(&[id; 32]);
+
+        Self {
+            listen_addr: listen_addr.parse().unwrap(),
+            peers: vec![peer.parse().unwrap()],
+            rpc_addr: rpc_addr.parse().unwrap(),
+            // Use a per-node sled database directory to avoid file locks
+            // when running multiple nodes on the same machine.
+            data_dir: PathBuf::from(format!("./data_{}", node_id)),
+            node_key: hex::encode(demo_key(own_key).to_bytes()),
+            trusted_peers: vec![hex::encode(demo_key(peer_key).verifying_key().to_bytes())],
+            mempool: MempoolSettings {
+                ttl_secs: env::var("MEMPOOL_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok()),
+                ..Default::default()
+            },
+            rpc: RpcSettings::default(),
+            block_time_ms: default_block_time_ms(),
+            produce_empty_blocks: false,
+            min_txs_per_block: default_min_txs_per_block(),
+            max_wait_ms: default_max_wait_ms(),
+            view_timeout_views: None,
+            view_timeout_ms: None,
+            compression_level: None,
+            flush_every_ms: default_flush_every_ms(),
+            flush_on_block: false,
+            retain_blocks: None,
+            wire_format: WireFormatSetting::default(),
+            subscribed_namespaces: None,
+            gossip_rate_limit: None,
+            // Fund the zero address, the sender of the demo walkthrough's
+            // transactions, which carry no `from`.
+            genesis: vec![GenesisAccount {
+                address: types::Address::default(),
+                balance: 1_000_000_000,
+            }],
+            fee_recipient: None,
+            namespaces: BTreeMap::new(),
+        }
+    }
+
+    pub fn consensus_config(&self) -> consensus::ConsensusConfig {
+        consensus::ConsensusConfig {
+            block_interval_ms: self.block_time_ms,
+            produce_empty_blocks: self.produce_empty_blocks,
+            min_txs_per_block: self.min_txs_per_block,
+            max_wait_ms: self.max_wait_ms,
+            view_timeout_views: self.view_timeout_views,
+            view_timeout_ms: self.view_timeout_ms,
+            genesis_balances: self
+                .genesis
+                .iter()
+                .map(|account| (account.address, account.balance))
+                .collect(),
+            fee_recipient: self.fee_recipient,
+            ..Default::default()
+        }
+    }
+
+    pub fn namespace_registry(&self) -> Result<types::NamespaceRegistry, ConfigError> {
+        let mut registry = types::NamespaceRegistry::default();
+        for (name, id) in &self.namespaces {
+            registry
+                .register(types::NamespaceId(*id), name.as_str())
+                .map_err(|e| ConfigError::Invalid(format!("namespaces: {e}")))?;
+        }
+        Ok(registry)
+    }
+
+    pub fn sled_config(&self) -> storage::SledConfig {
+        storage::SledConfig {
+            compression_level: self.compression_level,
+            flush_every_ms: (self.flush_every_ms > 0).then_some(self.flush_every_ms),
+            flush_on_block: self.flush_on_block,
+        }
+    }
+
+    pub fn signing_key(&self) -> Result<SigningKey, ConfigError> {
+        let secret = decode_key("node_key", &self.node_key)?;
+        Ok(SigningKey::from_bytes(&secret))
+    }
+
+    pub fn trusted_keys(&self) -> Result<Vec<VerifyingKey>, ConfigError> {
+        self.trusted_peers
+            .iter()
+            .map(|key| {
+                VerifyingKey::from_bytes(&decode_key("trusted_peers", key)?).map_err(|e| {
+                    ConfigError::Invalid(format!("trusted_peers: invalid public key: {e}"))
+                })
+            })
+            .collect()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.block_time_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "block_time_ms must be positive".into(),
+            ));
+        }
+        if self.view_timeout_views == Some(0) || self.view_timeout_ms == Some(0) {
+            return Err(ConfigError::Invalid(
+                "view_timeout_views and view_timeout_ms must be positive".into(),
+            ));
+        }
+        if self.retain_blocks == Some(0) {
+            return Err(ConfigError::Invalid(
+                "retain_blocks must be positive".into(),
+            ));
+        }
+        if self.mempool.max_tx == 0 {
+            return Err(ConfigError::Invalid(
+                "mempool.max_tx must be positive".into(),
+            ));
+        }
+        if self.rpc.max_body_bytes == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc.max_body_bytes must be positive".into(),
+            ));
+        }
+        if self.rpc.max_connections == Some(0) || self.rpc.accept_backlog == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc.max_connections and rpc.accept_backlog must be positive".into(),
+            ));
+        }
+        if self.rpc.max_block_age_ms == Some(0) {
+            return Err(ConfigError::Invalid(
+                "rpc.max_block_age_ms must be positive".into(),
+            ));
+        }
+        if self.rpc.max_block_age_ms.is_some() && !self.produce_empty_blocks {
+            // Without pending txs no blocks are built, so an idle but
+            // healthy node would report itself stale.
+            return Err(ConfigError::Invalid(
+                "rpc.max_block_age_ms requires produce_empty_blocks".into(),
+            ));
+        }
+        if self
+            .rpc
+            .tx_rate_limit
+            .as_ref()
+            .is_some_and(|limit| limit.requests_per_second == 0 || limit.burst == 0)
+        {
+            return Err(ConfigError::Invalid(
+                "rpc.tx_rate_limit rate and burst must be positive".into(),
+            ));
+        }
+        if self
+            .gossip_rate_limit
+            .is_some_and(|limit| limit.messages_per_second == 0 || limit.burst == 0)
+        {
+            return Err(ConfigError::Invalid(
+                "gossip_rate_limit rate and burst must be positive".into(),
+            ));
+        }
+        if self
+            .compression_level
+            .is_some_and(|level| !(1..=22).contains(&level))
+        {
+            return Err(ConfigError::Invalid(
+                "compression_level must be between 1 and 22".into(),
+            ));
+        }
+        if self.peers.contains(&self.listen_addr) {
+            return Err(ConfigError::Invalid(
+                "peers must not include listen_addr".into(),
+            ));
+        }
+        self.signing_key()?;
+        self.trusted_keys()?;
+        self.namespace_registry()?;
+        Ok(())
+    }
+}
+
+fn decode_key(field: &str, value: &str) -> Result<[u8; 32], ConfigError> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))
+        .map_err(|e| ConfigError::Invalid(format!("{field}: invalid hex: {e}")))?;
+    bytes
+        .try_into()
+        .map_err(|_| ConfigError::Invalid(format!("{field}: expected 32 bytes")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+listen_addr = "127.0.0.1:9001"
+peers = ["127.0.0.1:9002", "127.0.0.1:9003"]
+rpc_addr = "127.0.0.1:8080"
+data_dir = "/var/lib/sequencer"
+node_key = "0101010101010101010101010101010101010101010101010101010101010101"
+trusted_peers = []
+block_time_ms = 250
+produce_empty_blocks = true
+min_txs_per_block = 10
+max_wait_ms = 750
+view_timeout_views = 20
+compression_level = 3
+flush_on_block = true
+retain_blocks = 1000
+wire_format = "bincode"
+subscribed_namespaces = [1, 2]
+fee_recipient = "0x0707070707070707070707070707070707070707"
+
+[mempool]
+max_tx = 500
+ttl_secs = 60
+ordering = "fifo"
+overflow = "evict_lowest_fee"
+min_gas_price = 2
+wal = true
+
+[rpc]
+enable_cors = true
+allowed_origins = ["https://dashboard.example"]
+max_connections = 256
+max_block_age_ms = 30000
+priority_token = "operator-secret"
+
+[rpc.tx_rate_limit]
+requests_per_second = 5
+burst = 10
+
+[gossip_rate_limit]
+messages_per_second = 50
+burst = 100
+
+[namespaces]
+payments = 7
+
+[[genesis]]
+address = "0x0707070707070707070707070707070707070707"
+balance = 1000
+"#;
+
+    #[test]
+    fn parses_sample_config() {
+        let config = NodeConfig::parse(SAMPLE).unwrap();
+
+        assert_eq!(config.listen_addr, "127.0.0.1:9001".parse().unwrap());
+        assert_eq!(config.peers.len(), 2);
+        assert_eq!(config.rpc_addr, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/sequencer"));
+        assert_eq!(config.block_time_ms, 250);
+        assert!(config.consensus_config().produce_empty_blocks);
+        assert_eq!(config.consensus_config().min_txs_per_block, 10);
+        assert_eq!(config.consensus_config().max_wait_ms, 750);
+        assert_eq!(config.consensus_config().view_timeout_views, Some(20));
+        assert_eq!(config.consensus_config().view_timeout_ms, None);
+        assert_eq!(
+            config.consensus_config().fee_recipient,
+            Some(types::Address([7; 20]))
+        );
+        assert_eq!(config.sled_config().compression_level, Some(3));
+        assert_eq!(config.sled_config().flush_every_ms, Some(500));
+        assert!(config.sled_config().flush_on_block);
+        assert_eq!(config.retain_blocks, Some(1000));
+        assert_eq!(
+            config.wire_format.to_wire_format(),
+            networking::WireFormat::Bincode
+        );
+        assert_eq!(config.subscribed_namespaces, Some(vec![1, 2]));
+        assert_eq!(
+            config
+                .gossip_rate_limit
+                .map(|limit| limit.to_peer_rate_limit()),
+            Some(networking::PeerRateLimit {
+                messages_per_second: 50,
+                burst: 100,
+            })
+        );
+        assert_eq!(config.mempool.max_tx, 500);
+        assert_eq!(config.mempool.ttl_secs, Some(60));
+        assert_eq!(
+            config.mempool.to_mempool_config().anti_spam,
+            mempool::AntiSpamConfig {
+                min_gas_price: Some(2),
+                pow_difficulty_bits: None,
+            }
+        );
+        assert_eq!(
+            config.mempool.to_mempool_config().ordering,
+            mempool::OrderingStrategy::Fifo
+        );
+        assert_eq!(
+            config.mempool.to_mempool_config().overflow,
+            mempool::OverflowPolicy::EvictLowestFee
+        );
+        assert_eq!(
+            config.consensus_config().genesis_balances,
+            vec![(types::Address([7; 20]), 1000)]
+        );
+        assert_eq!(
+            config.namespace_registry().unwrap().id_of("payments"),
+            Some(types::NamespaceId(7))
+        );
+        let rpc = config.rpc.to_rpc_config();
+        assert!(rpc.enable_cors);
+        assert_eq!(rpc.allowed_origins, vec!["https://dashboard.example"]);
+        assert_eq!(rpc.max_connections, Some(256));
+        assert_eq!(rpc.max_block_age_ms, Some(30_000));
+        assert_eq!(rpc.priority_token.as_deref(), Some("operator-secret"));
+        assert_eq!(
+            rpc.tx_rate_limit,
+            Some(rpc::RateLimit {
+                requests_per_second: 5,
+                burst: 10,
+                trust_forwarded_for: false,
+            })
+        );
+        assert!(config.mempool.wal);
+        // Unset fields fall back to defaults.
+        assert_eq!(config.mempool.replacement_bump_percent, 10);
+        assert_eq!(rpc.max_body_bytes, rpc::DEFAULT_MAX_BODY_BYTES);
+        assert_eq!(rpc.accept_backlog, rpc::DEFAULT_ACCEPT_BACKLOG);
+        assert_eq!(
+            config.signing_key().unwrap().to_bytes(),
+            SigningKey::from_bytes(&[1; 32]).to_bytes()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let zero_block_time = SAMPLE.replace("block_time_ms = 250", "block_time_ms = 0");
+        assert!(matches!(
+            NodeConfig::parse(&zero_block_time),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let zero_timeout = SAMPLE.replace("view_timeout_views = 20", "view_timeout_views = 0");
+        assert!(matches!(
+            NodeConfig::parse(&zero_timeout),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let bad_level = SAMPLE.replace("compression_level = 3", "compression_level = 30");
+        assert!(matches!(
+            NodeConfig::parse(&bad_level),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let bad_addr = SAMPLE.replace("127.0.0.1:8080", "not-an-address");
+        assert!(matches!(
+            NodeConfig::parse(&bad_addr),
+            Err(ConfigError::Parse(_))
+        ));
+
+        let unknown_field = format!("{SAMPLE}\nunknown = true\n");
+        assert!(matches!(
+            NodeConfig::parse(&unknown_field),
+            Err(ConfigError::Parse(_))
+        ));
+
+        let zero_rate = SAMPLE.replace("requests_per_second = 5", "requests_per_second = 0");
+        assert!(matches!(
+            NodeConfig::parse(&zero_rate),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let no_connections = SAMPLE.replace("max_connections = 256", "max_connections = 0");
+        assert!(matches!(
+            NodeConfig::parse(&no_connections),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let shared_id = SAMPLE.replace("payments = 7", "payments = 7\nfees = 7");
+        assert!(matches!(
+            NodeConfig::parse(&shared_id),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let short_key = SAMPLE.replace("node_key = \"0101", "node_key = \"");
+        assert!(matches!(
+            NodeConfig::parse(&short_key),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn block_age_check_requires_empty_blocks() {
+        // An idle node without empty blocks never advances its head.
+        let idle = SAMPLE.replace(
+            "produce_empty_blocks = true",
+            "produce_empty_blocks = false",
+        );
+        assert!(matches!(
+            NodeConfig::parse(&idle),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        let unchecked = idle.replace("max_block_age_ms = 30000\n", "");
+        assert!(!NodeConfig::parse(&unchecked).unwrap().produce_empty_blocks);
+    }
+
+    #[test]
+    fn env_defaults_are_valid() {
+        NodeConfig::from_env().validate().unwrap();
+    }
+}
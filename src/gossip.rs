@@ -0,0 +1,98 @@
+//! Filtering gossip the node already knows about.
+//!
+//! Peers forward every transaction and block they hear, so most of what
+//! arrives is a copy of something already pending or committed here.
+//! Copies are dropped before they reach the mempool, where a committed
+//! transaction would otherwise be admitted again and only rejected at
+//! execution.
+
+use consensus::{ConsensusEngine, ConsensusError};
+use types::{Block, Transaction, TxId};
+
+/// Whether `id` is pending in the mempool or already committed.
+pub fn is_known_tx(engine: &impl ConsensusEngine, id: TxId) -> Result<bool, ConsensusError> {
+    Ok(engine.is_pending(id) || engine.get_tx(id)?.is_some())
+}
+
+/// Whether `block` is already stored.
+pub fn is_known_block(
+    engine: &impl ConsensusEngine,
+    block: &Block,
+) -> Result<bool, ConsensusError> {
+    Ok(engine.get_block(block.header.id())?.is_some())
+}
+
+/// Submit a gossiped transaction unless it is already known. `None` for a
+/// known one. Priority transactions are refused: only the operator may set
+/// the flag, through the RPC server.
+pub fn submit_gossiped_tx(
+    engine: &mut impl ConsensusEngine,
+    tx: Transaction,
+) -> Result<Option<TxId>, ConsensusError> {
+    if tx.priority {
+        return Err(ConsensusError::Mempool(
+            "priority transactions are not accepted from peers".into(),
+        ));
+    }
+    if is_known_tx(engine, tx.id())? {
+        return Ok(None);
+    }
+    engine.submit_tx(tx).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus::{ConsensusConfig, SingleNodeConsensus};
+    use mempool::SimpleMempool;
+    use storage::InMemoryStorage;
+    use types::{Address, NamespaceId};
+
+    #[test]
+    fn duplicate_gossiped_tx_is_not_inserted_again() {
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            genesis_balances: vec![(Address::default(), 1_000_000)],
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+        let tx = Transaction {
+            chain_id: 0,
+            from: Address::default(),
+            namespace: NamespaceId(1),
+            gas_price: 1,
+            nonce: 0,
+            valid_until_height: None,
+            priority: false,
+            payload: vec![],
+            signature: vec![],
+            pow_nonce: 0,
+        };
+
+        let priority = Transaction {
+            priority: true,
+            ..tx.clone()
+        };
+        assert!(submit_gossiped_tx(&mut engine, priority).is_err());
+        assert_eq!(engine.pending_count(), 0);
+
+        let id = submit_gossiped_tx(&mut engine, tx.clone()).unwrap();
+        assert_eq!(id, Some(tx.id()));
+        assert_eq!(submit_gossiped_tx(&mut engine, tx.clone()).unwrap(), None);
+        assert_eq!(engine.pending_count(), 1);
+
+        // Once committed, a late copy must not re-enter the mempool.
+        engine.step().unwrap().expect("block produced");
+        assert_eq!(engine.pending_count(), 0);
+        assert_eq!(submit_gossiped_tx(&mut engine, tx).unwrap(), None);
+        assert_eq!(engine.pending_count(), 0);
+
+        let head = engine.latest_block().unwrap().unwrap();
+        assert!(is_known_block(&engine, &head).unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+//! Log output: human-readable text by default, or one JSON object per line
+//! for log aggregators when `LOG_FORMAT=json`.
+
+use std::env;
+use std::str::FromStr;
+
+use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::{Format, Json, JsonFields};
+use tracing_subscriber::fmt::{MakeWriter, SubscriberBuilder};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "LOG_FORMAT must be `text` or `json`, got `{other}`"
+            )),
+        }
+    }
+}
+
+impl LogFormat {
+    /// The format named by `LOG_FORMAT`; text if unset.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("LOG_FORMAT") {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+/// Install the global subscriber, logging at `INFO` to stdout.
+pub fn init(format: LogFormat) {
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt().with_max_level(Level::INFO).init(),
+        LogFormat::Json => json_builder(std::io::stdout).init(),
+    }
+}
+
+/// JSON lines with the event's fields under `fields`, plus the innermost
+/// span under `span` and every enclosing span under `spans`, each with its
+/// own fields.
+fn json_builder<W>(writer: W) -> SubscriberBuilder<JsonFields, Format<Json>, LevelFilter, W>
+where
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use consensus::{ConsensusConfig, ConsensusEngine, SingleNodeConsensus};
+    use mempool::SimpleMempool;
+    use serde_json::Value;
+    use storage::InMemoryStorage;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_log_format() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("TEXT".parse(), Ok(LogFormat::Text));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn json_lines_carry_event_and_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = json_builder(move || writer.clone()).finish();
+
+        let config = ConsensusConfig {
+            block_interval_ms: 0,
+            produce_empty_blocks: true,
+            ..Default::default()
+        };
+        let mut engine = SingleNodeConsensus::with_config(
+            SimpleMempool::default(),
+            InMemoryStorage::default(),
+            config,
+        )
+        .unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            engine.step().unwrap();
+            let span = tracing::info_span!("rpc_request", request_id = 7);
+            span.in_scope(|| tracing::info!("handled"));
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let committed = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "committed block")
+            .expect("step logs the committed block");
+        assert_eq!(committed["level"], "INFO");
+        assert_eq!(committed["fields"]["height"], 1);
+        assert_eq!(committed["fields"]["tx_count"], 0);
+        assert_eq!(committed["span"]["name"], "step");
+        assert_eq!(committed["spans"][0]["name"], "step");
+
+        let handled = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "handled")
+            .unwrap();
+        assert_eq!(handled["span"]["request_id"], 7);
+    }
+}
@@ -1,102 +1,254 @@
-use std::env;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
-use consensus::{ConsensusEngine, FinalityEvent, SingleNodeConsensus};
-use mempool::SimpleMempool;
+mod cli;
+mod config;
+mod gossip;
+mod logging;
+
+use clap::Parser;
+use cli::{Cli, Command};
+use config::NodeConfig;
+use consensus::{ConsensusEngine, SingleNodeConsensus};
+use mempool::{Mempool, SimpleMempool};
 use metrics as sequencer_metrics;
-use networking::{start_network, GossipMessage, NetworkConfig};
-use rpc::{run_rpc_server, RpcState};
-use storage::SledStorage;
-use tokio::sync::Mutex;
-use tokio::time::{sleep, Duration};
-use tracing::{info, Level};
+use networking::{start_network, GossipMessage, NetworkConfig, NetworkHandle};
+use rpc::{run_rpc_server, RpcState, ShutdownHandle};
+use storage::{Prunable, SledStorage};
+use tokio::sync::{broadcast, Mutex};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, info, warn};
+use types::NamespaceId;
 // No direct use of types here; RPC constructs transactions.
 
+/// How often the main loop prunes old blocks and refreshes the storage
+/// size gauges.
+const STORAGE_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the node announces its pending transactions to peers, so they
+/// stop forwarding those.
+const TX_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most blocks sent back for one `GetBlocks` request.
+const MAX_BLOCKS_PER_REQUEST: u32 = 64;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+    let cli = Cli::parse();
+    match cli.command {
+        None => run(cli.run.node_config()?).await,
+        Some(Command::Run(args)) => run(args.node_config()?).await,
+        Some(Command::Head(args)) => cli::head(&args),
+        Some(Command::GetBlock { height, data }) => cli::get_block(height, &data),
+        Some(Command::DumpMetrics(args)) => cli::dump_metrics(&args),
+    }
+}
+
+/// Run the node from `config` until Ctrl-C.
+async fn run(config: NodeConfig) -> Result<(), Box<dyn std::error::Error>> {
+    logging::init(logging::LogFormat::from_env()?);
 
     // Install global metrics recorder; metrics are exposed via the RPC server.
     sequencer_metrics::init_metrics()?;
 
-    // Very simple two-node demo configuration based on NODE_ID env var.
-    let node_id = env::var("NODE_ID").unwrap_or_else(|_| "1".to_string());
-    let (listen_addr, peers, rpc_addr): (SocketAddr, Vec<SocketAddr>, SocketAddr) =
-        if node_id == "1" {
-            (
-                "127.0.0.1:9001".parse().unwrap(),
-                vec!["127.0.0.1:9002".parse().unwrap()],
-                "127.0.0.1:8080".parse().unwrap(),
-            )
-        } else {
-            (
-                "127.0.0.1:9002".parse().unwrap(),
-                vec!["127.0.0.1:9001".parse().unwrap()],
-                "127.0.0.1:8081".parse().unwrap(),
-            )
-        };
-
-    // Use a per-node sled database directory to avoid file locks when
-    // running multiple nodes on the same machine.
-    let data_dir = format!("./data_{}", node_id);
-    let storage = SledStorage::open(std::path::Path::new(&data_dir))?;
-    let mempool = SimpleMempool::default();
-
-    let engine = SingleNodeConsensus::new(mempool, storage);
+    info!(listen_addr = %config.listen_addr, rpc_addr = %config.rpc_addr, "starting node");
+
+    let storage = SledStorage::open_with_config(&config.data_dir, config.sled_config())?;
+    let namespaces = config.namespace_registry()?;
+    let mempool = SimpleMempool::open(mempool::MempoolConfig {
+        namespaces: namespaces.clone(),
+        wal: config
+            .mempool
+            .wal
+            .then(|| mempool::WalConfig::new(config.data_dir.join("mempool.wal"))),
+        ..config.mempool.to_mempool_config()
+    })?;
+    let mempool_ttl = config.mempool.ttl_secs.map(Duration::from_secs);
+
+    let consensus_config = consensus::ConsensusConfig {
+        namespaces,
+        ..config.consensus_config()
+    };
+    let engine = SingleNodeConsensus::with_config(mempool, storage, consensus_config)?;
     let shared_engine = Arc::new(Mutex::new(engine));
 
     // Start networking: gossip transactions into the local mempool and
     // committed blocks into local storage via the consensus engine.
     let net_engine = Arc::clone(&shared_engine);
-    let net_config = NetworkConfig { listen_addr, peers };
-    let net_handle = start_network(net_config, move |msg| {
+    let mut net_config = NetworkConfig::new(
+        config.listen_addr,
+        config.peers.clone(),
+        config.signing_key()?,
+        config.trusted_keys()?,
+    );
+    net_config.compression_level = config.compression_level;
+    net_config.wire_format = config.wire_format.to_wire_format();
+    net_config.subscribed_namespaces = config
+        .subscribed_namespaces
+        .as_ref()
+        .map(|ids| ids.iter().copied().map(NamespaceId).collect());
+    net_config.rate_limit = config
+        .gossip_rate_limit
+        .map(|limit| limit.to_peer_rate_limit());
+    // Set once the network is up; replies to peers go through it.
+    let replies: Arc<OnceLock<NetworkHandle>> = Arc::default();
+    let net_replies = Arc::clone(&replies);
+    let net_handle = start_network(net_config, move |from, msg| {
         let net_engine = Arc::clone(&net_engine);
         match msg {
             GossipMessage::Tx(tx) => {
-                // Best-effort: insert into mempool via consensus engine.
-                info!("received gossiped tx; inserting into local mempool");
+                // Best-effort: insert into mempool via consensus engine,
+                // skipping copies of transactions we already have.
                 tokio::spawn(async move {
                     let mut guard = net_engine.lock().await;
-                    let _ = guard.submit_tx(tx);
+                    let namespace = tx.namespace.name(&guard.config().namespaces);
+                    match gossip::submit_gossiped_tx(&mut *guard, tx) {
+                        Ok(Some(id)) => {
+                            info!(tx_id = ?id, %namespace, "inserted gossiped tx into local mempool")
+                        }
+                        Ok(None) => debug!(%namespace, "dropped already-known gossiped tx"),
+                        Err(e) => debug!(error = %e, %namespace, "rejected gossiped tx"),
+                    }
                 });
             }
-            GossipMessage::Block(_block) => {
+            GossipMessage::Block(block) => {
                 // In a fuller implementation, we would verify and import
-                // the block. For now, we log receipt only.
-                tracing::info!("received gossiped block (ignored in demo)");
+                // the block. For now, we log receipt of unknown ones only.
+                tokio::spawn(async move {
+                    if let Ok(false) = gossip::is_known_block(&*net_engine.lock().await, &block) {
+                        info!("received gossiped block (ignored in demo)");
+                    }
+                });
+            }
+            GossipMessage::GetBlocks { from_height, limit } => {
+                let Some(handle) = net_replies.get().cloned() else {
+                    return;
+                };
+                let limit = limit.clamp(1, MAX_BLOCKS_PER_REQUEST);
+                tokio::spawn(async move {
+                    let to_height = from_height.saturating_add(u64::from(limit) - 1);
+                    let blocks = net_engine
+                        .lock()
+                        .await
+                        .blocks_in_range(from_height, to_height);
+                    match blocks {
+                        Ok(blocks) => {
+                            for block in blocks {
+                                let _ = handle.send_to(from, GossipMessage::Block(block)).await;
+                            }
+                        }
+                        Err(e) => {
+                            warn!(peer = %from, error = %e, "failed to read requested blocks")
+                        }
+                    }
+                });
             }
+            // Heartbeats and announcements are handled inside the
+            // networking layer.
+            GossipMessage::Ping
+            | GossipMessage::Pong
+            | GossipMessage::HaveTxs(_)
+            | GossipMessage::Hello { .. } => {}
         }
     })
     .await;
+    let _ = replies.set(net_handle.clone());
+    let announcer = net_handle.clone();
 
     // Spawn RPC server, giving it access to both the engine and network
     // so it can gossip submitted transactions.
+    // Finality events are published to RPC subscribers (e.g. `GET /ws`).
+    let (events, _) = broadcast::channel(256);
     let rpc_state: RpcState<_> = Arc::new(rpc::RpcInnerState {
         engine: Arc::clone(&shared_engine),
         network: Some(net_handle),
+        events: events.clone(),
+        config: config.rpc.to_rpc_config(),
+        l1_batches: None,
     });
-    tokio::spawn(async move {
-        if let Err(e) = run_rpc_server(rpc_state, rpc_addr).await {
+    let rpc_addr = config.rpc_addr;
+    let shutdown = ShutdownHandle::new();
+    let rpc_shutdown = shutdown.clone();
+    let rpc_task = tokio::spawn(async move {
+        if let Err(e) = run_rpc_server(rpc_state, rpc_addr, rpc_shutdown).await {
             eprintln!("RPC server error: {e}");
         }
     });
 
+    // Ctrl-C stops the consensus loop between steps, never mid-commit.
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => {
+                info!("received Ctrl-C; shutting down");
+                signal_shutdown.shutdown();
+            }
+            Err(e) => warn!(error = %e, "failed to listen for Ctrl-C"),
+        }
+    });
+
     // Simple consensus loop that periodically seals blocks from the mempool.
-    loop {
+    let mut last_maintenance: Option<Instant> = None;
+    let mut last_announce: Option<Instant> = None;
+    while !shutdown.is_shutdown() {
+        let mut pending = None;
         {
             let mut engine_guard = shared_engine.lock().await;
-            if let Some(FinalityEvent::BlockCommitted { block, .. }) = engine_guard.step()? {
-                info!(
-                    height = block.header.height,
-                    tx_count = block.txs.len(),
-                    "committed block"
-                );
+            if let Some(ttl) = mempool_ttl {
+                let evicted = engine_guard.mempool_mut().evict_expired(ttl);
+                if evicted > 0 {
+                    info!(evicted, "evicted expired mempool transactions");
+                }
+            }
+            if let Some(event) = engine_guard.step()? {
+                // No subscribers is not an error.
+                let _ = events.send(event);
+            }
+            if last_maintenance.is_none_or(|at| at.elapsed() >= STORAGE_MAINTENANCE_INTERVAL) {
+                last_maintenance = Some(Instant::now());
+                if let Some(retain) = config.retain_blocks {
+                    match engine_guard.latest_block() {
+                        Ok(Some(head)) => {
+                            let keep_from = head.header.height.saturating_sub(retain) + 1;
+                            match engine_guard.storage_mut().prune_below(keep_from) {
+                                Ok(0) => {}
+                                Ok(pruned) => info!(pruned, keep_from, "pruned old blocks"),
+                                Err(e) => warn!(error = %e, "failed to prune old blocks"),
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!(error = %e, "failed to read the head block to prune"),
+                    }
+                }
+                if let Err(e) = engine_guard.storage().record_size_metrics() {
+                    warn!(error = %e, "failed to read storage size");
+                }
+            }
+            if last_announce.is_none_or(|at| at.elapsed() >= TX_ANNOUNCE_INTERVAL) {
+                last_announce = Some(Instant::now());
+                let mempool = engine_guard.mempool_mut();
+                let ids: Vec<_> = mempool
+                    .get_batch(mempool.len())
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect();
+                pending = Some(ids);
             }
         }
+        // Announced outside the engine lock; only fails after shutdown.
+        if let Some(ids) = pending {
+            let _ = announcer.announce_txs(ids.iter()).await;
+        }
 
-        sleep(Duration::from_millis(500)).await;
+        tokio::select! {
+            _ = sleep(Duration::from_millis(config.block_time_ms)) => {}
+            _ = shutdown.wait() => {}
+        }
     }
+
+    // Let in-flight RPC requests finish, then make sure everything committed
+    // so far is on disk.
+    let _ = rpc_task.await;
+    shared_engine.lock().await.storage().flush_async().await?;
+    info!("node stopped");
+    Ok(())
 }
@@ -0,0 +1,61 @@
+use std::process::Command;
+
+use consensus::{ConsensusConfig, ConsensusEngine, SingleNodeConsensus};
+use mempool::SimpleMempool;
+use serde_json::Value;
+use storage::SledStorage;
+
+/// The one-off subcommands read a data directory left behind by a node.
+#[test]
+fn head_prints_the_latest_block_of_a_data_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = ConsensusConfig {
+        block_interval_ms: 0,
+        produce_empty_blocks: true,
+        ..Default::default()
+    };
+    let mut engine = SingleNodeConsensus::with_config(
+        SimpleMempool::default(),
+        SledStorage::open(dir.path()).unwrap(),
+        config,
+    )
+    .unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        engine.step().unwrap().expect("empty block produced");
+        ids.push(engine.head().unwrap().1);
+    }
+    engine.storage().flush().unwrap();
+    // Release sled's lock on the directory.
+    drop(engine);
+
+    let bin = env!("CARGO_BIN_EXE_rollup-sequencer");
+    let output = Command::new(bin)
+        .args(["head", "--data-dir"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let head: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(head["header"]["height"], 3);
+    assert_eq!(
+        head["header"]["parent"],
+        serde_json::to_value(ids[1]).unwrap()
+    );
+
+    let output = Command::new(bin)
+        .args(["get-block", "--height", "1", "--data-dir"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let first: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(first["header"]["height"], 1);
+
+    let output = Command::new(bin)
+        .args(["get-block", "--height", "9", "--data-dir"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
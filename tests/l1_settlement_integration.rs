@@ -1,18 +1,39 @@
-use consensus::{build_l1_batch_commitment, ConsensusEngine, FinalityEvent, SingleNodeConsensus};
+use std::sync::Arc;
+
+use consensus::{
+    build_l1_batch_commitment, ConsensusConfig, ConsensusEngine, FinalityEvent, InMemoryL1Sink,
+    L1Batcher, L1BatcherConfig, SingleNodeConsensus,
+};
 use mempool::SimpleMempool;
-use storage::{InMemoryStorage, TxStore};
-use types::{NamespaceId, Transaction};
+use storage::InMemoryStorage;
+use tokio::sync::broadcast;
+use types::{Address, NamespaceId, Transaction};
 
 fn make_tx(nonce: u64) -> Transaction {
     Transaction {
+        chain_id: 0,
+        from: Address::default(),
         namespace: NamespaceId(1),
         gas_price: 1,
         nonce,
+        valid_until_height: None,
+        priority: false,
         payload: vec![],
         signature: vec![],
+        pow_nonce: 0,
     }
 }
 
+/// An engine whose only sender, the zero address, can pay for every test tx.
+fn funded_engine() -> SingleNodeConsensus<SimpleMempool, InMemoryStorage> {
+    let config = ConsensusConfig {
+        genesis_balances: vec![(Address::default(), 1_000_000_000)],
+        ..Default::default()
+    };
+    SingleNodeConsensus::with_config(SimpleMempool::default(), InMemoryStorage::default(), config)
+        .unwrap()
+}
+
 /// This integration test showcases how a component can subscribe to
 /// finality events from the consensus engine, build an L1 batch
 /// commitment from the committed blocks, and "post" it to a mock L1
@@ -20,9 +41,7 @@ fn make_tx(nonce: u64) -> Transaction {
 /// settlement contract.
 #[test]
 fn l1_batch_can_be_built_from_finality_stream() {
-    let mempool = SimpleMempool::default();
-    let storage = InMemoryStorage::default();
-    let mut engine = SingleNodeConsensus::new(mempool, storage);
+    let mut engine = funded_engine();
 
     // Seed enough transactions so we are guaranteed at least one
     // committed block when we drive the engine.
@@ -39,14 +58,47 @@ fn l1_batch_can_be_built_from_finality_stream() {
         }
     }
 
-    assert!(!committed_blocks.is_empty(), "expected at least one committed block");
+    assert!(
+        !committed_blocks.is_empty(),
+        "expected at least one committed block"
+    );
 
     // Build a batch commitment as would be posted to L1.
     let batch = build_l1_batch_commitment(42, &committed_blocks);
 
     // Mock L1 sink that stores commitment hashes.
-    let mut mock_l1_contract: Vec<types::Hash> = Vec::new();
-    mock_l1_contract.push(batch.hash());
+    let mock_l1_contract: Vec<types::Hash> = vec![batch.hash()];
 
     assert_eq!(mock_l1_contract.len(), 1);
 }
+
+/// Drives the engine with an `L1Batcher` subscribed to its finality events
+/// and checks that the committed blocks land in a single posted batch.
+#[tokio::test]
+async fn batcher_posts_one_commitment_for_committed_blocks() {
+    let mut engine = funded_engine();
+    let (events, rx) = broadcast::channel(16);
+    let sink = Arc::new(InMemoryL1Sink::default());
+    let config = L1BatcherConfig {
+        batch_size: 3,
+        ..Default::default()
+    };
+    let batcher = L1Batcher::new(Arc::clone(&sink), config).spawn(rx);
+
+    let mut committed = Vec::new();
+    for nonce in 0..3 {
+        engine.submit_tx(make_tx(nonce)).unwrap();
+        let event = engine.step().unwrap().expect("block committed");
+        if let FinalityEvent::BlockCommitted { block, .. } = &event {
+            committed.push(block.header.id());
+        }
+        events.send(event).unwrap();
+    }
+    drop(events);
+    batcher.await.unwrap();
+
+    let posted = sink.posted();
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0].batch_number, 0);
+    assert_eq!(posted[0].block_ids, committed);
+}